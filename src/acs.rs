@@ -0,0 +1,388 @@
+use crate::data::{Meta, Reading, Value};
+use crate::gzip::crc32;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Write};
+
+// The binary capture format (".acs" files). Where the JSON capture
+// keeps each reply's timestamp, DRF and value, this keeps the whole
+// reading (its status, cycle and the device, DI and units the data
+// source gave) in a fraction of the space. A file is "ACS1" followed
+// by frames,
+//
+//    kind (1 byte) | length (varint) | payload | CRC-32 of kind and payload (4 bytes, LE)
+//
+// of these kinds:
+//
+//    1  channel   a key (DRF, or a derived channel's name); channels
+//                 are numbered in the order they're defined
+//    2  meta      channel, device, DI and units, whenever they change
+//    3  reading   channel, timestamp (as the change from the previous
+//                 reading's), status, cycle (as its offset from the
+//                 timestamp), value
+//
+// Integers are LEB128 varints, signed ones zigzag encoded first, and
+// strings and byte arrays are a varint length and their bytes. An
+// optional field is a 0 byte when missing, or a 1 byte and the field.
+// A value is a byte giving its type (scalar, array, raw, text,
+// status) and the data; numbers are little-endian doubles. Alarm
+// blocks are kept as the raw block, as in JSON captures, and the
+// status attributes, which are decoded from the value, aren't kept.
+//
+// Each frame is written whole, so an interrupted session keeps every
+// reading it got but, at worst, a partial last frame, which readers
+// skip.
+
+pub const MAGIC: &[u8; 4] = b"ACS1";
+
+const CHANNEL: u8 = 1;
+const META: u8 = 2;
+const READING: u8 = 3;
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7
+    }
+    out.push(n as u8)
+}
+
+fn signed(out: &mut Vec<u8>, n: i64) {
+    varint(out, ((n << 1) ^ (n >> 63)) as u64)
+}
+
+fn bytes(out: &mut Vec<u8>, data: &[u8]) {
+    varint(out, data.len() as u64);
+    out.extend_from_slice(data)
+}
+
+fn optional<T>(out: &mut Vec<u8>, v: Option<T>, f: impl FnOnce(&mut Vec<u8>, T)) {
+    match v {
+        Some(v) => {
+            out.push(1);
+            f(out, v)
+        }
+        None => out.push(0),
+    }
+}
+
+// Writes readings to a binary capture.
+
+pub struct Writer<W: Write> {
+    out: W,
+    channels: HashMap<String, u64>,
+    meta: Vec<(Option<String>, Option<u32>, Option<String>)>,
+    last: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut out: W) -> io::Result<Writer<W>> {
+        out.write_all(MAGIC)?;
+        Ok(Writer { out, channels: HashMap::new(), meta: vec![], last: 0 })
+    }
+
+    fn frame(&mut self, kind: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![kind];
+
+        varint(&mut frame, payload.len() as u64);
+        frame.extend_from_slice(payload);
+
+        let crc = crc32(&[&[kind][..], payload].concat());
+
+        frame.extend_from_slice(&crc.to_le_bytes());
+        self.out.write_all(&frame)
+    }
+
+    // Writes a reading of the channel named `key`, defining it, and
+    // its metadata, first if needed.
+
+    pub fn write(&mut self, key: &str, reading: &Reading) -> io::Result<()> {
+        let channel = match self.channels.get(key) {
+            Some(c) => *c,
+            None => {
+                let c = self.channels.len() as u64;
+                let mut payload = vec![];
+
+                bytes(&mut payload, key.as_bytes());
+                self.frame(CHANNEL, &payload)?;
+                self.channels.insert(String::from(key), c);
+                self.meta.push((None, None, None));
+                c
+            }
+        };
+
+        let m = &reading.meta;
+        let meta = (m.device.clone(), m.di, m.units.clone());
+
+        if self.meta[channel as usize] != meta {
+            let mut payload = vec![];
+
+            varint(&mut payload, channel);
+            optional(&mut payload, meta.0.as_deref(), |o, d| bytes(o, d.as_bytes()));
+            optional(&mut payload, meta.1, |o, di| varint(o, u64::from(di)));
+            optional(&mut payload, meta.2.as_deref(), |o, u| bytes(o, u.as_bytes()));
+            self.frame(META, &payload)?;
+            self.meta[channel as usize] = meta
+        }
+
+        let mut payload = vec![];
+
+        varint(&mut payload, channel);
+        signed(&mut payload, reading.timestamp as i64 - self.last as i64);
+        signed(&mut payload, i64::from(m.status));
+        optional(&mut payload, m.cycle, |o, c| signed(o, c as i64 - reading.timestamp as i64));
+        match &reading.value {
+            Value::Scalar(v) => {
+                payload.push(0);
+                payload.extend_from_slice(&v.to_le_bytes())
+            }
+            Value::Array(v) => {
+                payload.push(1);
+                varint(&mut payload, v.len() as u64);
+                v.iter().for_each(|v| payload.extend_from_slice(&v.to_le_bytes()))
+            }
+            Value::Raw(v) => {
+                payload.push(2);
+                bytes(&mut payload, v)
+            }
+            Value::Alarm(a) => {
+                payload.push(2);
+                bytes(&mut payload, a.raw())
+            }
+            Value::Text(v) => {
+                payload.push(3);
+                bytes(&mut payload, v.as_bytes())
+            }
+            Value::Status(s) => {
+                payload.push(4);
+                signed(&mut payload, i64::from(*s))
+            }
+        }
+        self.last = reading.timestamp;
+        self.frame(READING, &payload)
+    }
+}
+
+// A reading of a capture, with its channel's key. The reading's index
+// is the channel's number.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub reading: Reading,
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < n {
+            return Err(String::from("truncated"));
+        }
+
+        let (head, rest) = self.data.split_at(n);
+
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut n = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+
+            n |= u64::from(b & 0x7f) << shift;
+            if b < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err(String::from("bad varint"))
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let n = self.varint()?;
+
+        Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let n = self.varint()?;
+
+        self.take(usize::try_from(n).map_err(|_| String::from("truncated"))?)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| String::from("bad text"))
+    }
+
+    fn double(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn optional<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<Option<T>, String> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => f(self).map(Some),
+            b => Err(format!("bad optional marker {}", b)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        Ok(match self.byte()? {
+            0 => Value::Scalar(self.double()?),
+            1 => {
+                let n = self.varint()?;
+
+                Value::Array((0..n).map(|_| self.double()).collect::<Result<_, _>>()?)
+            }
+            2 => Value::Raw(self.bytes()?.to_vec()),
+            3 => Value::Text(self.string()?),
+            4 => Value::Status(i16::try_from(self.signed()?).map_err(|_| String::from("bad status"))?),
+            t => return Err(format!("unknown value type {}", t)),
+        })
+    }
+}
+
+// Reads a binary capture. A partial frame at the end, left by an
+// interrupted session, is ignored.
+
+pub fn read(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let mut d = Decoder { data: data.strip_prefix(&MAGIC[..]).ok_or_else(|| String::from("not a binary capture"))? };
+    let mut keys: Vec<String> = vec![];
+    let mut meta: Vec<Meta> = vec![];
+    let mut last = 0u64;
+    let mut entries = vec![];
+
+    for n in 1.. {
+        if d.data.is_empty() {
+            break;
+        }
+
+        let frame = (|| -> Result<_, String> {
+            let kind = d.byte()?;
+            let payload = d.bytes()?;
+            let crc = u32::from_le_bytes(d.take(4)?.try_into().unwrap());
+
+            Ok((kind, payload, crc))
+        })();
+        let (kind, payload, crc) = match frame {
+            Ok(f) => f,
+            Err(_) => break,
+        };
+        let fail = |e: String| format!("frame {}: {}", n, e);
+
+        if crc32(&[&[kind][..], payload].concat()) != crc {
+            return Err(fail(String::from("bad checksum")));
+        }
+
+        let mut p = Decoder { data: payload };
+        let channel = |p: &mut Decoder, count: usize| match p.varint()? {
+            c if (c as usize) < count => Ok(c as usize),
+            c => Err(format!("undefined channel {}", c)),
+        };
+
+        match kind {
+            CHANNEL => {
+                keys.push(p.string().map_err(fail)?);
+                meta.push(Meta::default())
+            }
+            META => {
+                let c = channel(&mut p, keys.len()).map_err(fail)?;
+                let device = p.optional(Decoder::string).map_err(fail)?;
+                let di = p.optional(|p| p.varint().map(|di| di as u32)).map_err(fail)?;
+                let units = p.optional(Decoder::string).map_err(fail)?;
+
+                meta[c] = Meta { device, di, units, ..Meta::default() }
+            }
+            READING => {
+                let reading = (|| -> Result<_, String> {
+                    let c = channel(&mut p, keys.len())?;
+                    let timestamp = (last as i64 + p.signed()?) as u64;
+                    let status = i16::try_from(p.signed()?).map_err(|_| String::from("bad status"))?;
+                    let cycle = p.optional(|p| p.signed().map(|o| (timestamp as i64 + o) as u64))?;
+                    let value = p.value()?;
+
+                    Ok(Reading { index: c, timestamp, value, meta: Meta { status, cycle, ..meta[c].clone() } })
+                })()
+                .map_err(fail)?;
+
+                last = reading.timestamp;
+                entries.push(Entry { key: keys[reading.index].clone(), reading })
+            }
+
+            // Frames of kinds added later are skipped.
+            _ => (),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut first = Reading::new(0, 1_700_000_000_500_000, Value::Scalar(72.5));
+
+        first.meta = Meta {
+            device: Some(String::from("M:OUTTMP")),
+            di: Some(27235),
+            units: Some(String::from("degF")),
+            cycle: Some(1_700_000_000_000_000),
+            ..Meta::default()
+        };
+
+        let mut second = first.clone();
+        let readings = [
+            ("M:OUTTMP@p,1000", first.clone()),
+            ("B:BPM[0:1]", Reading::new(1, 1_700_000_000_400_000, Value::Array(vec![1.0, -2.5]))),
+            ("G:TEXT", Reading::new(2, 1_700_000_000_600_000, Value::Text(String::from("ok")))),
+            ("G:RAW.RAW", Reading::new(3, 1_700_000_000_600_000, Value::Raw(vec![1, 2, 255]))),
+            ("M:NONE", Reading::new(4, 1_700_000_000_700_000, Value::Status(-6))),
+            ("M:OUTTMP@p,1000", {
+                second.timestamp += 1_000_000;
+                second.meta.status = 1;
+                second
+            }),
+        ];
+        let mut w = Writer::new(vec![]).unwrap();
+        let mut sizes = vec![];
+
+        for (key, r) in &readings {
+            w.write(key, r).unwrap();
+            sizes.push(w.out.len())
+        }
+
+        let data = w.out;
+        let entries = read(&data).unwrap();
+
+        assert_eq!(entries.len(), readings.len());
+        for (e, (key, r)) in entries.iter().zip(&readings) {
+            assert_eq!((e.key.as_str(), &e.reading), (*key, r))
+        }
+
+        // The channel and metadata are only written once, so a later
+        // scalar reading takes a single, small frame.
+
+        assert_eq!(sizes[5] - sizes[4], 25);
+
+        // A partial last frame is dropped; a corrupt one fails.
+
+        assert_eq!(read(&data[..data.len() - 3]).unwrap().len(), readings.len() - 1);
+
+        let mut bad = data.clone();
+        let n = bad.len();
+
+        bad[n - 6] ^= 1;
+        assert_eq!(read(&bad).unwrap_err(), format!("frame {}: bad checksum", 12));
+        assert!(read(b"{\"timestamp\":1}").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+// Returns whether a device name matches a pattern, in which '*'
+// matches any run of characters and '?' any one character. Case is
+// ignored, as it is in DRF requests.
+
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.to_ascii_uppercase().chars().collect();
+    let n: Vec<char> = name.to_ascii_uppercase().chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ni));
+                pi += 1
+            }
+            Some(&c) if c == '?' || c == n[ni] => {
+                pi += 1;
+                ni += 1
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    pi = sp + 1;
+                    ni = sn + 1
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// Formats a duration, given in microseconds, e.g. "1h02m03s".
+
+pub fn format_duration(us: u64) -> String {
+    let s = us / 1_000_000;
+
+    match (s / 3600, s / 60 % 60, s % 60) {
+        (0, 0, s) => format!("{}.{:03}s", s, us / 1000 % 1000),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("M:OUTTMP", "m:outtmp"));
+        assert!(matches("M:*", "M:OUTTMP"));
+        assert!(matches("*TMP", "M:OUTTMP"));
+        assert!(matches("?:OUT*P", "M:OUTTMP"));
+        assert!(matches("*", ""));
+        assert!(!matches("M:*", "G:AMANDA"));
+        assert!(!matches("M:OUTTM", "M:OUTTMP"));
+        assert!(!matches("?:OUTTMP", "M:OUTTM"));
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(format_duration(1_500_000), "1.500s");
+        assert_eq!(format_duration(65_000_000), "1m05s");
+        assert_eq!(format_duration(3_723_000_000), "1h02m03s");
+    }
+}
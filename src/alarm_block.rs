@@ -0,0 +1,285 @@
+use crate::output::json_number;
+
+// A device's alarm block, as read through `.ANALOG.ALL` or
+// `.DIGITAL.ALL`. The raw block is kept so it can be sent back or
+// recorded as the data source returned it.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Alarm {
+    Analog(AnalogAlarm),
+    Digital(DigitalAlarm),
+}
+
+impl Alarm {
+    pub fn raw(&self) -> &[u8] {
+        match self {
+            Alarm::Analog(a) => &a.raw,
+            Alarm::Digital(a) => &a.raw,
+        }
+    }
+
+    // Returns the alarm's fields, by name, as text and as JSON.
+
+    fn fields(&self) -> Vec<(&'static str, String, String)> {
+        let number = |name, v: f64| (name, v.to_string(), json_number(v));
+        let flag = |name, v: bool| (name, v.to_string(), v.to_string());
+        let count = |name, v: u32| (name, v.to_string(), v.to_string());
+        let bits = |name, v: u32| (name, format!("{:#010x}", v), v.to_string());
+
+        match self {
+            Alarm::Analog(a) => vec![
+                number("min", a.min),
+                number("max", a.max),
+                number("nominal", a.nominal),
+                number("tolerance", a.tolerance),
+                flag("enabled", a.enabled),
+                flag("bad", a.bad),
+                flag("abort", a.abort),
+                flag("abort_inhibit", a.abort_inhibit),
+                count("tries_needed", a.tries_needed.into()),
+                count("tries_now", a.tries_now.into()),
+                count("ftd", a.ftd.into()),
+            ],
+            Alarm::Digital(a) => vec![
+                bits("nominal", a.nominal),
+                bits("mask", a.mask),
+                flag("enabled", a.enabled),
+                flag("bad", a.bad),
+                flag("abort", a.abort),
+                flag("abort_inhibit", a.abort_inhibit),
+                count("tries_needed", a.tries_needed.into()),
+                count("tries_now", a.tries_now.into()),
+                count("ftd", a.ftd.into()),
+            ],
+        }
+    }
+
+    // Formats the alarm as "NAME=VALUE" pairs, e.g. "min=-20 max=110
+    // ... ftd=0". A digital alarm's bits are written in hex.
+
+    pub fn format_text(&self) -> String {
+        self.fields().iter().map(|(name, text, _)| format!("{}={}", name, text)).collect::<Vec<_>>().join(" ")
+    }
+
+    // Formats the alarm as a JSON object with a member per field.
+
+    pub fn format_json(&self) -> String {
+        let members: Vec<String> = self.fields().iter().map(|(name, _, json)| format!("\"{}\":{}", name, json)).collect();
+
+        format!("{{{}}}", members.join(","))
+    }
+}
+
+// The bits of an alarm block's flags word.
+
+const AB_ENABLE: u16 = 0x0001;
+const AB_BAD: u16 = 0x0002;
+const AB_NOM_TOL: u16 = 0x0020;
+const AB_ABORT: u16 = 0x0100;
+const AB_ABORT_INHIBIT: u16 = 0x0200;
+
+// An analog alarm block. The raw block is 20 bytes, little-endian:
+//
+//    flags          u16   see the AB_ constants
+//    value 1        i32   the minimum or, with AB_NOM_TOL, nominal
+//    value 2        i32   the maximum or tolerance
+//    tries needed   u8    readings out of tolerance before alarming
+//    tries now      u8    readings out of tolerance so far
+//    FTD            u16   how often the front end checks the alarm
+//    (reserved)     6 bytes
+//
+// The limits are in the units of the device's raw data. Whichever way
+// the limits are given, both forms are returned.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalogAlarm {
+    pub min: f64,
+    pub max: f64,
+    pub nominal: f64,
+    pub tolerance: f64,
+    pub enabled: bool,
+    pub bad: bool,
+    pub abort: bool,
+    pub abort_inhibit: bool,
+    pub tries_needed: u8,
+    pub tries_now: u8,
+    pub ftd: u16,
+    pub raw: Vec<u8>,
+}
+
+pub const ANALOG_LEN: usize = 20;
+
+// Decodes the reply to a request for a whole alarm block. Replies to
+// other properties aren't alarm blocks, so `None` is returned.
+
+pub fn decode(property: &drf::Property, raw: &[u8]) -> Option<Result<Alarm, String>> {
+    match property {
+        drf::Property::Analog(drf::AnalogField::All) => Some(AnalogAlarm::decode(raw).map(Alarm::Analog)),
+        drf::Property::Digital(drf::DigitalField::All) => Some(DigitalAlarm::decode(raw).map(Alarm::Digital)),
+        _ => None,
+    }
+}
+
+impl AnalogAlarm {
+    pub fn decode(raw: &[u8]) -> Result<AnalogAlarm, String> {
+        if raw.len() != ANALOG_LEN {
+            return Err(format!("an analog alarm block has {} bytes, not {}", raw.len(), ANALOG_LEN));
+        }
+
+        let u16_at = |n: usize| u16::from_le_bytes([raw[n], raw[n + 1]]);
+        let i32_at = |n: usize| f64::from(i32::from_le_bytes([raw[n], raw[n + 1], raw[n + 2], raw[n + 3]]));
+        let flags = u16_at(0);
+        let (v1, v2) = (i32_at(2), i32_at(6));
+        let (min, max) = if flags & AB_NOM_TOL != 0 { (v1 - v2, v1 + v2) } else { (v1, v2) };
+
+        Ok(AnalogAlarm {
+            min,
+            max,
+            nominal: (min + max) / 2.0,
+            tolerance: (max - min).abs() / 2.0,
+            enabled: flags & AB_ENABLE != 0,
+            bad: flags & AB_BAD != 0,
+            abort: flags & AB_ABORT != 0,
+            abort_inhibit: flags & AB_ABORT_INHIBIT != 0,
+            tries_needed: raw[10],
+            tries_now: raw[11],
+            ftd: u16_at(12),
+            raw: raw.to_vec(),
+        })
+    }
+}
+
+// A digital alarm block. The raw block is 20 bytes, little-endian,
+// laid out like an analog one but for its values:
+//
+//    flags          u16   see the AB_ constants (AB_NOM_TOL is unused)
+//    nominal        u32   the status bits expected
+//    mask           u32   the bits which are checked
+//    tries needed   u8
+//    tries now      u8
+//    FTD            u16
+//    (reserved)     6 bytes
+//
+// The device is in alarm when `reading & mask != nominal & mask`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DigitalAlarm {
+    pub nominal: u32,
+    pub mask: u32,
+    pub enabled: bool,
+    pub bad: bool,
+    pub abort: bool,
+    pub abort_inhibit: bool,
+    pub tries_needed: u8,
+    pub tries_now: u8,
+    pub ftd: u16,
+    pub raw: Vec<u8>,
+}
+
+pub const DIGITAL_LEN: usize = 20;
+
+impl DigitalAlarm {
+    pub fn decode(raw: &[u8]) -> Result<DigitalAlarm, String> {
+        if raw.len() != DIGITAL_LEN {
+            return Err(format!("a digital alarm block has {} bytes, not {}", raw.len(), DIGITAL_LEN));
+        }
+
+        let u16_at = |n: usize| u16::from_le_bytes([raw[n], raw[n + 1]]);
+        let u32_at = |n: usize| u32::from_le_bytes([raw[n], raw[n + 1], raw[n + 2], raw[n + 3]]);
+        let flags = u16_at(0);
+
+        Ok(DigitalAlarm {
+            nominal: u32_at(2),
+            mask: u32_at(6),
+            enabled: flags & AB_ENABLE != 0,
+            bad: flags & AB_BAD != 0,
+            abort: flags & AB_ABORT != 0,
+            abort_inhibit: flags & AB_ABORT_INHIBIT != 0,
+            tries_needed: raw[10],
+            tries_now: raw[11],
+            ftd: u16_at(12),
+            raw: raw.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(flags: u16, v1: i32, v2: i32) -> Vec<u8> {
+        let mut raw = vec![0; ANALOG_LEN];
+
+        raw[0..2].copy_from_slice(&flags.to_le_bytes());
+        raw[2..6].copy_from_slice(&v1.to_le_bytes());
+        raw[6..10].copy_from_slice(&v2.to_le_bytes());
+        raw[10] = 3;
+        raw[11] = 1;
+        raw[12..14].copy_from_slice(&60u16.to_le_bytes());
+        raw
+    }
+
+    #[test]
+    fn test_analog() {
+        let a = AnalogAlarm::decode(&block(AB_ENABLE | AB_ABORT_INHIBIT, -100, 300)).unwrap();
+
+        assert_eq!((a.min, a.max, a.nominal, a.tolerance), (-100.0, 300.0, 100.0, 200.0));
+        assert_eq!((a.enabled, a.bad, a.abort, a.abort_inhibit), (true, false, false, true));
+        assert_eq!((a.tries_needed, a.tries_now, a.ftd), (3, 1, 60));
+
+        let a = AnalogAlarm::decode(&block(AB_NOM_TOL | AB_BAD, 500, 100)).unwrap();
+
+        assert_eq!((a.min, a.max, a.nominal, a.tolerance), (400.0, 600.0, 500.0, 100.0));
+        assert!(a.bad && !a.enabled);
+
+        let alarm = Alarm::Analog(a);
+
+        assert_eq!(alarm.raw(), &block(AB_NOM_TOL | AB_BAD, 500, 100)[..]);
+        assert_eq!(
+            alarm.format_text(),
+            "min=400 max=600 nominal=500 tolerance=100 enabled=false bad=true abort=false abort_inhibit=false \
+             tries_needed=3 tries_now=1 ftd=60"
+        );
+        assert_eq!(
+            alarm.format_json(),
+            "{\"min\":400,\"max\":600,\"nominal\":500,\"tolerance\":100,\"enabled\":false,\"bad\":true,\"abort\":false,\
+             \"abort_inhibit\":false,\"tries_needed\":3,\"tries_now\":1,\"ftd\":60}"
+        );
+
+        assert!(AnalogAlarm::decode(&[0; 8]).is_err());
+
+        let property = |drf| drf::parse_drf(drf).unwrap().property;
+
+        assert!(matches!(decode(&property("G@AMANDA"), &block(0, 0, 0)), Some(Ok(Alarm::Analog(_)))));
+        assert_eq!(decode(&property("G:AMANDA"), &block(0, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_digital() {
+        let raw = block(AB_ENABLE | AB_BAD | AB_ABORT, 0x05, 0xff);
+        let a = DigitalAlarm::decode(&raw).unwrap();
+
+        assert_eq!((a.nominal, a.mask), (0x05, 0xff));
+        assert_eq!((a.enabled, a.bad, a.abort, a.abort_inhibit), (true, true, true, false));
+        assert_eq!((a.tries_needed, a.tries_now, a.ftd), (3, 1, 60));
+
+        let alarm = Alarm::Digital(a);
+
+        assert_eq!(alarm.raw(), &raw[..]);
+        assert_eq!(
+            alarm.format_text(),
+            "nominal=0x00000005 mask=0x000000ff enabled=true bad=true abort=true abort_inhibit=false \
+             tries_needed=3 tries_now=1 ftd=60"
+        );
+        assert_eq!(
+            alarm.format_json(),
+            "{\"nominal\":5,\"mask\":255,\"enabled\":true,\"bad\":true,\"abort\":true,\"abort_inhibit\":false,\
+             \"tries_needed\":3,\"tries_now\":1,\"ftd\":60}"
+        );
+        assert!(DigitalAlarm::decode(&raw[..19]).is_err());
+        assert!(matches!(
+            decode(&drf::parse_drf("G$AMANDA").unwrap().property, &raw),
+            Some(Ok(Alarm::Digital(_)))
+        ));
+    }
+}
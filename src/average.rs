@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+
+// Specifies the portion of time, relative to the triggering event,
+// in which samples are collected. Both ends are in microseconds and
+// the end is exclusive.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Window {
+    // Parses a window specification of the form "START..END". Each
+    // end point is an integer with an optional suffix which follows
+    // the DRF time conventions: 'S' for seconds, 'M' for milliseconds
+    // (the default) and 'U' for microseconds.
+
+    pub fn parse(text: &str) -> Result<Window, String> {
+        let mut parts = text.splitn(2, "..");
+
+        match (parts.next(), parts.next()) {
+            (Some(s), Some(e)) => {
+                let start = Window::parse_offset(s)?;
+                let end = Window::parse_offset(e)?;
+
+                if start < end {
+                    Ok(Window { start, end })
+                } else {
+                    Err(format!("window '{}' is empty", text))
+                }
+            }
+            _ => Err(format!("window '{}' must be of the form START..END", text)),
+        }
+    }
+
+    fn parse_offset(text: &str) -> Result<u64, String> {
+        let text = text.trim();
+        let (num, scale) = match text.chars().last() {
+            Some('s') | Some('S') => (&text[..text.len() - 1], 1_000_000),
+            Some('m') | Some('M') => (&text[..text.len() - 1], 1_000),
+            Some('u') | Some('U') => (&text[..text.len() - 1], 1),
+            _ => (text, 1_000),
+        };
+
+        num.parse::<u64>()
+            .ok()
+            .and_then(|v| v.checked_mul(scale))
+            .ok_or_else(|| format!("bad window offset '{}'", text))
+    }
+
+    // Returns `true` if the offset (in microseconds) lies within the
+    // window.
+
+    pub fn contains(&self, offset: u64) -> bool {
+        offset >= self.start && offset < self.end
+    }
+}
+
+// Parses the clock event number given to `--event`. Like the DRF
+// clock event, the number is hexadecimal, but the "0x" prefix is
+// also accepted.
+
+pub fn parse_event(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("bad clock event '{}'", text))
+}
+
+// Running statistics for one point of a waveform. Uses Welford's
+// algorithm so the variance is numerically stable over many windows.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Stat {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Stat {
+    fn add(&mut self, v: f64) {
+        self.count += 1;
+
+        let delta = v - self.mean;
+
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (v - self.mean);
+    }
+
+    fn std_dev(&self) -> f64 {
+        if self.count > 1 {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+// One point of an averaged waveform.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub index: usize,
+    pub count: u64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+// Collects samples into event-aligned windows and folds each
+// completed window into per-device, per-index statistics.
+
+pub struct Averager {
+    window: Window,
+    trigger: Option<u64>,
+    current: BTreeMap<String, Vec<f64>>,
+    stats: BTreeMap<String, Vec<Stat>>,
+    windows: u64,
+}
+
+impl Averager {
+    pub fn new(window: Window) -> Self {
+        Averager {
+            window,
+            trigger: None,
+            current: BTreeMap::new(),
+            stats: BTreeMap::new(),
+            windows: 0,
+        }
+    }
+
+    // Marks the occurrence of the triggering event at `time`
+    // (microseconds). The previous window, if any, is completed.
+
+    pub fn trigger(&mut self, time: u64) {
+        self.complete();
+        self.trigger = Some(time);
+    }
+
+    // Adds the values of a reading taken at `time`. Samples that
+    // arrive before the first trigger, or outside the window, are
+    // ignored. Array readings contribute all of their elements.
+
+    pub fn sample(&mut self, device: &str, time: u64, values: &[f64]) {
+        if let Some(trigger) = self.trigger {
+            if time >= trigger && self.window.contains(time - trigger) {
+                self.current
+                    .entry(device.to_string())
+                    .or_default()
+                    .extend_from_slice(values);
+            }
+        }
+    }
+
+    fn complete(&mut self) {
+        if self.trigger.take().is_some() {
+            self.windows += 1;
+        }
+
+        for (device, samples) in std::mem::take(&mut self.current) {
+            let stats = self.stats.entry(device).or_default();
+
+            if stats.len() < samples.len() {
+                stats.resize(samples.len(), Stat::default());
+            }
+
+            for (stat, v) in stats.iter_mut().zip(samples) {
+                stat.add(v);
+            }
+        }
+    }
+
+    // Returns the number of windows that have been completed.
+
+    pub fn windows(&self) -> u64 {
+        self.windows
+    }
+
+    // Completes the current window and returns the averaged waveform
+    // of each device.
+
+    pub fn finish(mut self) -> BTreeMap<String, Vec<Point>> {
+        self.complete();
+        self.stats
+            .into_iter()
+            .map(|(device, stats)| {
+                let points = stats
+                    .iter()
+                    .enumerate()
+                    .map(|(index, s)| Point {
+                        index,
+                        count: s.count,
+                        mean: s.mean,
+                        std_dev: s.std_dev(),
+                    })
+                    .collect();
+
+                (device, points)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_parsing() {
+        assert_eq!(Window::parse("0..2s"), Ok(Window { start: 0, end: 2_000_000 }));
+        assert_eq!(Window::parse("100..200"), Ok(Window { start: 100_000, end: 200_000 }));
+        assert_eq!(Window::parse("10u..1M"), Ok(Window { start: 10, end: 1_000 }));
+        assert!(Window::parse("2s..0").is_err());
+        assert!(Window::parse("1s..1s").is_err());
+        assert!(Window::parse("0-2s").is_err());
+        assert!(Window::parse("a..2s").is_err());
+    }
+
+    #[test]
+    fn test_event_parsing() {
+        assert_eq!(parse_event("0x52"), Ok(0x52));
+        assert_eq!(parse_event("52"), Ok(0x52));
+        assert_eq!(parse_event("8F"), Ok(0x8f));
+        assert!(parse_event("0x12345").is_err());
+        assert!(parse_event("zz").is_err());
+    }
+
+    #[test]
+    fn test_averaging() {
+        let mut avg = Averager::new(Window::parse("0..1s").unwrap());
+
+        // Samples before the first trigger are dropped.
+
+        avg.sample("M:OUTTMP", 0, &[100.0]);
+
+        avg.trigger(1_000_000);
+        avg.sample("M:OUTTMP", 1_000_000, &[1.0, 2.0]);
+        avg.sample("M:OUTTMP", 2_500_000, &[50.0]);
+
+        avg.trigger(3_000_000);
+        avg.sample("M:OUTTMP", 3_500_000, &[3.0, 4.0, 5.0]);
+
+        let result = avg.finish();
+        let points = &result["M:OUTTMP"];
+
+        assert_eq!(points.len(), 3);
+        assert_eq!((points[0].count, points[0].mean), (2, 2.0));
+        assert_eq!((points[1].count, points[1].mean), (2, 3.0));
+        assert_eq!((points[2].count, points[2].mean), (1, 5.0));
+        assert!((points[0].std_dev - std::f64::consts::SQRT_2).abs() < 1e-12);
+        assert_eq!(points[2].std_dev, 0.0);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{
+    capture, data,
+    error::Error,
+    mock,
+    put::SetValue,
+    retry,
+    settings::{BackendKind, Settings},
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{info, info_span};
+
+// How a set of requests is acquired, decided by their events. If any
+// request's event fires repeatedly, replies are streamed until the
+// user interrupts the program. Otherwise, if any asks for a single
+// reply (`@I` or the default event), the replies are awaited and the
+// program exits. Requests which only use `@N` return no data, so
+// they're only validated.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Acquisition {
+    Validate,
+    OneShot,
+    Stream,
+}
+
+impl Acquisition {
+    pub fn of(requests: &[drf::Request]) -> Acquisition {
+        if requests.iter().any(|r| r.event.is_streaming()) {
+            Acquisition::Stream
+        } else if requests.iter().any(|r| r.event.is_one_shot()) {
+            Acquisition::OneShot
+        } else {
+            Acquisition::Validate
+        }
+    }
+}
+
+// Ends a stream of replies with the one which leaves none of the
+// requests flagged in `waiting` (by index) without a reply. If no
+// request is flagged, the stream is passed along unchanged.
+
+pub fn until_answered<I>(mut replies: I, mut waiting: Vec<bool>) -> impl Iterator<Item = Result<data::Reading, Error>>
+where
+    I: Iterator<Item = Result<data::Reading, Error>>,
+{
+    let mut done = false;
+
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+
+        let next = replies.next()?;
+
+        if let Ok(reading) = &next {
+            if let Some(w @ true) = waiting.get_mut(reading.index) {
+                *w = false;
+                done = !waiting.contains(&true)
+            }
+        }
+        Some(next)
+    })
+}
+
+// A connection to a data source, over one of the transports chosen
+// with `--backend`. Requests are added, each with an ID which becomes
+// the index of its readings, then started, after which the replies
+// are read until the stream ends or is cancelled. Settings may be
+// applied to requests which were added but not started. Subcommands
+// don't use this directly; they go through `Client`.
+
+pub trait Backend {
+    fn add_request(&mut self, id: usize, request: &drf::Request) -> Result<(), Error>;
+    fn start(&mut self) -> Result<(), Error>;
+    // Returns the next reply, or `None` once the stream has ended. An
+    // error ends the stream.
+    fn next_reply(&mut self) -> Option<Result<data::Reading, Error>>;
+    fn apply_setting(&mut self, id: usize, value: &SetValue) -> Result<(), Error>;
+    // Checks, with the data source, that the user may make settings
+    // in `role`.
+    fn verify_role(&mut self, role: &str) -> Result<(), Error>;
+    // Stops the replies to the started requests. Errors are only
+    // logged, since nothing more is wanted from the data source.
+    fn cancel(&mut self);
+}
+
+// The replies of a started backend, which is cancelled when they're
+// dropped.
+
+struct Replies(Box<dyn Backend>);
+
+impl Iterator for Replies {
+    type Item = Result<data::Reading, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_reply()
+    }
+}
+
+impl Drop for Replies {
+    fn drop(&mut self) {
+        self.0.cancel()
+    }
+}
+
+// A handle on the connection to the data sources. Clones share the
+// connection, and the handle is `Send + Sync`, so any number of
+// threads can read and set devices through it at once without
+// external locking. Each operation is given its own tag, which the
+// connection uses to route replies back to the operation that asked
+// for them, and which labels the operation's diagnostic messages.
+
+#[derive(Clone)]
+pub struct Client {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    settings: Settings,
+    next_tag: AtomicU64,
+}
+
+impl Client {
+    pub fn new(settings: Settings) -> Client {
+        Client {
+            shared: Arc::new(Shared {
+                settings,
+                next_tag: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.shared.settings
+    }
+
+    // Reserves the tag of a new operation.
+
+    fn tag(&self) -> u64 {
+        self.shared.next_tag.fetch_add(1, Ordering::Relaxed)
+    }
+
+    // Returns the requests as the data source expects them. It takes
+    // the state devices of events by index only, and there's no device
+    // database to look names up in, so a named one is refused before
+    // connecting. The data source doesn't know relative ranges either,
+    // so the whole array is requested and the range is applied to the
+    // reply (see `output::elements`.)
+
+    fn resolve(&self, requests: &[drf::Request]) -> Result<Vec<drf::Request>, Error> {
+        requests
+            .iter()
+            .map(|req| {
+                let mut resolved = req.clone();
+
+                if let drf::Event::State { device: drf::Device::Name(name), .. } = &resolved.event {
+                    return Err(Error::Parse(format!(
+                        "{}: give the state device {} by its index, since names can't be looked up",
+                        req.canonical(),
+                        name
+                    )));
+                }
+                if let drf::Range::Relative { .. } = resolved.range {
+                    resolved.range = drf::Range::Full
+                }
+                Ok(resolved)
+            })
+            .collect()
+    }
+
+    // Connects to the data source chosen by the settings. There's no
+    // DPM client, so the DPM backend always fails. Retrying can't
+    // help, so the failure isn't reported as a connection error.
+
+    fn connect(&self) -> Result<Box<dyn Backend>, Error> {
+        let settings = self.settings();
+
+        info!(backend = ?settings.backend, timeout = ?settings.timeout, "connecting to data source");
+
+        Ok(match &settings.backend {
+            BackendKind::Dpm => {
+                return Err(Error::Other(format!(
+                    "the dpm backend isn't available yet, so {} can't be reached (choose another with --backend)",
+                    settings.dpm_host
+                )))
+            }
+            BackendKind::Mock(None) => Box::new(mock::Session::new(None)),
+            BackendKind::Mock(Some(file)) => {
+                let records = capture::parse_bytes(&std::fs::read(file)?)
+                    .map_err(|e| Error::Parse(format!("{}: {}", file.display(), e)))?;
+
+                Box::new(mock::Session::new(Some(records)))
+            }
+        })
+    }
+
+    // Submits the requests to the data source and returns the stream
+    // of replies.
+
+    pub fn acquire(&self, requests: &[drf::Request]) -> Result<retry::Stream, Error> {
+        let _span = info_span!("acquire", tag = self.tag()).entered();
+        let requests = self.resolve(requests)?;
+        let mut backend = self.connect()?;
+
+        for (id, req) in requests.iter().enumerate() {
+            info!(id, drf = %req.canonical(), "adding request");
+            backend.add_request(id, req)?
+        }
+        backend.start()?;
+        Ok(Box::new(Replies(backend)))
+    }
+
+    // Like `acquire()`, but connection failures and timeouts,
+    // including ones in the middle of the stream, are retried (as
+    // configured by the settings) and the stream resumes. The
+    // iterator holds its own handle, so it doesn't borrow this one.
+
+    pub fn subscribe(
+        &self,
+        requests: Vec<drf::Request>,
+    ) -> impl Iterator<Item = Result<data::Reading, Error>> {
+        let client = self.clone();
+
+        retry::resume(retry::Policy::new(self.settings().retries), move || {
+            client.acquire(&requests)
+        })
+    }
+
+    // Retrieves the data logged for a request between `start` and
+    // `end` (in microseconds since the epoch), by making the request
+    // with the logger as its data source (see `drf::DataSource`.) The
+    // readings use `index` as their request index.
+
+    pub fn history(
+        &self,
+        index: usize,
+        request: &drf::Request,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<data::Reading>, Error> {
+        let _span = info_span!("history", tag = self.tag()).entered();
+        let mut request = self.resolve(std::slice::from_ref(request))?.remove(0);
+
+        request.source = drf::DataSource::Logger { start: start / 1000, end: end.div_ceil(1000) };
+        info!(index, drf = %request.canonical(), "fetching logged data");
+
+        let mut backend = self.connect()?;
+
+        backend.add_request(index, &request)?;
+        backend.start()?;
+        Replies(backend).collect()
+    }
+
+    // Sends a setting to the device named in the request. The user
+    // isn't authenticated here: `--role` is passed to the backend,
+    // whose server decides who may make settings.
+
+    pub fn set(&self, request: &drf::Request, value: &SetValue) -> Result<(), Error> {
+        let _span = info_span!("set", tag = self.tag()).entered();
+
+        info!(drf = %request.canonical(), ?value, role = ?self.settings().role, "sending setting");
+
+        let mut backend = self.connect()?;
+
+        backend.add_request(0, request)?;
+        backend.apply_setting(0, value)
+    }
+
+    // Checks, with the backend, that settings may be made in `role`.
+
+    pub fn verify_role(&self, role: &str) -> Result<(), Error> {
+        let _span = info_span!("verify_role", tag = self.tag()).entered();
+
+        info!(role, "verifying role");
+        self.connect()?.verify_role(role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::thread;
+
+    fn is_shareable<T: Clone + Send + Sync + 'static>() {}
+
+    fn settings(backend: BackendKind) -> Settings {
+        Settings {
+            backend,
+            dpm_host: String::from("localhost"),
+            role: None,
+            timeout: std::time::Duration::from_secs(1),
+            retries: 0,
+            journal: None,
+        }
+    }
+
+    #[test]
+    fn test_shared_client() {
+        is_shareable::<Client>();
+
+        let client = Client::new(settings(BackendKind::Dpm));
+        let tags: Vec<u64> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let client = client.clone();
+
+                    s.spawn(move || (0..100).map(|_| client.tag()).collect::<Vec<_>>())
+                })
+                .collect();
+
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(tags.iter().collect::<BTreeSet<_>>().len(), 400);
+
+        // Named state devices are refused before connecting.
+
+        let named = drf::parse_drf("M:OUTTMP@S,V:STATE,5,0,=").unwrap();
+        let indexed = drf::parse_drf("M:OUTTMP@S,1234,5,0,=").unwrap();
+
+        assert_eq!(client.resolve(std::slice::from_ref(&indexed)), Ok(vec![indexed]));
+        assert!(matches!(client.acquire(&[named]), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_history() {
+        let client = Client::new(settings(BackendKind::Mock(None)));
+        let request = drf::parse_drf("M:OUTTMP").unwrap();
+        let readings = client.history(2, &request, 10_000_000, 12_500_000).unwrap();
+
+        assert_eq!(
+            readings.iter().map(|r| (r.index, r.timestamp)).collect::<Vec<_>>(),
+            [(2, 10_000_000), (2, 11_000_000), (2, 12_000_000)]
+        );
+        assert_eq!(readings[1].value, data::Value::Scalar(crate::simulate::sample(2, 11.0)));
+    }
+
+    #[test]
+    fn test_acquisition() {
+        let of = |drfs: &[&str]| Acquisition::of(&drfs.iter().map(|d| drf::parse_drf(d).unwrap()).collect::<Vec<_>>());
+
+        assert_eq!(of(&["M:OUTTMP", "G:AMANDA@I"]), Acquisition::OneShot);
+        assert_eq!(of(&["M:OUTTMP@I", "G:AMANDA@P,1S"]), Acquisition::Stream);
+        assert_eq!(of(&["M:OUTTMP@E,8F", "G:AMANDA@N"]), Acquisition::Stream);
+        assert_eq!(of(&["M:OUTTMP@N", "G:AMANDA@N"]), Acquisition::Validate);
+        assert_eq!(of(&["M:OUTTMP@I", "G:AMANDA@N"]), Acquisition::OneShot);
+
+        let reading = |index| Ok(data::Reading::new(index, 0, data::Value::Scalar(1.0)));
+        let replies = || vec![reading(0), reading(2), reading(0), reading(1), reading(2)].into_iter();
+        let indices = |r: Vec<Result<data::Reading, Error>>| r.into_iter().map(|r| r.unwrap().index).collect::<Vec<_>>();
+
+        assert_eq!(indices(until_answered(replies(), vec![true, true, false]).collect()), [0, 2, 0, 1]);
+        assert_eq!(indices(until_answered(replies(), vec![]).collect()), [0, 2, 0, 1, 2]);
+    }
+}
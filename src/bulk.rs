@@ -0,0 +1,181 @@
+use crate::journal::Outcome;
+
+// A settings file, as read by `put --file`, has a setting per line:
+// the DRF, a comma and the value, which is written as it would be
+// given to `put` and may itself hold commas (e.g. an array), e.g.
+//
+//    # Ramp the correctors down
+//    Z:CORR1,0
+//    Z:CORR2[0:2],1.5,2.5,3.5
+//    Z:CORR3.RAW,"0x1F"
+//
+// Blank lines and lines starting with '#' are skipped. The rollback
+// files written by `put --rollback` have the same form.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub number: usize,
+    pub drf: String,
+    pub value: String,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Line>, String> {
+    let mut lines = vec![];
+    let mut errors = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(',') {
+            Some((drf, value)) if !drf.trim().is_empty() && !value.trim().is_empty() => {
+                let value = value.trim();
+
+                lines.push(Line {
+                    number: n + 1,
+                    drf: String::from(drf.trim()),
+                    value: String::from(value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value)),
+                })
+            }
+            _ => errors.push(format!("line {}: expected 'DRF,VALUE'", n + 1)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(lines)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+// What became of one line of a settings file: the value read before
+// it was sent (if it could be), the value sent and the outcome, which
+// is `None` if the line was skipped because an earlier one failed.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub line: usize,
+    pub drf: String,
+    pub old: Option<String>,
+    pub new: String,
+    pub outcome: Option<Outcome>,
+}
+
+// Formats the results as a table with a row per line of the file.
+
+pub fn format_results(results: &[Row]) -> Vec<String> {
+    let rows: Vec<[String; 5]> = results
+        .iter()
+        .map(|r| {
+            [
+                r.line.to_string(),
+                r.drf.clone(),
+                r.old.clone().unwrap_or_else(|| String::from("(unknown)")),
+                r.new.clone(),
+                match &r.outcome {
+                    Some(Outcome::Failed(e)) => format!("failed: {}", e),
+                    Some(o) => String::from(o.name()),
+                    None => String::from("skipped"),
+                },
+            ]
+        })
+        .collect();
+    let header = ["LINE", "DRF", "OLD", "NEW", "RESULT"].map(String::from);
+    let width = |i: usize| rows.iter().chain([&header]).map(|r| r[i].len()).max().unwrap_or(0);
+    let widths = [width(0), width(1), width(2), width(3)];
+
+    std::iter::once(&header)
+        .chain(&rows)
+        .map(|r| {
+            format!(
+                "{:>w0$}  {:w1$}  {:w2$}  {:w3$}  {}",
+                r[0],
+                r[1],
+                r[2],
+                r[3],
+                r[4],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3]
+            )
+        })
+        .collect()
+}
+
+// Writes a settings file which undoes the settings that were sent,
+// or tried: it sets their devices back to the values read beforehand,
+// in the reverse order. Settings whose old value isn't known, or
+// which can't be undone (control commands), are listed in comments.
+
+pub fn rollback(results: &[Row]) -> String {
+    let mut text = String::from("# Rollback of the settings made by acsys put --file\n");
+
+    for r in results.iter().rev().filter(|r| matches!(r.outcome, Some(Outcome::Sent) | Some(Outcome::Failed(_)))) {
+        match &r.old {
+            _ if r.drf.ends_with(".CONTROL") => text.push_str(&format!("# {}: can't be undone\n", r.drf)),
+            Some(old) => text.push_str(&format!("{},{}\n", r.drf, old)),
+            None => text.push_str(&format!("# {}: the old value isn't known\n", r.drf)),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let lines = parse("# comment\n\nZ:CORR1,0\n Z:CORR2[0:2] , 1.5,2.5,3.5\nZ:CORR3.RAW,\"0x1F\"\n").unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], Line { number: 4, drf: String::from("Z:CORR2[0:2]"), value: String::from("1.5,2.5,3.5") });
+        assert_eq!(lines[2].value, "0x1F");
+        assert_eq!(parse("Z:CORR1\nZ:CORR2,1\n,2").unwrap_err(), "line 1: expected 'DRF,VALUE'\nline 3: expected 'DRF,VALUE'");
+    }
+
+    #[test]
+    fn test_results() {
+        let result = |line, drf: &str, old: Option<&str>, outcome| Row {
+            line,
+            drf: String::from(drf),
+            old: old.map(String::from),
+            new: String::from("1"),
+            outcome,
+        };
+        let results = [
+            result(1, "Z:CORR1.SETTING.SCALED", Some("0.5"), Some(Outcome::Sent)),
+            result(2, "Z:CORR2.SETTING.SCALED", None, Some(Outcome::Sent)),
+            result(3, "Z:CORR3.CONTROL", Some("0"), Some(Outcome::Sent)),
+            result(10, "Z:CORR4.SETTING.SCALED", Some("2"), Some(Outcome::Failed(String::from("timed out")))),
+            result(11, "Z:CORR5.SETTING.SCALED", Some("3"), None),
+        ];
+
+        assert_eq!(
+            format_results(&results),
+            [
+                "LINE  DRF                     OLD        NEW  RESULT",
+                "   1  Z:CORR1.SETTING.SCALED  0.5        1    sent",
+                "   2  Z:CORR2.SETTING.SCALED  (unknown)  1    sent",
+                "   3  Z:CORR3.CONTROL         0          1    sent",
+                "  10  Z:CORR4.SETTING.SCALED  2          1    failed: timed out",
+                "  11  Z:CORR5.SETTING.SCALED  3          1    skipped"
+            ]
+        );
+
+        let undo = rollback(&results);
+
+        assert_eq!(
+            undo,
+            "# Rollback of the settings made by acsys put --file\n\
+             Z:CORR4.SETTING.SCALED,2\n\
+             # Z:CORR3.CONTROL: can't be undone\n\
+             # Z:CORR2.SETTING.SCALED: the old value isn't known\n\
+             Z:CORR1.SETTING.SCALED,0.5\n"
+        );
+        assert_eq!(parse(&undo).unwrap().len(), 2);
+    }
+}
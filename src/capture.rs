@@ -0,0 +1,681 @@
+use crate::acs;
+use crate::status::Status;
+use crate::data::{Reading, Value};
+use crate::output::{json_number, json_string};
+use crate::timefmt::{self, Formatter};
+use combine::error::ParseError;
+use combine::parser::{char, repeat};
+use combine::stream::Stream;
+use combine::{between, choice, eof, satisfy, sep_by, EasyParser, Parser};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+
+// A capture file holds a recorded session: the output of `get live
+// --format json`, one JSON object per line. Each line is a reading,
+// with "timestamp", "drf" and "value" keys, or the value of a derived
+// channel, which uses "name" in place of "drf". A request which failed
+// has a "status" (its ACNET status) in place of the "value".
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub timestamp: u64,
+    pub key: String,
+    pub value: Value,
+}
+
+// The subset of JSON values written to capture files.
+
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Number(String),
+    Text(String),
+    Array(Vec<Json>),
+}
+
+fn lex<Input, P>(p: P) -> impl Parser<Input, Output = P::Output>
+where
+    P: Parser<Input>,
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    p.skip(char::spaces())
+}
+
+fn parse_string<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let escaped = char::char('\\').with(choice((
+        char::char('"'),
+        char::char('\\'),
+        char::char('/'),
+        char::char('n').map(|_| '\n'),
+        char::char('r').map(|_| '\r'),
+        char::char('t').map(|_| '\t'),
+        char::char('u')
+            .with(repeat::count_min_max::<String, _, _>(4, 4, char::hex_digit()))
+            .map(|hex| std::char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap_or('\u{fffd}')),
+    )));
+
+    between(
+        char::char('"'),
+        char::char('"'),
+        repeat::many(choice((escaped, satisfy(|c| c != '"' && c != '\\')))),
+    )
+}
+
+fn parse_number<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    repeat::many1(satisfy(|c: char| c.is_ascii_digit() || "+-.eE".contains(c)))
+}
+
+fn parse_scalar<Input>() -> impl Parser<Input, Output = Json>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    lex(choice((
+        char::string("null").map(|_| Json::Null),
+        parse_number().map(Json::Number),
+        parse_string().map(Json::Text),
+    )))
+}
+
+fn parse_value<Input>() -> impl Parser<Input, Output = Json>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((
+        between(
+            lex(char::char('[')),
+            lex(char::char(']')),
+            sep_by(parse_scalar(), lex(char::char(','))),
+        )
+        .map(Json::Array),
+        parse_scalar(),
+    ))
+}
+
+fn parse_object<Input>() -> impl Parser<Input, Output = Vec<(String, Json)>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let member = (lex(parse_string()), lex(char::char(':')), parse_value()).map(|(k, _, v)| (k, v));
+
+    char::spaces()
+        .with(between(
+            lex(char::char('{')),
+            lex(char::char('}')),
+            sep_by(member, lex(char::char(','))),
+        ))
+        .skip(eof())
+}
+
+// Converts a timestamp, written as seconds with a fraction, into
+// microseconds.
+
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+    let (secs, frac) = text.split_once('.').unwrap_or((text, ""));
+
+    if frac.len() > 6 || !frac.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let usecs: u64 = format!("{:0<6}", frac).parse().ok()?;
+
+    Some(secs.parse::<u64>().ok()? * 1_000_000 + usecs)
+}
+
+fn number(j: &Json) -> Option<f64> {
+    match j {
+        Json::Null => Some(f64::NAN),
+        Json::Number(n) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+// Parses one line of a capture file.
+
+pub fn parse_record(line: &str) -> Result<Record, String> {
+    let (members, _) = parse_object()
+        .easy_parse(line)
+        .map_err(|_| String::from("malformed JSON object"))?;
+    let mut timestamp = None;
+    let mut key = None;
+    let mut value = None;
+
+    for (k, v) in members {
+        match (k.as_str(), v) {
+            ("timestamp", Json::Number(n)) => {
+                timestamp = Some(parse_timestamp(&n).ok_or_else(|| format!("bad timestamp '{}'", n))?)
+            }
+            ("timestamp", Json::Text(s)) => {
+                timestamp = Some(timefmt::parse_iso(&s).ok_or_else(|| format!("bad timestamp '{}'", s))?)
+            }
+            ("drf", Json::Text(s)) | ("name", Json::Text(s)) => key = Some(s),
+            ("value", Json::Text(s)) => value = Some(Value::Text(s)),
+            ("status", Json::Number(n)) => {
+                value = Some(Value::Status(n.parse().map_err(|_| format!("bad status '{}'", n))?))
+            }
+            ("value", Json::Array(a)) => {
+                value = Some(Value::Array(
+                    a.iter()
+                        .map(number)
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| String::from("arrays may only hold numbers"))?,
+                ))
+            }
+            ("value", v) => {
+                value = Some(Value::Scalar(number(&v).ok_or_else(|| String::from("bad value"))?))
+            }
+            _ => (),
+        }
+    }
+
+    Ok(Record {
+        timestamp: timestamp.ok_or_else(|| String::from("missing timestamp"))?,
+        key: key.ok_or_else(|| String::from("missing drf or name"))?,
+        value: value.ok_or_else(|| String::from("missing value"))?,
+    })
+}
+
+// Parses the contents of a capture file. Blank lines are skipped and
+// errors are prefixed with the line number on which they were found.
+
+pub fn parse_file(text: &str) -> Result<Vec<Record>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| parse_record(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+// Parses a capture file of either format: JSON lines or, if it starts
+// with its magic, the binary format of `acs`.
+
+pub fn parse_bytes(data: &[u8]) -> Result<Vec<Record>, String> {
+    if data.starts_with(acs::MAGIC) {
+        return Ok(acs::read(data)?
+            .into_iter()
+            .map(|e| Record { timestamp: e.reading.timestamp, key: e.key, value: e.reading.value })
+            .collect());
+    }
+    parse_file(std::str::from_utf8(data).map_err(|_| String::from("not a capture file"))?)
+}
+
+// Writes a capture file as a session goes: in the binary format if
+// its name ends in ".acs", and as JSON lines otherwise. Either is
+// written a reading at a time so an interrupted session keeps what it
+// got.
+
+pub enum Recorder {
+    Json(LineWriter<File>),
+    Binary(acs::Writer<File>),
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Recorder> {
+        let file = File::create(path)?;
+
+        if path.to_ascii_lowercase().ends_with(".acs") {
+            Ok(Recorder::Binary(acs::Writer::new(file)?))
+        } else {
+            Ok(Recorder::Json(LineWriter::new(file)))
+        }
+    }
+
+    // Records a reading of the channel named `key` (its DRF.)
+
+    pub fn write(&mut self, key: &str, reading: &Reading) -> io::Result<()> {
+        match self {
+            Recorder::Json(out) => {
+                let r = Record { timestamp: reading.timestamp, key: String::from(key), value: reading.value.clone() };
+
+                writeln!(out, "{}", format_json(&r, &Formatter::default()))
+            }
+            Recorder::Binary(out) => out.write(key, reading),
+        }
+    }
+}
+
+// Summarizes how one channel differs between two captures. Records
+// are compared in the order they were captured, so `compared` is the
+// smaller of the two counts. A pair differs if any element differs by
+// more than the tolerance (or, for text, isn't identical). `max_diff`
+// is the largest numeric difference seen.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub key: String,
+    pub count_a: usize,
+    pub count_b: usize,
+    pub compared: usize,
+    pub differ: usize,
+    pub max_diff: f64,
+}
+
+impl Difference {
+    pub fn is_same(&self) -> bool {
+        self.count_a == self.count_b && self.differ == 0
+    }
+}
+
+// Returns the largest difference between two values or `None` if they
+// can't be compared (e.g. arrays of different lengths.) NaNs only
+// match NaNs and text must match exactly.
+
+fn value_diff(a: &Value, b: &Value) -> Option<f64> {
+    let diff = |x: &f64, y: &f64| match (x.is_nan(), y.is_nan()) {
+        (true, true) => Some(0.0),
+        (false, false) => Some((x - y).abs()),
+        _ => None,
+    };
+
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => Some(if a == b { 0.0 } else { f64::INFINITY }),
+        (Value::Status(a), Value::Status(b)) => Some(if a == b { 0.0 } else { f64::INFINITY }),
+        _ => {
+            let (a, b) = (a.as_slice(), b.as_slice());
+
+            if a.is_empty() || a.len() != b.len() {
+                return None;
+            }
+            a.iter().zip(b).try_fold(0.0, |acc: f64, (x, y)| diff(x, y).map(|d| acc.max(d)))
+        }
+    }
+}
+
+// Compares two captures channel-by-channel. The result is sorted by
+// channel and includes channels found in only one of the captures.
+
+pub fn diff(a: &[Record], b: &[Record], tolerance: f64) -> Vec<Difference> {
+    let mut channels: BTreeMap<&str, (Vec<&Value>, Vec<&Value>)> = BTreeMap::new();
+
+    for r in a {
+        channels.entry(&r.key).or_default().0.push(&r.value)
+    }
+    for r in b {
+        channels.entry(&r.key).or_default().1.push(&r.value)
+    }
+
+    channels
+        .into_iter()
+        .map(|(key, (va, vb))| {
+            let mut result = Difference {
+                key: key.to_string(),
+                count_a: va.len(),
+                count_b: vb.len(),
+                compared: va.len().min(vb.len()),
+                differ: 0,
+                max_diff: 0.0,
+            };
+
+            for (x, y) in va.iter().zip(&vb) {
+                match value_diff(x, y) {
+                    Some(d) => {
+                        if d > tolerance {
+                            result.differ += 1
+                        }
+                        if d.is_finite() {
+                            result.max_diff = result.max_diff.max(d)
+                        }
+                    }
+                    None => result.differ += 1,
+                }
+            }
+            result
+        })
+        .collect()
+}
+
+// Merges captures into one, ordered by timestamp. Records with the
+// same timestamp keep the order of the captures they came from. If
+// more than one capture recorded a channel at the same time, only the
+// first record is kept. Records are entries as `replay::load` gives
+// them, so those of binary captures keep their metadata.
+
+pub fn merge(captures: Vec<Vec<acs::Entry>>) -> Vec<acs::Entry> {
+    let mut all: Vec<acs::Entry> = captures.into_iter().flatten().collect();
+
+    all.sort_by_key(|e| e.reading.timestamp);
+
+    let mut result: Vec<acs::Entry> = Vec::with_capacity(all.len());
+    let mut group = 0;
+
+    for e in all {
+        if result.last().is_some_and(|l| l.reading.timestamp != e.reading.timestamp) {
+            group = result.len()
+        }
+        if !result[group..].iter().any(|l| l.key == e.key) {
+            result.push(e)
+        }
+    }
+    result
+}
+
+// Returns the device name of a record's channel (the part of the DRF
+// in front of any property, range or event.) Labels and derived
+// channels are returned unchanged.
+
+pub fn device(key: &str) -> &str {
+    match key.find(|c| ".[@<{|".contains(c)) {
+        Some(n) if key.contains(':') => &key[..n],
+        _ => key,
+    }
+}
+
+// Selects the records of a capture to keep. Times are in microseconds
+// since the epoch; `start` is inclusive and `end` exclusive. If
+// `devices` isn't empty, only records whose device (or complete
+// channel name) is listed are kept.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    pub devices: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, r: &Record) -> bool {
+        self.start.is_none_or(|t| r.timestamp >= t)
+            && self.end.is_none_or(|t| r.timestamp < t)
+            && (self.devices.is_empty()
+                || self
+                    .devices
+                    .iter()
+                    .any(|d| d.eq_ignore_ascii_case(device(&r.key)) || *d == r.key))
+    }
+}
+
+// Renders a record as a line of a capture file. Captures which are
+// read back should use the default (epoch) formatter.
+
+pub fn format_json(r: &Record, tf: &Formatter) -> String {
+    let value = match &r.value {
+        Value::Scalar(v) => json_number(*v),
+        Value::Array(v) => {
+            let data: Vec<String> = v.iter().map(|v| json_number(*v)).collect();
+
+            format!("[{}]", data.join(","))
+        }
+        Value::Raw(v) => {
+            let data: Vec<String> = v.iter().map(u8::to_string).collect();
+
+            format!("[{}]", data.join(","))
+        }
+        Value::Text(v) => json_string(v),
+
+        // Alarm blocks are recorded as the raw block.
+        Value::Alarm(a) => {
+            let data: Vec<String> = a.raw().iter().map(u8::to_string).collect();
+
+            format!("[{}]", data.join(","))
+        }
+        Value::Status(s) => {
+            return format!(
+                "{{\"timestamp\":{},\"drf\":{},\"status\":{}}}",
+                tf.json(r.timestamp),
+                json_string(&r.key),
+                s
+            )
+        }
+    };
+
+    format!(
+        "{{\"timestamp\":{},\"drf\":{},\"value\":{}}}",
+        tf.json(r.timestamp),
+        json_string(&r.key),
+        value
+    )
+}
+
+const CSV_HEADER: [&str; 4] = ["timestamp", "channel", "index", "value"];
+
+// How CSV output is written. Spreadsheets set up for locales which
+// write numbers with a decimal comma expect it in the data, and then
+// use ';' to separate fields.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CsvStyle {
+    pub delimiter: char,
+    pub decimal_comma: bool,
+}
+
+impl Default for CsvStyle {
+    fn default() -> Self {
+        CsvStyle {
+            delimiter: ',',
+            decimal_comma: false,
+        }
+    }
+}
+
+impl CsvStyle {
+    // Builds the style from the `--delimiter` and `--decimal-comma`
+    // options. The delimiter is a single character (or "tab"); it
+    // defaults to ';' with a decimal comma and ',' otherwise.
+
+    pub fn new(delimiter: Option<&str>, decimal_comma: bool) -> Result<CsvStyle, String> {
+        let delimiter = match delimiter {
+            None if decimal_comma => ';',
+            None => ',',
+            Some(d) if d.eq_ignore_ascii_case("tab") => '\t',
+            Some(d) => {
+                let mut chars = d.chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if !"\"\r\n".contains(c) && !c.is_ascii_alphanumeric() && c != '.' => c,
+                    _ => return Err(format!("bad CSV delimiter '{}'", d)),
+                }
+            }
+        };
+
+        Ok(CsvStyle { delimiter, decimal_comma })
+    }
+
+    pub fn header(&self) -> String {
+        CSV_HEADER.join(&self.delimiter.to_string())
+    }
+
+    // Quotes a field if it holds the delimiter, a quote or a line
+    // break.
+
+    fn field(&self, text: &str) -> String {
+        if text.contains(|c| c == self.delimiter || "\"\r\n".contains(c)) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn number(&self, text: String) -> String {
+        if self.decimal_comma {
+            self.field(&text.replace('.', ","))
+        } else {
+            self.field(&text)
+        }
+    }
+
+    fn row(&self, fields: &[String]) -> String {
+        fields.join(&self.delimiter.to_string())
+    }
+}
+
+// Renders a record as CSV rows, following the style's header. Each
+// element of an array gets its own row; the index of scalars and text
+// is left empty. Epoch timestamps are numbers, so they're written
+// with the style's decimal separator too.
+
+pub fn format_csv(r: &Record, tf: &Formatter, style: &CsvStyle) -> Vec<String> {
+    let ts = match tf.format(r.timestamp) {
+        ts if tf.is_numeric() => style.number(ts),
+        ts => style.field(&ts),
+    };
+    let key = style.field(&r.key);
+
+    match &r.value {
+        Value::Text(v) => vec![style.row(&[ts, key, String::new(), style.field(v)])],
+        Value::Status(s) => vec![style.row(&[ts, key, String::new(), style.field(&Status(*s).to_string())])],
+        Value::Alarm(a) => vec![style.row(&[ts, key, String::new(), style.field(&a.format_text())])],
+        Value::Scalar(v) => vec![style.row(&[ts, key, String::new(), style.number(v.to_string())])],
+        v => v
+            .as_slice()
+            .iter()
+            .enumerate()
+            .map(|(i, v)| style.row(&[ts.clone(), key.clone(), i.to_string(), style.number(v.to_string())]))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        assert_eq!(
+            parse_record("{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":72.5}"),
+            Ok(Record {
+                timestamp: 1_500_000,
+                key: String::from("M:OUTTMP.READING.SCALED"),
+                value: Value::Scalar(72.5)
+            })
+        );
+        assert_eq!(
+            parse_record("{\"timestamp\":2.25,\"drf\":\"M:OUTTMP\",\"value\":[1,null,-3e2],\"start\":2}")
+                .map(|r| (r.timestamp, r.value.as_slice().len())),
+            Ok((2_250_000, 3))
+        );
+        assert_eq!(
+            parse_record("{\"timestamp\":1.0,\"name\":\"dt\",\"value\":\"a\\\"b\",\"units\":\"\"}").map(|r| r.value),
+            Ok(Value::Text(String::from("a\"b")))
+        );
+        assert!(parse_record("{\"timestamp\":1.0,\"value\":1}").is_err());
+        assert!(parse_record("{\"timestamp\":1.0,\"drf\":\"X\",\"value\":1} junk").is_err());
+        assert!(parse_file("{\"timestamp\":1,\"drf\":\"X\",\"value\":1}\n\nnot json\n").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let rec = |key: &str, value: Value| Record {
+            timestamp: 0,
+            key: String::from(key),
+            value,
+        };
+        let a = vec![
+            rec("A", 1.0.into()),
+            rec("A", 2.0.into()),
+            rec("B", vec![1.0, 2.0].into()),
+            rec("C", 5.0.into()),
+        ];
+        let b = vec![
+            rec("A", 1.0005.into()),
+            rec("A", 2.5.into()),
+            rec("A", 3.0.into()),
+            rec("B", vec![1.0, 2.0].into()),
+        ];
+        let result = diff(&a, &b, 1e-3);
+
+        assert_eq!(result.len(), 3);
+        assert_eq!((result[0].compared, result[0].differ), (2, 1));
+        assert!((result[0].max_diff - 0.5).abs() < 1e-9);
+        assert!(!result[0].is_same());
+        assert!(result[1].is_same());
+        assert_eq!((result[2].count_a, result[2].count_b), (1, 0));
+    }
+
+    #[test]
+    fn test_filter() {
+        let rec = |ts: u64, key: &str| Record {
+            timestamp: ts,
+            key: String::from(key),
+            value: 1.0.into(),
+        };
+        let f = Filter {
+            start: Some(10),
+            end: Some(20),
+            devices: vec![String::from("m:outtmp"), String::from("dt")],
+        };
+
+        assert_eq!(device("M:OUTTMP.READING.SCALED@P,1S,TRUE"), "M:OUTTMP");
+        assert_eq!(device("Outdoor temp"), "Outdoor temp");
+        assert!(f.matches(&rec(10, "M:OUTTMP.READING.SCALED")));
+        assert!(f.matches(&rec(15, "dt")));
+        assert!(!f.matches(&rec(20, "M:OUTTMP")));
+        assert!(!f.matches(&rec(15, "G:AMANDA")));
+        assert!(Filter::default().matches(&rec(0, "G:AMANDA")));
+    }
+
+    #[test]
+    fn test_export() {
+        let r = Record {
+            timestamp: 1_500_000,
+            key: String::from("M:OUTTMP[0:1]"),
+            value: vec![1.0, 2.5].into(),
+        };
+
+        let tf = Formatter::default();
+        let iso = Formatter::new(None, Some("UTC")).unwrap();
+
+        let csv = CsvStyle::default();
+
+        assert_eq!(format_csv(&r, &tf, &csv), vec!["1.500000,M:OUTTMP[0:1],0,1", "1.500000,M:OUTTMP[0:1],1,2.5"]);
+        assert_eq!(format_csv(&r, &iso, &csv)[0], "1970-01-01T00:00:01.500000+00:00[UTC],M:OUTTMP[0:1],0,1");
+        assert_eq!(parse_record(&format_json(&r, &tf)), Ok(r.clone()));
+        assert_eq!(parse_record(&format_json(&r, &iso)), Ok(r));
+
+        let r = Record {
+            timestamp: 0,
+            key: String::from("a,b"),
+            value: String::from("say \"hi\"").into(),
+        };
+
+        assert_eq!(format_csv(&r, &tf, &csv), vec!["0.000000,\"a,b\",,\"say \"\"hi\"\"\""]);
+    }
+
+    #[test]
+    fn test_csv_styles() {
+        let r = Record {
+            timestamp: 1_500_000,
+            key: String::from("M:OUTTMP;1"),
+            value: vec![1.0, 2.5].into(),
+        };
+        let tf = Formatter::default();
+        let european = CsvStyle::new(None, true).unwrap();
+
+        assert_eq!(european.header(), "timestamp;channel;index;value");
+        assert_eq!(format_csv(&r, &tf, &european)[1], "1,500000;\"M:OUTTMP;1\";1;2,5");
+        assert_eq!(format_csv(&r, &tf, &CsvStyle::new(Some(","), true).unwrap())[1],
+                   "\"1,500000\",M:OUTTMP;1,1,\"2,5\"");
+        assert_eq!(format_csv(&r, &Formatter::new(None, Some("UTC")).unwrap(), &european)[0],
+                   "1970-01-01T00:00:01.500000+00:00[UTC];\"M:OUTTMP;1\";0;1");
+        assert_eq!(CsvStyle::new(Some("tab"), false).map(|s| s.header()), Ok(String::from("timestamp\tchannel\tindex\tvalue")));
+        assert!(CsvStyle::new(Some(";;"), false).is_err());
+        assert!(CsvStyle::new(Some("\""), false).is_err());
+        assert!(CsvStyle::new(Some("."), true).is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let rec = |ts: u64, key: &str, v: f64| acs::Entry {
+            key: String::from(key),
+            reading: Reading::new(0, ts, v.into()),
+        };
+        let a = vec![rec(1, "A", 1.0), rec(3, "A", 3.0), rec(3, "B", 3.0)];
+        let b = vec![rec(2, "B", 2.0), rec(3, "A", 9.0), rec(4, "A", 4.0)];
+
+        assert_eq!(
+            merge(vec![a, b]),
+            vec![rec(1, "A", 1.0), rec(2, "B", 2.0), rec(3, "A", 3.0), rec(3, "B", 3.0), rec(4, "A", 4.0)]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::data::Value;
+
+// Suppresses replies which don't differ enough from the last one
+// shown for the same request. With `on_change`, a reply identical to
+// the last one shown is dropped. With `min_delta`, numeric replies are
+// only shown once some element has moved by more than the given
+// amount; other replies are shown whenever they change.
+
+pub struct ChangeFilter {
+    on_change: bool,
+    min_delta: Option<f64>,
+    last: Vec<Option<Value>>,
+}
+
+impl ChangeFilter {
+    pub fn new(on_change: bool, min_delta: Option<f64>, requests: usize) -> ChangeFilter {
+        ChangeFilter {
+            on_change,
+            min_delta,
+            last: vec![None; requests],
+        }
+    }
+
+    // Returns whether the reply to request `index` should be shown.
+    // If so, it becomes the value later replies are compared with.
+
+    pub fn pass(&mut self, index: usize, value: &Value) -> bool {
+        if !self.on_change && self.min_delta.is_none() {
+            return true;
+        }
+
+        let show = match (&self.last[index], self.min_delta) {
+            (None, _) => true,
+            (Some(prev), Some(delta)) => match (prev, value) {
+                (Value::Scalar(_), Value::Scalar(_)) | (Value::Array(_), Value::Array(_))
+                    if prev.as_slice().len() == value.as_slice().len() =>
+                {
+                    prev.as_slice()
+                        .iter()
+                        .zip(value.as_slice())
+                        .any(|(a, b)| (a - b).abs() > delta || a.is_nan() != b.is_nan())
+                }
+                _ => prev != value,
+            },
+            (Some(prev), None) => prev != value,
+        };
+
+        if show {
+            self.last[index] = Some(value.clone())
+        }
+        show
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_change() {
+        let mut f = ChangeFilter::new(true, None, 2);
+
+        assert!(f.pass(0, &1.0.into()));
+        assert!(!f.pass(0, &1.0.into()));
+        assert!(f.pass(1, &1.0.into()));
+        assert!(f.pass(0, &1.5.into()));
+        assert!(f.pass(0, &String::from("on").into()));
+        assert!(!f.pass(0, &String::from("on").into()));
+        assert!(ChangeFilter::new(false, None, 1).pass(0, &1.0.into()));
+    }
+
+    #[test]
+    fn test_min_delta() {
+        let mut f = ChangeFilter::new(false, Some(0.5), 1);
+
+        assert!(f.pass(0, &1.0.into()));
+        assert!(!f.pass(0, &1.4.into()));
+        assert!(!f.pass(0, &1.5.into()));
+        assert!(f.pass(0, &1.6.into()));
+
+        // Drift is measured from the last value shown.
+
+        assert!(!f.pass(0, &2.0.into()));
+        assert!(f.pass(0, &2.2.into()));
+
+        assert!(f.pass(0, &vec![2.2, 3.0].into()));
+        assert!(!f.pass(0, &vec![2.3, 3.1].into()));
+        assert!(f.pass(0, &vec![2.3, 4.0].into()));
+    }
+}
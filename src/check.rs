@@ -0,0 +1,175 @@
+use crate::output::{self, json_string};
+
+// A problem with a request: its byte offset in the request and a
+// message.
+
+pub type Problem = (usize, String);
+
+// The result of checking one request: its canonical form and
+// warnings, or the error which made it invalid.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checked {
+    pub drf: String,
+    pub result: Result<(String, Vec<Problem>), Problem>,
+}
+
+impl Checked {
+    pub fn is_valid(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+// Checks a request. Warnings which `--strict` rejects are errors when
+// `options` is strict.
+
+pub fn check(drf: &str, options: &drf::ParseOptions) -> Checked {
+    let result = match drf::parse_with(drf, options) {
+        Ok(parsed) => Ok((
+            parsed.request.canonical(),
+            parsed.warnings.iter().map(|w| (w.offset, w.kind.to_string())).collect(),
+        )),
+        Err(e) => Err((e.offset, e.reason())),
+    };
+
+    Checked { drf: String::from(drf), result }
+}
+
+// Checks each request in `text`, one per line, along with its line
+// number. Blank lines and lines starting with '#' are skipped.
+
+pub fn check_lines(text: &str, options: &drf::ParseOptions) -> Vec<(usize, Checked)> {
+    text.lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| (n, check(line, options)))
+        .collect()
+}
+
+// Formats the result of a check. Text output is a line with the
+// verdict followed by the request with a caret under each problem,
+// e.g.
+//
+//     M:OUTTMP.READNG: error
+//         M:OUTTMP.READNG
+//                  ^ unknown field '.READNG'; did you mean .READING?
+//
+// JSON output is one record per request. `line`, the request's line
+// number, is included when the request was read from a file.
+
+pub fn format(fmt: output::Format, line: Option<usize>, c: &Checked) -> String {
+    match fmt {
+        output::Format::Text => {
+            let indent = |text: String| text.lines().map(|l| format!("\n    {}", l)).collect::<String>();
+            let prefix = line.map(|n| format!("{}: ", n)).unwrap_or_default();
+
+            match &c.result {
+                Ok((_, warnings)) => {
+                    let verdict = match warnings.len() {
+                        0 => String::from("ok"),
+                        1 => String::from("ok, 1 warning"),
+                        n => format!("ok, {} warnings", n),
+                    };
+                    let notes: String = warnings.iter().map(|(o, w)| indent(drf::caret(&c.drf, *o, w))).collect();
+
+                    format!("{}{}: {}{}", prefix, c.drf, verdict, notes)
+                }
+                Err((o, e)) => format!("{}{}: error{}", prefix, c.drf, indent(drf::caret(&c.drf, *o, e))),
+            }
+        }
+        output::Format::Json => {
+            let problem = |(o, m): &Problem| format!("{{\"offset\":{},\"message\":{}}}", o, json_string(m));
+            let line = line.map(|n| format!("\"line\":{},", n)).unwrap_or_default();
+            let result = match &c.result {
+                Ok((canonical, warnings)) => format!(
+                    "\"valid\":true,\"canonical\":{},\"warnings\":[{}]",
+                    json_string(canonical),
+                    warnings.iter().map(problem).collect::<Vec<_>>().join(",")
+                ),
+                Err(e) => format!("\"valid\":false,\"error\":{}", problem(e)),
+            };
+
+            format!("{{{}\"drf\":{},{}}}", line, json_string(&c.drf), result)
+        }
+    }
+}
+
+// Formats the description of a request as a JSON record: a field
+// for each part `drf::explain_parts()` describes, `acquisition` --
+// "one-shot", "stream" or "none" (for @N) -- and, for periodic
+// events, `immediate` and `skip_dups`, so @P and @Q requests can be
+// told apart without reading the description.
+
+pub fn explain_json(req: &drf::Request, events: &drf::ClockEvents) -> String {
+    let mut fields: Vec<String> = drf::explain_parts(req, events)
+        .iter()
+        .map(|(part, text)| format!("\"{}\":{}", part, json_string(text)))
+        .collect();
+    let acquisition = if req.event.is_one_shot() {
+        "one-shot"
+    } else if req.event.is_streaming() {
+        "stream"
+    } else {
+        "none"
+    };
+
+    fields.push(format!("\"acquisition\":\"{}\"", acquisition));
+    if let drf::Event::Periodic { immediate, skip_dups, .. } = req.event {
+        fields.push(format!("\"immediate\":{},\"skip_dups\":{}", immediate, skip_dups))
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let options = drf::ParseOptions::default();
+        let checked = check_lines("# requests\nM:OUTTMP\n\n  M:OUTTMP.READNG\nM:OUTTMP[0:]\n", &options);
+
+        assert_eq!(checked.iter().map(|(n, _)| *n).collect::<Vec<_>>(), [2, 4, 5]);
+        assert_eq!(checked.iter().filter(|(_, c)| !c.is_valid()).count(), 1);
+
+        let text: Vec<String> = checked.iter().map(|(n, c)| format(output::Format::Text, Some(*n), c)).collect();
+
+        assert_eq!(text[0], "2: M:OUTTMP: ok");
+        assert_eq!(
+            text[1],
+            "4: M:OUTTMP.READNG: error\n    M:OUTTMP.READNG\n             ^ unknown field '.READNG'; did you mean .READING?"
+        );
+        assert_eq!(
+            format(output::Format::Text, None, &checked[2].1),
+            "M:OUTTMP[0:]: ok, 1 warning\n    M:OUTTMP[0:]\n            ^ '[0:]' selects all the data; read as '[]'"
+        );
+
+        let json: Vec<String> = checked.iter().map(|(n, c)| format(output::Format::Json, Some(*n), c)).collect();
+
+        assert_eq!(json[0], "{\"line\":2,\"drf\":\"M:OUTTMP\",\"valid\":true,\"canonical\":\"M:OUTTMP.READING.SCALED\",\"warnings\":[]}");
+        assert_eq!(
+            json[1],
+            "{\"line\":4,\"drf\":\"M:OUTTMP.READNG\",\"valid\":false,\"error\":{\"offset\":9,\"message\":\"unknown field '.READNG'; did you mean .READING?\"}}"
+        );
+        assert!(json[2].ends_with("\"warnings\":[{\"offset\":8,\"message\":\"'[0:]' selects all the data; read as '[]'\"}]}"));
+
+        assert!(!check("M:OUTTMP.PRREAD", &drf::ParseOptions::strict()).is_valid());
+    }
+
+    #[test]
+    fn test_explain_json() {
+        let json = |drf| explain_json(&drf::parse_drf(drf).unwrap(), &drf::ClockEvents::default());
+
+        assert_eq!(
+            json("M:OUTTMP@Q,500"),
+            "{\"canonical\":\"M:OUTTMP.READING.SCALED@Q,500,TRUE\",\"device\":\"M:OUTTMP\",\
+             \"property\":\"READING, field SCALED\",\"range\":\"element 0 (the default)\",\
+             \"event\":\"every 500ms, starting immediately, only when the value changes\",\
+             \"source\":\"the device's usual reading\",\"acquisition\":\"stream\",\"immediate\":true,\"skip_dups\":true}"
+        );
+        assert!(json("M:OUTTMP@P,1S,FALSE").ends_with("\"acquisition\":\"stream\",\"immediate\":false,\"skip_dups\":false}"));
+        assert!(json("M:OUTTMP@I").ends_with("\"acquisition\":\"one-shot\"}"));
+        assert!(json("M:OUTTMP@N").ends_with("\"acquisition\":\"none\"}"));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{alarm_block::Alarm, status::Status};
+
+// A value returned by the data source. The variant reflects the
+// typing of the reply: a scalar or array of engineering values, a
+// block of raw bytes, text, a decoded alarm block or, for a request
+// which failed, the ACNET status the data source sent in place of
+// data (see `status::Status`.)
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Scalar(f64),
+    Array(Vec<f64>),
+    Raw(Vec<u8>),
+    Text(String),
+    Status(i16),
+    Alarm(Alarm),
+}
+
+impl Value {
+    // Returns the numeric contents of the value. Raw, text, status
+    // and alarm values have no numeric representation so an empty
+    // slice is returned.
+
+    pub fn as_slice(&self) -> &[f64] {
+        match self {
+            Value::Scalar(v) => std::slice::from_ref(v),
+            Value::Array(v) => v,
+            Value::Raw(_) | Value::Text(_) | Value::Status(_) | Value::Alarm(_) => &[],
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Scalar(v)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(v: Vec<f64>) -> Self {
+        Value::Array(v)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::Raw(v)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+// What a data source tells of a reading besides its value, when it
+// does: the device's name, index (DI) and engineering units, the
+// start of the machine cycle the data were taken in (in microseconds
+// since the Unix epoch) and a status accompanying the data, which is
+// zero or, for data with a warning, positive. Every backend produces
+// readings and every formatter consumes them, so these are all the
+// formatters learn of a reading beyond the request it answers.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Meta {
+    pub device: Option<String>,
+    pub di: Option<u32>,
+    pub units: Option<String>,
+    pub cycle: Option<u64>,
+    pub status: i16,
+}
+
+// A single reply to a request. `index` refers to the position of the
+// request in the list that was submitted and `timestamp` is the
+// number of microseconds since the Unix epoch.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub index: usize,
+    pub timestamp: u64,
+    pub value: Value,
+    pub meta: Meta,
+}
+
+impl Reading {
+    // Returns a reading the data source said nothing more about.
+
+    pub fn new(index: usize, timestamp: u64, value: Value) -> Reading {
+        Reading { index, timestamp, value, meta: Meta::default() }
+    }
+
+    // Returns whether the reading reports a failed request.
+
+    pub fn failed(&self) -> bool {
+        matches!(self.value, Value::Status(s) if !Status(s).is_ok())
+    }
+}
@@ -0,0 +1,186 @@
+use super::{
+    AnalogField, ClockType, DataSource, Device, DigitalField, Event, Property, Range, ReadingField, Request,
+    SettingField, StateOp, StatusField,
+};
+use proptest::prelude::*;
+use proptest::sample::select;
+
+// Strategies which generate random, valid requests. Every generated
+// request is in canonical form (e.g. a range is never `[0:]`, which
+// canonicalizes to `[]`) so each one should survive a round trip
+// through its canonical text unchanged. (A raw range also can't
+// extend past the 32-bit offset limit.) When fields or events are
+// added to the DRF types, add them here too.
+
+fn device() -> impl Strategy<Value = Device> {
+    prop_oneof![
+        "[A-Za-z]:[A-Za-z0-9_<>;:-]{1,14}"
+            .prop_filter("\"<-\" starts a data source", |name| !name.contains("<-"))
+            .prop_map(Device::Name),
+        any::<u32>().prop_map(Device::Index),
+    ]
+}
+
+fn property() -> impl Strategy<Value = Property> {
+    use AnalogField as A;
+    use DigitalField as D;
+    use StatusField as S;
+
+    prop_oneof![
+        select(vec![ReadingField::Raw, ReadingField::Primary, ReadingField::Scaled])
+            .prop_map(Property::Reading),
+        select(vec![SettingField::Raw, SettingField::Primary, SettingField::Scaled])
+            .prop_map(Property::Setting),
+        select(vec![
+            S::Raw,
+            S::All,
+            S::Text,
+            S::ExtText,
+            S::On,
+            S::Ready,
+            S::Remote,
+            S::Positive,
+            S::Ramp
+        ])
+        .prop_map(Property::Status),
+        select(vec![
+            A::Raw,
+            A::All,
+            A::Text,
+            A::Min,
+            A::Max,
+            A::Nom,
+            A::Tol,
+            A::RawMin,
+            A::RawMax,
+            A::RawNom,
+            A::RawTol,
+            A::Enable,
+            A::Status,
+            A::TriesNeeded,
+            A::TriesNow,
+            A::FTD,
+            A::Abort,
+            A::AbortInhibit,
+            A::Flags
+        ])
+        .prop_map(Property::Analog),
+        select(vec![
+            D::Raw,
+            D::All,
+            D::Text,
+            D::Nom,
+            D::Mask,
+            D::Enable,
+            D::Status,
+            D::TriesNeeded,
+            D::TriesNow,
+            D::FTD,
+            D::Abort,
+            D::AbortInhibit,
+            D::Flags
+        ])
+        .prop_map(Property::Digital),
+        select(vec![
+            Property::Control,
+            Property::Description,
+            Property::Index,
+            Property::LongName,
+            Property::AlarmList
+        ]),
+    ]
+}
+
+fn range() -> impl Strategy<Value = Range> {
+    prop_oneof![
+        Just(Range::Full),
+        (0u16..=u16::MAX, 0u16..=u16::MAX).prop_map(|(a, b)| Range::Array {
+            start_index: a.min(b),
+            end_index: Some(a.max(b)),
+        }),
+        (1u16..=u16::MAX).prop_map(|s| Range::Array {
+            start_index: s,
+            end_index: None,
+        }),
+        (1u32..=u32::MAX)
+            .prop_flat_map(|length| (0..=u32::MAX - length, Just(length)))
+            .prop_map(|(offset, length)| Range::Raw {
+                offset,
+                length: Some(length),
+            }),
+        (1u32..=u32::MAX).prop_map(|offset| Range::Raw {
+            offset,
+            length: None,
+        }),
+        (-0x10000i32..0, -0x10000i32..0).prop_map(|(a, b)| Range::Relative {
+            start: a.min(b),
+            end: Some(a.max(b)),
+        }),
+        (-0x10000i32..0, prop::option::of(0i32..=0xffff)).prop_map(|(start, end)| Range::Relative { start, end }),
+        (0i32..=0xffff, -0x10000i32..0).prop_map(|(start, end)| Range::Relative {
+            start,
+            end: Some(end),
+        }),
+    ]
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    let clock_type = select(vec![ClockType::Hardware, ClockType::Software, ClockType::Either]);
+    let state_op = select(vec![
+        StateOp::Eq,
+        StateOp::NEq,
+        StateOp::GT,
+        StateOp::LT,
+        StateOp::LEq,
+        StateOp::GEq,
+        StateOp::All,
+    ]);
+
+    prop_oneof![
+        Just(Event::Never),
+        Just(Event::Immediate),
+        Just(Event::Default),
+        (1u32..=u32::MAX, any::<bool>(), any::<bool>()).prop_map(|(period, immediate, skip_dups)| {
+            Event::Periodic {
+                period,
+                immediate,
+                skip_dups,
+            }
+        }),
+        (any::<u16>(), clock_type, any::<u32>()).prop_map(|(event, clk_type, delay)| {
+            Event::Clock {
+                event,
+                clk_type,
+                delay,
+            }
+        }),
+        (device(), any::<u16>(), any::<u32>(), state_op).prop_map(
+            |(device, value, delay, expr)| Event::State {
+                device,
+                value,
+                delay,
+                expr,
+            }
+        ),
+        (any::<u64>(), any::<u32>()).prop_map(|(start, interval)| Event::Absolute { start, interval }),
+    ]
+}
+
+fn source() -> impl Strategy<Value = DataSource> {
+    prop_oneof![
+        select(vec![DataSource::Default, DataSource::Ftp, DataSource::Snapshot]),
+        (any::<u64>(), any::<u64>()).prop_map(|(start, end)| DataSource::Logger { start, end }),
+    ]
+}
+
+pub fn request() -> impl Strategy<Value = Request> {
+    (device(), property(), range(), event(), source()).prop_map(|(device, property, range, event, source)| {
+        Request {
+            device,
+            property,
+            range,
+            event,
+            source,
+        }
+    })
+}
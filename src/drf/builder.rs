@@ -0,0 +1,179 @@
+use super::error::invalid;
+use super::{device, parse_all, prop_field, DataSource, Device, Error, Event, Property, Range, Request};
+use std::convert::TryFrom;
+
+// Builds a `Request` from its parts, for programs which would
+// otherwise have to format a DRF string only to parse it again:
+//
+//     let req = Request::builder()
+//         .device("M:OUTTMP")
+//         .property(Property::Reading(ReadingField::Raw))
+//         .event(Event::Immediate)
+//         .build()?;
+//
+// Only the device is required. The parts are checked by `build()`,
+// which applies the same rules as the parser, so the result always
+// survives a round trip through its canonical form. Deserialized
+// requests are built with it, so they're checked the same way.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub struct RequestBuilder {
+    device: Option<String>,
+    property: Option<Property>,
+    range: Option<Range>,
+    event: Option<Event>,
+    source: Option<DataSource>,
+}
+
+impl RequestBuilder {
+    // Sets the device name. As in a DRF request, its qualifier (e.g.
+    // the '_' in "M_OUTTMP") selects the property used when none is
+    // given.
+
+    pub fn device(mut self, name: &str) -> Self {
+        self.device = Some(String::from(name));
+        self
+    }
+
+    pub fn property(mut self, property: Property) -> Self {
+        self.property = Some(property);
+        self
+    }
+
+    pub fn range(mut self, range: Range) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn event(mut self, event: Event) -> Self {
+        self.event = Some(event);
+        self
+    }
+
+    pub fn source(mut self, source: DataSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    // Checks the parts and returns the request. A device with
+    // anything other than the reading qualifier only accepts its own
+    // property, and ranges must be ones a DRF string can express.
+    // Ranges starting at 0 and running to the end of the data are
+    // stored as `Range::Full`, as the parser does.
+
+    pub fn build(self) -> Result<Request, Error> {
+        let name = self.device.ok_or_else(|| invalid(String::from("no device was given")))?;
+        let (device, qual_property): (Device, Option<Property>) = parse_all(device::parser(), &name)?;
+        let property = match self.property {
+            Some(p) if !prop_field::compatible(qual_property, p) => {
+                return Err(invalid(format!("device '{}' can't have the {} property", name, p.canonical().0)))
+            }
+            Some(p) => p,
+            None => prop_field::selected(qual_property),
+        };
+        let in_bounds = |i: i32| (-0x10000..=0xffff).contains(&i);
+        let range = match self.range {
+            None => Range::Array { start_index: 0, end_index: Some(0) },
+            Some(Range::Array { start_index: 0, end_index: None }) |
+            Some(Range::Raw { offset: 0, length: None }) => Range::Full,
+            Some(Range::Array { start_index, end_index: Some(e) }) if start_index > e => {
+                return Err(invalid(format!("bad range: {} is past {}", start_index, e)))
+            }
+            Some(Range::Raw { length: Some(0), .. }) => return Err(invalid(String::from("bad length"))),
+            Some(Range::Raw { offset, length: Some(l) }) if offset > u32::MAX - l => {
+                return Err(invalid(String::from("bad range")))
+            }
+            Some(Range::Relative { start, end }) if start.min(end.unwrap_or(start)) >= 0 => {
+                return Err(invalid(String::from("a relative range needs a negative index")))
+            }
+            Some(Range::Relative { start, end }) if !in_bounds(start) || !end.is_none_or(in_bounds) => {
+                return Err(invalid(String::from("bad index")))
+            }
+            Some(Range::Relative { start, end: Some(e) }) if start < 0 && e < 0 && start > e => {
+                return Err(invalid(format!("bad range: {} is past {}", start, e)))
+            }
+            Some(Range::Count { count: 0, .. }) => return Err(invalid(String::from("bad length"))),
+            Some(r @ Range::Count { .. }) => r.to_array().ok_or_else(|| invalid(String::from("bad range")))?,
+            Some(r) => r,
+        };
+
+        Ok(Request {
+            device,
+            property,
+            range,
+            event: self.event.unwrap_or(Event::Default),
+            source: self.source.unwrap_or(DataSource::Default),
+        })
+    }
+}
+
+impl Request {
+    pub fn builder() -> RequestBuilder {
+        RequestBuilder::default()
+    }
+}
+
+impl TryFrom<RequestBuilder> for Request {
+    type Error = Error;
+
+    fn try_from(b: RequestBuilder) -> Result<Self, Self::Error> {
+        b.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse_drf, verify, ReadingField, SettingField, StatusField};
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let req = Request::builder()
+            .device("M:OUTTMP")
+            .property(Property::Reading(ReadingField::Raw))
+            .range(Range::Array { start_index: 2, end_index: Some(4) })
+            .event(Event::Immediate)
+            .build()
+            .unwrap();
+
+        assert_eq!(req, parse_drf("M:OUTTMP[2:4].RAW@I").unwrap());
+        assert_eq!(Request::builder().device("M_OUTTMP").build(), parse_drf("M_OUTTMP"));
+        assert_eq!(Request::builder().device("M|OUTTMP").property(Property::Status(StatusField::On)).build(),
+                   parse_drf("M|OUTTMP.ON"));
+        assert_eq!(Request::builder().device("M:OUTTMP").range(Range::Array { start_index: 0, end_index: None }).build()
+                       .map(|r| r.range),
+                   Ok(Range::Full));
+        assert_eq!(verify(&Request::builder().device("0:1234").build().unwrap()), Ok(()));
+        assert_eq!(Request::builder().device("M:OUTTMP").range(Range::Count { start_index: 2, count: 3 }).build(),
+                   parse_drf("M:OUTTMP[2:4]"));
+        assert_eq!(Request::builder().device("M:OUTTMP").event(Event::Immediate).source(DataSource::Ftp).build(),
+                   parse_drf("M:OUTTMP@I<-FTP"));
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let error = |b: RequestBuilder| b.build().unwrap_err().to_string();
+
+        assert_eq!(error(Request::builder()), "at offset 0: no device was given");
+        assert_eq!(Request::builder().device("M:").build().map_err(|e| e.offset), Err(2));
+        assert_eq!(error(Request::builder().device("M_OUTTMP").property(Property::Status(StatusField::All))),
+                   "at offset 0: device 'M_OUTTMP' can't have the .STATUS property");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Array { start_index: 5, end_index: Some(1) })),
+                   "at offset 0: bad range: 5 is past 1");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Raw { offset: 1, length: Some(0) })),
+                   "at offset 0: bad length");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Relative { start: 1, end: Some(2) })),
+                   "at offset 0: a relative range needs a negative index");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Relative { start: -1, end: Some(-2) })),
+                   "at offset 0: bad range: -1 is past -2");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Relative { start: -70000, end: None })),
+                   "at offset 0: bad index");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Count { start_index: 65535, count: 2 })),
+                   "at offset 0: bad range");
+        assert_eq!(error(Request::builder().device("M:OUTTMP").range(Range::Count { start_index: 1, count: 0 })),
+                   "at offset 0: bad length");
+        assert!(Request::builder().device("M_OUTTMP").property(Property::Setting(SettingField::Raw)).build().is_ok());
+        assert!(Request::builder().device("M:OUTTMP junk").build().is_err());
+    }
+}
@@ -0,0 +1,117 @@
+// Names for TCLK clock events, so a request can be written
+// `@E,SUPERCYCLE` rather than `@E,0`. A few names are built in; more
+// are loaded from a data file with `ClockEvents::parse()`. Names
+// which read as hex numbers (e.g. "FACE") are rejected, since the
+// event parser takes them as event numbers.
+
+const BUILTIN: &[(&str, u16)] = &[("SUPERCYCLE", 0x00)];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClockEvents {
+    events: Vec<(String, u16)>,
+}
+
+// The table used when a parser isn't given one: only the built-in
+// names.
+
+pub(crate) static BUILTIN_EVENTS: ClockEvents = ClockEvents { events: Vec::new() };
+
+impl ClockEvents {
+    // Parses a table of clock events. Each line holds a name, an '='
+    // and the (hex) event number, which may be written with a leading
+    // '$', e.g. "MI_RESET = $29". Blank lines and those starting with
+    // '#' are ignored. Names are case-insensitive and may use letters,
+    // digits and '_', starting with a letter. A name given here
+    // replaces a built-in one.
+
+    pub fn parse(text: &str) -> Result<ClockEvents, String> {
+        let mut events: Vec<(String, u16)> = vec![];
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: String| format!("line {}: {}", n + 1, msg);
+            let (name, number) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| err(String::from("expected NAME = EVENT")))?;
+
+            if !is_name(name) {
+                return Err(err(format!("bad clock event name '{}'", name)));
+            }
+
+            let number = u16::from_str_radix(number.strip_prefix('$').unwrap_or(number), 16)
+                .map_err(|_| err(format!("bad clock event number '{}'", number)))?;
+            let name = name.to_ascii_uppercase();
+
+            if events.iter().any(|(n, _)| *n == name) {
+                return Err(err(format!("clock event {} is defined twice", name)));
+            }
+            events.push((name, number))
+        }
+        Ok(ClockEvents { events })
+    }
+
+    // Returns the number of the named event.
+
+    pub fn number(&self, name: &str) -> Option<u16> {
+        let name = name.to_ascii_uppercase();
+
+        self.entries().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    // Returns the name of an event, if it has one. If several names
+    // share the number, the first one is returned.
+
+    pub fn name(&self, event: u16) -> Option<&str> {
+        self.entries().find(|(_, v)| *v == event).map(|(n, _)| n)
+    }
+
+    // Returns the loaded events followed by the built-in ones they
+    // don't replace.
+
+    fn entries(&self) -> impl Iterator<Item = (&str, u16)> {
+        let loaded = self.events.iter().map(|(n, v)| (n.as_str(), *v));
+        let builtin = BUILTIN.iter().copied().filter(move |(n, _)| !self.events.iter().any(|(l, _)| l == n));
+
+        loaded.chain(builtin)
+    }
+}
+
+// Returns whether `text` can name a clock event.
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic())
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !text.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_events() {
+        let events = ClockEvents::parse("# TCLK\nmi_reset = $29\n\nBOOSTER = 0F\nSUPERCYCLE = 1\n").unwrap();
+
+        assert_eq!(events.number("MI_RESET"), Some(0x29));
+        assert_eq!(events.number("booster"), Some(0x0f));
+        assert_eq!(events.number("SUPERCYCLE"), Some(0x01));
+        assert_eq!(events.number("NOPE"), None);
+        assert_eq!(events.name(0x29), Some("MI_RESET"));
+        assert_eq!(events.name(0x02), None);
+        assert_eq!(events.name(0x00), None);
+
+        assert_eq!(BUILTIN_EVENTS.number("supercycle"), Some(0x00));
+        assert_eq!(BUILTIN_EVENTS.name(0x00), Some("SUPERCYCLE"));
+
+        assert_eq!(ClockEvents::parse("X = 1\nFACE = 2"), Err(String::from("line 2: bad clock event name 'FACE'")));
+        assert_eq!(ClockEvents::parse("MI = 1\nmi = 2"), Err(String::from("line 2: clock event MI is defined twice")));
+        assert!(ClockEvents::parse("MI = 10000").is_err());
+        assert!(ClockEvents::parse("MI 10").is_err());
+    }
+}
@@ -1,8 +1,8 @@
 use super::{AnalogField, Device, DigitalField, Property, ReadingField, SettingField, StatusField};
-use combine::error::ParseError;
+use combine::error::{ParseError, StreamError};
 use combine::parser::char;
-use combine::stream::Stream;
-use combine::{choice, many1, one_of, value, Parser};
+use combine::stream::{Stream, StreamErrorFor};
+use combine::{attempt, choice, many1, not_followed_by, one_of, satisfy, value, Parser};
 
 /*
 prop-qualifier	  = ":"       ; Reading and default
@@ -13,44 +13,70 @@ prop-qualifier	  = ":"       ; Reading and default
                   | "@"       ; Analog Alarm
                   | "$"       ; Digital Alarm
                   | "~"       ; Description
+
+The default qualifier, ':', is returned as `None`: it lets the request
+name any property. The others select their property explicitly.
 */
-fn parse_prop_symbol<Input>() -> impl Parser<Input, Output = Property>
+fn parse_prop_symbol<Input>() -> impl Parser<Input, Output = Option<Property>>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     choice((
-        char::char(':').with(value(Property::Reading(ReadingField::default()))),
-        char::char('?').with(value(Property::Reading(ReadingField::default()))),
-        char::char('_').with(value(Property::Setting(SettingField::default()))),
-        char::char('|').with(value(Property::Status(StatusField::default()))),
-        char::char('&').with(value(Property::Control)),
-        char::char('@').with(value(Property::Analog(AnalogField::default()))),
-        char::char('$').with(value(Property::Digital(DigitalField::default()))),
-        char::char('~').with(value(Property::Description)),
+        char::char(':').with(value(None)),
+        char::char('?').with(value(Some(Property::Reading(ReadingField::default())))),
+        char::char('_').with(value(Some(Property::Setting(SettingField::default())))),
+        char::char('|').with(value(Some(Property::Status(StatusField::default())))),
+        char::char('&').with(value(Some(Property::Control))),
+        char::char('@').with(value(Some(Property::Analog(AnalogField::default())))),
+        char::char('$').with(value(Some(Property::Digital(DigitalField::default())))),
+        char::char('~').with(value(Some(Property::Description))),
     ))
 }
 
-pub fn parser<Input>() -> impl Parser<Input, Output = (Device, Property)>
+pub fn parser<Input>() -> impl Parser<Input, Output = (Device, Option<Property>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let valid_characters = choice((char::alpha_num(), one_of("_-:<>;".chars())));
+    // Device names are restricted to ASCII. (`char::alpha_num()`
+    // would accept any Unicode letter or digit.) A name can't contain
+    // "<-", which starts the request's data source.
+
+    let valid_characters = choice((
+        satisfy(|c: char| c.is_ascii_alphanumeric()),
+        one_of("_-:>;".chars()),
+        attempt(char::char('<').skip(not_followed_by(char::char('-')))),
+    ));
+
+    // A device index is written with a '0' (or "DI") in place of the
+    // name's first character.
 
-    let parse_di = (char::char('0'), parse_prop_symbol(), many1(char::digit())).map(
-        |(character, prop, device): (char, Property, String)| {
-            (Device(format!("{}:{}", character, device)), prop)
-        },
-    );
+    let index = many1(char::digit())
+        .and_then(|v: String| {
+        v.parse::<u32>()
+            .map_err(|_| StreamErrorFor::<Input>::message_static_message("device index out of range"))
+    });
 
-    let parse_string = (char::letter(), parse_prop_symbol(), many1(valid_characters)).map(
-        |(character, prop, device): (char, Property, String)| {
-            (Device(format!("{}:{}", character, device)), prop)
-        },
-    );
+    let parse_di = choice((
+        char::char('0').with(parse_prop_symbol()),
+        attempt((one_of("dD".chars()), one_of("iI".chars()), parse_prop_symbol()))
+            .map(|(_, _, prop)| prop)
+            .silent(),
+    ))
+    .and(index)
+    .map(|(prop, index)| (Device::Index(index), prop));
+
+    let parse_string = (
+        satisfy(|c: char| c.is_ascii_alphabetic()),
+        parse_prop_symbol(),
+        many1(valid_characters),
+    )
+        .map(|(character, prop, device): (char, Option<Property>, String)| {
+            (Device::Name(format!("{}:{}", character, device)), prop)
+        });
 
-    choice((parse_di, parse_string))
+    choice((parse_di, parse_string)).expected("device name")
 }
 
 #[cfg(test)]
@@ -59,29 +85,22 @@ mod tests {
 
     #[test]
     fn test_device_parsing() {
-        let rdg_prop = Property::Reading(ReadingField::default());
-        let set_prop = Property::Setting(SettingField::default());
-        let sts_prop = Property::Status(StatusField::default());
-        let ana_prop = Property::Analog(AnalogField::default());
-        let dig_prop = Property::Digital(DigitalField::default());
+        let rdg_prop = None;
+        let qry_prop = Some(Property::Reading(ReadingField::default()));
+        let set_prop = Some(Property::Setting(SettingField::default()));
+        let sts_prop = Some(Property::Status(StatusField::default()));
+        let ana_prop = Some(Property::Analog(AnalogField::default()));
+        let dig_prop = Some(Property::Digital(DigitalField::default()));
 
         let device_data = &[
             ("M:OUTTMP", "M:OUTTMP", rdg_prop, ""),
-            ("M?OUTTMP", "M:OUTTMP", rdg_prop, ""),
+            ("M?OUTTMP", "M:OUTTMP", qry_prop, ""),
             ("M_OUTTMP", "M:OUTTMP", set_prop, ""),
             ("M|OUTTMP", "M:OUTTMP", sts_prop, ""),
-            ("M&OUTTMP", "M:OUTTMP", Property::Control, ""),
+            ("M&OUTTMP", "M:OUTTMP", Some(Property::Control), ""),
             ("M@OUTTMP", "M:OUTTMP", ana_prop, ""),
             ("M$OUTTMP", "M:OUTTMP", dig_prop, ""),
-            ("M~OUTTMP", "M:OUTTMP", Property::Description, ""),
-            ("0:123456", "0:123456", rdg_prop, ""),
-            ("0?123456", "0:123456", rdg_prop, ""),
-            ("0_123456", "0:123456", set_prop, ""),
-            ("0|123456", "0:123456", sts_prop, ""),
-            ("0&123456", "0:123456", Property::Control, ""),
-            ("0@123456", "0:123456", ana_prop, ""),
-            ("0$123456", "0:123456", dig_prop, ""),
-            ("0~123456", "0:123456", Property::Description, ""),
+            ("M~OUTTMP", "M:OUTTMP", Some(Property::Description), ""),
             (
                 "M:OUTTMP:outdoor:temp.VAL",
                 "M:OUTTMP:outdoor:temp",
@@ -122,12 +141,14 @@ mod tests {
             ("M:OUT\"TMP", "M:OUT", rdg_prop, "\"TMP"),
             ("M:OUT<TMP", "M:OUT<TMP", rdg_prop, ""),
             ("M:OUT>TMP", "M:OUT>TMP", rdg_prop, ""),
+            ("M:OUT<-FTP", "M:OUT", rdg_prop, "<-FTP"),
+            ("M:OUT<<-FTP", "M:OUT<", rdg_prop, "<-FTP"),
             ("M:OUT,TMP", "M:OUT", rdg_prop, ",TMP"),
             ("M:OUT/TMP", "M:OUT", rdg_prop, "/TMP"),
         ];
 
         for &(d, o, p, x) in device_data {
-            assert_eq!(parser().parse(d), Ok(((Device(o.to_string()), p), x)));
+            assert_eq!(parser().parse(d), Ok(((Device::Name(o.to_string()), p), x)));
         }
 
         assert!(parser().parse("M`OUTTMP").is_err());
@@ -156,4 +177,38 @@ mod tests {
         assert!(parser().parse("M/OUTTMP").is_err());
         assert!(parser().parse("1:123456").is_err());
     }
+
+    #[test]
+    fn test_device_index_parsing() {
+        let rdg_prop = None;
+        let index_data = &[
+            ("0:123456", 123456, rdg_prop, ""),
+            ("0?123456", 123456, Some(Property::Reading(ReadingField::default())), ""),
+            ("0_123456", 123456, Some(Property::Setting(SettingField::default())), ""),
+            ("0|123456", 123456, Some(Property::Status(StatusField::default())), ""),
+            ("0&123456", 123456, Some(Property::Control), ""),
+            ("0@123456", 123456, Some(Property::Analog(AnalogField::default())), ""),
+            ("0$123456", 123456, Some(Property::Digital(DigitalField::default())), ""),
+            ("0~123456", 123456, Some(Property::Description), ""),
+            ("0:0001234", 1234, rdg_prop, ""),
+            ("0:4294967295", u32::MAX, rdg_prop, ""),
+            ("DI:123456", 123456, rdg_prop, ""),
+            ("di_123456", 123456, Some(Property::Setting(SettingField::default())), ""),
+            ("Di:12.SETTING", 12, rdg_prop, ".SETTING"),
+            ("0:12[0:3]", 12, rdg_prop, "[0:3]"),
+        ];
+
+        for &(d, i, p, x) in index_data {
+            assert_eq!(parser().parse(d), Ok(((Device::Index(i), p), x)));
+        }
+
+        assert!(parser().parse("0:4294967296").is_err());
+        assert!(parser().parse("0:OUTTMP").is_err());
+        assert!(parser().parse("DI:OUTTMP").is_err());
+        assert!(parser().parse("DX:123456").is_err());
+        assert_eq!(
+            parser().parse("D:IOUTTMP"),
+            Ok(((Device::Name(String::from("D:IOUTTMP")), rdg_prop), ""))
+        );
+    }
 }
@@ -0,0 +1,158 @@
+use combine::stream::easy;
+use std::fmt;
+
+// Separates the description of an unknown name from the suggested
+// replacement in the parser's messages (see `prop_field::unknown()`.)
+
+pub(crate) const SUGGEST: &str = "; did you mean ";
+
+// Describes why a DRF string couldn't be parsed. `offset` is the byte
+// offset, in the parsed text, at which parsing failed. `unexpected`
+// is what was found there and `expected` lists what would have been
+// accepted (e.g. "'['" or "end of input".) `message` holds any other
+// explanation, like an unknown property name and, for near-misses of
+// property and field names, `suggestion` holds the closest valid
+// name(s). When a complete request was followed by more text,
+// `remainder` holds the text that wasn't parsed.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub offset: usize,
+    pub unexpected: Option<String>,
+    pub expected: Vec<String>,
+    pub message: Option<String>,
+    pub suggestion: Option<String>,
+    pub remainder: Option<String>,
+}
+
+fn show<R: fmt::Display>(info: easy::Info<char, R>) -> String {
+    match info {
+        easy::Info::Token(c) => format!("'{}'", c.escape_debug()),
+        easy::Info::Range(r) => format!("'{}'", r),
+        easy::Info::Owned(s) => s,
+        easy::Info::Static(s) => String::from(s),
+    }
+}
+
+// Converts the index of a character in `text` to its byte offset.
+
+pub(crate) fn byte_offset(text: &str, index: usize) -> usize {
+    text.char_indices().nth(index).map(|(b, _)| b).unwrap_or(text.len())
+}
+
+// Builds the error for a value which the parser would have rejected,
+// for requests put together by a program (see `RequestBuilder`.)
+
+pub(crate) fn invalid(message: String) -> Error {
+    Error {
+        offset: 0,
+        unexpected: None,
+        expected: vec![],
+        message: Some(message),
+        suggestion: None,
+        remainder: None,
+    }
+}
+
+impl Error {
+    // Converts the errors reported by combine. Their position is the
+    // index of the character at which parsing failed, which is
+    // converted to a byte offset into `text`.
+
+    pub(crate) fn from_easy<R: fmt::Display>(text: &str, e: easy::Errors<char, R, usize>) -> Error {
+        let mut error = Error {
+            offset: byte_offset(text, e.position),
+            unexpected: None,
+            expected: vec![],
+            message: None,
+            suggestion: None,
+            remainder: None,
+        };
+
+        for e in e.errors {
+            match e {
+                easy::Error::Unexpected(info) => error.unexpected = Some(show(info)),
+                easy::Error::Expected(info) => {
+                    let info = show(info);
+
+                    if !error.expected.contains(&info) {
+                        error.expected.push(info)
+                    }
+                }
+                easy::Error::Message(info) => {
+                    let text = show(info);
+
+                    match text.split_once(SUGGEST) {
+                        Some((m, s)) => {
+                            error.suggestion = Some(s.trim_end_matches('?').to_string());
+                            error.message = Some(m.to_string())
+                        }
+                        None => error.message = Some(text),
+                    }
+                }
+                easy::Error::Other(e) => error.message = Some(e.to_string()),
+            }
+        }
+
+        // Letters are accepted in either case, so only list the upper
+        // case one.
+
+        let expected = error.expected.clone();
+
+        error.expected.retain(|e| e.to_uppercase() == *e || !expected.contains(&e.to_uppercase()));
+
+        // If the text could have ended here, everything that follows
+        // is left over from an otherwise complete request.
+
+        if error.offset < text.len() && error.expected.iter().any(|e| e == "end of input") {
+            error.remainder = Some(text[error.offset..].to_string())
+        }
+        error
+    }
+}
+
+// Errors are written as, e.g., "at offset 9: unknown field '.SCALLED';
+// did you mean .SCALED?" or "at offset 8: unexpected 'x'; expected
+// '@', '[' or end of input". Text following a complete request is
+// reported as, e.g., "at offset 8: unparsed text ' junk'".
+
+impl Error {
+    // Returns the explanation of the error, without its offset.
+
+    pub fn reason(&self) -> String {
+        let mut parts = vec![];
+
+        if let Some(m) = &self.message {
+            parts.push(m.clone())
+        } else if let Some(r) = &self.remainder {
+            parts.push(format!("unparsed text '{}'", r.escape_debug()))
+        } else if let Some(u) = &self.unexpected {
+            parts.push(format!("unexpected {}", u))
+        }
+
+        if self.message.is_none() && self.remainder.is_none() {
+            match self.expected.split_last() {
+                None => (),
+                Some((last, [])) => parts.push(format!("expected {}", last)),
+                Some((last, rest)) => parts.push(format!("expected {} or {}", rest.join(", "), last)),
+            }
+        }
+
+        if let Some(s) = &self.suggestion {
+            parts.push(format!("did you mean {}?", s))
+        }
+
+        if parts.is_empty() {
+            parts.push(String::from("invalid request"))
+        }
+        parts.join("; ")
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at offset {}: {}", self.offset, self.reason())
+    }
+}
+
+impl std::error::Error for Error {}
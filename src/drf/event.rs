@@ -1,53 +1,71 @@
-use super::{ClockType, Event, StateOp};
+use super::error::invalid;
+use super::{device, parse_all, ws, ClockEvents, ClockType, Device, Error, Event, Note, StateOp, WarningKind};
 use combine::error::{ParseError, StreamError};
 use combine::parser::{char, repeat};
 use combine::stream::{Stream, StreamErrorFor};
-use combine::{attempt, choice, one_of, optional, value, Parser};
-
-// Takes numeric text and an optional suffix character and computes
-// the microsecond, periodic rate that represents it. If the user
-// provided bad input, the result is clipped to the extreme that was
-// exceeded.
-
-fn scale_rate(v: u32, suf: Option<char>) -> u32 {
-    match suf {
-        Some('s') | Some('S') => {
-            if v > u32::MAX / 1000000 {
-                u32::MAX
-            } else {
-                v * 1000000
-            }
-        }
-        Some('m') | Some('M') | None => {
-            if v > u32::MAX / 1000 {
-                u32::MAX
-            } else {
-                v * 1000
-            }
-        }
-        Some('u') | Some('U') => v,
-        Some('k') | Some('K') => {
-            if v == 0 {
-                u32::MAX
-            } else if v > 1000 {
-                1
-            } else {
-                1000 / v
-            }
-        }
-        Some('h') | Some('H') => {
-            if v == 0 {
-                u32::MAX
-            } else if v > 1000000 {
-                1
-            } else {
-                1000000 / v
-            }
-        }
+use combine::{attempt, choice, one_of, optional, position, satisfy, value, Parser};
+use std::convert::TryFrom;
+use std::time::Duration;
+
+// Takes a number, `num` / 10^`places`, and an optional suffix
+// character and computes the microsecond, periodic rate that
+// represents it, rounded to the nearest microsecond. If the user
+// provided bad input (a rate of zero, a period longer than a u32
+// holds or a non-zero one shorter than 1U), the result is clipped to
+// the extreme that was exceeded, and the returned flag is set.
+
+fn scale_rate(num: u128, places: u32, suf: Option<char>) -> (u32, bool) {
+    let den = 10u128.pow(places);
+
+    // The period is `n` / `d` microseconds.
+
+    let (n, d) = match suf {
+        Some('s') | Some('S') => (num.checked_mul(1_000_000), den),
+        Some('m') | Some('M') | None => (num.checked_mul(1_000), den),
+        Some('u') | Some('U') => (Some(num), den),
+        Some('k') | Some('K') if num == 0 => return (u32::MAX, true),
+        Some('k') | Some('K') => (Some(1_000 * den), num),
+        Some('h') | Some('H') if num == 0 => return (u32::MAX, true),
+        Some('h') | Some('H') => (Some(1_000_000 * den), num),
         Some(_) => unreachable!(),
+    };
+
+    match n {
+        Some(0) => (0, false),
+        Some(n) if n < d => (1, true),
+        Some(n) => match u32::try_from(n / d + u128::from(n % d >= d - n % d)) {
+            Ok(v) => (v, false),
+            Err(_) => (u32::MAX, true),
+        },
+        None => (u32::MAX, true),
     }
 }
 
+// Consumes a decimal number, e.g. "10" or "2.5", and returns its
+// text with its digits as an integer and the number of them after
+// the decimal point. Digits past the twelfth decimal place (under a
+// picosecond) are dropped and numbers too large for a u128 saturate;
+// either way, the rate they give is clipped.
+
+fn parse_decimal<Input>() -> impl Parser<Input, Output = (String, u128, u32)>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        repeat::many1(char::digit()),
+        optional(char::char('.').with(repeat::many1(char::digit()))),
+    )
+        .map(|(int, frac): (String, Option<String>)| {
+            let frac = frac.unwrap_or_default();
+            let kept = &frac[..frac.len().min(12)];
+            let num = format!("{}{}", int, kept).parse::<u128>().unwrap_or(u128::MAX);
+            let text = if frac.is_empty() { int } else { format!("{}.{}", int, frac) };
+
+            (text, num, kept.len() as u32)
+        })
+}
+
 // Consumes a block of digits and converts them to a `u32` type, if
 // possible.
 
@@ -62,28 +80,65 @@ where
         .and_then(|v: String| v.parse::<Output>().map_err(StreamErrorFor::<Input>::other))
 }
 
-// Consumes a block of hexadecimal digits and converts them to a `u16`
-// type, if possible.
+// Consumes a clock event: a block of hexadecimal digits, which may
+// follow a '$' (as TCLK events are often written), or the name of an
+// event in `events`. Text which reads as a hex number is taken as one.
 
-fn parse_clock_event<Input>() -> impl Parser<Input, Output = u16>
+fn parse_clock_event<'a, Input>(events: &'a ClockEvents) -> impl Parser<Input, Output = u16> + use<'a, Input>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    repeat::many1(char::hex_digit())
-        .and_then(|v: String| u16::from_str_radix(&v, 16).map_err(StreamErrorFor::<Input>::other))
+    let hex = |v: &str| u16::from_str_radix(v, 16).map_err(StreamErrorFor::<Input>::other);
+
+    choice((
+        char::char('$').with(repeat::many1(char::hex_digit())).and_then(move |v: String| hex(&v)),
+        repeat::many1(satisfy(|c: char| c.is_ascii_alphanumeric() || c == '_')).and_then(move |v: String| {
+            if v.chars().all(|c| c.is_ascii_hexdigit()) {
+                hex(&v)
+            } else {
+                events
+                    .number(&v)
+                    .ok_or_else(|| StreamErrorFor::<Input>::message_format(format!("unknown clock event '{}'", v)))
+            }
+        }),
+    ))
 }
 
 // Returns a time-freq value (u32) of the form ",TIME-FREQ". This
 // field is assumed to be optional, so the function may return None.
+// The number may have a fraction, e.g. "0.5S" or "2.5H". A value
+// which had to be clipped is noted.
+
+fn parse_time_freq<Input>() -> impl Parser<Input, Output = (u32, Note<Input::Position>)>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (position(), parse_decimal(), optional(one_of("sSmMuUkKhH".chars())))
+        .map(|(pos, (text, num, places), suf): (_, _, Option<char>)| {
+            let (rate, clipped) = scale_rate(num, places, suf);
+            let note = if clipped {
+                let text = format!("{}{}", text, suf.map(String::from).unwrap_or_default());
 
-fn parse_time_freq<Input>() -> impl Parser<Input, Output = u32>
+                Some((pos, WarningKind::Clipped { text, value: rate }))
+            } else {
+                None
+            };
+
+            (rate, note)
+        })
+}
+
+// Consumes the comma between the fields of an event, and any
+// whitespace around it.
+
+fn comma<Input>() -> impl Parser<Input, Output = char>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    (parse_int(), optional(one_of("sSmMuUkKhH".chars())))
-        .map(|(val, suf): (u32, Option<char>)| scale_rate(val, suf))
+    attempt(ws().with(char::char(','))).skip(ws())
 }
 
 // Returns a parser that looks for the trailing ",TRUE/FALSE" portion
@@ -95,15 +150,17 @@ where
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     optional(
-        char::char(',').with(repeat::many1(char::letter()).and_then(|v: String| {
-            match v.to_uppercase().as_str() {
-                "TRUE" | "T" => Ok(true),
-                "FALSE" | "F" => Ok(false),
-                _ => Err(StreamErrorFor::<Input>::message("unknown keyword")),
-            }
-        })),
+        comma().with(
+            repeat::many1(satisfy(|c: char| c.is_ascii_alphabetic())).and_then(|v: String| {
+                match v.to_ascii_uppercase().as_str() {
+                    "TRUE" | "T" => Ok(true),
+                    "FALSE" | "F" => Ok(false),
+                    _ => Err(StreamErrorFor::<Input>::message("unknown keyword")),
+                }
+            }),
+        ),
     )
-    .map(|v| if let Some(v) = v { v } else { true })
+    .map(|v| v.unwrap_or(true))
 }
 
 // Returns a parser that looks for the trailing state-op portion
@@ -128,14 +185,18 @@ where
 // Returns a parser that looks for the ",rate[,imm]" portion of a
 // periodic event string.
 
-fn parse_periodic_rate<Input>() -> impl Parser<Input, Output = (u32, bool)>
+fn parse_periodic_rate<Input>() -> impl Parser<Input, Output = (u32, bool, Note<Input::Position>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    optional(char::char(',').with(parse_time_freq()))
+    optional(comma().with(parse_time_freq()))
         .and(parse_periodic_imm())
-        .map(|(r, i)| (if let Some(r) = r { r } else { 1000000u32 }, i))
+        .map(|(r, i)| {
+            let (r, note) = r.unwrap_or((1000000u32, None));
+
+            (r, i, note)
+        })
 }
 
 // Returns a parser that understands the clock type field in a clock
@@ -146,7 +207,7 @@ where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    char::char(',').with(
+    comma().with(
         one_of("eE".chars())
             .with(value(ClockType::Either))
             .or(one_of("hH".chars()).with(value(ClockType::Hardware)))
@@ -156,59 +217,83 @@ where
 
 // This is the entry point for this module. It uses the other parsers
 // in this module to decode the event string from the incoming text.
+// The event is returned with a note when a rate or delay was clipped.
+// Clock events may be named by `events`.
 
-pub fn parser<Input>() -> impl Parser<Input, Output = Event>
+pub fn parser<'a, Input>(events: &'a ClockEvents) -> impl Parser<Input, Output = (Event, Note<Input::Position>)> + use<'a, Input>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let parse_never = one_of("nN".chars()).with(value(Event::Never));
+    let parse_never = one_of("nN".chars()).with(value((Event::Never, None)));
 
-    let parse_immediate = one_of("iI".chars()).with(value(Event::Immediate));
+    let parse_immediate = one_of("iI".chars()).with(value((Event::Immediate, None)));
 
     let parse_periodic = one_of("pP".chars())
         .with(parse_periodic_rate())
-        .map(|(r, i)| Event::Periodic {
+        .map(|(r, i, note)| (Event::Periodic {
             period: r,
             immediate: i,
             skip_dups: false,
-        });
+        }, note));
 
     let parse_periodic_filt = one_of("qQ".chars())
         .with(parse_periodic_rate())
-        .map(|(r, i)| Event::Periodic {
+        .map(|(r, i, note)| (Event::Periodic {
             period: r,
             immediate: i,
             skip_dups: true,
-        });
+        }, note));
 
     let parse_clock = one_of("eE".chars())
         .with((
-            char::char(',').with(parse_clock_event()),
+            comma().with(parse_clock_event(events)),
             optional(parse_clock_type()),
-            optional(char::char(',').with(parse_time_freq())),
+            optional(comma().with(parse_time_freq())),
         ))
-        .map(
-            |(event, ct, r): (u16, Option<ClockType>, Option<u32>)| Event::Clock {
+        .map(|(event, ct, r): (u16, Option<ClockType>, Option<(u32, _)>)| {
+            let (delay, note) = r.unwrap_or((0, None));
+
+            (Event::Clock {
                 event,
                 clk_type: ct.unwrap_or_else(ClockType::default),
-                delay: r.unwrap_or(0),
-            },
-        );
+                delay,
+            }, note)
+        });
+
+    // The state device is given by its index or its name (with any
+    // qualifier.)
+
+    let state_device = choice((
+        attempt(device::parser()).map(|(device, _)| device),
+        parse_int().map(Device::Index),
+    ));
 
     let parse_state = one_of("sS".chars())
         .with((
-            char::char(',').with(parse_int()),
-            char::char(',').with(parse_int()),
-            char::char(',').with(parse_time_freq()),
-            char::char(',').with(parse_ops()),
+            comma().with(state_device),
+            comma().with(parse_int()),
+            comma().with(parse_time_freq()),
+            comma().with(parse_ops()),
         ))
-        .map(|(device, value, delay, expr)| Event::State {
+        .map(|(device, value, (delay, note), expr)| (Event::State {
             device,
             value,
             delay,
             expr,
-        });
+        }, note));
+
+    // An absolute event fires every INTERVAL, counting from START
+    // (in seconds since the epoch), so samples line up with a fixed
+    // grid rather than with when the request was made. Loggers use
+    // it to return data at regular times.
+
+    let parse_absolute = one_of("aA".chars())
+        .with((
+            comma().with(parse_int()),
+            comma().with(parse_time_freq()),
+        ))
+        .map(|(start, (interval, note))| (Event::Absolute { start, interval }, note));
 
     // Create an all-encompassing parser which tries each of the
     // parsers above. None of these need to be wtapped with `attempt`
@@ -220,6 +305,7 @@ where
     // sub-parser.)
 
     char::char('@')
+        .skip(ws())
         .with(choice((
             parse_never,
             parse_immediate,
@@ -227,15 +313,84 @@ where
             parse_periodic_filt,
             parse_clock,
             parse_state,
+            parse_absolute,
         )))
-        .or(value(Event::Default))
+        .or(value((Event::Default, None)))
+}
+
+// Constructors for programs which build events rather than parse
+// them. They apply the limits the parser does, so the event can be
+// written as a DRF string and read back.
+
+impl Event {
+    // A periodic event firing every `period`, starting immediately.
+    // The period is kept in microseconds (any fraction is dropped)
+    // and must be at least 1U and fit in 32 bits.
+
+    pub fn periodic(period: Duration) -> Result<Event, Error> {
+        match u32::try_from(period.as_micros()) {
+            Ok(0) => Err(invalid(String::from("a periodic event needs a non-zero period"))),
+            Ok(period) => Ok(Event::Periodic { period, immediate: true, skip_dups: false }),
+            Err(_) => Err(invalid(format!("period {:?} is out of range", period))),
+        }
+    }
+
+    // An event firing on each occurrence of clock event `event`
+    // (e.g. 0x0F), from either the hardware or software clock, with
+    // no delay.
+
+    pub fn every_clock_event(event: u32) -> Result<Event, Error> {
+        match u16::try_from(event) {
+            Ok(event) => Ok(Event::Clock { event, clk_type: ClockType::default(), delay: 0 }),
+            Err(_) => Err(invalid(format!("clock event {:X} is out of range", event))),
+        }
+    }
+
+    // An event firing when the state device, named as in a request
+    // (e.g. "V:STATE" or "0:1234"), takes a value for which `expr`
+    // holds, with no delay.
+
+    pub fn on_state(device: &str, value: u16, expr: StateOp) -> Result<Event, Error> {
+        let (device, _) = parse_all(device::parser(), device)?;
+
+        Ok(Event::State { device, value, delay: 0, expr })
+    }
+
+    // Returns the period of a periodic event.
+
+    pub fn period(&self) -> Option<Duration> {
+        match *self {
+            Event::Periodic { period, .. } => Some(Duration::from_micros(period.into())),
+            _ => None,
+        }
+    }
+
+    // Returns the delay of a clock or state event.
+
+    pub fn delay(&self) -> Option<Duration> {
+        match *self {
+            Event::Clock { delay, .. } | Event::State { delay, .. } => Some(Duration::from_micros(delay.into())),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::BUILTIN_EVENTS;
     use combine::EasyParser;
 
+    // The event parser, without its notes.
+
+    fn event<Input>() -> impl Parser<Input, Output = Event>
+    where
+        Input: Stream<Token = char>,
+        Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    {
+        parser(&BUILTIN_EVENTS).map(|(e, _)| e)
+    }
+
     #[test]
     fn test_time_freq_parsing() {
         let data: &[(&str, Option<u32>, &str)] = &[
@@ -272,20 +427,50 @@ mod tests {
             ("1000001H", Some(1u32), ""),
             ("0k", Some(u32::MAX), ""),
             ("1001K", Some(1u32), ""),
+            // Tests for fractions, which are rounded to the nearest
+            // microsecond.
+            ("0.5s", Some(500000u32), ""),
+            ("2.5h", Some(400000u32), ""),
+            ("1.5", Some(1500u32), ""),
+            ("0.0015S", Some(1500u32), ""),
+            ("2.5U", Some(3u32), ""),
+            ("2.49U", Some(2u32), ""),
+            ("6h", Some(166667u32), ""),
+            ("0.1h", Some(10000000u32), ""),
+            ("1.5k", Some(667u32), ""),
+            ("4294.967295S", Some(4294967295u32), ""),
+            ("4294967.2954", Some(4294967295u32), ""),
+            ("0.000000000000000001s", Some(0u32), ""),
+            // Check the clipping of fractions.
+            ("4294.9672955S", Some(u32::MAX), ""),
+            ("0.4u", Some(1u32), ""),
+            ("0.0h", Some(u32::MAX), ""),
+            ("99999999999999999999999999999999999999999999", Some(u32::MAX), ""),
+            ("1000000.5h", Some(1u32), ""),
         ];
 
         for &(p, r, x) in data {
-            assert_eq!(optional(parse_time_freq()).parse(p), Ok((r, x)));
+            assert_eq!(optional(parse_time_freq().map(|(r, _)| r)).parse(p), Ok((r, x)));
         }
+
+        // Clipped values are noted.
+
+        assert_eq!(parse_time_freq().parse("1001K").map(|((_, note), _)| note.map(|(_, k)| k.to_string())),
+                   Ok(Some(String::from("'1001K' is out of range; clipped to 1U"))));
+        assert_eq!(parse_time_freq().parse("1000K").map(|((_, note), _)| note), Ok(None));
+        assert_eq!(parse_time_freq().parse("0.25u").map(|((_, note), _)| note.map(|(_, k)| k.to_string())),
+                   Ok(Some(String::from("'0.25u' is out of range; clipped to 1U"))));
+        assert_eq!(parse_time_freq().parse("2.5H").map(|((_, note), _)| note), Ok(None));
+        assert!(parse_time_freq().parse("1.s").is_err());
     }
 
     #[test]
     fn test_event_parsing() {
-        assert_eq!(parser().parse("@N"), Ok((Event::Never, "")));
-        assert_eq!(parser().parse("@n"), Ok((Event::Never, "")));
+        assert_eq!(event().parse("@N"), Ok((Event::Never, "")));
+        assert_eq!(event().parse("@n"), Ok((Event::Never, "")));
 
-        assert_eq!(parser().parse("@I"), Ok((Event::Immediate, "")));
-        assert_eq!(parser().parse("@i"), Ok((Event::Immediate, "")));
+        assert_eq!(event().parse("@I"), Ok((Event::Immediate, "")));
+        assert_eq!(event().parse("@i"), Ok((Event::Immediate, "")));
 
         let periodic_data = &[
             ("@P", 1000000u32, true, false, ""),
@@ -298,6 +483,8 @@ mod tests {
             ("@P,2K,FALSE", 500u32, false, false, ""),
             ("@P,1H", 1000000u32, true, false, ""),
             ("@P,10H", 100000u32, true, false, ""),
+            ("@P,0.5s", 500000u32, true, false, ""),
+            ("@P,2.5h,F", 400000u32, false, false, ""),
             ("@Q", 1000000u32, true, true, ""),
             ("@QD", 1000000u32, true, true, "D"),
             ("@Q,1000", 1000000u32, true, true, ""),
@@ -313,7 +500,7 @@ mod tests {
 
         for &(p, r, i, s, x) in periodic_data {
             assert_eq!(
-                parser().parse(p),
+                event().parse(p),
                 Ok((
                     Event::Periodic {
                         period: r,
@@ -328,20 +515,20 @@ mod tests {
         // This should fail because we consumed the comma, but didn't
         // find a digit.
 
-        assert!(parser().parse("@P,").is_err());
-        assert!(parser().parse("@P,junk").is_err());
-        assert!(parser().parse("@P,1000,").is_err());
+        assert!(event().parse("@P,").is_err());
+        assert!(event().parse("@P,junk").is_err());
+        assert!(event().parse("@P,1000,").is_err());
 
         // These should fail because, if we don't have the time-freq
         // field, then we can't proceed to parse the immediate flag
         // field.
 
-        assert!(parser().parse("@P,1s,TASK").is_err());
-        assert!(parser().parse("@Q,1s,FLOAT").is_err());
-        assert!(parser().parse("@P,TRUE").is_err());
-        assert!(parser().parse("@p,T").is_err());
-        assert!(parser().parse("@P,FALSE").is_err());
-        assert!(parser().parse("@P,F").is_err());
+        assert!(event().parse("@P,1s,TASK").is_err());
+        assert!(event().parse("@Q,1s,FLOAT").is_err());
+        assert!(event().parse("@P,TRUE").is_err());
+        assert!(event().parse("@p,T").is_err());
+        assert!(event().parse("@P,FALSE").is_err());
+        assert!(event().parse("@P,F").is_err());
 
         let clock_data = &[
             ("@E,0", 0, ClockType::Either, 0, ""),
@@ -353,11 +540,13 @@ mod tests {
             ("@E,8f", 0x8fu16, ClockType::Either, 0, ""),
             ("@E,89ab", 0x89abu16, ClockType::Either, 0, ""),
             ("@E,000089ab", 0x89abu16, ClockType::Either, 0, ""),
+            ("@E,$0F", 0xfu16, ClockType::Either, 0, ""),
+            ("@E,supercycle,h", 0, ClockType::Hardware, 0, ""),
         ];
 
         for &(txt, ev, ct, dly, extra) in clock_data {
             assert_eq!(
-                parser().easy_parse(txt),
+                event().easy_parse(txt),
                 Ok((
                     Event::Clock {
                         event: ev,
@@ -369,9 +558,21 @@ mod tests {
             );
         }
 
-        assert!(parser().parse("@E,12345").is_err());
-        assert!(parser().parse("@E,12345,e").is_err());
-        assert!(parser().parse("@E,1234,a").is_err());
+        assert!(event().parse("@E,12345").is_err());
+        assert!(event().parse("@E,12345,e").is_err());
+        assert!(event().parse("@E,1234,a").is_err());
+        assert!(event().parse("@E,$SUPERCYCLE").is_err());
+        assert!(event().parse("@E,NOPE").is_err());
+
+        // Names come from the table the parser is given.
+
+        let events = ClockEvents::parse("MI_RESET = $29").unwrap();
+        let mut named = parser(&events).map(|(e, _)| e);
+
+        assert_eq!(
+            named.parse("@E,mi_reset,s,1s"),
+            Ok((Event::Clock { event: 0x29, clk_type: ClockType::Software, delay: 1_000_000 }, ""))
+        );
 
         let state_data = &[
             ("@S,100,10,0,*", 100, 10, 0, StateOp::All, ""),
@@ -385,10 +586,10 @@ mod tests {
 
         for &(txt, device, value, delay, expr, extra) in state_data {
             assert_eq!(
-                parser().easy_parse(txt),
+                event().easy_parse(txt),
                 Ok((
                     Event::State {
-                        device,
+                        device: Device::Index(device),
                         value,
                         delay,
                         expr
@@ -397,5 +598,64 @@ mod tests {
                 ))
             );
         }
+
+        assert_eq!(
+            event().parse("@S,V:STATE,5,100,="),
+            Ok((Event::State {
+                device: Device::Name(String::from("V:STATE")),
+                value: 5,
+                delay: 100000,
+                expr: StateOp::Eq,
+            }, ""))
+        );
+        assert_eq!(event().parse("@S,0:1234,5,0,*").map(|(e, _)| e.canonical()), Ok(String::from("@S,1234,5,0,*")));
+        assert!(event().parse("@S,V:,5,0,=").is_err());
+        assert!(event().parse("@S,,5,0,=").is_err());
+
+        let absolute_data = &[
+            ("@A,1700000000,1s", 1700000000u64, 1000000u32, ""),
+            ("@a,0,100", 0, 100000, ""),
+            ("@A,18446744073709551615,10u", u64::MAX, 10, ""),
+            ("@A,1700000000,15h<-FTP", 1700000000, 66667, "<-FTP"),
+        ];
+
+        for &(txt, start, interval, extra) in absolute_data {
+            assert_eq!(event().easy_parse(txt), Ok((Event::Absolute { start, interval }, extra)));
+        }
+
+        assert!(event().parse("@A").is_err());
+        assert!(event().parse("@A,1700000000").is_err());
+        assert!(event().parse("@A,,1s").is_err());
+        assert!(event().parse("@A,18446744073709551616,1s").is_err());
+        assert_eq!(parser(&BUILTIN_EVENTS).parse("@A,0,0k").map(|((_, note), _)| note.is_some()), Ok(true));
+    }
+
+    #[test]
+    fn test_event_constructors() {
+        let reason = |r: Result<Event, Error>| r.unwrap_err().reason();
+
+        assert_eq!(Event::periodic(Duration::from_millis(500)).map(|e| e.canonical()), Ok(String::from("@P,500,TRUE")));
+        assert_eq!(Event::periodic(Duration::from_nanos(1500)).map(|e| e.canonical()), Ok(String::from("@P,1U,TRUE")));
+        assert_eq!(reason(Event::periodic(Duration::ZERO)), "a periodic event needs a non-zero period");
+        assert_eq!(reason(Event::periodic(Duration::from_nanos(999))), "a periodic event needs a non-zero period");
+        assert_eq!(reason(Event::periodic(Duration::from_secs(4295))), "period 4295s is out of range");
+
+        assert_eq!(Event::every_clock_event(0x0F).map(|e| e.canonical()), Ok(String::from("@E,F,E,0")));
+        assert_eq!(reason(Event::every_clock_event(0x10000)), "clock event 10000 is out of range");
+
+        assert_eq!(
+            Event::on_state("V:STATE", 5, StateOp::Eq),
+            Ok(Event::State { device: Device::Name(String::from("V:STATE")), value: 5, delay: 0, expr: StateOp::Eq })
+        );
+        assert_eq!(Event::on_state("0:1234", 5, StateOp::All).map(|e| e.canonical()), Ok(String::from("@S,1234,5,0,*")));
+        assert!(Event::on_state("V:", 5, StateOp::Eq).is_err());
+        assert!(Event::on_state("", 5, StateOp::Eq).is_err());
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(event().parse("@Q,250").map(|(e, _)| e.period()), Ok(Some(Duration::from_millis(250))));
+        assert_eq!(event().parse("@E,8F,H,2S").map(|(e, _)| e.delay()), Ok(Some(Duration::from_secs(2))));
+        assert_eq!(Event::Immediate.period(), None);
     }
 }
@@ -0,0 +1,182 @@
+use super::{ClockEvents, ClockType, DataSource, Device, Event, Property, Range, Request, StateOp};
+
+// Writes a time, in microseconds, with the largest unit that keeps it
+// whole, e.g. "1s", "250ms" or "10us".
+
+fn duration(us: u32) -> String {
+    if us == 0 {
+        String::from("0s")
+    } else if us.is_multiple_of(1_000_000) {
+        format!("{}s", us / 1_000_000)
+    } else if us.is_multiple_of(1_000) {
+        format!("{}ms", us / 1_000)
+    } else {
+        format!("{}us", us)
+    }
+}
+
+// Returns the delay of an event, as a phrase to follow it.
+
+fn after(delay: u32) -> String {
+    if delay == 0 {
+        String::new()
+    } else {
+        format!(", after a delay of {}", duration(delay))
+    }
+}
+
+fn device(d: &Device) -> String {
+    match d {
+        Device::Name(name) => name.clone(),
+        Device::Index(index) => format!("{} (device index {})", d, index),
+    }
+}
+
+fn property(p: &Property) -> String {
+    let (prop, field) = p.canonical();
+    let prop = prop.trim_start_matches('.');
+
+    match field.trim_start_matches('.') {
+        "" => String::from(prop),
+        field => format!("{}, field {}", prop, field),
+    }
+}
+
+fn range(r: &Range) -> String {
+    const FROM_END: &str = "negative indices count from the end of the array";
+
+    match r.to_array().unwrap_or_else(|| r.clone()) {
+        Range::Full => String::from("all of the data"),
+        Range::Array { start_index: 0, end_index: Some(0) } => String::from("element 0 (the default)"),
+        Range::Array { start_index, end_index: Some(e) } if start_index == e => format!("element {}", e),
+        Range::Array { start_index, end_index: Some(e) } => format!("elements {} to {}", start_index, e),
+        Range::Array { start_index, end_index: None } => format!("elements {} to the end", start_index),
+        Range::Raw { offset, length: Some(1) } => format!("byte {} of the raw data", offset),
+        Range::Raw { offset, length: Some(l) } => format!("{} bytes of the raw data, from byte {}", l, offset),
+        Range::Raw { offset, length: None } => format!("the raw data from byte {} to the end", offset),
+        Range::Relative { start, end: Some(e) } if start == e => format!("element {} ({})", start, FROM_END),
+        Range::Relative { start, end: Some(e) } => format!("elements {} to {} ({})", start, e, FROM_END),
+        Range::Relative { start, end: None } => format!("elements {} to the end ({})", start, FROM_END),
+        Range::Count { start_index, count } => format!("{} elements from element {}", count, start_index),
+    }
+}
+
+fn event(e: &Event, events: &ClockEvents) -> String {
+    match *e {
+        Event::Default => String::from("the property's default event"),
+        Event::Never => String::from("never; no data is returned (used for settings)"),
+        Event::Immediate => String::from("once, immediately"),
+        Event::Periodic { period, immediate, skip_dups } => format!(
+            "every {}{}{}",
+            duration(period),
+            if immediate { ", starting immediately" } else { ", starting after one period" },
+            if skip_dups { ", only when the value changes" } else { "" }
+        ),
+        Event::Clock { event, clk_type, delay } => {
+            let name = events.name(event).map(|n| format!(" ({})", n)).unwrap_or_default();
+            let kind = match clk_type {
+                ClockType::Hardware => "hardware",
+                ClockType::Software => "software",
+                ClockType::Either => "hardware or software",
+            };
+
+            format!("on clock event {:X}{}, {}{}", event, name, kind, after(delay))
+        }
+        Event::State { ref device, value, delay, expr } => {
+            let device = match device {
+                Device::Name(name) => name.clone(),
+                Device::Index(index) => format!("index {}", index),
+            };
+            let test = match expr {
+                StateOp::Eq => format!("becomes {}", value),
+                StateOp::NEq => format!("becomes anything but {}", value),
+                StateOp::GT => format!("becomes greater than {}", value),
+                StateOp::LT => format!("becomes less than {}", value),
+                StateOp::LEq => format!("becomes {} or less", value),
+                StateOp::GEq => format!("becomes {} or more", value),
+                StateOp::All => String::from("changes"),
+            };
+
+            format!("when state device {} {}{}", device, test, after(delay))
+        }
+        Event::Absolute { start, interval } => format!(
+            "logged data every {}, on a grid starting at {} (seconds since the epoch)",
+            duration(interval),
+            start
+        ),
+    }
+}
+
+fn source(s: DataSource) -> String {
+    match s {
+        DataSource::Default => String::from("the device's usual reading"),
+        DataSource::Ftp => String::from("fast time plot (data collected at a high rate)"),
+        DataSource::Snapshot => String::from("snapshot (a high-rate burst of data)"),
+        DataSource::Logger { start, end } => {
+            format!("the data logger, from {} to {} (milliseconds since the epoch)", start, end)
+        }
+    }
+}
+
+// Describes each part of a request, for people learning DRF: its
+// canonical form, device, property, range, event and source, each
+// with the name of the part. Clock events are named from `events`,
+// where they have a name.
+
+pub fn explain_parts(req: &Request, events: &ClockEvents) -> Vec<(&'static str, String)> {
+    vec![
+        ("canonical", req.canonical()),
+        ("device", device(&req.device)),
+        ("property", property(&req.property)),
+        ("range", range(&req.range)),
+        ("event", event(&req.event, events)),
+        ("source", source(req.source)),
+    ]
+}
+
+// Describes each part of a request, one per line.
+
+pub fn explain(req: &Request, events: &ClockEvents) -> String {
+    explain_parts(req, events)
+        .iter()
+        .map(|(part, text)| format!("{:10} {}\n", format!("{}:", part), text))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::BUILTIN_EVENTS, parse_drf};
+
+    fn explained(drf: &str) -> String {
+        explain(&parse_drf(drf).unwrap(), &BUILTIN_EVENTS)
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(
+            explained("M:OUTTMP[2:-1]@q,500"),
+            "canonical: M:OUTTMP.READING[2:-1].SCALED@Q,500,TRUE\n\
+             device:    M:OUTTMP\n\
+             property:  READING, field SCALED\n\
+             range:     elements 2 to -1 (negative indices count from the end of the array)\n\
+             event:     every 500ms, starting immediately, only when the value changes\n\
+             source:    the device's usual reading\n"
+        );
+
+        let line = |drf, part: &str| {
+            explained(drf).lines().find(|l| l.starts_with(part)).map(|l| l[11..].to_string()).unwrap()
+        };
+
+        assert_eq!(line("0:1234", "device"), "0:1234 (device index 1234)");
+        assert_eq!(line("M|OUTTMP", "property"), "STATUS, field ALL");
+        assert_eq!(line("M:OUTTMP.DESC", "property"), "DESCRIPTION");
+        assert_eq!(line("M:OUTTMP", "range"), "element 0 (the default)");
+        assert_eq!(line("M:OUTTMP[3;2]", "range"), "elements 3 to 4");
+        assert_eq!(line("G:AMANDA{4:2}", "range"), "2 bytes of the raw data, from byte 4");
+        assert_eq!(line("M:OUTTMP@E,0,H,1500U", "event"), "on clock event 0 (SUPERCYCLE), hardware, after a delay of 1500us");
+        assert_eq!(line("M:OUTTMP@S,V:STATE,5,0,>=", "event"), "when state device V:STATE becomes 5 or more");
+        assert_eq!(line("M:OUTTMP@A,1700000000,1s", "event"), "logged data every 1s, on a grid starting at 1700000000 (seconds since the epoch)");
+        assert_eq!(line("B:LINFRQ[]@p,1k<-ftp", "source"), "fast time plot (data collected at a high rate)");
+    }
+}
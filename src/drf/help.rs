@@ -0,0 +1,194 @@
+use super::prop_field::{ANALOG_FIELDS, DEPRECATED, DIGITAL_FIELDS, PROPERTIES, READING_FIELDS, SETTING_FIELDS, STATUS_FIELDS};
+use super::{parse_drf, AnalogField, DigitalField, Property, ReadingField, SettingField, StatusField};
+use std::fmt::Write;
+
+const GRAMMAR: &str = "\
+DRF REQUESTS
+    A request names a device and, optionally, the property, range,
+    field and event to acquire. Names are case-insensitive.
+
+        DEVICE [.PROPERTY] [RANGE] [.FIELD] [@EVENT] [<-SOURCE]
+
+DEVICE
+    A letter, a qualifier and the rest of the name (M:OUTTMP), or '0'
+    (or 'DI'), a qualifier and a device index (0:12345, DI:12345). The
+    qualifier selects the property used when none is given:
+
+        :  ?  READING    |  STATUS     @  ANALOG      ~  DESCRIPTION
+        _     SETTING    &  CONTROL    $  DIGITAL
+
+    Only ':' lets the request name another property (M:OUTTMP.SETTING);
+    the others must match the property named, if any.
+
+RANGE
+    [N]        element N
+    [N:M]      elements N through M
+    [N:]       element N to the end
+    [N;C]      C elements starting at element N
+    []         every element
+    [-N]       element N from the end ([-1] is the last); a negative
+               index works in [N:M] and [N:] too, e.g. [2:-1]
+    {N}        byte N of the raw data
+    {N:L}      L bytes starting at byte N
+    {N:}       byte N to the end
+
+    Without a range, only the first element is returned.
+
+EVENT
+    @I                       once, immediately
+    @N                       never (settings only)
+    @P,PERIOD[,IMMEDIATE]    periodically; PERIOD is in ms, or has a
+                             unit: S, M (ms), U (us), H (Hz), K (kHz),
+                             and may have a fraction, e.g. 0.5S
+    @Q,PERIOD[,IMMEDIATE]    like @P, but only when the value changes
+    @E,EVENT[,TYPE][,DELAY]  on a clock event, in hex (8F or $8F)
+                             or by name (SUPERCYCLE); TYPE is H
+                             (hardware), S (software) or E (either)
+    @S,DEVICE,VALUE,DELAY,OP on a state transition; DEVICE is an
+                             index (1234) or a name (V:STATE); OP
+                             is one of = != < > <= >= *
+    @A,START,INTERVAL        every INTERVAL (a PERIOD), on a grid
+                             starting at START (seconds since the
+                             epoch); used by loggers
+
+SOURCE
+    <-FTP      fast time plot: data collected at a high rate
+    <-SNP      snapshot: a high-rate burst of data
+    <-LOGGER:START:END
+               the data logged from START to END (milliseconds
+               since the epoch)
+
+    Without a source, the data comes from the device's usual reading.
+    A device name can't contain '<-'.
+";
+
+const EXAMPLES: [&str; 9] = [
+    "M:OUTTMP",
+    "M:OUTTMP@p,1s",
+    "M_OUTTMP.RAW",
+    "M|OUTTMP.ON",
+    "B:LINFRQ[0:9]@e,02",
+    "G:AMANDA{0:4}.RAW@I",
+    "Z@ACLTST.MAX",
+    "M:OUTTMP@q,100,false",
+    "B:LINFRQ[]@p,1k<-ftp",
+];
+
+// Returns an alias as it's listed: deprecated ones are marked.
+
+fn alias(name: &str) -> String {
+    if DEPRECATED.contains(&name) {
+        format!("{}*", name)
+    } else {
+        String::from(name)
+    }
+}
+
+// The properties, in the order they're listed.
+
+const ORDER: [Property; 10] = [
+    Property::Reading(ReadingField::default()),
+    Property::Setting(SettingField::default()),
+    Property::Status(StatusField::default()),
+    Property::Control,
+    Property::Analog(AnalogField::default()),
+    Property::Digital(DigitalField::default()),
+    Property::Description,
+    Property::Index,
+    Property::LongName,
+    Property::AlarmList,
+];
+
+// Lists the fields of a property. Each field is shown with its
+// canonical name, followed by the other names the parser accepts.
+
+fn fields<F: Copy + PartialEq>(out: &mut String, table: &[(&str, F)], canonical: fn(&F) -> &'static str, default: F) {
+    let mut seen: Vec<F> = vec![];
+
+    for (_, field) in table {
+        if seen.contains(field) {
+            continue;
+        }
+        seen.push(*field);
+
+        let name = &canonical(field)[1..];
+        let aliases: Vec<String> = table
+            .iter()
+            .filter(|(n, f)| f == field && *n != name)
+            .map(|(n, _)| alias(n))
+            .collect();
+        let mut notes = vec![];
+
+        if *field == default {
+            notes.push(String::from("default"))
+        }
+        if !aliases.is_empty() {
+            notes.push(format!("(also {})", aliases.join(", ")))
+        }
+        let _ = writeln!(out, "{}", format!("        {:<16}{}", name, notes.join(" ")).trim_end());
+    }
+}
+
+// Returns the DRF reference shown by `acsys help drf`. The property
+// and field sections are built from the parser's own tables.
+
+pub fn reference() -> String {
+    let mut out = String::from(GRAMMAR);
+
+    out.push_str("\nPROPERTIES\n");
+    for prop in &ORDER {
+        let name = &prop.canonical().0[1..];
+        let aliases: Vec<String> = PROPERTIES
+            .iter()
+            .filter(|(n, p)| p.canonical().0 == prop.canonical().0 && *n != name)
+            .map(|(n, _)| alias(n))
+            .collect();
+
+        let _ = writeln!(out, "{}", format!("    {:<20}{}", name, aliases.join(", ")).trim_end());
+    }
+
+    out.push_str("\nFIELDS\n");
+    out.push_str("    READING\n");
+    fields(&mut out, &READING_FIELDS, ReadingField::canonical, ReadingField::default());
+    out.push_str("    SETTING\n");
+    fields(&mut out, &SETTING_FIELDS, SettingField::canonical, SettingField::default());
+    out.push_str("    STATUS\n");
+    fields(&mut out, &STATUS_FIELDS, StatusField::canonical, StatusField::default());
+    out.push_str("    ANALOG\n");
+    fields(&mut out, &ANALOG_FIELDS, AnalogField::canonical, AnalogField::default());
+    out.push_str("    DIGITAL\n");
+    fields(&mut out, &DIGITAL_FIELDS, DigitalField::canonical, DigitalField::default());
+    out.push_str("    The other properties have no fields.\n");
+    out.push_str("\n    Names marked * are deprecated; --strict rejects them.\n");
+
+    out.push_str("\nEXAMPLES (as written, then in canonical form)\n");
+    for ex in &EXAMPLES {
+        if let Ok(req) = parse_drf(ex) {
+            let _ = writeln!(out, "    {:<24}{}", ex, req);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference() {
+        let text = reference();
+
+        for ex in &EXAMPLES {
+            assert!(parse_drf(ex).is_ok(), "bad example {}", ex);
+            assert!(text.contains(ex));
+        }
+        for (name, _) in &PROPERTIES {
+            assert!(text.contains(name), "{} isn't documented", name);
+        }
+        for (name, _) in &ANALOG_FIELDS {
+            assert!(text.contains(name), "{} isn't documented", name);
+        }
+        assert!(text.contains("SCALED          default (also COMMON*)"));
+        assert!(text.contains("READING             PRREAD*, READ"));
+    }
+}
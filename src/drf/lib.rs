@@ -0,0 +1,1690 @@
+// The DRF parser, built as the `drf` library so other tools can parse
+// requests without depending on the command line program. The public
+// API is `parse_drf()` (and `parse()`, the underlying combine parser,
+// or `parse_prefix()` to embed requests in a larger grammar),
+// `Request` (which `Request::builder()` also builds) and its parts
+// (`Device`, `Property`, `Range`, `Event`, `DataSource` and their
+// fields),
+// `verify()`, `parse_bytes_lossy()`, `reference()`, a description of the DRF syntax, and `explain()`, a
+// description of a request.
+
+// Parse errors describe the failure in full (see `Error`), which makes
+// them large, but they're rare enough that it doesn't matter.
+
+#![allow(clippy::result_large_err)]
+
+use combine::{Stream, ParseError, attempt, look_ahead, one_of, optional, skip_many, skip_many1, Parser, EasyParser, eof};
+use combine::parser::char;
+use combine::error::StreamError;
+use combine::stream::{easy, position, StreamErrorFor};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub enum Device {
+    // A device name, e.g. "M:OUTTMP".
+    Name(String),
+    // A device index, written "0:123456" (or "DI:123456").
+    Index(u32),
+}
+
+impl Device {
+    // Returns the device, in its canonical form: its name or, for an
+    // index, "0:" followed by the index.
+
+    pub fn name(&self) -> Cow<'_, str> {
+        match self {
+            Device::Name(name) => Cow::Borrowed(name),
+            Device::Index(index) => Cow::Owned(format!("0:{}", index)),
+        }
+    }
+
+    // Returns whether the device is given by its index.
+
+    pub fn is_index(&self) -> bool {
+        matches!(self, Device::Index(_))
+    }
+
+    // Returns the device in upper case. Device names are
+    // case-insensitive and kept as they were written (see
+    // `Parsed::device_text`), so this is the form to compare them in.
+
+    pub fn normalized(&self) -> Cow<'_, str> {
+        match self {
+            Device::Name(name) if name.bytes().any(|b| b.is_ascii_lowercase()) => {
+                Cow::Owned(name.to_ascii_uppercase())
+            }
+            d => d.name(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReadingField {
+    Raw,
+    Primary,
+    Scaled,
+}
+
+impl ReadingField {
+    pub const fn default() -> Self { ReadingField::Scaled }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            ReadingField::Raw => ".RAW",
+            ReadingField::Primary => ".PRIMARY",
+            ReadingField::Scaled => ".SCALED",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SettingField {
+    Raw,
+    Primary,
+    Scaled,
+}
+
+impl SettingField {
+    pub const fn default() -> Self { SettingField::Scaled }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            SettingField::Raw => ".RAW",
+            SettingField::Primary => ".PRIMARY",
+            SettingField::Scaled => ".SCALED",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatusField {
+    Raw,
+    All,
+    Text,
+    ExtText,
+    On,
+    Ready,
+    Remote,
+    Positive,
+    Ramp,
+}
+
+impl StatusField {
+    pub const fn default() -> Self { StatusField::All }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            StatusField::Raw => ".RAW",
+            StatusField::All => ".ALL",
+            StatusField::Text => ".TEXT",
+            StatusField::ExtText => ".EXTENDED_TEXT",
+            StatusField::On => ".ON",
+            StatusField::Ready => ".READY",
+            StatusField::Remote => ".REMOTE",
+            StatusField::Positive => ".POSITIVE",
+            StatusField::Ramp => ".RAMP",
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnalogField {
+    Raw,
+    All,
+    Text,
+    Min,
+    Max,
+    Nom,
+    Tol,
+    RawMin,
+    RawMax,
+    RawNom,
+    RawTol,
+    Enable,
+    Status,
+    TriesNeeded,
+    TriesNow,
+    FTD,
+    Abort,
+    AbortInhibit,
+    Flags,
+}
+
+impl AnalogField {
+    pub const fn default() -> Self { AnalogField::All }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            AnalogField::Raw => ".RAW",
+            AnalogField::All => ".ALL",
+            AnalogField::Text => ".TEXT",
+            AnalogField::Min => ".MIN",
+            AnalogField::Max => ".MAX",
+            AnalogField::Nom => ".NOM",
+            AnalogField::Tol => ".TOL",
+            AnalogField::RawMin => ".RAW_MIN",
+            AnalogField::RawMax => ".RAW_MAX",
+            AnalogField::RawNom => ".RAW_NOM",
+            AnalogField::RawTol => ".RAW_TOL",
+            AnalogField::Enable => ".ALARM_ENABLE",
+            AnalogField::Status => ".ALARM_STATUS",
+            AnalogField::TriesNeeded => ".TRIES_NEEDED",
+            AnalogField::TriesNow => ".TRIES_NOW",
+            AnalogField::FTD => ".ALARM_FTD",
+            AnalogField::Abort => ".ABORT",
+            AnalogField::AbortInhibit => ".ABORT_INHIBIT",
+            AnalogField::Flags => ".FLAGS",
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DigitalField {
+    Raw,
+    All,
+    Text,
+    Nom,
+    Mask,
+    Enable,
+    Status,
+    TriesNeeded,
+    TriesNow,
+    FTD,
+    Abort,
+    AbortInhibit,
+    Flags,
+}
+
+impl DigitalField {
+    pub const fn default() -> Self { DigitalField::All }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            DigitalField::Raw => ".RAW",
+            DigitalField::All => ".ALL",
+            DigitalField::Text => ".TEXT",
+            DigitalField::Nom => ".NOM",
+            DigitalField::Mask => ".MASK",
+            DigitalField::Enable => ".ALARM_ENABLE",
+            DigitalField::Status => ".ALARM_STATUS",
+            DigitalField::TriesNeeded => ".TRIES_NEEDED",
+            DigitalField::TriesNow => ".TRIES_NOW",
+            DigitalField::FTD => ".ALARM_FTD",
+            DigitalField::Abort => ".ABORT",
+            DigitalField::AbortInhibit => ".ABORT_INHIBIT",
+            DigitalField::Flags => ".FLAGS",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Property {
+    Reading(ReadingField),
+    Setting(SettingField),
+    Status(StatusField),
+    Control,
+    Analog(AnalogField),
+    Digital(DigitalField),
+    Description,
+    Index,
+    LongName,
+    AlarmList,
+}
+
+impl Property {
+    pub fn canonical(&self) -> (&'static str, &'static str) {
+        match *self {
+            Property::Reading(fld) => (".READING", fld.canonical()),
+            Property::Setting(fld) => (".SETTING", fld.canonical()),
+            Property::Status(fld) => (".STATUS", fld.canonical()),
+            Property::Control => (".CONTROL", ""),
+            Property::Analog(fld) => (".ANALOG", fld.canonical()),
+            Property::Digital(fld) => (".DIGITAL", fld.canonical()),
+            Property::Description => (".DESCRIPTION", ""),
+            Property::Index => (".INDEX", ""),
+            Property::LongName => (".LONG_NAME", ""),
+            Property::AlarmList => (".ALARM_LIST_NAME", ""),
+        }
+    }
+
+    // Returns the qualifier character which, written in place of the
+    // ':' of a device name, selects the property (e.g. "M|OUTTMP" for
+    // the status), if there is one.
+
+    fn symbol(&self) -> Option<char> {
+        match *self {
+            Property::Reading(_) => Some(':'),
+            Property::Setting(_) => Some('_'),
+            Property::Status(_) => Some('|'),
+            Property::Control => Some('&'),
+            Property::Analog(_) => Some('@'),
+            Property::Digital(_) => Some('$'),
+            Property::Description => Some('~'),
+            Property::Index | Property::LongName | Property::AlarmList => None,
+        }
+    }
+
+    // Returns whether the property's field is the one used when none
+    // is given.
+
+    fn has_default_field(&self) -> bool {
+        match *self {
+            Property::Reading(f) => f == ReadingField::default(),
+            Property::Setting(f) => f == SettingField::default(),
+            Property::Status(f) => f == StatusField::default(),
+            Property::Analog(f) => f == AnalogField::default(),
+            Property::Digital(f) => f == DigitalField::default(),
+            _ => true,
+        }
+    }
+}
+
+// Type which specifies a range of data.
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Range {
+    Full,
+    Array {
+        start_index: u16,
+        end_index: Option<u16>,
+    },
+    Raw {
+        offset: u32,
+        length: Option<u32>,
+    },
+    // An array range with an index counted back from the end of the
+    // array, e.g. "[-1]" (the last element) or "[2:-1]". Negative
+    // indices go down to -65536; at least one index is negative.
+    // The range is resolved once the array's length is known (see
+    // `resolve()`.)
+    Relative {
+        start: i32,
+        end: Option<i32>,
+    },
+    // `count` array elements starting at `start_index`, written
+    // "[start;count]". It's the same range as an `Array` and has the
+    // same canonical form, so requests store the `Array` (see
+    // `to_array()`.)
+    Count {
+        start_index: u16,
+        count: u32,
+    },
+}
+
+impl Range {
+    // Returns the index of the first element (or byte, for raw
+    // ranges) selected by the range. A relative range's start isn't
+    // known until it's resolved, so one counted from the end gives 0.
+
+    pub fn start(&self) -> usize {
+        match *self {
+            Range::Full => 0,
+            Range::Array { start_index, .. } => start_index as usize,
+            Range::Raw { offset, .. } => offset as usize,
+            Range::Relative { start, .. } => start.max(0) as usize,
+            Range::Count { start_index, .. } => start_index as usize,
+        }
+    }
+
+    // Returns the number of elements (or bytes) selected by the
+    // range. `None` is returned if the range extends to the end of
+    // the data, or, for a relative range, depends on its length.
+
+    pub fn count(&self) -> Option<usize> {
+        match *self {
+            Range::Full | Range::Relative { .. } => None,
+            Range::Array {
+                start_index,
+                end_index,
+            } => end_index.map(|e| (e - start_index) as usize + 1),
+            Range::Raw { length, .. } => length.map(|l| l as usize),
+            Range::Count { count, .. } => Some(count as usize),
+        }
+    }
+
+    // Returns the `Array` range a counted range selects, or `None` if
+    // it selects no elements or runs past the last index (65535).
+    // Other ranges are returned as they are.
+
+    pub fn to_array(&self) -> Option<Range> {
+        match *self {
+            Range::Count { start_index, count } => {
+                let end = u32::from(start_index) + count.checked_sub(1)?;
+
+                Some(Range::Array { start_index, end_index: Some(u16::try_from(end).ok()?) })
+            }
+            ref r => Some(r.clone()),
+        }
+    }
+
+    // Returns the array range a relative range selects from an array
+    // of `len` elements, or `None` if it selects none of them. Other
+    // ranges are returned as they are.
+
+    pub fn resolve(&self, len: usize) -> Option<Range> {
+        let Range::Relative { start, end } = *self else { return Some(self.clone()) };
+        let index = |i: i32| if i < 0 { len as i64 + i64::from(i) } else { i64::from(i) };
+        let last = len as i64 - 1;
+        let start = index(start).max(0);
+        let end = end.map_or(last, index).min(last);
+
+        if start > end {
+            None
+        } else {
+            Some(Range::Array {
+                start_index: u16::try_from(start).ok()?,
+                end_index: Some(u16::try_from(end).unwrap_or(u16::MAX)),
+            })
+        }
+    }
+
+    // Selects the part of a complete array that's covered by the
+    // range. Portions of the range beyond the end of the data are
+    // ignored.
+
+    pub fn slice<'a, T>(&self, data: &'a [T]) -> &'a [T] {
+        if let Range::Relative { .. } = self {
+            return self.resolve(data.len()).map_or(&data[..0], |r| r.slice(data));
+        }
+
+        let start = self.start().min(data.len());
+        let end = match self.count() {
+            Some(n) => (start + n).min(data.len()),
+            None => data.len(),
+        };
+
+        &data[start..end]
+    }
+
+    // Returns the canonical form of the range.
+
+    pub fn canonical(&self) -> String {
+        match *self {
+            Range::Full => String::from("[]"),
+
+            Range::Array {
+                start_index,
+                end_index,
+            } => match (start_index, end_index) {
+                (0, Some(0)) => String::from(""),
+                (s, Some(e)) => {
+                    if s == e {
+                        format!("[{}]", s)
+                    } else {
+                        format!("[{}:{}]", s, e)
+                    }
+                }
+                (s, None) => format!("[{}:]", s),
+            },
+
+            Range::Raw { offset, length } => match (offset, length) {
+                (o, Some(1)) => format!("{{{}}}", o),
+                (o, Some(l)) => format!("{{{}:{}}}", o, l),
+                (o, None) => format!("{{{}:}}", o),
+            },
+
+            // A counted range which can't be an array range (the
+            // builder rejects it) is written as given.
+
+            Range::Count { start_index, count } => match self.to_array() {
+                Some(r) => r.canonical(),
+                None => format!("[{};{}]", start_index, count),
+            },
+
+            Range::Relative { start, end } => match end {
+                Some(e) if e == start => format!("[{}]", start),
+                Some(e) => format!("[{}:{}]", start, e),
+                None => format!("[{}:]", start),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StateOp {
+    Eq,
+    NEq,
+    GT,
+    LT,
+    LEq,
+    GEq,
+    All,
+}
+
+impl StateOp {
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            StateOp::Eq => "=",
+            StateOp::NEq => "!=",
+            StateOp::GT => ">",
+            StateOp::LT => "<",
+            StateOp::LEq => "<=",
+            StateOp::GEq => ">=",
+            StateOp::All => "*",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClockType {
+    Hardware,
+    Software,
+    Either,
+}
+
+impl ClockType {
+    pub const fn default() -> Self { ClockType::Either }
+
+    pub fn canonical(&self) -> &'static str {
+        match *self {
+            ClockType::Hardware => "H",
+            ClockType::Software => "S",
+            ClockType::Either => "E",
+        }
+    }
+}
+
+// A state event's device is either an index or a name. Data sources
+// take an index, so a name has to be resolved by the program first.
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    Never,
+    Immediate,
+    Default,
+    Periodic {
+        period: u32,
+        immediate: bool,
+        skip_dups: bool,
+    },
+    Clock {
+        event: u16,
+        clk_type: ClockType,
+        delay: u32,
+    },
+    State {
+        device: Device,
+        value: u16,
+        delay: u32,
+        expr: StateOp,
+    },
+    Absolute {
+        start: u64,
+        interval: u32,
+    },
+}
+
+impl Event {
+    fn canonical_delay(dly: u32) -> String {
+        if dly == 0 {
+            String::from("0")
+        } else if dly.is_multiple_of(1000000) {
+            format!("{}S", dly / 1000000)
+        } else if dly.is_multiple_of(1000) {
+            format!("{}", dly / 1000)
+        } else {
+            format!("{}U", dly)
+        }
+    }
+
+    pub fn canonical(&self) -> String {
+        match *self {
+            Event::Default => String::from(""),
+            Event::Never => String::from("@N"),
+            Event::Immediate => String::from("@I"),
+            Event::Periodic {
+                period,
+                immediate,
+                skip_dups,
+            } => format!(
+                "@{},{},{}",
+                if skip_dups { 'Q' } else { 'P' },
+                Event::canonical_delay(period),
+                if immediate { "TRUE" } else { "FALSE" }
+            ),
+            Event::Clock {
+                event,
+                clk_type,
+                delay,
+            } => format!("@E,{:X},{},{}", event, clk_type.canonical(),
+                         Event::canonical_delay(delay)),
+            // A state device's index is written as a plain number.
+            Event::State {
+                ref device,
+                value,
+                delay,
+                expr,
+            } => format!("@S,{},{},{},{}",
+                         match device {
+                             Device::Index(index) => index.to_string(),
+                             Device::Name(name) => name.clone(),
+                         },
+                         value, Event::canonical_delay(delay), expr.canonical()),
+            Event::Absolute { start, interval } => {
+                format!("@A,{},{}", start, Event::canonical_delay(interval))
+            }
+        }
+    }
+
+    // Returns the shortest text which parses as the event: fields
+    // with their default value are left off the end, and the default
+    // event is empty.
+
+    pub fn minimal(&self) -> String {
+        match *self {
+            Event::Periodic {
+                period,
+                immediate,
+                skip_dups,
+            } => {
+                let kind = if skip_dups { 'Q' } else { 'P' };
+
+                match (period, immediate) {
+                    (1000000, true) => format!("@{}", kind),
+                    (p, true) => format!("@{},{}", kind, Event::canonical_delay(p)),
+                    (p, false) => format!("@{},{},FALSE", kind, Event::canonical_delay(p)),
+                }
+            }
+            Event::Clock {
+                event,
+                clk_type,
+                delay,
+            } => match (clk_type, delay) {
+                (ClockType::Either, 0) => format!("@E,{:X}", event),
+                (ct, 0) => format!("@E,{:X},{}", event, ct.canonical()),
+                (ct, d) => format!("@E,{:X},{},{}", event, ct.canonical(), Event::canonical_delay(d)),
+            },
+            _ => self.canonical(),
+        }
+    }
+
+    // Like `canonical()`, but a clock event with a name in `events` is
+    // written by name, e.g. "@E,SUPERCYCLE,E,0".
+
+    pub fn symbolic(&self, events: &ClockEvents) -> String {
+        match *self {
+            Event::Clock {
+                event,
+                clk_type,
+                delay,
+            } => match events.name(event) {
+                Some(name) => format!("@E,{},{},{}", name, clk_type.canonical(), Event::canonical_delay(delay)),
+                None => self.canonical(),
+            },
+            _ => self.canonical(),
+        }
+    }
+
+    // Returns whether the event asks for a single reply: it fires
+    // immediately, once. The default event is taken to be one, as it
+    // is for the properties data sources serve.
+
+    pub fn is_one_shot(&self) -> bool {
+        matches!(self, Event::Immediate | Event::Default)
+    }
+
+    // Returns whether the event fires repeatedly, so replies keep
+    // coming until the request is cancelled. `@N` is neither a
+    // stream nor a one-shot; it never returns data.
+
+    pub fn is_streaming(&self) -> bool {
+        matches!(self, Event::Periodic { .. } | Event::Clock { .. } | Event::State { .. } | Event::Absolute { .. })
+    }
+}
+
+// Where the data of a request comes from. `Ftp` (fast time plot)
+// and `Snapshot` collect data at high rates, for plots; they're
+// written "<-FTP" and "<-SNP" at the end of the request. `Logger`
+// returns the data logged between `start` and `end` (in milliseconds
+// since the epoch), written "<-LOGGER:start:end", after which the
+// request ends.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DataSource {
+    Default,
+    Ftp,
+    Snapshot,
+    Logger { start: u64, end: u64 },
+}
+
+impl DataSource {
+    pub fn canonical(&self) -> String {
+        match *self {
+            DataSource::Default => String::new(),
+            DataSource::Ftp => String::from("<-FTP"),
+            DataSource::Snapshot => String::from("<-SNP"),
+            DataSource::Logger { start, end } => format!("<-LOGGER:{}:{}", start, end),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RequestBuilder"))]
+pub struct Request {
+    pub device: Device,
+    pub property: Property,
+    pub range: Range,
+    pub event: Event,
+    pub source: DataSource,
+}
+
+impl Request {
+    pub fn canonical(&self) -> String {
+        self.render(self.event.canonical())
+    }
+
+    // Returns the canonical form with clock events written by name
+    // (see `Event::symbolic()`.) It parses back to the same request
+    // when parsed with the same `events`.
+
+    pub fn symbolic(&self, events: &ClockEvents) -> String {
+        self.render(self.event.symbolic(events))
+    }
+
+    // Returns the shortest text which parses as the request, for
+    // display: the property is given by the device's qualifier where
+    // one selects it (e.g. "M|OUTTMP" rather than
+    // "M:OUTTMP.STATUS.ALL"), and default fields, ranges and events
+    // are left out. Canonical text, not this, is what should be
+    // stored or compared.
+
+    pub fn canonical_minimal(&self) -> String {
+        let name = self.device.name();
+        let symbolic = match (self.property.symbol(), name.char_indices().nth(1)) {
+            (Some(symbol), Some((i, ':'))) => Some(format!("{}{}{}", &name[..i], symbol, &name[i + 1..])),
+            _ => None,
+        };
+        let (device, prop) = match symbolic {
+            Some(device) => (device, ""),
+            None => (name.into_owned(), self.property.canonical().0),
+        };
+        let field = if self.property.has_default_field() { "" } else { self.property.canonical().1 };
+
+        format!(
+            "{}{}{}{}{}{}",
+            device,
+            prop,
+            self.range.canonical(),
+            field,
+            self.event.minimal(),
+            self.source.canonical()
+        )
+    }
+
+    // Returns whether two requests ask for the same data, however they
+    // were written. Parsing already fills in default properties,
+    // fields and ranges (so "M:OUTTMP" matches
+    // "M:OUTTMP.READING[0:0].SCALED"); this also ignores the case of
+    // device names and treats ranges which select the same data as
+    // equal, e.g. "[0:]" and "[]", or "[2;3]" and "[2:4]".
+
+    pub fn matches(&self, other: &Request) -> bool {
+        self.normalized() == other.normalized()
+    }
+
+    // Returns the request in the form `matches()` compares.
+
+    fn normalized(&self) -> Request {
+        let name = |d: &Device| match d {
+            Device::Name(_) => Device::Name(d.normalized().into_owned()),
+            d => d.clone(),
+        };
+        let range = match self.range.to_array().unwrap_or_else(|| self.range.clone()) {
+            Range::Array { start_index: 0, end_index: None } | Range::Raw { offset: 0, length: None } => Range::Full,
+            r => r,
+        };
+        let event = match &self.event {
+            Event::State { device, value, delay, expr } => Event::State {
+                device: name(device),
+                value: *value,
+                delay: *delay,
+                expr: *expr,
+            },
+            e => e.clone(),
+        };
+
+        Request { device: name(&self.device), property: self.property, range, event, source: self.source }
+    }
+
+    fn render(&self, event: String) -> String {
+        let (prop, field) = self.property.canonical();
+
+        format!(
+            "{}{}{}{}{}{}",
+            self.device,
+            prop,
+            self.range.canonical(),
+            field,
+            event,
+            self.source.canonical()
+        )
+    }
+}
+
+// Every DRF type is displayed in its canonical form and can be parsed
+// from a string. Parsing accepts the same (non-canonical) spellings
+// the request parser does, so `x.to_string().parse()` returns `x`.
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prop, field) = self.canonical();
+
+        write!(f, "{}{}", prop, field)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.canonical())
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.canonical())
+    }
+}
+
+impl fmt::Display for DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.canonical())
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.canonical())
+    }
+}
+
+// The field enums, `StateOp` and `ClockType` already return their
+// canonical form as a `&'static str`.
+
+macro_rules! display_canonical {
+    ($($t:ty),*) => {
+        $(
+            impl fmt::Display for $t {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str(self.canonical())
+                }
+            }
+        )*
+    };
+}
+
+display_canonical!(ReadingField, SettingField, StatusField, AnalogField, DigitalField, StateOp, ClockType);
+
+// The stream the public parsing functions use. It tracks the index
+// of each character so errors can report where they occurred.
+
+type Input<'a> = easy::Stream<position::Stream<&'a str, position::IndexPositioner>>;
+
+// Something the parsers accepted but `parse_with()` may warn about,
+// or reject, with the position at which it was found.
+
+type Note<P> = Option<(P, WarningKind)>;
+
+// Runs a parser over all of `text`; unparsed text is an error.
+
+fn parse_all<'a, P>(parser: P, text: &'a str) -> Result<P::Output, Error>
+where
+    P: Parser<Input<'a>>,
+{
+    parser
+        .skip(eof())
+        .easy_parse(position::Stream::with_positioner(text, position::IndexPositioner::new()))
+        .map(|(result, _)| result)
+        .map_err(|e| Error::from_easy(text, e))
+}
+
+// Builds the error for a string which isn't one of `names`.
+
+fn not_one_of(text: &str, names: &[&str]) -> Error {
+    Error {
+        offset: 0,
+        unexpected: Some(format!("'{}'", text)),
+        expected: names.iter().map(|n| format!("'{}'", n)).collect(),
+        message: None,
+        suggestion: None,
+        remainder: None,
+    }
+}
+
+impl FromStr for Device {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_all(device::parser().map(|(device, _)| device), s)
+    }
+}
+
+// Device names also convert to and from strings, which is how they're
+// serialized.
+
+impl TryFrom<String> for Device {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Device> for String {
+    fn from(d: Device) -> Self {
+        d.name().into_owned()
+    }
+}
+
+// A property is written with its leading dot and an optional field,
+// e.g. ".SETTING" or ".STATUS.ON".
+
+impl FromStr for Property {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parser = prop_field::parse_property().then(|(prop, _)| {
+            optional(prop_field::parse_field(prop)).map(move |p| p.map_or(prop, |(p, _)| p))
+        });
+
+        parse_all(parser, s)
+    }
+}
+
+// Fields are written with their leading dot, e.g. ".RAW".
+
+macro_rules! field_from_str {
+    ($($t:ident => $prop:ident),*) => {
+        $(
+            impl FromStr for $t {
+                type Err = Error;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    match parse_all(prop_field::parse_field(Property::$prop($t::default())), s)?.0 {
+                        Property::$prop(field) => Ok(field),
+                        _ => unreachable!("parse_field() returned another property"),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+field_from_str!(ReadingField => Reading, SettingField => Setting, StatusField => Status,
+                AnalogField => Analog, DigitalField => Digital);
+
+impl FromStr for Range {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_all(range::parser(), s).map(|(range, _)| range)
+    }
+}
+
+impl FromStr for Event {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_all(event::parser(&clock::BUILTIN_EVENTS), s).map(|(event, _)| event)
+    }
+}
+
+impl FromStr for DataSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_all(source::parser(), s)
+    }
+}
+
+impl FromStr for StateOp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const OPS: [StateOp; 7] =
+            [StateOp::Eq, StateOp::NEq, StateOp::GT, StateOp::LT, StateOp::LEq, StateOp::GEq, StateOp::All];
+
+        OPS.iter()
+            .find(|op| op.canonical() == s)
+            .copied()
+            .ok_or_else(|| not_one_of(s, &OPS.map(|op| op.canonical())))
+    }
+}
+
+impl FromStr for ClockType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const TYPES: [ClockType; 3] = [ClockType::Hardware, ClockType::Software, ClockType::Either];
+
+        TYPES.iter()
+            .find(|ct| ct.canonical().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| not_one_of(s, &TYPES.map(|ct| ct.canonical())))
+    }
+}
+
+impl FromStr for Request {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_drf(s)
+    }
+}
+
+#[cfg(test)]
+mod arbitrary;
+mod builder;
+mod clock;
+mod device;
+mod error;
+mod event;
+mod explain;
+mod help;
+mod prop_field;
+mod range;
+mod source;
+mod warning;
+
+pub use builder::RequestBuilder;
+pub use clock::ClockEvents;
+pub use error::Error;
+pub use explain::{explain, explain_parts};
+pub use help::reference;
+pub use warning::{Warning, WarningKind};
+
+// Returns a parser for a DRF request. The request must make up all
+// of the input; any text following it is an error.
+
+pub fn parse<Input>() -> impl Parser<Input, Output = Request>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    parse_prefix().skip(eof())
+}
+
+// Returns a parser for a DRF request at the start of the input. It
+// stops at the end of the request and leaves the rest of the input
+// for the enclosing parser, for grammars which embed DRF requests.
+
+pub fn parse_prefix<Input>() -> impl Parser<Input, Output = Request>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    parse_noted(&clock::BUILTIN_EVENTS).map(|(request, _)| request)
+}
+
+// Skips whitespace, which pasted requests often contain, e.g.
+// "M:OUTTMP [0:3] @ p,1s". It's ignored inside ranges and events.
+
+fn ws<Input>() -> impl Parser<Input, Output = ()>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    skip_many(char::space()).silent()
+}
+
+// Skips whitespace between the parts of a request, but only if
+// another part follows it, so the whitespace after an embedded
+// request (see `parse_prefix()`) is left alone.
+
+fn gap<Input>() -> impl Parser<Input, Output = ()>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    optional(attempt(skip_many1(char::space()).skip(look_ahead(one_of(".[{@<".chars()))))).silent().map(|_| ())
+}
+
+// Parses a DRF request and notes where it used deprecated names,
+// clipped a rate or delay, or normalized a range.
+
+fn parse_noted<'a, Input>(
+    events: &'a ClockEvents,
+) -> impl Parser<Input, Output = (Request, Vec<(Input::Position, WarningKind)>)> + use<'a, Input>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    // A property the device's qualifier doesn't allow is an error,
+    // rather than being left for the field parser to reject.
+
+    device::parser().then(move |(device, qual_property)| {
+        optional(attempt(gap().with(prop_field::parse_property())))
+            .and_then(move |v| prop_field::reconcile(qual_property, v).map_err(StreamErrorFor::<Input>::message_format))
+            .map(move |v| (device.clone(), v))
+            .then(move |(dev, (use_prop, prop_note))| {
+                (gap().with(range::parser()),
+                 gap().with(optional(prop_field::parse_field(use_prop))),
+                 gap().with(event::parser(events)),
+                 gap().with(source::parser()))
+                    .map(move |((range, range_note), field, (event, event_note), source)| {
+                        let (property, field_note) = field.unwrap_or((use_prop, None));
+
+                        (Request {
+                            device: dev.clone(),
+                            property,
+                            range,
+                            event,
+                            source,
+                        },
+                         vec![prop_note.clone(), range_note, field_note, event_note].into_iter().flatten().collect())
+                    })
+            })
+    })
+}
+
+// Selects how forgiving `parse_with()` is. Lenient parsing (the
+// default) accepts deprecated property and field names, and rates or
+// delays which are out of range (they're clipped), but reports them
+// as warnings. Strict parsing rejects them, for pipelines that want
+// to keep their requests clean. Either way, ranges which are
+// normalized are reported as warnings. `clock_events` names the
+// clock events requests may use; without it, only the built-in names
+// are known.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParseOptions<'a> {
+    pub strict: bool,
+    pub clock_events: Option<&'a ClockEvents>,
+}
+
+impl ParseOptions<'_> {
+    pub fn strict() -> Self {
+        ParseOptions { strict: true, clock_events: None }
+    }
+}
+
+// The result of `parse_with()`: the request and the warnings found
+// while parsing it. `device_text` is the device exactly as it was
+// written, qualifier and case included (e.g. "m_outtmp"), for output
+// which should show what the user typed.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parsed {
+    pub request: Request,
+    pub warnings: Vec<Warning>,
+    pub device_text: String,
+}
+
+impl Parsed {
+    // Returns the qualifier the device was written with, e.g. '_' for
+    // "M_OUTTMP" or ':' for "DI:1234".
+
+    pub fn qualifier(&self) -> char {
+        let skip = if self.request.device.is_index() && !self.device_text.starts_with('0') { 2 } else { 1 };
+
+        self.device_text.chars().nth(skip).unwrap_or(':')
+    }
+}
+
+// Parses a DRF request with the given options. In strict mode, the
+// first warning strict parsing rejects is returned as the error.
+
+pub fn parse_with(drf: &str, options: &ParseOptions) -> Result<Parsed, Error> {
+    let events = options.clock_events.unwrap_or(&clock::BUILTIN_EVENTS);
+    let (request, notes) = parse_all(parse_noted(events), drf)?;
+    let mut warnings: Vec<Warning> = notes
+        .into_iter()
+        .map(|(pos, kind)| Warning { offset: error::byte_offset(drf, pos), kind })
+        .collect();
+
+    // Whitespace is reported once, where it first appears.
+
+    if let Some(offset) = drf.find(|c: char| c.is_whitespace()) {
+        warnings.push(Warning { offset, kind: WarningKind::Whitespace });
+        warnings.sort_by_key(|w| w.offset)
+    }
+
+    match warnings.iter().find(|w| w.kind.is_rejected_when_strict()) {
+        Some(w) if options.strict => Err(Error {
+            offset: w.offset,
+            unexpected: None,
+            expected: vec![],
+            message: Some(w.kind.to_string()),
+            suggestion: None,
+            remainder: None,
+        }),
+        _ => {
+            let rest = device::parser().parse(drf).map_or("", |(_, rest)| rest);
+            let device_text = String::from(&drf[..drf.len() - rest.len()]);
+
+            Ok(Parsed { request, warnings, device_text })
+        }
+    }
+}
+
+// Parses a DRF request. On failure, the error describes where, and
+// why, parsing stopped.
+
+pub fn parse_drf(drf: &str) -> Result<Request, Error> {
+    parse_all(parse(), drf)
+}
+
+// Describes why `drf` isn't a valid request, for error messages. When
+// a property or field name is misspelled, the closest valid names are
+// suggested.
+
+pub fn describe_error(drf: &str) -> String {
+    match parse_drf(drf) {
+        Ok(_) => format!("'{}' is a valid DRF request", drf),
+        Err(e) => describe(drf, &e),
+    }
+}
+
+// Describes an error returned when parsing `drf`.
+
+pub fn describe(drf: &str, e: &Error) -> String {
+    format!("bad DRF request '{}' ({})", drf, e)
+}
+
+// Returns `drf` with a caret under the character at byte `offset`,
+// followed by `message`, e.g.
+//
+//     M:OUTTMP.READNG
+//              ^ unknown field '.READNG'
+//
+// Control characters are shown escaped, so the caret stays in line.
+
+pub fn caret(drf: &str, offset: usize, message: &str) -> String {
+    let shown = |s: &str| s.chars().map(|c| c.escape_debug().to_string()).collect::<String>();
+    let column = shown(drf.get(..offset).unwrap_or(drf)).chars().count();
+
+    format!("{}\n{:column$}^ {}", shown(drf), "", message, column = column)
+}
+
+// Parses arbitrary bytes as a DRF request. Invalid UTF-8 sequences
+// are replaced (and, since DRF requests are ASCII, rejected by the
+// parser.) This never panics, whatever the input, which makes it the
+// entry point for fuzzing the parser, e.g. with cargo-fuzz:
+//
+//     fuzz_target!(|data: &[u8]| { let _ = drf::parse_bytes_lossy(data); });
+
+pub fn parse_bytes_lossy(bytes: &[u8]) -> Result<Request, Error> {
+    parse_drf(&String::from_utf8_lossy(bytes))
+}
+
+// Checks that a request survives a round trip through its canonical
+// form, i.e. that parsing `r.canonical()` returns `r`. This is the
+// invariant the rest of the program relies on when it stores or
+// compares canonical DRFs.
+
+pub fn verify(r: &Request) -> Result<(), String> {
+    let text = r.canonical();
+
+    match parse_drf(&text) {
+        Ok(parsed) if parsed == *r => Ok(()),
+        Ok(parsed) => Err(format!("'{}' parses as {:?}, not {:?}", text, parsed, r)),
+        Err(_) => Err(format!("'{}' doesn't parse", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_canonical_round_trip(r in arbitrary::request()) {
+            prop_assert_eq!(verify(&r), Ok(()));
+            prop_assert_eq!(r.to_string().parse::<Request>(), Ok(r.clone()));
+            prop_assert_eq!(r.device.to_string().parse::<Device>(), Ok(r.device.clone()));
+            prop_assert_eq!(r.property.to_string().parse::<Property>(), Ok(r.property));
+            prop_assert_eq!(r.range.to_string().parse::<Range>(), Ok(r.range.clone()));
+            prop_assert_eq!(r.event.to_string().parse::<Event>(), Ok(r.event.clone()));
+            prop_assert_eq!(r.source.to_string().parse::<DataSource>(), Ok(r.source));
+
+            let built = Request::builder()
+                .device(&r.device.name())
+                .property(r.property)
+                .range(r.range.clone())
+                .event(r.event.clone())
+                .source(r.source)
+                .build();
+
+            prop_assert_eq!(built, Ok(r.clone()));
+
+            let minimal = r.canonical_minimal();
+
+            prop_assert!(minimal.len() <= r.canonical().len());
+            prop_assert_eq!(parse_drf(&minimal), Ok(r.clone()));
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn test_serde_round_trip(r in arbitrary::request()) {
+            let json = serde_json::to_string(&r).unwrap();
+
+            prop_assert_eq!(serde_json::from_str::<Request>(&json).ok(), Some(r));
+        }
+
+        // Arbitrary input must never make the parser panic.
+
+        #[test]
+        fn test_arbitrary_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let _ = parse_bytes_lossy(&bytes);
+        }
+
+        // Strings built from DRF syntax, in any order, are more likely
+        // to reach deep into the parser than random bytes.
+
+        #[test]
+        fn test_arbitrary_text(text in "[A-Za-z0-9:.,@\\[\\]{}|_$&~?<>;=!*éı\u{0}-]{0,40}") {
+            if let Ok(r) = parse_drf(&text) {
+                prop_assert_eq!(verify(&r), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_drf_parsing() {
+        assert!(parse_drf("M:OUTTMP.ON[0]").is_err());
+        assert!(parse_drf("M|OUTTMP.ON[0]").is_err());
+        assert!(parse_drf("M:OUTTMPé").is_err());
+        assert!(parse_drf("M:OUTTMP.READıNG").is_err());
+        assert!(parse_bytes_lossy(b"M:OUT\xffTMP").is_err());
+        assert!(parse_bytes_lossy(b"M:OUTTMP@p,1s").is_ok());
+    }
+
+    #[test]
+    fn test_suggestions() {
+        assert_eq!(describe_error("M:OUTTMP.SCALLED"),
+                   "bad DRF request 'M:OUTTMP.SCALLED' (at offset 9: unknown field '.SCALLED'; did you mean .SCALED?)");
+        assert_eq!(describe_error("M:OUTTMP.REDING"),
+                   "bad DRF request 'M:OUTTMP.REDING' (at offset 9: unknown field '.REDING'; did you mean .READING?)");
+        assert_eq!(parse_drf("M|OUTTMP.STATUS.RAMPP").map_err(|e| e.suggestion), Err(Some(String::from(".RAMP"))));
+        assert_eq!(parse_drf("M:OUTTMP.ANALOG.MAXX").map_err(|e| e.suggestion), Err(Some(String::from(".MAX"))));
+        assert_eq!(parse_drf("M:OUTTMP.XYZZY").map_err(|e| (e.offset, e.suggestion)), Err((9, None)));
+        assert_eq!(prop_field::nearest("SET", ["SETTING", "SAT", "SIT"].iter().copied()),
+                   vec!["SAT", "SIT"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let e = parse_drf("M:OUTTMP.READING.SCALED@Z").unwrap_err();
+
+        assert_eq!(e.offset, 24);
+        assert_eq!(e.unexpected.as_deref(), Some("'Z'"));
+        assert_eq!(e.expected, ["'N'", "'I'", "'P'", "'Q'", "'E'", "'S'", "'A'"]);
+        assert_eq!(e.to_string(), "at offset 24: unexpected 'Z'; expected 'N', 'I', 'P', 'Q', 'E', 'S' or 'A'");
+        assert_eq!(caret("M:OUTTMP.READNG", 9, &parse_drf("M:OUTTMP.READNG").unwrap_err().reason()),
+                   "M:OUTTMP.READNG\n         ^ unknown field '.READNG'; did you mean .READING?");
+        assert_eq!(caret("M:\tX.Y", 6, "here"), "M:\\tX.Y\n       ^ here");
+
+        let e = parse_drf("M:OUTTMP junk").unwrap_err();
+
+        assert_eq!((e.offset, e.remainder.as_deref()), (8, Some(" junk")));
+        assert_eq!(e.to_string(), "at offset 8: unparsed text ' junk'");
+        assert!(parse().parse("M:OUTTMP junk").is_err());
+        assert_eq!(parse_prefix().parse("M:OUTTMP junk").map(|(r, rest)| (r.to_string(), rest)),
+                   Ok((String::from("M:OUTTMP.READING.SCALED"), " junk")));
+        assert_eq!(parse_drf("").unwrap_err().to_string(), "at offset 0: unexpected end of input; expected device name");
+
+        // A range whose start is past its end is reported as reversed,
+        // at the start of the range.
+
+        assert_eq!(parse_drf("M:OUTTMP[2:1]").unwrap_err().to_string(),
+                   "at offset 9: range [2:1] is reversed; did you mean [1:2]?");
+
+        // Only the default qualifier, ':', lets a request name another
+        // property.
+
+        assert_eq!(parse_drf("M:OUTTMP.SETTING").map(|r| r.property), Ok(Property::Setting(SettingField::Scaled)));
+        assert_eq!(parse_drf("M_OUTTMP.SET.RAW").map(|r| r.property), Ok(Property::Setting(SettingField::Raw)));
+        assert_eq!(parse_drf("M_OUTTMP.READING").unwrap_err().to_string(),
+                   "at offset 8: '.READING' doesn't match the device's qualifier, which selects '.SETTING'");
+        assert!(parse_drf("M?OUTTMP.SETTING").is_err());
+        assert!(parse_drf("0|12.SETTING").is_err());
+
+        // Offsets are in bytes.
+
+        assert_eq!(parse_drf("é:OUTTMP").map_err(|e| e.offset), Err(0));
+        assert_eq!(parse_drf("M:OUTTMP.é").map_err(|e| e.offset), Err(9));
+        assert_eq!(parse_drf("M:OUTTMP@P,1,é").map_err(|e| e.offset), Err(13));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let lenient = ParseOptions::default();
+        let parsed = parse_with("M:OUTTMP.PRREAD.VOLTS@P,2000K", &lenient).unwrap();
+
+        assert_eq!(parsed.request.to_string(), "M:OUTTMP.READING.PRIMARY@P,1U,TRUE");
+        assert_eq!(parsed.warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(), [
+            "at offset 9: '.PRREAD' is deprecated; use '.READING'",
+            "at offset 16: '.VOLTS' is deprecated; use '.PRIMARY'",
+            "at offset 24: '2000K' is out of range; clipped to 1U",
+        ]);
+        assert_eq!(parse_with("M:OUTTMP@P,1S", &lenient).map(|p| p.warnings), Ok(vec![]));
+
+        let e = parse_with("M:OUTTMP.PRREAD.VOLTS", &ParseOptions::strict()).unwrap_err();
+
+        assert_eq!(e.to_string(), "at offset 9: '.PRREAD' is deprecated; use '.READING'");
+        assert_eq!(describe("M_OUTTMP@E,2,H,9999999S", &parse_with("M_OUTTMP@E,2,H,9999999S", &ParseOptions::strict()).unwrap_err()),
+                   "bad DRF request 'M_OUTTMP@E,2,H,9999999S' (at offset 15: '9999999S' is out of range; clipped to 4294967295U)");
+        assert!(parse_with("M:OUTTMP.READING", &ParseOptions::strict()).is_ok());
+
+        // Normalized ranges are reported, even when parsing strictly.
+
+        let parsed = parse_with("M:OUTTMP[0:]@P,0H", &lenient).unwrap();
+
+        assert_eq!(parsed.warnings, [
+            Warning { offset: 8, kind: WarningKind::Normalized { text: String::from("[0:]") } },
+            Warning { offset: 15, kind: WarningKind::Clipped { text: String::from("0H"), value: u32::MAX } },
+        ]);
+        assert_eq!(parse_with("M:OUTTMP[:]", &ParseOptions::strict()).map(|p| p.warnings.len()), Ok(1));
+        assert!(parse_with("M:OUTTMP junk", &lenient).is_err());
+
+        // A reversed range isn't taken as the range it likely meant,
+        // even leniently, but the error says which that is.
+
+        for options in [lenient, ParseOptions::strict()] {
+            let e = parse_with("M:OUTTMP[2:1]@P,1S", &options).unwrap_err();
+
+            assert_eq!(e.offset, 9);
+            assert_eq!(e.message.as_deref(), Some("range [2:1] is reversed"));
+            assert_eq!(e.suggestion.as_deref(), Some("[1:2]"));
+        }
+
+        // Clock events may be named by the options' table, and written
+        // back by name.
+
+        let events = ClockEvents::parse("MI_RESET = 29").unwrap();
+        let named = ParseOptions { strict: false, clock_events: Some(&events) };
+        let parsed = parse_with("M:OUTTMP@E,MI_RESET", &named).unwrap();
+
+        assert_eq!(parsed.request.to_string(), "M:OUTTMP.READING.SCALED@E,29,E,0");
+        assert_eq!(parsed.request.symbolic(&events), "M:OUTTMP.READING.SCALED@E,MI_RESET,E,0");
+        assert_eq!(parse_drf("M:OUTTMP@E,0").unwrap().symbolic(&events), "M:OUTTMP.READING.SCALED@E,SUPERCYCLE,E,0");
+        assert_eq!(parse_drf("M:OUTTMP@E,2").unwrap().symbolic(&events), "M:OUTTMP.READING.SCALED@E,2,E,0");
+        assert!(parse_drf("M:OUTTMP@E,MI_RESET").is_err());
+    }
+
+    #[test]
+    fn test_minimal_forms() {
+        let data = &[
+            ("M:OUTTMP.READING.SCALED", "M:OUTTMP"),
+            ("M:OUTTMP.READING.RAW@P,1S,TRUE", "M:OUTTMP.RAW@P"),
+            ("M:OUTTMP.STATUS", "M|OUTTMP"),
+            ("M:OUTTMP.STATUS.ON@Q,500,FALSE", "M|OUTTMP.ON@Q,500,FALSE"),
+            ("M:OUTTMP.SETTING[0:3]@e,8f", "M_OUTTMP[0:3]@E,8F"),
+            ("M:OUTTMP@E,2,H,0", "M:OUTTMP@E,2,H"),
+            ("M:OUTTMP@E,2,E,100", "M:OUTTMP@E,2,E,100"),
+            ("M:OUTTMP.DESCRIPTION@I", "M~OUTTMP@I"),
+            ("M:OUTTMP.INDEX", "M:OUTTMP.INDEX"),
+            ("0:1234.DIGITAL.NOM<-FTP", "0$1234.NOM<-FTP"),
+            ("M:OUTTMP[]@P,2S", "M:OUTTMP[]@P,2S"),
+        ];
+
+        for &(drf, minimal) in data {
+            assert_eq!(parse_drf(drf).unwrap().canonical_minimal(), minimal, "{}", drf)
+        }
+    }
+
+    #[test]
+    fn test_acquisition() {
+        let event = |drf: &str| parse_drf(drf).unwrap().event;
+
+        for drf in &["M:OUTTMP", "M:OUTTMP@I"] {
+            assert!(event(drf).is_one_shot() && !event(drf).is_streaming(), "{}", drf);
+        }
+        for drf in &["M:OUTTMP@P,1S", "M:OUTTMP@Q,1S,FALSE", "M:OUTTMP@E,8F", "M:OUTTMP@S,V:STATE,5,0,=", "M:OUTTMP@A,0,1S"] {
+            assert!(event(drf).is_streaming() && !event(drf).is_one_shot(), "{}", drf);
+        }
+        assert!(!event("M:OUTTMP@N").is_one_shot() && !event("M:OUTTMP@N").is_streaming());
+
+        // Filtering duplicates and the immediate flag survive a round
+        // trip through every form of the request.
+
+        let req = parse_drf("M:OUTTMP@q,500,f").unwrap();
+
+        for text in &[req.canonical(), req.canonical_minimal()] {
+            assert_eq!(parse_drf(text).unwrap().event, Event::Periodic { period: 500_000, immediate: false, skip_dups: true });
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let same = &[
+            ("M:OUTTMP", "M:OUTTMP.READING.SCALED"),
+            ("M:OUTTMP", "m?outtmp[0:0]"),
+            ("M:OUTTMP[]", "M:OUTTMP{0:}"),
+            ("M:OUTTMP[2;3]", "M:OUTTMP[2:4]"),
+            ("M|OUTTMP@p,1000", "M:OUTTMP.STATUS.ALL@P,1S,TRUE"),
+            ("M:OUTTMP@S,v:state,5,0,=", "M:OUTTMP@S,V:STATE,5,0,="),
+        ];
+        let different = &[
+            ("M:OUTTMP", "M:OUTTMP.RAW"),
+            ("M:OUTTMP", "M:OUTTMP[1]"),
+            ("M:OUTTMP[-1]", "M:OUTTMP[]"),
+            ("M:OUTTMP@p,1000", "M:OUTTMP@q,1000"),
+            ("M:OUTTMP@S,1234,5,0,=", "M:OUTTMP@S,V:STATE,5,0,="),
+        ];
+
+        for &(a, b) in same {
+            assert!(parse_drf(a).unwrap().matches(&parse_drf(b).unwrap()), "{} {}", a, b)
+        }
+        for &(a, b) in different {
+            assert!(!parse_drf(a).unwrap().matches(&parse_drf(b).unwrap()), "{} {}", a, b)
+        }
+
+        let mut counted = parse_drf("M:OUTTMP[2:4]").unwrap();
+
+        counted.range = Range::Count { start_index: 2, count: 3 };
+        assert!(counted.matches(&parse_drf("m:outtmp[2:4]").unwrap()));
+    }
+
+    #[test]
+    fn test_device_text() {
+        let parsed = |drf| parse_with(drf, &ParseOptions::default()).unwrap();
+        let p = parsed("m_outTmp.RAW@I");
+
+        assert_eq!(p.request.device, Device::Name(String::from("m:outTmp")));
+        assert_eq!(p.request.device.normalized(), "M:OUTTMP");
+        assert!(!p.request.device.is_index());
+        assert_eq!((p.device_text.as_str(), p.qualifier()), ("m_outTmp", '_'));
+
+        let p = parsed("di|1234");
+
+        assert_eq!(p.request.device.normalized(), "0:1234");
+        assert!(p.request.device.is_index());
+        assert_eq!((p.device_text.as_str(), p.qualifier()), ("di|1234", '|'));
+        assert_eq!(parsed("0~12").qualifier(), '~');
+        assert_eq!(parsed("M:OUTTMP [0:3]").device_text, "M:OUTTMP");
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let data = &[
+            ("M:OUTTMP [0:3] @ p,1s", "M:OUTTMP.READING[0:3].SCALED@P,1S,TRUE"),
+            ("M:OUTTMP .SETTING [ 0 : 3 ] .RAW @p , 1s , false", "M:OUTTMP.SETTING[0:3].RAW@P,1S,FALSE"),
+            ("M:OUTTMP{ 2 }@E, 8f, h", "M:OUTTMP.READING{2}.SCALED@E,8F,H,0"),
+            ("M:OUTTMP @s, V:STATE, 5, 100, =  <-FTP", "M:OUTTMP.READING.SCALED@S,V:STATE,5,100,=<-FTP"),
+        ];
+
+        for &(drf, result) in data {
+            assert_eq!(parse_drf(drf).map(|r| r.to_string()), Ok(String::from(result)), "{}", drf)
+        }
+
+        // Whitespace is a warning, so strict parsing rejects it.
+
+        let parsed = parse_with("M:OUTTMP  [0:3]@P,2000K", &ParseOptions::default()).unwrap();
+
+        assert_eq!(parsed.warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(), [
+            "at offset 8: whitespace in a request is ignored",
+            "at offset 18: '2000K' is out of range; clipped to 1U",
+        ]);
+        assert_eq!(parse_with("M:OUTTMP @p,1s", &ParseOptions::strict()).unwrap_err().to_string(),
+                   "at offset 8: whitespace in a request is ignored");
+
+        // Whitespace that isn't followed by more of the request ends it.
+
+        assert!(parse_drf("M:OUTTMP ").is_err());
+        assert!(parse_drf("M:OUTTMP@p,1s ").is_err());
+        assert_eq!(parse_prefix().parse("M:OUTTMP @p,1s next").map(|(r, rest)| (r.to_string(), rest)),
+                   Ok((String::from("M:OUTTMP.READING.SCALED@P,1S,TRUE"), " next")));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let r = parse_drf("M|OUTTMP[2:4].ON@P,1S").unwrap();
+        let json = serde_json::to_string(&r).unwrap();
+
+        assert_eq!(json, concat!(r#"{"device":"M:OUTTMP","property":{"Status":"On"},"#,
+                                 r#""range":{"Array":{"start_index":2,"end_index":4}},"#,
+                                 r#""event":{"Periodic":{"period":1000000,"immediate":true,"skip_dups":false}},"#,
+                                 r#""source":"Default"}"#));
+        assert_eq!(serde_json::from_str::<Request>(&json).ok(), Some(r));
+        assert_eq!(serde_json::from_str::<Request>(r#"{"device": "M_OUTTMP"}"#).ok(), parse_drf("M_OUTTMP").ok());
+        assert!(serde_json::from_str::<Request>(r#"{"device": "M:OUTTMP", "range": {"Array": {"start_index": 5, "end_index": 1}}}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"device": "M_OUTTMP", "property": "Control"}"#).is_err());
+        assert!(serde_json::from_str::<Device>(r#""OUTTMP""#).is_err());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("M|OUTTMP".parse::<Device>().map(|d| d.to_string()), Ok(String::from("M:OUTTMP")));
+        assert_eq!(".sts.on".parse::<Property>(), Ok(Property::Status(StatusField::On)));
+        assert_eq!(".CONTROL".parse::<Property>(), Ok(Property::Control));
+        assert_eq!(".VOLTS".parse::<ReadingField>(), Ok(ReadingField::Primary));
+        assert_eq!(".ALARM_FTD".parse::<AnalogField>().map(|f| f.to_string()), Ok(String::from(".ALARM_FTD")));
+        assert_eq!("h".parse::<ClockType>(), Ok(ClockType::Hardware));
+        assert_eq!("<=".parse::<StateOp>(), Ok(StateOp::LEq));
+        assert_eq!(Property::Setting(SettingField::Raw).to_string(), ".SETTING.RAW");
+        assert!(".ON".parse::<ReadingField>().is_err());
+        assert!("[1:2]x".parse::<Range>().is_err());
+        assert!("@P,1s".parse::<Event>().is_ok());
+        assert!("M:OUTTMP.READING.SCALED junk".parse::<Request>().is_err());
+    }
+
+    #[test]
+    fn test_event_canonical_forms() {
+        let data = &[
+            ("@N", "@N"),
+            ("@n", "@N"),
+            ("@I", "@I"),
+            ("@i", "@I"),
+            ("@P,1s", "@P,1S,TRUE"),
+            ("@P,1s,f", "@P,1S,FALSE"),
+            ("@P,2h,false", "@P,500,FALSE"),
+            ("@P,10u,false", "@P,10U,FALSE"),
+            ("@P,20m", "@P,20,TRUE"),
+            ("@P,30", "@P,30,TRUE"),
+            ("@P,10k", "@P,100U,TRUE"),
+            ("@E,008f,h,10h", "@E,8F,H,100"),
+            ("@E,0", "@E,0,E,0"),
+            ("@S,1234,0,1s,=", "@S,1234,0,1S,="),
+            ("@S,0:1234,0,1s,=", "@S,1234,0,1S,="),
+            ("@s,v_state,5,100,=", "@S,v:state,5,100,="),
+            ("@a,1700000000,2000", "@A,1700000000,2S"),
+            ("@A,0,5h", "@A,0,200"),
+        ];
+
+        for &(event, result) in data {
+            assert_eq!(event::parser(&clock::BUILTIN_EVENTS).parse(event).unwrap().0.0.canonical(), result)
+        }
+    }
+
+    #[test]
+    fn test_range_canonical_forms() {
+        let data = &[
+            ("[]", "[]"),
+            ("{}", "[]"),
+            ("[:]", "[]"),
+            ("{:}", "[]"),
+            ("[0:]", "[]"),
+            ("{0:}", "[]"),
+            ("[1:2]", "[1:2]"),
+            ("[0:0]", ""),
+            ("[1:1]", "[1]"),
+            ("{1:1}", "{1}"),
+            ("{1:2}", "{1:2}"),
+            ("[-1:-1]", "[-1]"),
+            ("[:-1]", "[0:-1]"),
+            ("[-3:]", "[-3:]"),
+        ];
+
+        for &(range, result) in data {
+            assert_eq!(range::parser().parse(range).unwrap().0.0.canonical(), result)
+        }
+    }
+
+    #[test]
+    fn test_range_slicing() {
+        let data = [0, 1, 2, 3, 4, 5];
+        let slice = |r: &str| range::parser().parse(r).unwrap().0.0.slice(&data).to_vec();
+
+        assert_eq!(slice("[]"), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(slice(""), vec![0]);
+        assert_eq!(slice("[2]"), vec![2]);
+        assert_eq!(slice("[1:3]"), vec![1, 2, 3]);
+        assert_eq!(slice("[4:]"), vec![4, 5]);
+        assert_eq!(slice("[4:10]"), vec![4, 5]);
+        assert_eq!(slice("[10]"), Vec::<i32>::new());
+        assert_eq!(slice("{2:3}"), vec![2, 3, 4]);
+
+        // Relative ranges are resolved against the data's length.
+
+        assert_eq!(slice("[-1]"), vec![5]);
+        assert_eq!(slice("[2:-2]"), vec![2, 3, 4]);
+        assert_eq!(slice("[-2:]"), vec![4, 5]);
+        assert_eq!(slice("[-10:1]"), vec![0, 1]);
+        assert_eq!(slice("[-7]"), Vec::<i32>::new());
+        assert_eq!(slice("[4:-3]"), Vec::<i32>::new());
+
+        let relative = Range::Relative { start: -2, end: None };
+
+        assert_eq!(relative.resolve(6), Some(Range::Array { start_index: 4, end_index: Some(5) }));
+        assert_eq!(relative.resolve(0), None);
+        assert_eq!(Range::Full.resolve(6), Some(Range::Full));
+    }
+
+    #[test]
+    fn test_request_canonical_forms() {
+        let data = &[
+            ("M:OUTTMP", "M:OUTTMP.READING.SCALED"),
+            ("M:OUTTMP[0:3]", "M:OUTTMP.READING[0:3].SCALED"),
+            ("M|OUTTMP[]", "M:OUTTMP.STATUS[].ALL"),
+            ("M|OUTTMP[]@e,02", "M:OUTTMP.STATUS[].ALL@E,2,E,0"),
+            ("M|OUTTMP.STATUS[]@e,02", "M:OUTTMP.STATUS[].ALL@E,2,E,0"),
+            ("M|OUTTMP.On@e,02", "M:OUTTMP.STATUS.ON@E,2,E,0"),
+            ("0:0123456", "0:123456.READING.SCALED"),
+            ("di|123456.ON", "0:123456.STATUS.ON"),
+            ("M:OUTTMP@p,100<-ftp", "M:OUTTMP.READING.SCALED@P,100,TRUE<-FTP"),
+            ("M:OUTTMP[]<-Snp", "M:OUTTMP.READING[].SCALED<-SNP"),
+        ];
+
+        for &(drf, result) in data {
+            assert_eq!(parse_drf(drf).unwrap().canonical(), result,
+                       "\n input: {}", drf)
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use super::{AnalogField, DigitalField, Property, ReadingField, SettingField, StatusField};
+use super::error::SUGGEST;
+use super::{AnalogField, DigitalField, Note, Property, ReadingField, SettingField, StatusField, WarningKind};
 use combine::error::{ParseError, StreamError};
 use combine::parser::{char, choice, repeat};
 use combine::stream::{Stream, StreamErrorFor};
-use combine::{Parser, EasyParser};
+use combine::{position, satisfy, Parser};
 
 // This generic function provides a Key->Value lookup from a &str to a
 // type of the caller's choice. If the keys isn't found, `None` is
@@ -21,126 +22,238 @@ fn lookup<PropField: Clone>(
     None
 }
 
+// Returns the number of single-character edits needed to turn `a`
+// into `b` (the Levenshtein distance.)
+
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(prev + usize::from(ca != *cb));
+
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// Returns the names in `names` closest to `name`, for "did you mean"
+// hints. Only names within a few edits (depending on the length of
+// `name`) are considered, and only the closest ones are returned.
+
+pub fn nearest<'a>(name: &str, names: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let limit = (name.len() / 3).max(1);
+    let mut found: Vec<(usize, &str)> = names
+        .map(|n| (distance(name, n), n))
+        .filter(|(d, _)| *d <= limit)
+        .collect();
+
+    found.sort();
+    found.dedup();
+
+    let best = found.first().map(|(d, _)| *d);
+
+    found.into_iter().take_while(|(d, _)| Some(*d) == best).map(|(_, n)| n).collect()
+}
+
+// Builds the error message for an unknown property or field name,
+// suggesting the closest valid names.
+
+fn unknown(what: &str, name: &str, names: Vec<&str>) -> String {
+    match nearest(name, names.into_iter()).as_slice() {
+        [] => format!("unknown {} '.{}'", what, name),
+        names => format!("unknown {} '.{}'{}.{}?", what, name, SUGGEST, names.join(" or .")),
+    }
+}
+
+// Names which are still accepted, for old scripts, but which have
+// been replaced by the names the canonical form uses. Strict parsing
+// rejects them.
+
+pub const DEPRECATED: [&str; 13] = [
+    "COMMON", "LNGNAM", "LSTNAM", "PRALNM", "PRANAB", "PRBCTL", "PRBSTS",
+    "PRDABL", "PRDESC", "PRLNAM", "PRREAD", "PRSET", "VOLTS",
+];
+
+// Returns the note for a name which is deprecated in favor of
+// `canonical`.
+
+fn deprecated<P>(pos: P, name: &str, canonical: &'static str) -> Note<P> {
+    if DEPRECATED.contains(&name) {
+        Some((pos, WarningKind::Deprecated { name: String::from(name), replacement: canonical }))
+    } else {
+        None
+    }
+}
+
+// The names of each property's fields. The tables are public so the
+// DRF reference (see `help.rs`) lists exactly what the parser accepts.
+
+pub const READING_FIELDS: [(&str, ReadingField); 5] = [
+    ("COMMON", ReadingField::Scaled),
+    ("PRIMARY", ReadingField::Primary),
+    ("RAW", ReadingField::Raw),
+    ("SCALED", ReadingField::Scaled),
+    ("VOLTS", ReadingField::Primary),
+];
+
+pub const SETTING_FIELDS: [(&str, SettingField); 5] = [
+    ("COMMON", SettingField::Scaled),
+    ("PRIMARY", SettingField::Primary),
+    ("RAW", SettingField::Raw),
+    ("SCALED", SettingField::Scaled),
+    ("VOLTS", SettingField::Primary),
+];
+
+pub const STATUS_FIELDS: [(&str, StatusField); 9] = [
+    ("ALL", StatusField::All),
+    ("EXTENDED_TEXT", StatusField::ExtText),
+    ("ON", StatusField::On),
+    ("POSITIVE", StatusField::Positive),
+    ("RAMP", StatusField::Ramp),
+    ("RAW", StatusField::Raw),
+    ("READY", StatusField::Ready),
+    ("REMOTE", StatusField::Remote),
+    ("TEXT", StatusField::Text),
+];
+
+pub const ANALOG_FIELDS: [(&str, AnalogField); 30] = [
+    ("ABORT", AnalogField::Abort),
+    ("ABORT_INHIBIT", AnalogField::AbortInhibit),
+    ("ALARM_ENABLE", AnalogField::Enable),
+    ("ALARM_FTD", AnalogField::FTD),
+    ("ALARM_STATUS", AnalogField::Status),
+    ("ALL", AnalogField::All),
+    ("ENABLE", AnalogField::Enable),
+    ("FLAGS", AnalogField::Flags),
+    ("FTD", AnalogField::FTD),
+    ("MAX", AnalogField::Max),
+    ("MAXIMUM", AnalogField::Max),
+    ("MIN" , AnalogField::Min),
+    ("MINIMUM", AnalogField::Min),
+    ("NOM", AnalogField::Nom),
+    ("NOMINAL", AnalogField::Nom),
+    ("RAW", AnalogField::Raw),
+    ("RAW_MAX", AnalogField::RawMax),
+    ("RAWMAX", AnalogField::RawMax),
+    ("RAW_MIN", AnalogField::RawMin),
+    ("RAWMIN", AnalogField::RawMin),
+    ("RAW_NOM", AnalogField::RawNom),
+    ("RAWNOM", AnalogField::RawNom),
+    ("RAW_TOL", AnalogField::RawTol),
+    ("RAWTOL", AnalogField::RawTol),
+    ("STATUS", AnalogField::Status),
+    ("TEXT", AnalogField::Text),
+    ("TOL", AnalogField::Tol),
+    ("TOLERANCE", AnalogField::Tol),
+    ("TRIES_NEEDED", AnalogField::TriesNeeded),
+    ("TRIES_NOW", AnalogField::TriesNow),
+];
+
+pub const DIGITAL_FIELDS: [(&str, DigitalField); 17] = [
+    ("ABORT", DigitalField::Abort),
+    ("ABORT_INHIBIT", DigitalField::AbortInhibit),
+    ("ALARM_ENABLE", DigitalField::Enable),
+    ("ALARM_FTD", DigitalField::FTD),
+    ("ALARM_STATUS", DigitalField::Status),
+    ("ALL", DigitalField::All),
+    ("ENABLE", DigitalField::Enable),
+    ("FLAGS", DigitalField::Flags),
+    ("FTD", DigitalField::FTD),
+    ("NOM", DigitalField::Nom),
+    ("NOMINAL", DigitalField::Nom),
+    ("MASK", DigitalField::Mask),
+    ("RAW", DigitalField::Raw),
+    ("STATUS", DigitalField::Status),
+    ("TEXT", DigitalField::Text),
+    ("TRIES_NEEDED", DigitalField::TriesNeeded),
+    ("TRIES_NOW", DigitalField::TriesNow),
+];
+
+// The names of the properties, each mapped to the property with its
+// default field.
+
+pub const PROPERTIES: [(&str, Property); 32] = [
+    ("AA", Property::Analog(AnalogField::default())),
+    ("ALARM_LIST_NAME", Property::AlarmList),
+    ("ANALOG_ALARM", Property::Analog(AnalogField::default())),
+    ("ANALOG", Property::Analog(AnalogField::default())),
+    ("BASIC_CONTROL", Property::Control),
+    ("BASIC_STATUS", Property::Status(StatusField::default())),
+    ("CONTROL", Property::Control),
+    ("CTRL", Property::Control),
+    ("DA", Property::Digital(DigitalField::default())),
+    ("DESC", Property::Description),
+    ("DESCRIPTION", Property::Description),
+    ("DIGITAL_ALARM", Property::Digital(DigitalField::default())),
+    ("DIGITAL", Property::Digital(DigitalField::default())),
+    ("INDEX", Property::Index),
+    ("LNGNAM", Property::LongName),
+    ("LONG_NAME", Property::LongName),
+    ("LSTNAM", Property::AlarmList),
+    ("PRALNM", Property::AlarmList),
+    ("PRANAB", Property::Analog(AnalogField::default())),
+    ("PRBCTL", Property::Control),
+    ("PRBSTS", Property::Status(StatusField::default())),
+    ("PRDABL", Property::Digital(DigitalField::default())),
+    ("PRDESC", Property::Description),
+    ("PRLNAM", Property::LongName),
+    ("PRREAD", Property::Reading(ReadingField::default())),
+    ("PRSET", Property::Setting(SettingField::default())),
+    ("READ", Property::Reading(ReadingField::default())),
+    ("READING", Property::Reading(ReadingField::default())),
+    ("SET", Property::Setting(SettingField::default())),
+    ("SETTING", Property::Setting(SettingField::default())),
+    ("STATUS", Property::Status(StatusField::default())),
+    ("STS", Property::Status(StatusField::default())),
+];
+
 // This function returns a parser for the DRF ".FIELD" portion of the
 // request. The function takes a parameter, `use_prop`, to determine
 // which field names are valid. It returns the property with the field
-// value adjusted to the parsed value.
+// value adjusted to the parsed value, and a note if the field name is
+// deprecated.
 
-pub fn parse_field<Input>(use_prop: Property) -> impl Parser<Input, Output = Property>
+pub fn parse_field<Input>(use_prop: Property) -> impl Parser<Input, Output = (Property, Note<Input::Position>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     char::char('.').with(
-        repeat::many1(choice::or(char::letter(), char::char('_')))
-            .and_then(move |v: String| {
-                let v = v.to_uppercase();
+        (position(), repeat::many1(choice::or(satisfy(|c: char| c.is_ascii_alphabetic()), char::char('_'))))
+            .and_then(move |(pos, v): (_, String)| {
+                let v = v.to_ascii_uppercase();
 
                 match use_prop {
                     Property::Reading(_) => {
-                        const FIELDS: [(&str, ReadingField); 5] = [
-                            ("COMMON", ReadingField::Scaled),
-                            ("PRIMARY", ReadingField::Primary),
-                            ("RAW", ReadingField::Raw),
-                            ("SCALED", ReadingField::Scaled),
-                            ("VOLTS", ReadingField::Primary),
-                        ];
-
-                        if let Some(v) = lookup(&v, &FIELDS) {
-                            return Ok(Property::Reading(v))
+                        if let Some(f) = lookup(&v, &READING_FIELDS) {
+                            return Ok((Property::Reading(f), deprecated(pos, &v, f.canonical())))
                         }
                     }
                     Property::Setting(_) => {
-                        const FIELDS: [(&str, SettingField); 5] = [
-                            ("COMMON", SettingField::Scaled),
-                            ("PRIMARY", SettingField::Primary),
-                            ("RAW", SettingField::Raw),
-                            ("SCALED", SettingField::Scaled),
-                            ("VOLTS", SettingField::Primary),
-                        ];
-
-                        if let Some(v) = lookup(&v, &FIELDS) {
-                            return Ok(Property::Setting(v))
+                        if let Some(f) = lookup(&v, &SETTING_FIELDS) {
+                            return Ok((Property::Setting(f), deprecated(pos, &v, f.canonical())))
                         }
                     }
                     Property::Status(_) => {
-                        const FIELDS: [(&str, StatusField); 9] = [
-                            ("ALL", StatusField::All),
-                            ("EXTENDED_TEXT", StatusField::ExtText),
-                            ("ON", StatusField::On),
-                            ("POSITIVE", StatusField::Positive),
-                            ("RAMP", StatusField::Ramp),
-                            ("RAW", StatusField::Raw),
-                            ("READY", StatusField::Ready),
-                            ("REMOTE", StatusField::Remote),
-                            ("TEXT", StatusField::Text),
-                        ];
-
-                        if let Some(v) = lookup(&v, &FIELDS) {
-                            return Ok(Property::Status(v))
+                        if let Some(f) = lookup(&v, &STATUS_FIELDS) {
+                            return Ok((Property::Status(f), deprecated(pos, &v, f.canonical())))
                         }
                     }
                     Property::Analog(_) => {
-                        const FIELDS: [(&str, AnalogField); 30] = [
-                            ("ABORT", AnalogField::Abort),
-                            ("ABORT_INHIBIT", AnalogField::AbortInhibit),
-                            ("ALARM_ENABLE", AnalogField::Enable),
-                            ("ALARM_FTD", AnalogField::FTD),
-                            ("ALARM_STATUS", AnalogField::Status),
-                            ("ALL", AnalogField::All),
-                            ("ENABLE", AnalogField::Enable),
-                            ("FLAGS", AnalogField::Flags),
-                            ("FTD", AnalogField::FTD),
-                            ("MAX", AnalogField::Max),
-                            ("MAXIMUM", AnalogField::Max),
-                            ("MIN" , AnalogField::Min),
-                            ("MINIMUM", AnalogField::Min),
-                            ("NOM", AnalogField::Nom),
-                            ("NOMINAL", AnalogField::Nom),
-                            ("RAW", AnalogField::Raw),
-                            ("RAW_MAX", AnalogField::RawMax),
-                            ("RAWMAX", AnalogField::RawMax),
-                            ("RAW_MIN", AnalogField::RawMin),
-                            ("RAWMIN", AnalogField::RawMin),
-                            ("RAW_NOM", AnalogField::RawNom),
-                            ("RAWNOM", AnalogField::RawNom),
-                            ("RAW_TOL", AnalogField::RawTol),
-                            ("RAWTOL", AnalogField::RawTol),
-                            ("STATUS", AnalogField::Status),
-                            ("TEXT", AnalogField::Text),
-                            ("TOL", AnalogField::Tol),
-                            ("TOLERANCE", AnalogField::Tol),
-                            ("TRIES_NEEDED", AnalogField::TriesNeeded),
-                            ("TRIES_NOW", AnalogField::TriesNow),
-                        ];
-
-                        if let Some(v) = lookup(&v, &FIELDS) {
-                            return Ok(Property::Analog(v))
+                        if let Some(f) = lookup(&v, &ANALOG_FIELDS) {
+                            return Ok((Property::Analog(f), deprecated(pos, &v, f.canonical())))
                         }
                     }
                     Property::Digital(_) => {
-                        const FIELDS: [(&str, DigitalField); 17] = [
-                            ("ABORT", DigitalField::Abort),
-                            ("ABORT_INHIBIT", DigitalField::AbortInhibit),
-                            ("ALARM_ENABLE", DigitalField::Enable),
-                            ("ALARM_FTD", DigitalField::FTD),
-                            ("ALARM_STATUS", DigitalField::Status),
-                            ("ALL", DigitalField::All),
-                            ("ENABLE", DigitalField::Enable),
-                            ("FLAGS", DigitalField::Flags),
-                            ("FTD", DigitalField::FTD),
-                            ("NOM", DigitalField::Nom),
-                            ("NOMINAL", DigitalField::Nom),
-                            ("MASK", DigitalField::Mask),
-                            ("RAW", DigitalField::Raw),
-                            ("STATUS", DigitalField::Status),
-                            ("TEXT", DigitalField::Text),
-                            ("TRIES_NEEDED", DigitalField::TriesNeeded),
-                            ("TRIES_NOW", DigitalField::TriesNow),
-                        ];
-
-                        if let Some(v) = lookup(&v, &FIELDS) {
-                            return Ok(Property::Digital(v))
+                        if let Some(f) = lookup(&v, &DIGITAL_FIELDS) {
+                            return Ok((Property::Digital(f), deprecated(pos, &v, f.canonical())))
                         }
                     }
                     Property::Control | Property::Description |
@@ -148,79 +261,88 @@ where
                     Property::AlarmList =>
                         return Err(StreamErrorFor::<Input>::message("property has no fields"))
                 }
-                Err(StreamErrorFor::<Input>::message("invalid field"))
+
+                // The name may also be a misspelled property, when the
+                // request didn't name one.
+
+                let names = match use_prop {
+                    Property::Reading(_) => READING_FIELDS.iter().map(|f| f.0).collect(),
+                    Property::Setting(_) => SETTING_FIELDS.iter().map(|f| f.0).collect(),
+                    Property::Status(_) => STATUS_FIELDS.iter().map(|f| f.0).collect(),
+                    Property::Analog(_) => ANALOG_FIELDS.iter().map(|f| f.0).collect(),
+                    Property::Digital(_) => DIGITAL_FIELDS.iter().map(|f| f.0).collect(),
+                    _ => vec![],
+                };
+                let names = names.into_iter().chain(PROPERTIES.iter().map(|p| p.0)).collect();
+
+                Err(StreamErrorFor::<Input>::message_format(unknown("field", &v, names)))
             }))
 }
 
+// Returns the property selected by a device's qualifier (see
+// `device::parser()`): the reading, for the default qualifier.
+
+pub fn selected(qual_prop: Option<Property>) -> Property {
+    qual_prop.unwrap_or(Property::Reading(ReadingField::default()))
+}
+
+// Returns whether a request whose device name has the qualifier for
+// `qual_prop` may name `property`. The default qualifier (':') allows
+// any property; the others only allow the one they select.
+
+pub fn compatible(qual_prop: Option<Property>, property: Property) -> bool {
+    matches!((qual_prop, property),
+             (None, _) |
+             (Some(Property::Reading(_)), Property::Reading(_)) |
+             (Some(Property::Setting(_)), Property::Setting(_)) |
+             (Some(Property::Status(_)), Property::Status(_)) |
+             (Some(Property::Analog(_)), Property::Analog(_)) |
+             (Some(Property::Digital(_)), Property::Digital(_)) |
+             (Some(Property::Control), Property::Control) |
+             (Some(Property::Description), Property::Description) |
+             (Some(Property::Index), Property::Index) |
+             (Some(Property::LongName), Property::LongName) |
+             (Some(Property::AlarmList), Property::AlarmList))
+}
+
+// Reconciles the device's qualifier with the property the request
+// names, if it names one, and returns the request's property. A
+// property the qualifier doesn't allow is an error.
+
+pub fn reconcile<P>(qual_prop: Option<Property>, named: Option<(Property, Note<P>)>) -> Result<(Property, Note<P>), String> {
+    match named {
+        None => Ok((selected(qual_prop), None)),
+        Some((property, note)) if compatible(qual_prop, property) => Ok((property, note)),
+        Some((property, _)) => Err(format!(
+            "'{}' doesn't match the device's qualifier, which selects '{}'",
+            property.canonical().0,
+            selected(qual_prop).canonical().0
+        )),
+    }
+}
+
 // Returns a parser that recognizes all the names for valid
 // properties. The parse returns a property with the field set to the
-// default for that property. The parameter, `qual_prop`, restricts
-// which property will be accepted (it is usually set to the property
-// found in the second character of the device name.)
+// default for that property; whether the device's qualifier allows it
+// is checked by `reconcile()`. Deprecated names are noted.
 
-pub fn parse_property<Input>(qual_prop: Property) -> impl Parser<Input, Output = Property>
+pub fn parse_property<Input>() -> impl Parser<Input, Output = (Property, Note<Input::Position>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    const PROPERTIES: [(&str, Property); 32] = [
-        ("AA", Property::Analog(AnalogField::default())),
-        ("ALARM_LIST_NAME", Property::AlarmList),
-        ("ANALOG_ALARM", Property::Analog(AnalogField::default())),
-        ("ANALOG", Property::Analog(AnalogField::default())),
-        ("BASIC_CONTROL", Property::Control),
-        ("BASIC_STATUS", Property::Status(StatusField::default())),
-        ("CONTROL", Property::Control),
-        ("CTRL", Property::Control),
-        ("DA", Property::Digital(DigitalField::default())),
-        ("DESC", Property::Description),
-        ("DESCRIPTION", Property::Description),
-        ("DIGITAL_ALARM", Property::Digital(DigitalField::default())),
-        ("DIGITAL", Property::Digital(DigitalField::default())),
-        ("INDEX", Property::Index),
-        ("LNGNAM", Property::LongName),
-        ("LONG_NAME", Property::LongName),
-        ("LSTNAM", Property::AlarmList),
-        ("PRALNM", Property::AlarmList),
-        ("PRANAB", Property::Analog(AnalogField::default())),
-        ("PRBCTL", Property::Control),
-        ("PRBSTS", Property::Status(StatusField::default())),
-        ("PRDABL", Property::Digital(DigitalField::default())),
-        ("PRDESC", Property::Description),
-        ("PRLNAM", Property::LongName),
-        ("PRREAD", Property::Reading(ReadingField::default())),
-        ("PRSET", Property::Setting(SettingField::default())),
-        ("READ", Property::Reading(ReadingField::default())),
-        ("READING", Property::Reading(ReadingField::default())),
-        ("SET", Property::Setting(SettingField::default())),
-        ("SETTING", Property::Setting(SettingField::default())),
-        ("STATUS", Property::Status(StatusField::default())),
-        ("STS", Property::Status(StatusField::default())),
-    ];
-
     char::char('.').with(
-        repeat::many1(choice::or(char::letter(), char::char('_')))
-            .and_then(move |v: String| {
-                let v = v.to_uppercase();
-
-                if let Some(property) = lookup(&v, &PROPERTIES) {
-                    match (qual_prop, property) {
-                        (Property::Reading(_), _) |
-                        (Property::Setting(_), Property::Setting(_)) |
-                        (Property::Status(_), Property::Status(_)) |
-                        (Property::Analog(_), Property::Analog(_)) |
-                        (Property::Digital(_), Property::Digital(_)) |
-                        (Property::Control, Property::Control) |
-                        (Property::Description, Property::Description) |
-                        (Property::Index, Property::Index) |
-                        (Property::LongName, Property::LongName) |
-                        (Property::AlarmList, Property::AlarmList) =>
-                            Ok(property),
-                        _ =>
-                            Err(StreamErrorFor::<Input>::message("mismatched properties"))
+        (position(), repeat::many1(choice::or(satisfy(|c: char| c.is_ascii_alphabetic()), char::char('_'))))
+            .and_then(move |(pos, v): (_, String)| {
+                let v = v.to_ascii_uppercase();
+
+                match lookup(&v, &PROPERTIES) {
+                    Some(property) => Ok((property, deprecated(pos, &v, property.canonical().0))),
+                    None => {
+                        let names = PROPERTIES.iter().map(|p| p.0).collect();
+
+                        Err(StreamErrorFor::<Input>::message_format(unknown("property", &v, names)))
                     }
-                } else {
-                    Err(StreamErrorFor::<Input>::message("unknown property"))
                 }
             })
     )
@@ -229,6 +351,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use combine::EasyParser;
 
     #[test]
     fn test_property_parsing() {
@@ -274,8 +397,25 @@ mod tests {
         ];
 
         for &(d, o, x) in device_data {
-            assert_eq!(parse_property(o).easy_parse(d), Ok((o, x)),
+            assert_eq!(parse_property().map(|(p, _)| p).easy_parse(d), Ok((o, x)),
                        "\n input: \"{}\"", d);
         }
     }
+
+    #[test]
+    fn test_deprecated_names() {
+        let rdg_prop = Property::Reading(ReadingField::default());
+        let note = |r: Result<((Property, Note<_>), &str), _>| r.ok().and_then(|((_, n), _)| n).map(|(_, k)| k.to_string());
+
+        assert_eq!(note(parse_property().parse(".PRREAD")),
+                   Some(String::from("'.PRREAD' is deprecated; use '.READING'")));
+        assert_eq!(note(parse_field(rdg_prop).parse(".volts")),
+                   Some(String::from("'.VOLTS' is deprecated; use '.PRIMARY'")));
+        assert_eq!(note(parse_property().parse(".READ")), None);
+
+        for name in &DEPRECATED {
+            assert!(PROPERTIES.iter().any(|p| p.0 == *name) || READING_FIELDS.iter().any(|f| f.0 == *name),
+                    "{} isn't a name", name);
+        }
+    }
 }
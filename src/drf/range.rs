@@ -1,8 +1,10 @@
-use super::Range;
+use super::error::SUGGEST;
+use super::{ws, Note, Range, WarningKind};
 use combine::error::{ParseError, StreamError};
 use combine::parser::{char, repeat};
 use combine::stream::{Stream, StreamErrorFor};
-use combine::{attempt, choice, optional, value, Parser};
+use combine::{attempt, choice, optional, position, value, Parser};
+use std::convert::TryFrom;
 
 // Consumes a block of digits and converts them to an integer type, if
 // possible.
@@ -18,77 +20,162 @@ where
         .and_then(|v: String| v.parse::<Output>().map_err(StreamErrorFor::<Input>::other))
 }
 
-fn parse_array_range<Input>() -> impl Parser<Input, Output = Range>
+// Returns the warning for a range, written with the `open` bracket,
+// which selects all the data, e.g. "[0:]".
+
+fn normalized(open: char, start: Option<u32>) -> Option<WarningKind> {
+    let close = if open == '[' { ']' } else { '}' };
+    let start = start.map(|s| s.to_string()).unwrap_or_default();
+
+    Some(WarningKind::Normalized { text: format!("{}{}:{}", open, start, close) })
+}
+
+// Consumes an array index: an element number or, with a leading
+// '-', a count back from the end of the array (down to -65536.)
+
+fn parse_index<Input>() -> impl Parser<Input, Output = i32>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((
+        char::char('-').with(parse_int()).and_then(|v: u32| {
+            if (1..=0x10000).contains(&v) {
+                Ok(-(v as i32))
+            } else {
+                Err(StreamErrorFor::<Input>::message("bad index"))
+            }
+        }),
+        parse_int().map(|v: u16| i32::from(v)),
+    ))
+}
+
+fn parse_array_range<Input>() -> impl Parser<Input, Output = (Range, Option<WarningKind>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let one_element = parse_int()
+    let one_element = parse_index()
+        .skip(ws())
         .skip(char::char(']'))
-        .map(|v: u16| Range::Array {
-            start_index: v,
-            end_index: Some(v),
+        .map(|v| match u16::try_from(v) {
+            Ok(v) => (Range::Array {
+                start_index: v,
+                end_index: Some(v),
+            }, None),
+            Err(_) => (Range::Relative {
+                start: v,
+                end: Some(v),
+            }, None),
         });
 
-    let multi_element = (
-        optional(parse_int()).skip(char::char(':')),
-        optional(parse_int()).skip(char::char(']')),
-    )
-        .and_then(|v| match v {
-            (None, None) | (Some(0), None) => Ok(Range::Full),
-            (Some(s), None) => Ok(Range::Array {
-                start_index: s,
-                end_index: None,
-            }),
-            (None, Some(e)) => Ok(Range::Array {
-                start_index: 0,
-                end_index: Some(e),
-            }),
-            (Some(s), Some(e)) => {
-                if s <= e {
-                    Ok(Range::Array {
-                        start_index: s,
-                        end_index: Some(e),
-                    })
-                } else {
-                    Err(StreamErrorFor::<Input>::message("bad range"))
-                }
+    // Indices which fit in a `u16` are counted from the start of the
+    // array; the others are relative to its end. Like a counted
+    // range, once the text reads as "[start:end]", a start past the
+    // end is reported as a reversed range, at the range's start, with
+    // the range it was likely meant to be.
+
+    let reversed = |s: i32, e: i32| {
+        StreamErrorFor::<Input>::message_format(format!("range [{}:{}] is reversed{}[{}:{}]?", s, e, SUGGEST, e, s))
+    };
+    let multi_element = attempt((
+        optional(parse_index()).skip(ws()).skip(char::char(':')).skip(ws()),
+        optional(parse_index()).skip(ws()).skip(char::char(']')),
+    ))
+        .and_then(move |v: (Option<i32>, Option<i32>)| {
+            let from_start = |i: Option<i32>| i.map(u16::try_from).transpose();
+
+            match (from_start(v.0), from_start(v.1), v) {
+                (Ok(s), Ok(e), _) => array_range(s, e).ok_or_else(|| reversed(v.0.unwrap_or(0), v.1.unwrap_or(0))),
+                (_, _, (Some(s), Some(e))) if s < 0 && e < 0 && s > e => Err(reversed(s, e)),
+                (_, _, (s, e)) => Ok((Range::Relative {
+                    start: s.unwrap_or(0),
+                    end: e,
+                }, None)),
             }
         });
 
-    char::char('[').with(choice((
-        char::char(']').with(value(Range::Full)),
+    // "[start;count]" selects `count` elements. It's stored as the
+    // equivalent "[start:end]" range. Once the text reads as one, a
+    // count which is zero, or runs past the last index, is an error
+    // of its own rather than a reason to try the other forms.
+
+    let counted = attempt((
+        parse_int().skip(ws()).skip(char::char(';')).skip(ws()),
+        parse_int().skip(ws()).skip(char::char(']')),
+    ))
+        .and_then(|(start_index, count): (u16, u32)| {
+            if count == 0 {
+                return Err(StreamErrorFor::<Input>::message("bad length"));
+            }
+            match u32::from(start_index).checked_add(count - 1).and_then(|e| u16::try_from(e).ok()) {
+                Some(end) => Ok((Range::Array {
+                    start_index,
+                    end_index: Some(end),
+                }, None)),
+                None => Err(StreamErrorFor::<Input>::message_format(format!(
+                    "range [{};{}] exceeds the maximum index",
+                    start_index, count
+                ))),
+            }
+        });
+
+    char::char('[').skip(ws()).with(choice((
+        char::char(']').with(value((Range::Full, None))),
         attempt(one_element),
-        attempt(multi_element),
+        multi_element,
+        counted,
     )))
 }
 
-fn parse_byte_range<Input>() -> impl Parser<Input, Output = Range>
+// Returns the range "[s:e]", where both indices count from the start
+// of the array, or `None` if `s` is past `e`.
+
+fn array_range(s: Option<u16>, e: Option<u16>) -> Option<(Range, Option<WarningKind>)> {
+    match (s, e) {
+        (None, None) | (Some(0), None) => Some((Range::Full, normalized('[', s.map(u32::from)))),
+        (Some(s), None) => Some((Range::Array {
+            start_index: s,
+            end_index: None,
+        }, None)),
+        (None, Some(e)) => Some((Range::Array {
+            start_index: 0,
+            end_index: Some(e),
+        }, None)),
+        (Some(s), Some(e)) if s <= e => Some((Range::Array {
+            start_index: s,
+            end_index: Some(e),
+        }, None)),
+        (Some(_), Some(_)) => None,
+    }
+}
+
+fn parse_byte_range<Input>() -> impl Parser<Input, Output = (Range, Option<WarningKind>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    let one_element = parse_int().skip(char::char('}')).map(|v: u32| Range::Raw {
+    let one_element = parse_int().skip(ws()).skip(char::char('}')).map(|v: u32| (Range::Raw {
         offset: v,
         length: Some(1),
-    });
-
-    let multi_element = (
-        optional(parse_int()).skip(char::char(':')),
-        optional(parse_int()).skip(char::char('}')),
-    )
-        .and_then(|v| match v {
-            (None, None) | (Some(0), None) => Ok(Range::Full),
-            (Some(o), None) => Ok(Range::Raw {
+    }, None));
+
+    let multi_element = attempt((
+        optional(parse_int()).skip(ws()).skip(char::char(':')).skip(ws()),
+        optional(parse_int()).skip(ws()).skip(char::char('}')),
+    ))
+        .and_then(|v: (Option<u32>, Option<u32>)| match v {
+            (None, None) | (Some(0), None) => Ok((Range::Full, normalized('{', v.0))),
+            (Some(o), None) => Ok((Range::Raw {
                 offset: o,
                 length: None,
-            }),
+            }, None)),
             (None, Some(l)) => {
                 if l > 0 {
-                    Ok(Range::Raw {
+                    Ok((Range::Raw {
                         offset: 0,
                         length: Some(l),
-                    })
+                    }, None))
                 } else {
                     Err(StreamErrorFor::<Input>::message("bad length"))
                 }
@@ -96,10 +183,10 @@ where
             (Some(o), Some(l)) => {
                 if l > 0 {
                     if o <= u32::MAX - l {
-                        Ok(Range::Raw {
+                        Ok((Range::Raw {
                             offset: o,
                             length: Some(l),
-                        })
+                        }, None))
                     } else {
                         Err(StreamErrorFor::<Input>::message("bad range"))
                     }
@@ -109,40 +196,61 @@ where
             }
         });
 
-    char::char('{').with(choice((
-        char::char('}').with(value(Range::Full)),
+    char::char('{').skip(ws()).with(choice((
+        char::char('}').with(value((Range::Full, None))),
         attempt(one_element),
-        attempt(multi_element),
+        multi_element,
     )))
 }
 
-pub fn parser<Input>() -> impl Parser<Input, Output = Range>
+// Returns a parser for the optional range of a request. Ranges which
+// select all the data, but aren't written "[]" or "{}", are noted.
+
+pub fn parser<Input>() -> impl Parser<Input, Output = (Range, Note<Input::Position>)>
 where
     Input: Stream<Token = char>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    choice((
+    (position(), choice((
         parse_array_range(),
         parse_byte_range(),
-        value(Range::Array {
+        value((Range::Array {
             start_index: 0,
             end_index: Some(0),
-        }),
-    ))
+        }, None)),
+    )))
+        .map(|(pos, (range, kind))| (range, kind.map(|k| (pos, k))))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // The range parser, without its notes.
+
+    fn range<Input>() -> impl Parser<Input, Output = Range>
+    where
+        Input: Stream<Token = char>,
+        Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    {
+        parser().map(|(r, _)| r)
+    }
+
     #[test]
     fn test_full_range_parsing() {
-        assert_eq!(parser().parse("[]"), Ok((Range::Full, "")));
-        assert_eq!(parser().parse("[:]"), Ok((Range::Full, "")));
-        assert_eq!(parser().parse("[0:]"), Ok((Range::Full, "")));
-        assert_eq!(parser().parse("{}"), Ok((Range::Full, "")));
-        assert_eq!(parser().parse("{:}"), Ok((Range::Full, "")));
-        assert_eq!(parser().parse("{0:}"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("[]"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("[:]"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("[0:]"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("{}"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("{:}"), Ok((Range::Full, "")));
+        assert_eq!(range().parse("{0:}"), Ok((Range::Full, "")));
+
+        let note = |text| parser().parse(text).ok().and_then(|((_, n), _)| n).map(|(_, k): (_, WarningKind)| k.to_string());
+
+        assert_eq!(note("[0:]"), Some(String::from("'[0:]' selects all the data; read as '[]'")));
+        assert_eq!(note("{:}"), Some(String::from("'{:}' selects all the data; read as '[]'")));
+        assert_eq!(note("[]"), None);
+        assert_eq!(note("[1:]"), None);
     }
 
     #[test]
@@ -157,7 +265,7 @@ mod tests {
 
         for &(text, start_index, end_index, extra) in range_data {
             assert_eq!(
-                parser().parse(text),
+                range().parse(text),
                 Ok((
                     Range::Array {
                         start_index,
@@ -168,9 +276,56 @@ mod tests {
             );
         }
 
-        assert!(parser().parse("[A]").is_err());
-        assert!(parser().parse("[65536]").is_err());
-        assert!(parser().parse("[2:1]").is_err());
+        assert!(range().parse("[A]").is_err());
+        assert!(range().parse("[65536]").is_err());
+        assert!(range().parse("[2:1]").is_err());
+
+        // A reversed range is reported as one, where the range starts.
+
+        assert_eq!(crate::parse_drf("M:OUTTMP[ 5 : 1 ]").unwrap_err().to_string(), "at offset 10: range [5:1] is reversed; did you mean [1:5]?");
+        assert_eq!(crate::parse_drf("M:OUTTMP[-1:-2]").unwrap_err().to_string(), "at offset 9: range [-1:-2] is reversed; did you mean [-2:-1]?");
+    }
+
+    #[test]
+    fn test_relative_range_parsing() {
+        let range_data = &[
+            ("[-1]", -1, Some(-1)),
+            ("[2:-1]", 2, Some(-1)),
+            ("[-10:]", -10, None),
+            ("[:-2]", 0, Some(-2)),
+            ("[-5:-2]", -5, Some(-2)),
+            ("[-5:3]", -5, Some(3)),
+            ("[-65536]", -65536, Some(-65536)),
+        ];
+
+        for &(text, start, end) in range_data {
+            assert_eq!(range().parse(text), Ok((Range::Relative { start, end }, "")), "{}", text);
+        }
+
+        assert!(range().parse("[-0]").is_err());
+    }
+
+    #[test]
+    fn test_counted_range_parsing() {
+        let array = |s, e| Range::Array { start_index: s, end_index: Some(e) };
+
+        assert_eq!(range().parse("[2;3]"), Ok((array(2, 4), "")));
+        assert_eq!(range().parse("[0;1]"), Ok((array(0, 0), "")));
+        assert_eq!(range().parse("[ 10 ; 1 ]"), Ok((array(10, 10), "")));
+        assert_eq!(range().parse("[65535;1]"), Ok((array(65535, 65535), "")));
+        assert_eq!(range().parse("[0;65536]"), Ok((array(0, 65535), "")));
+        assert!(range().parse("[2;0]").is_err());
+        assert!(range().parse("[0;65537]").is_err());
+
+        let overflow = crate::parse_drf("M:OUTTMP[65535;2]").unwrap_err();
+
+        assert!(overflow.to_string().contains("range [65535;2] exceeds the maximum index"), "{}", overflow);
+        assert!(crate::parse_drf("M:OUTTMP[1;4294967295]").unwrap_err().to_string().contains("exceeds the maximum index"));
+        assert!(range().parse("[;2]").is_err());
+        assert!(range().parse("{2;3}").is_err());
+        assert!(range().parse("[-65537]").is_err());
+        assert!(range().parse("[-1:-2]").is_err());
+        assert!(range().parse("{-1}").is_err());
     }
 
     #[test]
@@ -187,15 +342,16 @@ mod tests {
 
         for &(text, offset, length, extra) in range_data {
             assert_eq!(
-                parser().parse(text),
+                range().parse(text),
                 Ok((Range::Raw { offset, length }, extra))
             );
         }
 
-        assert!(parser().parse("{A}").is_err());
-        assert!(parser().parse("{:0}").is_err());
-        assert!(parser().parse("{:-1}").is_err());
-        assert!(parser().parse("{4294967296}").is_err());
-        assert!(parser().parse("{4000000000:294967296}").is_err());
+        assert!(range().parse("{A}").is_err());
+        assert!(range().parse("{:0}").is_err());
+        assert!(range().parse("{:-1}").is_err());
+        assert!(range().parse("{4294967296}").is_err());
+        assert!(range().parse("{4000000000:294967296}").is_err());
+        assert_eq!(crate::parse_drf("M:OUTTMP{0:0}").unwrap_err().to_string(), "at offset 9: bad length");
     }
 }
@@ -0,0 +1,65 @@
+use super::DataSource;
+use combine::error::{ParseError, StreamError};
+use combine::parser::{char, repeat};
+use combine::stream::{Stream, StreamErrorFor};
+use combine::{attempt, choice, optional, Parser};
+
+// Consumes a block of digits and converts them to a `u64`, if
+// possible.
+
+fn parse_time<Input>() -> impl Parser<Input, Output = u64>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    repeat::many1(char::digit()).and_then(|v: String| v.parse::<u64>().map_err(StreamErrorFor::<Input>::other))
+}
+
+/*
+data-source = "<-" ( "FTP" | "SNP" | "LOGGER:" start ":" end )
+*/
+pub fn parser<Input>() -> impl Parser<Input, Output = DataSource>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let name = |text: &'static str| char::string_cmp(text, |l: char, r: char| l.eq_ignore_ascii_case(&r));
+
+    optional(attempt(char::string("<-")).with(choice((
+        name("FTP").map(|_| DataSource::Ftp),
+        name("SNP").map(|_| DataSource::Snapshot),
+        name("LOGGER")
+            .with((char::char(':').with(parse_time()), char::char(':').with(parse_time())))
+            .map(|(start, end)| DataSource::Logger { start, end }),
+    ))))
+    .map(|source| source.unwrap_or(DataSource::Default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_parsing() {
+        let data = &[
+            ("", DataSource::Default, ""),
+            ("<-FTP", DataSource::Ftp, ""),
+            ("<-ftp", DataSource::Ftp, ""),
+            ("<-SNP", DataSource::Snapshot, ""),
+            ("<-Snp junk", DataSource::Snapshot, " junk"),
+            ("<FTP", DataSource::Default, "<FTP"),
+            ("<-LOGGER:1700000000000:1700003600000", DataSource::Logger { start: 1700000000000, end: 1700003600000 }, ""),
+            ("<-logger:0:5", DataSource::Logger { start: 0, end: 5 }, ""),
+        ];
+
+        for &(text, source, rest) in data {
+            assert_eq!(parser().parse(text), Ok((source, rest)), "\n input: {}", text);
+        }
+
+        assert!(parser().parse("<-XYZ").is_err());
+        assert!(parser().parse("<-FT").is_err());
+        assert!(parser().parse("<-LOGGER").is_err());
+        assert!(parser().parse("<-LOGGER:1").is_err());
+        assert!(parser().parse("<-LOGGER:1:18446744073709551616").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use super::Event;
+use std::fmt;
+
+// Describes input which the parser accepted, but didn't take
+// literally.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WarningKind {
+    // A deprecated property or field name (without its leading dot)
+    // and the canonical name which replaces it.
+    Deprecated {
+        name: String,
+        replacement: &'static str,
+    },
+
+    // A rate or delay, as written, which was out of range, and the
+    // value (in microseconds) it was clipped to.
+    Clipped {
+        text: String,
+        value: u32,
+    },
+
+    // A range, as written, which selects all the data, so it was
+    // stored as `Range::Full`.
+    Normalized {
+        text: String,
+    },
+
+    // Whitespace between, or inside, the parts of a request.
+    Whitespace,
+}
+
+impl WarningKind {
+    // Returns whether strict parsing rejects the input. Normalized
+    // ranges are only reported since they mean what they say.
+
+    pub fn is_rejected_when_strict(&self) -> bool {
+        match self {
+            WarningKind::Deprecated { .. } | WarningKind::Clipped { .. } | WarningKind::Whitespace => true,
+            WarningKind::Normalized { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::Deprecated { name, replacement } => {
+                write!(f, "'.{}' is deprecated; use '{}'", name, replacement)
+            }
+            WarningKind::Clipped { text, value } => {
+                write!(f, "'{}' is out of range; clipped to {}", text, Event::canonical_delay(*value))
+            }
+            WarningKind::Normalized { text } => write!(f, "'{}' selects all the data; read as '[]'", text),
+            WarningKind::Whitespace => write!(f, "whitespace in a request is ignored"),
+        }
+    }
+}
+
+// A warning returned by `parse_with()`. `offset` is the byte offset
+// of the text it's about.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub offset: usize,
+    pub kind: WarningKind,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at offset {}: {}", self.offset, self.kind)
+    }
+}
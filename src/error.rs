@@ -0,0 +1,107 @@
+use std::{fmt, io};
+
+// The errors reported by the subcommands. Each kind of error maps to
+// its own exit status so scripts can react to failures without
+// parsing the message written to stderr.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Parse(String),
+    Connection(String),
+    // Reported when a request names a device that doesn't exist.
+    DeviceNotFound(String),
+    PermissionDenied(String),
+    Timeout(String),
+    Other(String),
+}
+
+impl Error {
+    // Returns the process exit status for the error. Zero is reserved
+    // for success. These values are part of the program's interface
+    // and shouldn't be renumbered.
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Other(_) => 1,
+            Error::Parse(_) => 2,
+            Error::Connection(_) => 3,
+            Error::DeviceNotFound(_) => 4,
+            Error::PermissionDenied(_) => 5,
+            Error::Timeout(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(m) => write!(f, "parse error: {}", m),
+            Error::Connection(m) => write!(f, "connection failed: {}", m),
+            Error::DeviceNotFound(m) => write!(f, "device not found: {}", m),
+            Error::PermissionDenied(m) => write!(f, "permission denied: {}", m),
+            Error::Timeout(m) => write!(f, "timed out: {}", m),
+            Error::Other(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(m: String) -> Self {
+        Error::Other(m)
+    }
+}
+
+// I/O errors are classified by their kind so, for instance, a socket
+// timeout is reported with the timeout exit status.
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout(e.to_string()),
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(e.to_string()),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => Error::Connection(e.to_string()),
+            _ => Error::Other(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            Error::Other(String::new()),
+            Error::Parse(String::new()),
+            Error::Connection(String::new()),
+            Error::DeviceNotFound(String::new()),
+            Error::PermissionDenied(String::new()),
+            Error::Timeout(String::new()),
+        ];
+        let mut codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0));
+    }
+
+    #[test]
+    fn test_io_error_mapping() {
+        let err = |k| Error::from(io::Error::new(k, "x")).exit_code();
+
+        assert_eq!(err(io::ErrorKind::TimedOut), 6);
+        assert_eq!(err(io::ErrorKind::PermissionDenied), 5);
+        assert_eq!(err(io::ErrorKind::ConnectionRefused), 3);
+        assert_eq!(err(io::ErrorKind::NotFound), 1);
+    }
+}
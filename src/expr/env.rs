@@ -0,0 +1,152 @@
+use super::Unit;
+use std::collections::{HashMap, VecDeque};
+
+// Named constants available to expressions. Global constants are
+// referenced by name (e.g. "PI") and per-device constants by the
+// device name, a period, and the constant name (e.g.
+// "M:OUTTMP.OFFSET"). Names are case-insensitive.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constants(HashMap<String, (f64, Option<Unit>)>);
+
+impl Constants {
+    // Returns the set of built-in constants.
+
+    pub fn builtin() -> Constants {
+        let mut result = Constants(HashMap::new());
+
+        result.insert("PI", std::f64::consts::PI, Some(Unit::none()));
+        result.insert("E", std::f64::consts::E, Some(Unit::none()));
+        result
+    }
+
+    fn insert(&mut self, name: &str, value: f64, units: Option<Unit>) {
+        self.0.insert(name.to_uppercase(), (value, units));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&(f64, Option<Unit>)> {
+        self.0.get(&name.to_uppercase())
+    }
+
+    // Adds the constants defined in the text of a constants file.
+    // Each non-blank line, that isn't a comment, has the form
+    // `NAME = VALUE [UNITS]`. A constant without units is
+    // dimensionless; use "?" for units that shouldn't be checked.
+
+    pub fn load(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+            let (name, rest) = line
+                .split_once('=')
+                .ok_or_else(|| err("expected 'NAME = VALUE [UNITS]'"))?;
+            let name = name.trim();
+            let mut rest = rest.split_whitespace();
+            let value = rest
+                .next()
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| err("missing or bad value"))?;
+            let units = match rest.next() {
+                None => Some(Unit::none()),
+                Some("?") => None,
+                Some(u) => Some(Unit::parse(u).map_err(|e| err(&e))?),
+            };
+
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(err("bad constant name"));
+            }
+            self.insert(name, value, units)
+        }
+        Ok(())
+    }
+}
+
+// Holds the recent values of each device used by a set of
+// expressions. Only as many values as the largest window requested
+// for the device are kept.
+
+#[derive(Clone, Debug, Default)]
+pub struct Samples {
+    depth: HashMap<String, usize>,
+    history: HashMap<String, VecDeque<f64>>,
+}
+
+impl Samples {
+    pub fn new() -> Samples {
+        Samples::default()
+    }
+
+    // Makes sure at least `n` values of the device are retained.
+
+    pub fn keep(&mut self, device: &str, n: usize) {
+        let d = self.depth.entry(device.to_string()).or_insert(1);
+
+        *d = (*d).max(n);
+    }
+
+    pub fn push(&mut self, device: &str, v: f64) {
+        let depth = self.depth.get(device).copied().unwrap_or(1);
+        let h = self.history.entry(device.to_string()).or_default();
+
+        if h.len() == depth {
+            h.pop_front();
+        }
+        h.push_back(v)
+    }
+
+    pub fn latest(&self, device: &str) -> Option<f64> {
+        self.history.get(device).and_then(|h| h.back().copied())
+    }
+
+    // Returns the last `n` values of the device, oldest first. If
+    // fewer than `n` values have been collected, `None` is returned.
+
+    pub fn last(&self, device: &str, n: usize) -> Option<Vec<f64>> {
+        self.history
+            .get(device)
+            .filter(|h| h.len() >= n)
+            .map(|h| h.iter().skip(h.len() - n).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constants_loading() {
+        let mut c = Constants::builtin();
+
+        c.load("# gains\ngain = 2.5\nM:OUTTMP.offset = 32 DegF\nscale = 1e3 ?\n").unwrap();
+
+        assert_eq!(c.get("GAIN"), Some(&(2.5, Some(Unit::none()))));
+        assert_eq!(c.get("m:outtmp.OFFSET"), Some(&(32.0, Some(Unit::parse("DegF").unwrap()))));
+        assert_eq!(c.get("scale"), Some(&(1000.0, None)));
+        assert!(c.get("pi").is_some());
+        assert!(c.load("gain 2").is_err());
+        assert!(c.load("gain = x").is_err());
+        assert!(c.load("gain = 1 m^x").is_err());
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut s = Samples::new();
+
+        s.keep("M:OUTTMP", 3);
+        for v in 1..=5 {
+            s.push("M:OUTTMP", v as f64);
+            s.push("G:AMANDA", v as f64);
+        }
+
+        assert_eq!(s.latest("M:OUTTMP"), Some(5.0));
+        assert_eq!(s.last("M:OUTTMP", 3), Some(vec![3.0, 4.0, 5.0]));
+        assert_eq!(s.last("M:OUTTMP", 4), None);
+        assert_eq!(s.last("G:AMANDA", 1), Some(vec![5.0]));
+        assert_eq!(s.latest("G:OTHER"), None);
+    }
+}
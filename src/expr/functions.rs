@@ -0,0 +1,249 @@
+use super::Unit;
+
+// Describes a built-in function. `min_args` and `max_args` bound the
+// number of arguments (`None` means there is no upper limit.)
+// `units` computes the units of the result from the units of the
+// arguments (with `None` meaning unknown) and `eval` computes the
+// result.
+
+type UnitsFn = fn(&[Option<Unit>]) -> Result<Option<Unit>, String>;
+
+pub struct Function {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: Option<usize>,
+    pub units: UnitsFn,
+    pub eval: fn(&[f64]) -> f64,
+}
+
+// Returns the common units of the arguments. Unknown units are
+// skipped; if all are unknown, the result is unknown.
+
+fn same(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    let mut result: Option<&Unit> = None;
+
+    for u in args.iter().flatten() {
+        match result {
+            Some(r) if r != u => {
+                return Err(format!("arguments in '{}' and '{}' are incompatible", r, u))
+            }
+            _ => result = Some(u),
+        }
+    }
+    Ok(result.cloned())
+}
+
+fn dimensionless(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    match args.iter().flatten().find(|u| !u.is_none()) {
+        Some(u) => Err(format!("argument must be dimensionless, not '{}'", u)),
+        None => Ok(Some(Unit::none())),
+    }
+}
+
+fn deg2rad_units(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    match &args[0] {
+        Some(u) if !u.is_none() && *u != Unit::parse("deg").unwrap() => {
+            Err(format!("deg2rad expects degrees, not '{}'", u))
+        }
+        _ => Ok(Some(Unit::none())),
+    }
+}
+
+fn rad2deg_units(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    dimensionless(args).map(|_| Some(Unit::parse("deg").unwrap()))
+}
+
+fn sqrt_units(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    match &args[0] {
+        Some(u) => u
+            .root(2)
+            .map(Some)
+            .ok_or_else(|| format!("can't take the square root of '{}'", u)),
+        None => Ok(None),
+    }
+}
+
+// interp(x, x0, y0, x1, y1, ...): the x values must share units with
+// `x` and the result has the units of the y values.
+
+fn interp_units(args: &[Option<Unit>]) -> Result<Option<Unit>, String> {
+    if args.len().is_multiple_of(2) {
+        return Err(String::from("interp needs a value followed by (x, y) pairs"));
+    }
+
+    let xs: Vec<Option<Unit>> = args[..1].iter().chain(args[1..].iter().step_by(2)).cloned().collect();
+    let ys: Vec<Option<Unit>> = args[2..].iter().step_by(2).cloned().collect();
+
+    same(&xs)?;
+    same(&ys)
+}
+
+// Performs a piecewise linear interpolation of `x` through the
+// points. Values beyond the end points are clamped to the end
+// points' y values.
+
+fn interp(args: &[f64]) -> f64 {
+    let x = args[0];
+    let pts: Vec<(f64, f64)> = args[1..].chunks(2).map(|p| (p[0], p[1])).collect();
+
+    if x <= pts[0].0 {
+        return pts[0].1;
+    }
+    for w in pts.windows(2) {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+
+        if x <= x1 {
+            return if x1 == x0 { y1 } else { y0 + (y1 - y0) * (x - x0) / (x1 - x0) };
+        }
+    }
+    pts[pts.len() - 1].1
+}
+
+pub const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "ABS",
+        min_args: 1,
+        max_args: Some(1),
+        units: same,
+        eval: |a| a[0].abs(),
+    },
+    Function {
+        name: "CLIP",
+        min_args: 3,
+        max_args: Some(3),
+        units: same,
+        eval: |a| a[0].max(a[1]).min(a[2]),
+    },
+    Function {
+        name: "COS",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].cos(),
+    },
+    Function {
+        name: "DEG2RAD",
+        min_args: 1,
+        max_args: Some(1),
+        units: deg2rad_units,
+        eval: |a| a[0].to_radians(),
+    },
+    Function {
+        name: "EXP",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].exp(),
+    },
+    Function {
+        name: "INTERP",
+        min_args: 5,
+        max_args: None,
+        units: interp_units,
+        eval: interp,
+    },
+    Function {
+        name: "LN",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].ln(),
+    },
+    Function {
+        name: "LOG10",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].log10(),
+    },
+    Function {
+        name: "MAX",
+        min_args: 1,
+        max_args: None,
+        units: same,
+        eval: |a| a.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    },
+    Function {
+        name: "MIN",
+        min_args: 1,
+        max_args: None,
+        units: same,
+        eval: |a| a.iter().copied().fold(f64::INFINITY, f64::min),
+    },
+    Function {
+        name: "RAD2DEG",
+        min_args: 1,
+        max_args: Some(1),
+        units: rad2deg_units,
+        eval: |a| a[0].to_degrees(),
+    },
+    Function {
+        name: "SIN",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].sin(),
+    },
+    Function {
+        name: "SQRT",
+        min_args: 1,
+        max_args: Some(1),
+        units: sqrt_units,
+        eval: |a| a[0].sqrt(),
+    },
+    Function {
+        name: "TAN",
+        min_args: 1,
+        max_args: Some(1),
+        units: dimensionless,
+        eval: |a| a[0].tan(),
+    },
+];
+
+// Looks up a function by (case-insensitive) name.
+
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    let name = name.to_uppercase();
+
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interp() {
+        let pts = [0.0, 0.0, 10.0, 100.0, 20.0, 0.0];
+        let at = |x: f64| interp(&[&[x][..], &pts[..]].concat());
+
+        assert_eq!(at(-5.0), 0.0);
+        assert_eq!(at(5.0), 50.0);
+        assert_eq!(at(10.0), 100.0);
+        assert_eq!(at(15.0), 50.0);
+        assert_eq!(at(25.0), 0.0);
+    }
+
+    #[test]
+    fn test_function_units() {
+        let units = |f: &str, args: &[&str]| {
+            let args: Vec<Option<Unit>> = args
+                .iter()
+                .map(|u| if *u == "?" { None } else { Some(Unit::parse(u).unwrap()) })
+                .collect();
+
+            (lookup(f).unwrap().units)(&args).map(|u| u.map(|u| u.to_string()))
+        };
+
+        assert_eq!(units("deg2rad", &["deg"]), Ok(Some(String::new())));
+        assert!(units("deg2rad", &["A"]).is_err());
+        assert_eq!(units("sqrt", &["A^2"]), Ok(Some(String::from("A"))));
+        assert!(units("sqrt", &["A"]).is_err());
+        assert_eq!(units("clip", &["A", "?", "A"]), Ok(Some(String::from("A"))));
+        assert!(units("clip", &["A", "?", "deg"]).is_err());
+        assert!(units("sin", &["A"]).is_err());
+        assert_eq!(units("interp", &["A", "A", "V", "?", "V"]), Ok(Some(String::from("V"))));
+        assert!(units("interp", &["A", "A", "V", "A"]).is_err());
+        assert!(lookup("nosuch").is_none());
+    }
+}
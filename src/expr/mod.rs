@@ -0,0 +1,368 @@
+use combine::{eof, Parser};
+pub use env::{Constants, Samples};
+pub use units::Unit;
+
+mod env;
+mod functions;
+mod parser;
+mod units;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl Op {
+    fn symbol(&self) -> char {
+        match *self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+            Op::Pow => '^',
+        }
+    }
+}
+
+// The parsed form of a derived-channel expression. `Constant` names
+// either a global constant or a per-device constant (in the form
+// "DEVICE.NAME").
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Device(String),
+    Constant(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    // Returns the names of the devices referenced by the expression.
+
+    pub fn devices(&self) -> Vec<&str> {
+        let mut result = vec![];
+
+        self.visit(&mut |e| {
+            if let Expr::Device(d) = e {
+                if !result.contains(&d.as_str()) {
+                    result.push(d.as_str())
+                }
+            }
+        });
+        result
+    }
+
+    // Returns the windows used by calls to `rms()`. Each entry holds
+    // the device and the number of its values which need to be kept.
+
+    pub fn windows(&self) -> Vec<(&str, usize)> {
+        let mut result = vec![];
+
+        self.visit(&mut |e| {
+            if let Expr::Call(f, args) = e {
+                if let ("RMS", [Expr::Device(d), Expr::Number(n)]) = (f.as_str(), &args[..]) {
+                    result.push((d.as_str(), *n as usize))
+                }
+            }
+        });
+        result
+    }
+
+    fn visit<'a, F: FnMut(&'a Expr)>(&'a self, f: &mut F) {
+        f(self);
+        match self {
+            Expr::Number(_) | Expr::Device(_) | Expr::Constant(_) => (),
+            Expr::Neg(e) => e.visit(f),
+            Expr::Binary(_, l, r) => {
+                l.visit(f);
+                r.visit(f)
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.visit(f)),
+        }
+    }
+
+    // Returns the value of a constant expression, or `None` if it
+    // refers to any device.
+
+    fn constant(&self, consts: &Constants) -> Option<f64> {
+        self.eval(&Samples::new(), consts)
+    }
+
+    // Determines the units of the result of the expression and
+    // validates any function calls. `lookup` returns the units of a
+    // device, or `None` if they're unknown. Quantities with unknown
+    // units can't be checked, so they're accepted as-is and make the
+    // result unknown, too. Adding or subtracting quantities with
+    // different units is an error, as is raising a quantity with
+    // units to anything other than a constant integer.
+
+    pub fn units<F>(&self, lookup: &F, consts: &Constants) -> Result<Option<Unit>, String>
+    where
+        F: Fn(&str) -> Option<Unit>,
+    {
+        match self {
+            Expr::Number(_) => Ok(Some(Unit::none())),
+            Expr::Device(d) => Ok(lookup(d)),
+            Expr::Constant(c) => consts
+                .get(c)
+                .map(|(_, u)| u.clone())
+                .ok_or_else(|| format!("unknown constant '{}'", c)),
+            Expr::Neg(e) => e.units(lookup, consts),
+            Expr::Binary(op, l, r) => {
+                let lu = l.units(lookup, consts)?;
+                let ru = r.units(lookup, consts)?;
+
+                match op {
+                    Op::Add | Op::Sub => match (lu, ru) {
+                        (Some(a), Some(b)) if a != b => Err(format!(
+                            "can't apply '{}' to quantities in '{}' and '{}'",
+                            op.symbol(),
+                            a,
+                            b
+                        )),
+                        (Some(a), _) => Ok(Some(a)),
+                        (None, b) => Ok(b),
+                    },
+                    Op::Mul => Ok(lu.zip(ru).map(|(a, b)| a.mul(&b))),
+                    Op::Div => Ok(lu.zip(ru).map(|(a, b)| a.div(&b))),
+                    Op::Pow => {
+                        match ru {
+                            Some(u) if !u.is_none() => {
+                                return Err(format!("exponent must be dimensionless, not '{}'", u))
+                            }
+                            _ => (),
+                        }
+
+                        match lu {
+                            Some(u) if !u.is_none() => match r.constant(consts) {
+                                Some(n) if n.fract() == 0.0 => Ok(Some(u.powi(n as i32))),
+                                _ => Err(format!(
+                                    "a quantity in '{}' can only be raised to a constant integer",
+                                    u
+                                )),
+                            },
+                            u => Ok(u),
+                        }
+                    }
+                }
+            }
+            Expr::Call(f, args) if f == "RMS" => match &args[..] {
+                [Expr::Device(d), Expr::Number(n)] if *n >= 1.0 && n.fract() == 0.0 => {
+                    Ok(lookup(d))
+                }
+                _ => Err(String::from("rms() expects a device and a window size")),
+            },
+            Expr::Call(f, args) => {
+                let func = functions::lookup(f).ok_or_else(|| format!("unknown function '{}'", f))?;
+
+                if args.len() < func.min_args || func.max_args.is_some_and(|m| args.len() > m) {
+                    return Err(format!("wrong number of arguments to {}()", f.to_lowercase()));
+                }
+
+                let units = args
+                    .iter()
+                    .map(|a| a.units(lookup, consts))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                (func.units)(&units).map_err(|e| format!("{}(): {}", f.to_lowercase(), e))
+            }
+        }
+    }
+
+    // Computes the value of the expression from the values collected
+    // in `samples`. If any device doesn't have a value (or enough
+    // values to fill an `rms()` window), `None` is returned. The
+    // expression should have been validated by `units()` first.
+
+    pub fn eval(&self, samples: &Samples, consts: &Constants) -> Option<f64> {
+        match self {
+            Expr::Number(v) => Some(*v),
+            Expr::Device(d) => samples.latest(d),
+            Expr::Constant(c) => consts.get(c).map(|(v, _)| *v),
+            Expr::Neg(e) => e.eval(samples, consts).map(|v| -v),
+            Expr::Binary(op, l, r) => {
+                let l = l.eval(samples, consts)?;
+                let r = r.eval(samples, consts)?;
+
+                Some(match op {
+                    Op::Add => l + r,
+                    Op::Sub => l - r,
+                    Op::Mul => l * r,
+                    Op::Div => l / r,
+                    Op::Pow => l.powf(r),
+                })
+            }
+            Expr::Call(f, args) if f == "RMS" => match &args[..] {
+                [Expr::Device(d), Expr::Number(n)] => {
+                    let v = samples.last(d, *n as usize)?;
+
+                    Some((v.iter().map(|x| x * x).sum::<f64>() / v.len() as f64).sqrt())
+                }
+                _ => None,
+            },
+            Expr::Call(f, args) => {
+                let func = functions::lookup(f)?;
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(samples, consts))
+                    .collect::<Option<Vec<_>>>()?;
+
+                Some((func.eval)(&args))
+            }
+        }
+    }
+}
+
+// Parses an expression. The entire string must be consumed.
+
+pub fn parse(text: &str) -> Result<Expr, String> {
+    parser::parser()
+        .skip(eof())
+        .parse(text)
+        .map(|(e, _)| e)
+        .map_err(|_| format!("bad expression '{}'", text))
+}
+
+// A derived channel is a named expression given on the command line
+// as "NAME=EXPR".
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Derived {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl Derived {
+    pub fn parse(text: &str) -> Result<Derived, String> {
+        match text.split_once('=') {
+            Some((name, expr)) if !name.trim().is_empty() => Ok(Derived {
+                name: name.trim().to_string(),
+                expr: parse(expr)?,
+            }),
+            _ => Err(format!("derived channel '{}' must be of the form NAME=EXPR", text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Samples {
+        let mut s = Samples::new();
+
+        s.keep("G:AMANDA", 4);
+        s.push("M:OUTTMP", 50.0);
+        for v in &[1.0, 1.0, 7.0, 1.0, 1.0] {
+            s.push("G:AMANDA", *v);
+        }
+        s
+    }
+
+    fn consts() -> Constants {
+        let mut c = Constants::builtin();
+
+        c.load("gain = 3\nM:OUTTMP.OFFSET = 32 DegF").unwrap();
+        c
+    }
+
+    fn dev_units(d: &str) -> Option<Unit> {
+        match d {
+            "M:OUTTMP" => Some(Unit::parse("DegF").unwrap()),
+            "G:AMANDA" => Some(Unit::parse("A").unwrap()),
+            "G:VOLTS" => Some(Unit::parse("V").unwrap()),
+            "G:ANGLE" => Some(Unit::parse("deg").unwrap()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expr_evaluation() {
+        let data = &[
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("2 ^ 3 ^ 2", 512.0),
+            ("-2 ^ 2", -4.0),
+            ("10 - 4 - 3", 3.0),
+            ("1.5e1 / 3", 5.0),
+            ("(m:outtmp - 32) * 5 / 9", 10.0),
+            ("G:AMANDA*G:AMANDA", 1.0),
+            ("(M:OUTTMP - M:OUTTMP.offset) * gain", 54.0),
+            ("clip(M:OUTTMP, 0, 40)", 40.0),
+            ("max(1, 5, 3) - min(4, 2)", 3.0),
+            ("interp(5, 0, 0, 10, 100)", 50.0),
+            ("rms(G:AMANDA, 4) ^ 2", 13.0),
+            ("rad2deg(deg2rad(90))", 90.0),
+            ("abs(-pi) / PI", 1.0),
+        ];
+
+        for &(text, result) in data {
+            let v = parse(text).unwrap().eval(&samples(), &consts()).unwrap();
+
+            assert!((v - result).abs() < 1e-9, "\n input: {} = {}", text, v);
+        }
+
+        assert_eq!(parse("M:OUTTMP + G:OTHER").unwrap().eval(&samples(), &consts()), None);
+        assert_eq!(parse("rms(G:AMANDA, 5)").unwrap().eval(&samples(), &consts()), None);
+        assert!(parse("1 +").is_err());
+        assert!(parse("(1").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("sin(1,)").is_err());
+    }
+
+    #[test]
+    fn test_expr_units() {
+        let units = |text: &str| {
+            parse(text)
+                .unwrap()
+                .units(&dev_units, &consts())
+                .map(|u| u.map(|u| u.to_string()))
+        };
+
+        assert_eq!(units("G:AMANDA * G:VOLTS"), Ok(Some(String::from("A*V"))));
+        assert_eq!(units("G:VOLTS / G:AMANDA"), Ok(Some(String::from("V/A"))));
+        assert_eq!(units("G:AMANDA ^ 2 + G:AMANDA * G:AMANDA"), Ok(Some(String::from("A^2"))));
+        assert_eq!(units("2 * G:AMANDA - G:AMANDA"), Ok(Some(String::from("A"))));
+        assert_eq!(units("G:UNKNOWN * G:AMANDA"), Ok(None));
+        assert_eq!(units("G:UNKNOWN + G:AMANDA"), Ok(Some(String::from("A"))));
+        assert_eq!(units("M:OUTTMP - M:OUTTMP.OFFSET"), Ok(Some(String::from("DegF"))));
+        assert_eq!(units("sin(deg2rad(G:ANGLE))"), Ok(Some(String::new())));
+        assert_eq!(units("rms(G:AMANDA, 10)"), Ok(Some(String::from("A"))));
+        assert_eq!(units("sqrt(G:AMANDA * G:AMANDA)"), Ok(Some(String::from("A"))));
+        assert!(units("G:AMANDA + M:OUTTMP").is_err());
+        assert!(units("G:AMANDA + 1").is_err());
+        assert!(units("G:AMANDA ^ 0.5").is_err());
+        assert!(units("2 ^ G:AMANDA").is_err());
+        assert!(units("sin(G:ANGLE)").is_err());
+        assert!(units("clip(G:AMANDA, 0, M:OUTTMP)").is_err());
+        assert!(units("rms(G:AMANDA)").is_err());
+        assert!(units("rms(2, 10)").is_err());
+        assert!(units("nosuch(1)").is_err());
+        assert!(units("abs(1, 2)").is_err());
+        assert!(units("G:AMANDA.NOSUCH").is_err());
+    }
+
+    #[test]
+    fn test_expr_windows() {
+        let e = parse("rms(G:AMANDA, 10) + rms(M:OUTTMP, 3)").unwrap();
+
+        assert_eq!(e.windows(), vec![("G:AMANDA", 10), ("M:OUTTMP", 3)]);
+        assert_eq!(e.devices(), vec!["G:AMANDA", "M:OUTTMP"]);
+    }
+
+    #[test]
+    fn test_derived_parsing() {
+        let d = Derived::parse("power = G:AMANDA * G:VOLTS").unwrap();
+
+        assert_eq!(d.name, "power");
+        assert_eq!(d.expr.devices(), vec!["G:AMANDA", "G:VOLTS"]);
+        assert!(Derived::parse("G:AMANDA").is_err());
+        assert!(Derived::parse("=G:AMANDA").is_err());
+    }
+}
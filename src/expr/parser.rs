@@ -0,0 +1,159 @@
+use super::{Expr, Op};
+use combine::error::ParseError;
+use combine::parser::{char, repeat};
+use combine::stream::Stream;
+use combine::{attempt, between, choice, one_of, optional, sep_by, Parser};
+
+// Skips any whitespace following the token recognized by `p`.
+
+fn lex<Input, P>(p: P) -> impl Parser<Input, Output = P::Output>
+where
+    P: Parser<Input>,
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    p.skip(char::spaces())
+}
+
+// Recognizes a decimal number with an optional fraction and exponent.
+
+fn parse_number<Input>() -> impl Parser<Input, Output = Expr>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let digits = || repeat::many1::<String, _, _>(char::digit());
+
+    (
+        digits(),
+        optional(char::char('.').with(digits())),
+        optional((
+            one_of("eE".chars()),
+            optional(one_of("+-".chars())),
+            digits(),
+        )),
+    )
+        .map(|(int, frac, exp)| {
+            let mut text = int;
+
+            if let Some(frac) = frac {
+                text.push('.');
+                text.push_str(&frac);
+            }
+            if let Some((_, sign, exp)) = exp {
+                text.push('e');
+                text.extend(sign);
+                text.push_str(&exp);
+            }
+            Expr::Number(text.parse().unwrap())
+        })
+}
+
+fn parse_ident<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        char::letter(),
+        repeat::many::<String, _, _>(choice((char::alpha_num(), char::char('_')))),
+    )
+        .map(|(c, rest)| format!("{}{}", c, rest).to_uppercase())
+}
+
+// Recognizes a device name, like "M:OUTTMP", or a per-device constant,
+// like "M:OUTTMP.OFFSET". Unlike DRF device names, a '-' isn't allowed
+// since it would be ambiguous with subtraction.
+
+fn parse_device<Input>() -> impl Parser<Input, Output = Expr>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        attempt((char::letter(), char::char(':'))),
+        repeat::many1::<String, _, _>(choice((char::alpha_num(), one_of("_:".chars())))),
+        optional(char::char('.').with(parse_ident())),
+    )
+        .map(|((c, _), name, constant)| {
+            let device = format!("{}:{}", c, name).to_uppercase();
+
+            match constant {
+                Some(constant) => Expr::Constant(format!("{}.{}", device, constant)),
+                None => Expr::Device(device),
+            }
+        })
+}
+
+fn parse_binop<Input>(ops: &'static str) -> impl Parser<Input, Output = Op>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    lex(one_of(ops.chars())).map(|c| match c {
+        '+' => Op::Add,
+        '-' => Op::Sub,
+        '*' => Op::Mul,
+        '/' => Op::Div,
+        '^' => Op::Pow,
+        _ => unreachable!(),
+    })
+}
+
+fn binary(op: Op) -> impl Fn(Expr, Expr) -> Expr {
+    move |l, r| Expr::Binary(op, Box::new(l), Box::new(r))
+}
+
+combine::parser! {
+    fn parse_factor[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char>]
+    {
+        choice((
+            lex(parse_number()),
+            lex(parse_device()),
+            (
+                lex(parse_ident()),
+                optional(between(
+                    lex(char::char('(')),
+                    lex(char::char(')')),
+                    sep_by(parse_expr(), lex(char::char(','))),
+                )),
+            )
+                .map(|(name, args)| match args {
+                    Some(args) => Expr::Call(name, args),
+                    None => Expr::Constant(name),
+                }),
+            between(lex(char::char('(')), lex(char::char(')')), parse_expr()),
+            lex(char::char('-')).with(parse_power()).map(|e| Expr::Neg(Box::new(e))),
+        ))
+    }
+}
+
+combine::parser! {
+    fn parse_power[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char>]
+    {
+        repeat::chainr1(parse_factor(), parse_binop("^").map(binary))
+    }
+}
+
+combine::parser! {
+    pub fn parse_expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char>]
+    {
+        let term = repeat::chainl1(parse_power(), parse_binop("*/").map(binary));
+
+        repeat::chainl1(term, parse_binop("+-").map(binary))
+    }
+}
+
+// Returns a parser for a complete expression, including any leading
+// whitespace.
+
+pub fn parser<Input>() -> impl Parser<Input, Output = Expr>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    char::spaces().with(parse_expr())
+}
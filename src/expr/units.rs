@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Engineering units are represented as a product of base symbols,
+// each raised to an integer power. Symbols aren't interpreted so any
+// unit a data source reports can be used; two quantities are
+// compatible only if their symbols and powers match exactly.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Unit(BTreeMap<String, i32>);
+
+// Common spellings, as data sources report them, which name the same
+// unit.
+
+const ALIASES: &[(&str, &str)] = &[
+    ("AMP", "A"),
+    ("AMPS", "A"),
+    ("VOLT", "V"),
+    ("VOLTS", "V"),
+    ("SEC", "s"),
+    ("SECS", "s"),
+    ("DEG", "deg"),
+    ("DEGREES", "deg"),
+];
+
+impl Unit {
+    // Returns the unit of a dimensionless quantity.
+
+    pub fn none() -> Unit {
+        Unit::default()
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Parses unit text of the form "A*V/s^2". Whitespace and "1" (as
+    // in "1/s") are ignored. An empty string is dimensionless.
+
+    pub fn parse(text: &str) -> Result<Unit, String> {
+        let mut result = Unit::none();
+
+        for (n, part) in text.split('/').enumerate() {
+            let sign = if n == 0 { 1 } else { -1 };
+
+            for factor in part.split('*').map(str::trim) {
+                if factor.is_empty() || factor == "1" {
+                    continue;
+                }
+
+                let (sym, pow) = match factor.split_once('^') {
+                    Some((s, p)) => (
+                        s.trim(),
+                        p.trim()
+                            .parse::<i32>()
+                            .map_err(|_| format!("bad exponent in unit '{}'", text))?,
+                    ),
+                    None => (factor, 1),
+                };
+
+                if !sym.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '%') {
+                    return Err(format!("bad unit '{}'", text));
+                }
+                result.add(Unit::canonical_symbol(sym), sign * pow);
+            }
+        }
+        Ok(result)
+    }
+
+    fn canonical_symbol(sym: &str) -> String {
+        let upper = sym.to_uppercase();
+
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == upper)
+            .map(|(_, v)| String::from(*v))
+            .unwrap_or_else(|| String::from(sym))
+    }
+
+    fn add(&mut self, sym: String, pow: i32) {
+        let total = self.0.get(&sym).copied().unwrap_or(0) + pow;
+
+        if total == 0 {
+            self.0.remove(&sym);
+        } else {
+            self.0.insert(sym, total);
+        }
+    }
+
+    pub fn mul(&self, other: &Unit) -> Unit {
+        let mut result = self.clone();
+
+        for (sym, pow) in &other.0 {
+            result.add(sym.clone(), *pow)
+        }
+        result
+    }
+
+    pub fn div(&self, other: &Unit) -> Unit {
+        self.mul(&other.powi(-1))
+    }
+
+    // Returns the n-th root of the unit, if every power is divisible
+    // by `n`.
+
+    pub fn root(&self, n: i32) -> Option<Unit> {
+        if self.0.values().all(|p| p % n == 0) {
+            Some(Unit(self.0.iter().map(|(s, p)| (s.clone(), p / n)).collect()))
+        } else {
+            None
+        }
+    }
+
+    pub fn powi(&self, n: i32) -> Unit {
+        Unit(self.0.iter().map(|(s, p)| (s.clone(), p * n)).filter(|(_, p)| *p != 0).collect())
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt_part = |pos: bool| {
+            self.0
+                .iter()
+                .filter(|(_, p)| (**p > 0) == pos)
+                .map(|(s, p)| match p.abs() {
+                    1 => s.clone(),
+                    n => format!("{}^{}", s, n),
+                })
+                .collect::<Vec<_>>()
+                .join("*")
+        };
+        let num = fmt_part(true);
+        let den = fmt_part(false);
+
+        match (num.is_empty(), den.is_empty()) {
+            (true, true) => Ok(()),
+            (false, true) => write!(f, "{}", num),
+            (true, false) => write!(f, "1/{}", den),
+            (false, false) => write!(f, "{}/{}", num, den),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_parsing() {
+        let data = &[
+            ("", ""),
+            ("A", "A"),
+            ("Amps", "A"),
+            ("V/A", "V/A"),
+            ("m/s^2", "m/s^2"),
+            ("1/s", "1/s"),
+            ("A*A", "A^2"),
+            ("A*s/s", "A"),
+            ("DegF", "DegF"),
+        ];
+
+        for &(text, result) in data {
+            assert_eq!(Unit::parse(text).unwrap().to_string(), result, "\n input: {}", text);
+        }
+
+        assert!(Unit::parse("m^x").is_err());
+        assert!(Unit::parse("m+s").is_err());
+    }
+
+    #[test]
+    fn test_unit_arithmetic() {
+        let a = Unit::parse("A").unwrap();
+        let v = Unit::parse("V").unwrap();
+
+        assert_eq!(v.mul(&a).to_string(), "A*V");
+        assert_eq!(v.div(&a).to_string(), "V/A");
+        assert!(a.div(&a).is_none());
+        assert_eq!(a.powi(2).to_string(), "A^2");
+        assert!(a.powi(0).is_none());
+        assert_eq!(a.powi(2).root(2), Some(a.clone()));
+        assert_eq!(a.root(2), None);
+    }
+}
@@ -0,0 +1,207 @@
+// A gzip (RFC 1952) compressor, for the files output rotates out.
+// The data is one deflate (RFC 1951) block with the fixed Huffman
+// codes, after LZ77 matching against the previous 32 kB. That's a
+// good deal less than the dynamic codes of `gzip -9` would save, but
+// the repetitive text of CSV and JSON output still shrinks severalfold
+// and any gzip reader can expand it.
+
+// The length codes (257 on) and the distance codes: their smallest
+// value and number of extra bits.
+
+const LENGTHS: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0), (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2),
+    (27, 2), (31, 2), (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4), (131, 5), (163, 5), (195, 5),
+    (227, 5), (258, 0),
+];
+
+const DISTANCES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2), (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5),
+    (129, 6), (193, 6), (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10), (4097, 11),
+    (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+
+// How many earlier positions are tried for each match; more finds
+// longer matches, slower.
+
+const CHAIN: usize = 64;
+
+// Returns the CRC-32 (IEEE) of the data.
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ u32::from(*b), |c, _| if c & 1 == 1 { (c >> 1) ^ 0xedb8_8320 } else { c >> 1 })
+    })
+}
+
+// Deflate packs bits from the least significant end of each byte,
+// with Huffman codes written from their most significant bit.
+
+struct Bits {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8
+        }
+    }
+
+    fn code(&mut self, code: u32, len: u32) {
+        self.put(code.reverse_bits() >> (32 - len), len)
+    }
+
+    fn literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    // Writes a match of `len` bytes, `dist` bytes back.
+
+    fn matched(&mut self, len: usize, dist: usize) {
+        let code = LENGTHS.iter().rposition(|(base, _)| usize::from(*base) <= len).unwrap();
+        let (base, extra) = LENGTHS[code];
+
+        self.literal(257 + code as u16);
+        self.put((len - usize::from(base)) as u32, u32::from(extra));
+
+        let code = DISTANCES.iter().rposition(|(base, _)| usize::from(*base) <= dist).unwrap();
+        let (base, extra) = DISTANCES[code];
+
+        self.code(code as u32, 5);
+        self.put((dist - usize::from(base)) as u32, u32::from(extra))
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8)
+        }
+        self.out
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    ((usize::from(data[0]) << 10) ^ (usize::from(data[1]) << 5) ^ usize::from(data[2])) & (WINDOW - 1)
+}
+
+// Returns the data as a raw deflate stream.
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut bits = Bits { out: Vec::with_capacity(data.len() / 2), bits: 0, count: 0 };
+    let mut head = vec![usize::MAX; WINDOW];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + 3 <= data.len() {
+            let h = hash(&data[i..]);
+
+            prev[i % WINDOW] = head[h];
+            head[h] = i
+        }
+    };
+    let mut i = 0;
+
+    // A single, final block with the fixed codes.
+
+    bits.put(1, 1);
+    bits.put(1, 2);
+    while i < data.len() {
+        let (mut len, mut dist) = (0, 0);
+
+        if i + 3 <= data.len() {
+            let limit = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(&data[i..])];
+
+            for _ in 0..CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW {
+                    break;
+                }
+
+                let n = data[candidate..].iter().zip(&data[i..i + limit]).take_while(|(a, b)| a == b).count();
+
+                if n > len {
+                    len = n;
+                    dist = i - candidate;
+                    if n == limit {
+                        break;
+                    }
+                }
+
+                // A slot reused by a later position ends the chain.
+
+                let next = prev[candidate % WINDOW];
+
+                if next >= candidate {
+                    break;
+                }
+                candidate = next
+            }
+        }
+
+        if len >= 3 {
+            bits.matched(len, dist);
+            for j in i..i + len {
+                insert(j, &mut head, &mut prev)
+            }
+            i += len
+        } else {
+            bits.literal(u16::from(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1
+        }
+    }
+    bits.literal(256);
+    bits.finish()
+}
+
+// Returns the data as a gzip member.
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        // "a" is the literal 0x61 (code 0x91, 8 bits) and the end of
+        // the block (code 0, 7 bits), after the 3 header bits.
+
+        assert_eq!(deflate(b"a"), [0x4b, 0x04, 0x00]);
+
+        // A run is a literal and a match one byte back.
+
+        assert_eq!(deflate(&[0; 100]), [0x63, 0xa0, 0x03, 0x00, 0x00]);
+
+        let text: Vec<u8> =
+            (0..2000).flat_map(|i| format!("1700000000.{:06},M:OUTTMP,,{}\n", i, i % 7).into_bytes()).collect();
+        let gz = compress(&text);
+
+        assert_eq!(gz[..3], [0x1f, 0x8b, 8]);
+        assert_eq!(gz[gz.len() - 4..], (text.len() as u32).to_le_bytes());
+        assert!(gz.len() * 4 < text.len());
+    }
+}
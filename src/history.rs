@@ -0,0 +1,389 @@
+use crate::{
+    data::{Reading, Value},
+    error::Error,
+};
+use drf::Request;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Parses a time given on the command line: seconds since the epoch,
+// with an optional fraction, or an RFC 3339 timestamp. The result is
+// in microseconds since the epoch.
+
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    crate::capture::parse_timestamp(text.trim())
+        .or_else(|| crate::timefmt::parse_iso(text.trim()))
+        .ok_or_else(|| format!("bad time '{}'", text))
+}
+
+// Parses a length of time written for people rather than in a DRF
+// request: one or more numbers, each with a unit -- d, h, m (or min),
+// s, ms or us -- which are added up, e.g. "250ms", "1.5s" or "1m30s"
+// (or "1m 30s"). A plain number is in seconds. Unlike in a request's
+// PERIOD, "m" is minutes, which is why this isn't part of `drf`.
+
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let t = text.trim().to_ascii_lowercase();
+    let bad = || format!("bad duration '{}'", text);
+    let number = |n: &str| n.parse::<f64>().ok().filter(|v| v.is_finite() && *v >= 0.0);
+    let mut nanos = 0.0;
+
+    if let Some(v) = number(&t) {
+        nanos = v * 1e9
+    } else {
+        let mut rest = t.as_str();
+
+        while !rest.is_empty() {
+            let n = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(bad)?;
+            let u = rest[n..].find(|c: char| !c.is_ascii_alphabetic()).map_or(rest.len(), |u| n + u);
+            let scale = match &rest[n..u] {
+                "d" => 86400e9,
+                "h" => 3600e9,
+                "m" | "min" => 60e9,
+                "s" => 1e9,
+                "ms" => 1e6,
+                "us" => 1e3,
+                _ => return Err(bad()),
+            };
+
+            nanos += number(&rest[..n]).ok_or_else(bad)? * scale;
+            rest = rest[u..].trim_start()
+        }
+    }
+
+    if t.is_empty() || nanos.round() >= u64::MAX as f64 {
+        Err(bad())
+    } else {
+        Ok(Duration::from_nanos(nanos.round() as u64))
+    }
+}
+
+// Parses the length of a span of time, like "30m", "6h", "1d" or
+// "1h30m", into microseconds. Plain numbers are seconds.
+
+pub fn parse_span(text: &str) -> Result<u64, String> {
+    parse_duration(text)
+        .ok()
+        .and_then(|d| u64::try_from(d.as_micros()).ok())
+        .filter(|v| *v >= 1)
+        .ok_or_else(|| format!("bad time span '{}'", text))
+}
+
+// Splits the time range [start, end) into consecutive sub-ranges no
+// longer than `size`. Without a size, the whole range is returned.
+
+pub fn chunks(start: u64, end: u64, size: Option<u64>) -> Vec<(u64, u64)> {
+    match size {
+        Some(size) if size > 0 => (start..end)
+            .step_by(size.min(usize::MAX as u64) as usize)
+            .map(|s| (s, s.saturating_add(size).min(end)))
+            .collect(),
+        _ => vec![(start, end)],
+    }
+}
+
+// Describes a historical query. The range [start, end) is in
+// microseconds since the epoch. Loggers limit the number of points
+// in a reply so, if `chunk` is given, the range of each request is
+// fetched in pieces no longer than it. Up to `jobs` pieces are
+// fetched at once.
+
+pub struct Query {
+    pub start: u64,
+    pub end: u64,
+    pub chunk: Option<u64>,
+    pub jobs: usize,
+}
+
+type Fetched = Result<Vec<Reading>, Error>;
+
+// Fetches the data of every request over the query's range and
+// merges the results in timestamp order. Readings with the same
+// timestamp stay in request order. `fetch` is given the index of the
+// request, which it should use as the index of its readings, and the
+// sub-range to retrieve. Readings outside the sub-range are dropped
+// so a logger which includes both end points doesn't produce
+// duplicates at the seams. `progress` is called with the number of
+// pieces done and the total after each piece arrives. If any fetch
+// fails, the first error (in request order) is returned.
+
+pub fn fetch_all<F, P>(requests: &[Request], query: &Query, progress: P, fetch: F) -> Result<Vec<Reading>, Error>
+where
+    F: Fn(usize, &Request, u64, u64) -> Fetched + Sync,
+    P: Fn(usize, usize) + Sync,
+{
+    let pieces = chunks(query.start, query.end, query.chunk);
+    let total = requests.len() * pieces.len();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Fetched>>> = Mutex::new(vec![None; total]);
+
+    thread::scope(|s| {
+        for _ in 0..query.jobs.clamp(1, total.max(1)) {
+            s.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+
+                if idx >= total {
+                    break;
+                }
+
+                let (req, (start, end)) = (idx / pieces.len(), pieces[idx % pieces.len()]);
+                let result = fetch(req, &requests[req], start, end)
+                    .map(|v| v.into_iter().filter(|r| r.timestamp >= start && r.timestamp < end).collect());
+
+                results.lock().unwrap()[idx] = Some(result);
+                progress(done.fetch_add(1, Ordering::Relaxed) + 1, total)
+            });
+        }
+    });
+
+    let mut all = vec![];
+
+    for result in results.into_inner().unwrap().into_iter().flatten() {
+        all.extend(result?)
+    }
+    all.sort_by_key(|r| r.timestamp);
+    Ok(all)
+}
+
+// How `bin()` reduces the points of an interval to one.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Agg {
+    Mean,
+    Min,
+    Max,
+    Last,
+}
+
+impl Agg {
+    pub fn parse(text: &str) -> Result<Agg, String> {
+        match text {
+            "mean" => Ok(Agg::Mean),
+            "min" => Ok(Agg::Min),
+            "max" => Ok(Agg::Max),
+            "last" => Ok(Agg::Last),
+            _ => Err(format!("bad aggregate '{}' (expected mean, min, max or last)", text)),
+        }
+    }
+
+    // Reduces values to one, or NaN if there are none.
+
+    fn apply(self, values: impl Iterator<Item = f64>) -> f64 {
+        let (n, sum, min, max, last) = values.fold((0, 0.0, f64::INFINITY, f64::NEG_INFINITY, f64::NAN), |acc, v| {
+            (acc.0 + 1, acc.1 + v, acc.2.min(v), acc.3.max(v), v)
+        });
+
+        match self {
+            _ if n == 0 => f64::NAN,
+            Agg::Mean => sum / f64::from(n),
+            Agg::Min => min,
+            Agg::Max => max,
+            Agg::Last => last,
+        }
+    }
+}
+
+// Reduces the readings of a channel in one interval to one, stamped
+// with the start of the interval. Arrays are reduced element by
+// element (to the length of the shortest) and NaNs are ignored.
+// Readings without numbers (failures, text, etc.) only count when
+// the interval has nothing else, or for `Agg::Last`, in which case
+// the last of them is kept.
+
+fn reduce(start: u64, readings: Vec<Reading>, agg: Agg) -> Reading {
+    let numeric: Vec<&Reading> = readings.iter().filter(|r| !r.value.as_slice().is_empty()).collect();
+    let mut reading = match numeric.last() {
+        Some(last) if agg != Agg::Last => {
+            let width = numeric.iter().map(|r| r.value.as_slice().len()).min().unwrap_or(0);
+            let values: Vec<f64> = (0..width)
+                .map(|i| agg.apply(numeric.iter().map(|r| r.value.as_slice()[i]).filter(|v| !v.is_nan())))
+                .collect();
+            let value = if numeric.iter().all(|r| matches!(r.value, Value::Scalar(_))) {
+                Value::Scalar(values[0])
+            } else {
+                Value::Array(values)
+            };
+
+            Reading { value, ..(*last).clone() }
+        }
+        _ => readings[readings.len() - 1].clone(),
+    };
+
+    reading.timestamp = start;
+    reading
+}
+
+// Bins the readings of each channel into intervals `every`
+// microseconds long, aligned to the epoch, and reduces each bin to
+// one reading. Only the first `channels` requests are binned; the
+// readings of the rest (e.g. the states device of --tag-state) pass
+// unchanged. No logger client exists to do this at the source, so
+// the points are all fetched and binned here. The result is in
+// timestamp order.
+
+pub fn bin(readings: Vec<Reading>, every: u64, agg: Agg, channels: usize) -> Vec<Reading> {
+    let mut bins: BTreeMap<(u64, usize), Vec<Reading>> = BTreeMap::new();
+    let mut all = vec![];
+
+    for r in readings {
+        if r.index < channels {
+            bins.entry((r.timestamp - r.timestamp % every, r.index)).or_default().push(r)
+        } else {
+            all.push(r)
+        }
+    }
+    all.extend(bins.into_iter().map(|((start, _), readings)| reduce(start, readings, agg)));
+    all.sort_by_key(|r| r.timestamp);
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drf::parse_drf;
+
+    #[test]
+    fn test_durations() {
+        let data = &[
+            ("250ms", Duration::from_millis(250)),
+            ("1.5s", Duration::from_millis(1500)),
+            ("1m30s", Duration::from_secs(90)),
+            ("1H 30MIN", Duration::from_secs(5400)),
+            ("2d", Duration::from_secs(172800)),
+            ("10us", Duration::from_micros(10)),
+            ("90", Duration::from_secs(90)),
+            ("0.25", Duration::from_millis(250)),
+        ];
+
+        for &(text, d) in data {
+            assert_eq!(parse_duration(text), Ok(d), "{}", text);
+        }
+
+        for text in &["", "s", "1x", "1.2.3s", "-1s", "1m30", "ms250", "1e30d"] {
+            assert_eq!(parse_duration(text), Err(format!("bad duration '{}'", text)));
+        }
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(parse_time("1700000000"), Ok(1_700_000_000_000_000));
+        assert_eq!(parse_time("1.5"), Ok(1_500_000));
+        assert_eq!(parse_time("1970-01-01T00:00:01.5-00:00"), Ok(1_500_000));
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let requests: Vec<Request> = ["M:OUTTMP", "G:AMANDA", "Z:ACLTST"]
+            .iter()
+            .map(|d| parse_drf(d).unwrap())
+            .collect();
+        let query = Query {
+            start: 0,
+            end: 30,
+            chunk: None,
+            jobs: 2,
+        };
+
+        // Each fetch returns a point every 10us, including both end
+        // points of its range.
+
+        let fetch = |idx: usize, _: &Request, start: u64, end: u64| {
+            Ok((start..=end)
+                .step_by(10)
+                .map(|ts| Reading::new(idx, ts + idx as u64, 0.0.into()))
+                .collect())
+        };
+        let all = fetch_all(&requests, &query, |_, _| (), fetch).unwrap();
+
+        assert_eq!(all.len(), 9);
+        assert!(all.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert_eq!(all[..3].iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        // Chunked queries return the same data.
+
+        let calls = AtomicUsize::new(0);
+        let chunked = Query {
+            chunk: Some(10),
+            ..query
+        };
+        let progress = |done: usize, total: usize| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            assert!(done <= total && total == 9)
+        };
+
+        assert_eq!(fetch_all(&requests, &chunked, progress, fetch).unwrap(), all);
+        assert_eq!(calls.into_inner(), 9);
+
+        let failing = |idx: usize, req: &Request, start: u64, end: u64| {
+            if idx == 0 {
+                fetch(idx, req, start, end)
+            } else {
+                Err(Error::Timeout(format!("{}", idx)))
+            }
+        };
+
+        assert_eq!(fetch_all(&requests, &chunked, |_, _| (), failing), Err(Error::Timeout(String::from("1"))));
+    }
+
+    #[test]
+    fn test_chunks() {
+        assert_eq!(chunks(0, 25, Some(10)), vec![(0, 10), (10, 20), (20, 25)]);
+        assert_eq!(chunks(5, 25, None), vec![(5, 25)]);
+        assert_eq!(parse_span("1d"), Ok(86_400_000_000));
+        assert_eq!(parse_span("30m"), Ok(1_800_000_000));
+        assert_eq!(parse_span("90"), Ok(90_000_000));
+        assert_eq!(parse_span("500ms"), Ok(500_000));
+        assert_eq!(parse_span("1h30m"), Ok(5_400_000_000));
+        assert!(parse_span("0").is_err());
+        assert!(parse_span("soon").is_err());
+    }
+
+    #[test]
+    fn test_bin() {
+        let readings = vec![
+            Reading::new(0, 5, 1.0.into()),
+            Reading::new(1, 7, vec![1.0, 8.0, 3.0].into()),
+            Reading::new(0, 8, 3.0.into()),
+            Reading::new(2, 9, 4.0.into()),
+            Reading::new(0, 9, f64::NAN.into()),
+            Reading::new(1, 12, vec![5.0, 2.0].into()),
+            Reading::new(0, 14, Value::Status(-6)),
+            Reading::new(1, 15, vec![3.0, 6.0].into()),
+            Reading::new(0, 31, 7.0.into()),
+        ];
+        let binned = |agg| {
+            bin(readings.clone(), 10, agg, 2)
+                .into_iter()
+                .map(|r| (r.index, r.timestamp, r.value))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            binned(Agg::Mean),
+            vec![
+                (0, 0, 2.0.into()),
+                (1, 0, vec![1.0, 8.0, 3.0].into()),
+                (2, 9, 4.0.into()),
+                (0, 10, Value::Status(-6)),
+                (1, 10, vec![4.0, 4.0].into()),
+                (0, 30, 7.0.into()),
+            ]
+        );
+        assert_eq!(binned(Agg::Min)[0].2, 1.0.into());
+        assert_eq!(binned(Agg::Max)[4].2, vec![5.0, 6.0].into());
+        // The last point of a bin is kept, whatever it is.
+
+        let last = binned(Agg::Last);
+
+        assert!(last[0].2.as_slice()[0].is_nan());
+        assert_eq!(last[4].2, vec![3.0, 6.0].into());
+        assert_eq!(Agg::parse("max"), Ok(Agg::Max));
+        assert!(Agg::parse("median").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{error::Error, json::Json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tracing::debug;
+
+// A plain HTTP/1.1 client, used by the sinks which write to web
+// services (InfluxDB) or to TCP servers. Only plain `http://` URLs
+// are supported.
+
+// Splits a "SCHEME://HOST[:PORT][/PATH]" URL into its host, port
+// (80 by default) and path. The secure form of the scheme is
+// rejected, since there's no TLS.
+
+pub fn parse_url<'a>(url: &'a str, scheme: &str) -> Result<(&'a str, u16, &'a str), String> {
+    let rest = match url.split_once("://") {
+        Some((s, rest)) if s == scheme => rest,
+        Some((s, _)) if s == format!("{}s", scheme) => {
+            return Err(format!("{}: secure connections ({}://) aren't supported", url, s))
+        }
+        _ => return Err(format!("{}: expected a {}:// URL", url, scheme)),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(n) => rest.split_at(n),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().map_err(|_| format!("{}: bad port '{}'", url, p))?),
+        None => (authority, 80),
+    };
+
+    if host.is_empty() {
+        return Err(format!("{}: no host", url));
+    }
+    Ok((host, port, path))
+}
+
+// Opens a TCP connection to the server of `url`, whose reads time out
+// after `timeout`.
+
+pub fn connect(url: &str, host: &str, port: u16, timeout: Duration) -> Result<TcpStream, Error> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| Error::Connection(format!("can't resolve {}: {}", host, e)))?
+        .next()
+        .ok_or_else(|| Error::Connection(format!("no address for {}", host)))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| match Error::from(e) {
+        Error::Connection(m) | Error::Other(m) => Error::Connection(format!("can't connect to {}: {}", url, m)),
+        e => e,
+    })?;
+
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    debug!(%addr, url, "connected");
+    Ok(stream)
+}
+
+// Percent-encodes a query parameter.
+
+pub fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Reads the body of a response sent with chunked transfer coding.
+
+fn read_chunked(stream: &mut impl BufRead) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+
+    loop {
+        let mut line = String::new();
+
+        stream.read_line(&mut line)?;
+
+        let size = line.split(';').next().map(str::trim).and_then(|s| usize::from_str_radix(s, 16).ok());
+
+        match size {
+            Some(0) => return Ok(body),
+            Some(n) => {
+                let start = body.len();
+
+                body.resize(start + n, 0);
+                stream.read_exact(&mut body[start..])?;
+                stream.read_line(&mut line)?;
+            }
+            None => return Err(Error::Connection(format!("bad chunk size '{}'", line.trim()))),
+        }
+    }
+}
+
+// Sends an HTTP request to `url` and returns the JSON object it's
+// answered with (null for an empty reply.) `authorization` is the
+// request's `Authorization` header line, if it has one. HTTP errors
+// become the errors they correspond to. Only JSON bodies are logged;
+// forms may hold credentials.
+
+pub fn exchange(
+    url: &str,
+    method: &str,
+    authorization: &str,
+    content_type: &str,
+    body: &str,
+    timeout: Duration,
+) -> Result<Json, Error> {
+    let (host, port, path) = parse_url(url, "http").map_err(Error::Parse)?;
+    let mut stream = BufReader::new(connect(url, host, port, timeout)?);
+
+    debug!(method, url, body = if content_type == "application/json" { body } else { "<redacted>" }, "sending HTTP request");
+    write!(
+        stream.get_mut(),
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nAccept: application/json\r\nConnection: close\r\n{}\
+         Content-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        host,
+        port,
+        authorization,
+        content_type,
+        body.len(),
+        body
+    )?;
+
+    let mut status = String::new();
+    let mut length = None;
+    let mut chunked = false;
+
+    stream.read_line(&mut status)?;
+    loop {
+        let mut line = String::new();
+
+        if stream.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => length = value.trim().parse::<u64>().ok(),
+                "transfer-encoding" => chunked = value.trim().eq_ignore_ascii_case("chunked"),
+                _ => (),
+            }
+        }
+    }
+
+    let body = match (chunked, length) {
+        (true, _) => read_chunked(&mut stream)?,
+        (false, Some(n)) => {
+            let mut body = vec![];
+
+            stream.take(n).read_to_end(&mut body)?;
+            body
+        }
+        (false, None) => {
+            let mut body = vec![];
+
+            stream.read_to_end(&mut body)?;
+            body
+        }
+    };
+    let text = String::from_utf8_lossy(&body);
+    let code = status.split_whitespace().nth(1).and_then(|c| c.parse::<u16>().ok());
+    let failed = || format!("{} answered {}: {}", url, status.trim(), text.trim());
+
+    match code {
+        Some(200..=299) if text.trim().is_empty() => Ok(Json::Null),
+        Some(200..=299) => Json::parse(&text).map_err(|e| Error::Connection(format!("bad reply from {}: {}", url, e))),
+        Some(401 | 403) => Err(Error::PermissionDenied(failed())),
+        Some(404) => Err(Error::DeviceNotFound(failed())),
+        Some(408 | 504) => Err(Error::Timeout(failed())),
+        Some(500..=599) | None => Err(Error::Connection(failed())),
+        Some(_) => Err(Error::Other(failed())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    // Plays an HTTP server: each connection gets the next response
+    // and its request is sent back through the channel.
+
+    fn serve(responses: Vec<&'static str>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/acsys/", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(conn.try_clone().unwrap());
+                let mut request = String::new();
+                let mut length = 0;
+
+                loop {
+                    let mut line = String::new();
+
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(n) = line.strip_prefix("Content-Length:") {
+                        length = n.trim().parse().unwrap()
+                    }
+                    request += &line
+                }
+
+                let mut body = vec![0; length];
+
+                reader.read_exact(&mut body).unwrap();
+                request += &String::from_utf8(body).unwrap();
+                tx.send(request).unwrap();
+                conn.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url("http://lab.example.gov/api", "http"), Ok(("lab.example.gov", 80, "/api")));
+        assert_eq!(parse_url("http://lab.example.gov:8086", "http"), Ok(("lab.example.gov", 8086, "/")));
+        assert!(parse_url("https://lab.example.gov/api", "http").is_err());
+        assert!(parse_url("http://:80/api", "http").is_err());
+    }
+
+    #[test]
+    fn test_exchange() {
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Length: 22\r\n\r\n{\"access_token\":\"new\"}",
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 7\r\n\r\nexpired",
+            "HTTP/1.1 204 No Content\r\n\r\n",
+        ]);
+        let reply = exchange(
+            &url,
+            "POST",
+            "Authorization: Token abc\r\n",
+            "application/x-www-form-urlencoded",
+            "a=1",
+            Duration::from_secs(5),
+        );
+
+        assert_eq!(reply.unwrap().get("access_token").and_then(Json::as_str), Some("new"));
+
+        let request = requests.recv().unwrap();
+
+        assert!(request.contains("\r\nAuthorization: Token abc\r\n"));
+        assert!(request.contains("\r\nContent-Type: application/x-www-form-urlencoded\r\n"));
+        assert!(matches!(
+            exchange(&url, "GET", "", "application/json", "", Duration::from_secs(5)),
+            Err(Error::PermissionDenied(_))
+        ));
+        assert!(!requests.recv().unwrap().contains("Authorization"));
+        assert_eq!(exchange(&url, "POST", "", "text/plain", "x", Duration::from_secs(5)), Ok(Json::Null));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    data::{Reading, Value},
+    error::Error,
+    http, output,
+};
+use drf::Request;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+// Output in InfluxDB's line protocol, for feeding dashboards. Each
+// reading is a point of the device's measurement,
+//
+//    M:OUTTMP value=72.5,status=0i 1700000000000000000
+//
+// with the timestamp in nanoseconds. Array elements are points with
+// an "index" tag and failed requests have only a status. Values which
+// aren't numbers (text, raw bytes and alarm blocks) are written to a
+// "text" field so they don't clash with the numeric "value" one.
+
+// Escapes a measurement name, a tag's key or value, and a field
+// string, respectively.
+
+fn measurement(text: &str) -> String {
+    text.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+fn tag(text: &str) -> String {
+    measurement(text).replace('=', "\\=")
+}
+
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Builds a line from the measurement, its tags (which are sorted, as
+// InfluxDB prefers), fields and timestamp in microseconds.
+
+fn line(name: &str, tags: &[(&str, String)], fields: &[String], timestamp: u64) -> String {
+    let mut tags: Vec<String> = tags.iter().map(|(k, v)| format!(",{}={}", tag(k), tag(v))).collect();
+
+    tags.sort();
+    format!("{}{} {} {}", measurement(name), tags.concat(), fields.join(","), u128::from(timestamp) * 1000)
+}
+
+// Returns the "value" field of a number, unless it's NaN or infinite,
+// which line protocol can't hold.
+
+fn value(v: f64) -> Option<String> {
+    Some(v).filter(|v| v.is_finite()).map(|v| format!("value={}", v))
+}
+
+// Returns the lines for a reply to `req`. `tags` are added to each of
+// them, e.g. the machine state.
+
+pub fn lines(req: &Request, reading: &Reading, tags: &[(&str, String)]) -> Vec<String> {
+    let device = reading.meta.device.clone().unwrap_or_else(|| req.device.name().into_owned());
+    let status = format!("status={}i", reading.meta.status);
+    let text = |t: &str| vec![line(&device, tags, &[format!("text={}", string(t)), status.clone()], reading.timestamp)];
+
+    match &reading.value {
+        Value::Scalar(v) => {
+            let fields: Vec<String> = value(*v).into_iter().chain(Some(status.clone())).collect();
+
+            vec![line(&device, tags, &fields, reading.timestamp)]
+        }
+        Value::Array(v) => {
+            let (start, data) = output::elements(&req.range, v);
+
+            data.iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let tags: Vec<(&str, String)> =
+                        tags.iter().cloned().chain(Some(("index", (start + i).to_string()))).collect();
+                    let fields: Vec<String> = value(*v).into_iter().chain(Some(status.clone())).collect();
+
+                    line(&device, &tags, &fields, reading.timestamp)
+                })
+                .collect()
+        }
+        Value::Raw(v) => text(&v.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")),
+        Value::Text(v) => text(v),
+        Value::Alarm(a) => text(&a.format_text()),
+        Value::Status(s) => vec![line(&device, tags, &[format!("status={}i", s)], reading.timestamp)],
+    }
+}
+
+// Returns the line for a value of a derived channel.
+
+pub fn derived(name: &str, timestamp: u64, v: f64, tags: &[(&str, String)]) -> Option<String> {
+    value(v).map(|v| line(name, tags, &[v], timestamp))
+}
+
+// Points are sent in batches of this many lines, or sooner once the
+// oldest has waited this long.
+
+const BATCH: usize = 5000;
+const BATCH_AGE: Duration = Duration::from_secs(1);
+
+// Writes points to a bucket of an InfluxDB (2.x) server with its
+// write API,
+//
+//    POST <url>/api/v2/write?bucket=BUCKET&org=ORG&precision=ns
+//    Authorization: Token TOKEN
+//
+// The token and organization are taken from the INFLUX_TOKEN and
+// INFLUX_ORG environment variables, which the `influx` command also
+// uses.
+
+pub struct Server {
+    url: String,
+    authorization: String,
+    timeout: Duration,
+    batch: Vec<String>,
+    oldest: Instant,
+}
+
+impl Server {
+    pub fn new(url: &str, bucket: &str, timeout: Duration) -> Result<Server, Error> {
+        http::parse_url(url, "http").map_err(Error::Parse)?;
+
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let org = var("INFLUX_ORG").map(|o| format!("&org={}", http::encode(&o))).unwrap_or_default();
+        let authorization = var("INFLUX_TOKEN").map(|t| format!("Authorization: Token {}\r\n", t)).unwrap_or_default();
+
+        Ok(Server {
+            url: format!("{}/api/v2/write?bucket={}{}&precision=ns", url.trim_end_matches('/'), http::encode(bucket), org),
+            authorization,
+            timeout,
+            batch: vec![],
+            oldest: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, line: String) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            self.oldest = Instant::now()
+        }
+        self.batch.push(line);
+        if self.batch.len() >= BATCH || self.oldest.elapsed() >= BATCH_AGE {
+            self.flush()?
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let body = self.batch.join("\n");
+
+        http::exchange(&self.url, "POST", &self.authorization, "text/plain; charset=utf-8", &body, self.timeout)
+            .map_err(|e| match e {
+                Error::Connection(m) => Error::Connection(format!("can't write to InfluxDB: {}", m)),
+                e => e,
+            })?;
+        debug!(lines = self.batch.len(), "wrote points to InfluxDB");
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines() {
+        let req = drf::parse_drf("B:BPM[2:3]").unwrap();
+        let state = [("state", String::from("STORE"))];
+
+        assert_eq!(
+            lines(&req, &Reading::new(0, 1_500_000, Value::Array(vec![1.5, f64::NAN])), &state),
+            [
+                "B:BPM,index=2,state=STORE value=1.5,status=0i 1500000000",
+                "B:BPM,index=3,state=STORE status=0i 1500000000"
+            ]
+        );
+        assert_eq!(
+            lines(&req, &Reading::new(0, 1, Value::Text(String::from("say \"hi\""))), &[]),
+            ["B:BPM text=\"say \\\"hi\\\"\",status=0i 1000"]
+        );
+        assert_eq!(lines(&req, &Reading::new(0, 1, Value::Status(-6)), &[]), ["B:BPM status=-6i 1000"]);
+        assert_eq!(derived("a b,c", 2, 3.0, &[("state", String::from("x=1"))]), Some(String::from("a\\ b\\,c,state=x\\=1 value=3 2000")));
+        assert_eq!(derived("d", 2, f64::INFINITY, &[]), None);
+    }
+}
@@ -0,0 +1,388 @@
+use tracing::warn;
+
+
+// Linear conversions between common engineering units. A value is
+// converted to the base unit of its dimension with `v * scale +
+// offset`.
+
+const CONVERSIONS: &[(&str, &str, f64, f64)] = &[
+    ("K", "temperature", 1.0, 0.0),
+    ("DEGC", "temperature", 1.0, 273.15),
+    ("DEGF", "temperature", 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0),
+    ("M", "length", 1.0, 0.0),
+    ("CM", "length", 1e-2, 0.0),
+    ("MM", "length", 1e-3, 0.0),
+    ("UM", "length", 1e-6, 0.0),
+    ("IN", "length", 0.0254, 0.0),
+    ("A", "current", 1.0, 0.0),
+    ("MA", "current", 1e-3, 0.0),
+    ("UA", "current", 1e-6, 0.0),
+    ("V", "voltage", 1.0, 0.0),
+    ("KV", "voltage", 1e3, 0.0),
+    ("MV", "voltage", 1e-3, 0.0),
+    ("S", "time", 1.0, 0.0),
+    ("MS", "time", 1e-3, 0.0),
+    ("US", "time", 1e-6, 0.0),
+];
+
+fn conversion(units: &str) -> Option<&'static (&'static str, &'static str, f64, f64)> {
+    let units = units.to_uppercase();
+
+    CONVERSIONS.iter().find(|c| c.0 == units)
+}
+
+// Converts `v` from the units `from` to the units `to`. `None` is
+// returned if either unit is unknown or if they measure different
+// quantities.
+
+pub fn convert(v: f64, from: &str, to: &str) -> Option<f64> {
+    let (_, fd, fs, fo) = conversion(from)?;
+    let (_, td, ts, to) = conversion(to)?;
+
+    if fd == td {
+        Some((v * fs + fo - to) / ts)
+    } else {
+        None
+    }
+}
+
+// A request along with the acquisition overrides that apply to it.
+// `label` replaces the DRF in the output, `deadband` suppresses
+// values which differ from the last one shown by less than the given
+// amount, and `convert` names the units in which to display the
+// value.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub request: drf::Request,
+    pub label: Option<String>,
+    pub deadband: Option<f64>,
+    pub convert: Option<String>,
+}
+
+impl Job {
+    pub fn new(request: drf::Request) -> Job {
+        Job {
+            request,
+            label: None,
+            deadband: None,
+            convert: None,
+        }
+    }
+
+    // Returns the job in the canonical form of a request file line:
+    // the canonical DRF followed by the overrides, in a fixed order.
+
+    pub fn canonical(&self) -> String {
+        self.render(self.request.canonical())
+    }
+
+    // Like `canonical()`, but clock events are written by name (see
+    // `drf::Request::symbolic()`.)
+
+    pub fn symbolic(&self, events: &drf::ClockEvents) -> String {
+        self.render(self.request.symbolic(events))
+    }
+
+    fn render(&self, drf: String) -> String {
+        let mut overrides = vec![];
+
+        if let Some(label) = &self.label {
+            overrides.push(format!("label=\"{}\"", label))
+        }
+        if let Some(db) = self.deadband {
+            overrides.push(format!("deadband={}", db))
+        }
+        if let Some(units) = &self.convert {
+            overrides.push(format!("convert={}", units))
+        }
+
+        if overrides.is_empty() {
+            drf
+        } else {
+            format!("{} | {}", drf, overrides.join(" "))
+        }
+    }
+
+    // Applies one `key=value` override to the job.
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), String> {
+        match key {
+            "label" => self.label = Some(value),
+            "deadband" => {
+                self.deadband = Some(
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|v| *v >= 0.0)
+                        .ok_or_else(|| format!("bad deadband '{}'", value))?,
+                )
+            }
+            "convert" => {
+                if conversion(&value).is_none() {
+                    return Err(format!("can't convert to unknown units '{}'", value));
+                }
+                self.convert = Some(value)
+            }
+            _ => return Err(format!("unknown override '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+// Splits the override portion of a request line into `key=value`
+// pairs. Values may be enclosed in double quotes to include spaces.
+
+fn split_overrides(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut result = vec![];
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=' && !c.is_whitespace()))
+            .collect();
+
+        if chars.next() != Some('=') {
+            return Err(format!("override '{}' must be of the form key=value", key));
+        }
+
+        let value: String = if chars.next_if_eq(&'"').is_some() {
+            let v = std::iter::from_fn(|| chars.next_if(|c| *c != '"')).collect();
+
+            if chars.next() != Some('"') {
+                return Err(format!("unterminated quote in override '{}'", key));
+            }
+            v
+        } else {
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect()
+        };
+
+        result.push((key.to_lowercase(), value));
+    }
+    Ok(result)
+}
+
+// Parses one line of a request file. Blank lines and lines starting
+// with '#' return `None`. Otherwise, the line holds a DRF request,
+// optionally followed by '|' and overrides, e.g.
+//
+//     M:OUTTMP@p,1s | label="Outdoor" deadband=0.1 convert=degF
+//
+// Requests which weren't taken literally (see `drf::Warning`) are
+// reported as warnings.
+
+pub fn parse_line(line: &str) -> Result<Option<Job>, String> {
+    parse_line_with(line, &drf::ParseOptions::default())
+}
+
+// Like `parse_line()`, but the request is parsed with `options`.
+
+fn parse_line_with(line: &str, options: &drf::ParseOptions) -> Result<Option<Job>, String> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    // A '|' is also the basic status qualifier (e.g. "M|OUTTMP") so
+    // the separator is only recognized if the text in front of it is
+    // a valid request.
+
+    let (drf, overrides) = match line.rsplit_once(" | ").or_else(|| line.rsplit_once('|')) {
+        Some((d, o)) if drf::parse_with(d.trim(), options).is_ok() => (d.trim(), o),
+        _ => (line, ""),
+    };
+    let parsed = drf::parse_with(drf, options).map_err(|e| drf::describe(drf, &e))?;
+
+    for w in &parsed.warnings {
+        warn!(drf, "{}", w)
+    }
+
+    let mut job = Job::new(parsed.request);
+
+    for (k, v) in split_overrides(overrides)? {
+        job.set(&k, v)?
+    }
+    Ok(Some(job))
+}
+
+// Parses the contents of a request file into jobs. Errors are
+// prefixed with the line number on which they were found.
+
+pub fn parse_file(text: &str) -> Result<Vec<Job>, String> {
+    let mut result = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        if let Some(job) = parse_line(line).map_err(|e| format!("line {}: {}", n + 1, e))? {
+            result.push(job)
+        }
+    }
+    Ok(result)
+}
+
+// Rewrites the contents of a request file in canonical form. Each
+// request is canonicalized, duplicates are removed and the requests
+// are sorted (stably) by their canonical text. Comments directly
+// above a request move with it; comments at the top of the file
+// (separated from the first request by a blank line) and at the end
+// of the file stay in place. Clock events may be named by `events`;
+// if `symbolic` is set, they're written by name too.
+
+pub fn format_file(text: &str, events: &drf::ClockEvents, symbolic: bool) -> Result<String, String> {
+    let options = drf::ParseOptions { strict: false, clock_events: Some(events) };
+    let mut header: Vec<&str> = vec![];
+    let mut entries: Vec<(String, Vec<&str>)> = vec![];
+    let mut pending: Vec<&str> = vec![];
+
+    for (n, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() {
+            if entries.is_empty() && header.is_empty() {
+                header.append(&mut pending)
+            }
+        } else if trimmed.starts_with('#') {
+            pending.push(trimmed)
+        } else if let Some(job) = parse_line_with(line, &options).map_err(|e| format!("line {}: {}", n + 1, e))? {
+            let canonical = if symbolic { job.symbolic(events) } else { job.canonical() };
+
+            match entries.iter_mut().find(|(c, _)| *c == canonical) {
+                Some((_, comments)) => comments.append(&mut pending),
+                None => entries.push((canonical, std::mem::take(&mut pending))),
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut result = String::new();
+
+    for line in &header {
+        result.push_str(line);
+        result.push('\n');
+    }
+    if !header.is_empty() && (!entries.is_empty() || !pending.is_empty()) {
+        result.push('\n');
+    }
+    for (canonical, comments) in &entries {
+        for line in comments {
+            result.push_str(line);
+            result.push('\n');
+        }
+        result.push_str(canonical);
+        result.push('\n');
+    }
+    for line in &pending {
+        result.push_str(line);
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_lines() {
+        let job = parse_line("M:OUTTMP@p,1s | label=\"Outdoor temp\" deadband=0.1 convert=degF")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(job.request.canonical(), "M:OUTTMP.READING.SCALED@P,1S,TRUE");
+        assert_eq!(job.label.as_deref(), Some("Outdoor temp"));
+        assert_eq!(job.deadband, Some(0.1));
+        assert_eq!(job.convert.as_deref(), Some("degF"));
+
+        let job = parse_line("M|OUTTMP").unwrap().unwrap();
+
+        assert_eq!(job, Job::new(drf::parse_drf("M|OUTTMP").unwrap()));
+
+        let job = parse_line("M|OUTTMP|label=x").unwrap().unwrap();
+
+        assert_eq!(job.request.canonical(), "M:OUTTMP.STATUS.ALL");
+        assert_eq!(job.label.as_deref(), Some("x"));
+
+        assert_eq!(parse_line("  # comment"), Ok(None));
+        assert_eq!(parse_line(""), Ok(None));
+        assert!(parse_line("M:OUTTMP | label").is_err());
+        assert!(parse_line("M:OUTTMP | color=red").is_err());
+        assert!(parse_line("M:OUTTMP | deadband=-1").is_err());
+        assert!(parse_line("M:OUTTMP | convert=furlongs").is_err());
+        assert!(parse_line("M:OUTTMP | label=\"open").is_err());
+        assert!(parse_line("M:OUTTMP@Z").is_err());
+    }
+
+    #[test]
+    fn test_request_files() {
+        let jobs = parse_file("# devices\nM:OUTTMP\n\nG:AMANDA | label=amanda\n").unwrap();
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(
+            parse_file("M:OUTTMP\nM:OUTTMP@Z"),
+            Err(String::from(
+                "line 2: bad DRF request 'M:OUTTMP@Z' \
+                 (at offset 9: unexpected 'Z'; expected 'N', 'I', 'P', 'Q', 'E', 'S' or 'A')"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_file_formatting() {
+        let text = "# Shared device list\n\
+                    \n\
+                    # outdoor\n\
+                    M:OUTTMP@p,1s|label=Outdoor\n\
+                    G:AMANDA\n\
+                    # again\n\
+                    G:AMANDA.READING\n\
+                    # trailer\n";
+        let none = drf::ClockEvents::default();
+
+        assert_eq!(
+            format_file(text, &none, false).unwrap(),
+            "# Shared device list\n\
+             \n\
+             # again\n\
+             G:AMANDA.READING.SCALED\n\
+             # outdoor\n\
+             M:OUTTMP.READING.SCALED@P,1S,TRUE | label=\"Outdoor\"\n\
+             # trailer\n"
+        );
+        assert_eq!(format_file("", &none, false).unwrap(), "");
+        assert!(format_file("M:OUTTMP\nbad junk\n", &none, false).is_err());
+
+        // Formatting is idempotent.
+
+        let once = format_file(text, &none, false).unwrap();
+
+        assert_eq!(format_file(&once, &none, false).unwrap(), once);
+
+        // Clock events may be read, and written, by name.
+
+        let events = drf::ClockEvents::parse("MI_RESET = 29").unwrap();
+
+        assert_eq!(
+            format_file("M:OUTTMP@e,mi_reset | label=x\nG:AMANDA@E,0\n", &events, true).unwrap(),
+            "G:AMANDA.READING.SCALED@E,SUPERCYCLE,E,0\nM:OUTTMP.READING.SCALED@E,MI_RESET,E,0 | label=\"x\"\n"
+        );
+        assert_eq!(format_file("M:OUTTMP@E,MI_RESET\n", &events, false).unwrap(), "M:OUTTMP.READING.SCALED@E,29,E,0\n");
+    }
+
+    #[test]
+    fn test_conversions() {
+        let near = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-9;
+
+        assert!(near(convert(100.0, "degC", "degF"), 212.0));
+        assert!(near(convert(32.0, "DegF", "K"), 273.15));
+        assert!(near(convert(1.5, "m", "mm"), 1500.0));
+        assert_eq!(convert(1.0, "m", "A"), None);
+        assert_eq!(convert(1.0, "furlong", "m"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::output::{self, json_string};
+use crate::json::Json;
+use crate::timefmt::Formatter;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+// The settings journal: an audit trail of every setting `put` and
+// `control` were asked to make, one JSON object per line, e.g.
+//
+//    {"timestamp":1700000000.000000,"user":"op","drf":"Z:DEV.SETTING.SCALED",
+//     "old":"1.5","new":"2","result":"sent"}
+//
+// `old` is the value read before the setting (null if it couldn't be
+// read) and `result` one of "sent", "failed" (with the error in
+// "error"), "declined" or "dry-run". Records are only ever appended.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub timestamp: u64,
+    pub user: String,
+    pub drf: String,
+    pub old: Option<String>,
+    pub new: String,
+    pub result: Outcome,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Sent,
+    Failed(String),
+    Declined,
+    DryRun,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Sent => "sent",
+            Outcome::Failed(_) => "failed",
+            Outcome::Declined => "declined",
+            Outcome::DryRun => "dry-run",
+        }
+    }
+}
+
+// Returns the name of the user running the program.
+
+pub fn user() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| String::from("unknown"))
+}
+
+// Opens the journal for appending, creating it (and its directory) if
+// needed. This is done before a setting is sent, so a setting which
+// couldn't be recorded isn't made.
+
+pub fn open(path: &Path) -> Result<File, String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("can't create {}: {}", dir.display(), e))?
+    }
+    OpenOptions::new().create(true).append(true).open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))
+}
+
+// Appends an entry to the journal.
+
+pub fn append(file: &mut File, e: &Entry) -> Result<(), String> {
+    writeln!(file, "{}", format_json(e)).map_err(|e| format!("can't write to the journal: {}", e))
+}
+
+pub fn format_json(e: &Entry) -> String {
+    format!(
+        "{{\"timestamp\":{},\"user\":{},\"drf\":{},\"old\":{},\"new\":{},\"result\":{}{}}}",
+        Formatter::default().json(e.timestamp),
+        json_string(&e.user),
+        json_string(&e.drf),
+        e.old.as_deref().map(json_string).unwrap_or_else(|| String::from("null")),
+        json_string(&e.new),
+        json_string(e.result.name()),
+        match &e.result {
+            Outcome::Failed(m) => format!(",\"error\":{}", json_string(m)),
+            _ => String::new(),
+        }
+    )
+}
+
+// Parses one line of the journal.
+
+pub fn parse_entry(line: &str) -> Result<Entry, String> {
+    let j = Json::parse(line)?;
+    let text = |key: &str| j.get(key).and_then(Json::as_str).map(String::from).ok_or_else(|| format!("no \"{}\"", key));
+
+    Ok(Entry {
+        timestamp: j
+            .get("timestamp")
+            .and_then(Json::as_f64)
+            .map(|t| (t * 1e6).round() as u64)
+            .ok_or_else(|| String::from("no \"timestamp\""))?,
+        user: text("user")?,
+        drf: text("drf")?,
+        old: j.get("old").and_then(Json::as_str).map(String::from),
+        new: text("new")?,
+        result: match text("result")?.as_str() {
+            "sent" => Outcome::Sent,
+            "failed" => Outcome::Failed(text("error").unwrap_or_default()),
+            "declined" => Outcome::Declined,
+            "dry-run" => Outcome::DryRun,
+            r => return Err(format!("unknown result '{}'", r)),
+        },
+    })
+}
+
+// Parses the journal. Blank lines are skipped and errors are prefixed
+// with the line number on which they were found.
+
+pub fn parse_file(text: &str) -> Result<Vec<Entry>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| parse_entry(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+// Formats an entry for review. Text output is a line with the time,
+// user, request, old and new values and the result; JSON output is
+// the entry as it's stored, with timestamps written by `tf`.
+
+pub fn format(fmt: output::Format, tf: &Formatter, e: &Entry) -> String {
+    match fmt {
+        output::Format::Text => format!(
+            "{} {} {} {} -> {} {}{}",
+            tf.format(e.timestamp),
+            e.user,
+            e.drf,
+            e.old.as_deref().unwrap_or("(unknown)"),
+            e.new,
+            e.result.name(),
+            match &e.result {
+                Outcome::Failed(m) => format!(": {}", m),
+                _ => String::new(),
+            }
+        ),
+        output::Format::Json => {
+            let line = format_json(e);
+            let (_, rest) = line.split_once(',').unwrap();
+
+            format!("{{\"timestamp\":{},{}", tf.json(e.timestamp), rest)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let mut e = Entry {
+            timestamp: 1_700_000_000_123_456,
+            user: String::from("op"),
+            drf: String::from("Z:DEV.SETTING.SCALED"),
+            old: Some(String::from("1.5")),
+            new: String::from("2"),
+            result: Outcome::Sent,
+        };
+
+        assert_eq!(
+            format_json(&e),
+            "{\"timestamp\":1700000000.123456,\"user\":\"op\",\"drf\":\"Z:DEV.SETTING.SCALED\",\"old\":\"1.5\",\
+             \"new\":\"2\",\"result\":\"sent\"}"
+        );
+        assert_eq!(parse_entry(&format_json(&e)), Ok(e.clone()));
+        assert_eq!(
+            format(output::Format::Text, &Formatter::default(), &e),
+            "1700000000.123456 op Z:DEV.SETTING.SCALED 1.5 -> 2 sent"
+        );
+
+        e.old = None;
+        e.result = Outcome::Failed(String::from("timed out"));
+
+        assert!(format_json(&e).ends_with("\"old\":null,\"new\":\"2\",\"result\":\"failed\",\"error\":\"timed out\"}"));
+        assert_eq!(parse_entry(&format_json(&e)), Ok(e.clone()));
+        assert_eq!(
+            format(output::Format::Text, &Formatter::default(), &e),
+            "1700000000.123456 op Z:DEV.SETTING.SCALED (unknown) -> 2 failed: timed out"
+        );
+
+        let iso = Formatter::new(Some("iso"), Some("utc")).unwrap();
+
+        assert!(format(output::Format::Json, &iso, &e).starts_with("{\"timestamp\":\"2023-11-14T22:13:20.123456"));
+        assert_eq!(parse_file("\n"), Ok(vec![]));
+        assert_eq!(parse_file("{}").unwrap_err(), "line 1: no \"timestamp\"");
+    }
+}
@@ -0,0 +1,233 @@
+// A JSON value, as read from a server or a file. Objects keep their
+// members in order.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut p = Parser { text: text.as_bytes(), pos: 0 };
+        let v = p.value()?;
+
+        p.space();
+        if p.pos < p.text.len() {
+            return Err(format!("unexpected text at offset {}", p.pos));
+        }
+        Ok(v)
+    }
+
+    // Returns the member of an object.
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn space(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.pos) {
+            self.pos += 1
+        }
+    }
+
+    fn error<T>(&self, what: &str) -> Result<T, String> {
+        Err(format!("expected {} at offset {}", what, self.pos))
+    }
+
+    fn literal(&mut self, word: &str, v: Json) -> Result<Json, String> {
+        if self.text[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(v)
+        } else {
+            self.error(word)
+        }
+    }
+
+    // Parses the elements of an array or the members of an object,
+    // which end with `close`.
+
+    fn list<T>(&mut self, close: u8, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut items = vec![];
+
+        self.pos += 1;
+        self.space();
+        if self.text.get(self.pos) == Some(&close) {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            self.space();
+            match self.text.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(c) if *c == close => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                _ => return self.error(&format!("',' or '{}'", close as char)),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.space();
+        match self.text.get(self.pos) {
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => self.list(b']', Self::value).map(Json::Array),
+            Some(b'{') => self
+                .list(b'}', |p| {
+                    p.space();
+                    if p.text.get(p.pos) != Some(&b'"') {
+                        return p.error("a member name");
+                    }
+
+                    let key = p.string()?;
+
+                    p.space();
+                    if p.text.get(p.pos) != Some(&b':') {
+                        return p.error("':'");
+                    }
+                    p.pos += 1;
+                    Ok((key, p.value()?))
+                })
+                .map(Json::Object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.error("a value"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.text.get(self.pos) {
+            self.pos += 1
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| format!("bad number at offset {}", start))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).and_then(|d| std::str::from_utf8(d).ok());
+        let v = digits.and_then(|d| u32::from_str_radix(d, 16).ok());
+
+        match v {
+            Some(v) => {
+                self.pos += 4;
+                Ok(v)
+            }
+            None => self.error("4 hex digits"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut s = Vec::new();
+
+        self.pos += 1;
+        loop {
+            match self.text.get(self.pos) {
+                None => return self.error("'\"'"),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+
+                    let c = match self.text.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.pos += 1;
+
+                            let mut code = self.hex4()?;
+
+                            // A high surrogate is followed by the low
+                            // one.
+
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (self.hex4()?.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            self.pos -= 1;
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return self.error("an escape"),
+                    };
+
+                    s.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                }
+                Some(c) => s.push(*c),
+            }
+            self.pos += 1
+        }
+        self.pos += 1;
+        String::from_utf8(s).map_err(|_| String::from("text isn't UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let v = Json::parse(" {\"type\": \"data\", \"id\": 3, \"value\": [1.5, -2e3, true, null], \"x\": {}} ").unwrap();
+
+        assert_eq!(v.get("type").and_then(Json::as_str), Some("data"));
+        assert_eq!(v.get("id").and_then(Json::as_f64), Some(3.0));
+        assert_eq!(
+            v.get("value"),
+            Some(&Json::Array(vec![Json::Number(1.5), Json::Number(-2000.0), Json::Bool(true), Json::Null]))
+        );
+        assert_eq!(v.get("x"), Some(&Json::Object(vec![])));
+        assert_eq!(v.get("missing"), None);
+
+        assert_eq!(Json::parse(r#""a\"b\\c\n\u00e9\ud83d\ude00é""#), Ok(Json::String(String::from("a\"b\\c\né😀é"))));
+        assert_eq!(Json::parse("[]"), Ok(Json::Array(vec![])));
+
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("\"open").is_err());
+        assert!(Json::parse("1 2").is_err());
+        assert!(Json::parse("tru").is_err());
+        assert!(Json::parse("--1").is_err());
+    }
+}
@@ -0,0 +1,698 @@
+use crate::data::{Reading, Value};
+use crate::{error::Error, http, output, retry};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+// Publishes readings to a Kafka topic. Only what a producer needs of
+// Kafka's protocol is implemented: the cluster's metadata is asked
+// for (Metadata, version 4) to learn the topic's partitions and their
+// leaders, and records are sent to the leaders in batches (Produce,
+// version 3, with version 2 record batches.) Each record's key is the
+// device, which picks its partition as Kafka's own clients do, so a
+// device's readings stay in order.
+//
+// A batch which can't be delivered is retried (as the `retries`
+// setting says) after fetching the metadata again, since its
+// partitions may have moved. Partitions which were written aren't
+// sent again.
+
+const METADATA: i16 = 3;
+const PRODUCE: i16 = 0;
+const CLIENT_ID: &str = "acsys";
+
+// Records are sent in batches of this many, or sooner once the oldest
+// has waited this long.
+
+const BATCH: usize = 1000;
+const BATCH_AGE: Duration = Duration::from_secs(1);
+
+// How a record's value is encoded.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Json,
+    Avro,
+}
+
+impl Encoding {
+    pub fn parse(text: &str) -> Result<Encoding, String> {
+        match text.to_lowercase().as_str() {
+            "json" => Ok(Encoding::Json),
+            "avro" => Ok(Encoding::Avro),
+            _ => Err(format!("unknown encoding '{}'", text)),
+        }
+    }
+}
+
+// Avro records have this schema. The value is null for a failed
+// request, whose status is then set, `start` is the index of an
+// array's first element and `state` is the machine state, with
+// --tag-state.
+//
+//    {"type":"record","name":"Reading","namespace":"gov.fnal.acsys","fields":[
+//      {"name":"timestamp","type":{"type":"long","logicalType":"timestamp-micros"}},
+//      {"name":"drf","type":"string"},
+//      {"name":"device","type":"string"},
+//      {"name":"value","type":["null","double",{"type":"array","items":"double"},"string","bytes"]},
+//      {"name":"start","type":"int"},
+//      {"name":"status","type":"int"},
+//      {"name":"state","type":["null","string"]}]}
+
+// Avro writes strings and bytes after their length.
+
+fn avro_bytes(out: &mut Vec<u8>, b: &[u8]) {
+    varlong(out, b.len() as i64);
+    out.extend(b)
+}
+
+// Encodes a reading of `req` as an Avro record.
+
+pub fn avro(req: &drf::Request, reading: &Reading, state: Option<&str>) -> Vec<u8> {
+    let device = reading.meta.device.clone().unwrap_or_else(|| req.device.name().into_owned());
+
+    avro_record(reading.timestamp, &req.canonical(), &device, &reading.value, &req.range, reading.meta.status, state)
+}
+
+// Encodes a value of a derived channel as an Avro record, whose DRF
+// and device are the channel's name.
+
+pub fn avro_derived(name: &str, timestamp: u64, v: f64, state: Option<&str>) -> Vec<u8> {
+    avro_record(timestamp, name, name, &Value::Scalar(v), &drf::Range::Full, 0, state)
+}
+
+fn avro_record(
+    timestamp: u64,
+    drf: &str,
+    device: &str,
+    value: &Value,
+    range: &drf::Range,
+    status: i16,
+    state: Option<&str>,
+) -> Vec<u8> {
+    let mut out = vec![];
+    let mut start = 0;
+
+    varlong(&mut out, timestamp as i64);
+    avro_bytes(&mut out, drf.as_bytes());
+    avro_bytes(&mut out, device.as_bytes());
+    match value {
+        Value::Scalar(v) => {
+            varlong(&mut out, 1);
+            out.extend(v.to_le_bytes())
+        }
+        Value::Array(v) => {
+            let (first, data) = output::elements(range, v);
+
+            start = first;
+            varlong(&mut out, 2);
+            if !data.is_empty() {
+                varlong(&mut out, data.len() as i64);
+                data.iter().for_each(|v| out.extend(v.to_le_bytes()));
+            }
+            varlong(&mut out, 0)
+        }
+        Value::Text(v) => {
+            varlong(&mut out, 3);
+            avro_bytes(&mut out, v.as_bytes())
+        }
+        Value::Alarm(a) => {
+            varlong(&mut out, 3);
+            avro_bytes(&mut out, a.format_text().as_bytes())
+        }
+        Value::Raw(v) => {
+            varlong(&mut out, 4);
+            avro_bytes(&mut out, v)
+        }
+        Value::Status(_) => varlong(&mut out, 0),
+    }
+    varlong(&mut out, start as i64);
+    varlong(
+        &mut out,
+        match value {
+            Value::Status(s) => (*s).into(),
+            _ => status.into(),
+        },
+    );
+    match state {
+        Some(s) => {
+            varlong(&mut out, 1);
+            avro_bytes(&mut out, s.as_bytes())
+        }
+        None => varlong(&mut out, 0),
+    }
+    out
+}
+
+// Kafka's records and Avro write integers as zigzag-encoded LEB128
+// varints.
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7
+    }
+    out.push(n as u8)
+}
+
+fn varlong(out: &mut Vec<u8>, n: i64) {
+    varint(out, ((n << 1) ^ (n >> 63)) as u64)
+}
+
+// The CRC-32C (Castagnoli) checksum of record batches.
+
+fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ u32::from(*b), |c, _| if c & 1 != 0 { (c >> 1) ^ 0x82f6_3b78 } else { c >> 1 })
+    })
+}
+
+// Kafka's default partitioner hashes keys with MurmurHash2; using it
+// puts a device's records in the partition other producers would.
+
+fn murmur2(data: &[u8]) -> i32 {
+    const M: u32 = 0x5bd1_e995;
+    let mut h = 0x9747_b28c ^ data.len() as u32;
+    let chunks = data.chunks_exact(4);
+    let tail = chunks.remainder();
+
+    for c in chunks {
+        let mut k = u32::from_le_bytes([c[0], c[1], c[2], c[3]]).wrapping_mul(M);
+
+        k ^= k >> 24;
+        h = h.wrapping_mul(M) ^ k.wrapping_mul(M)
+    }
+    if !tail.is_empty() {
+        for (i, b) in tail.iter().enumerate().rev() {
+            h ^= u32::from(*b) << (8 * i)
+        }
+        h = h.wrapping_mul(M)
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    (h ^ (h >> 15)) as i32
+}
+
+fn partition(key: &[u8], partitions: usize) -> usize {
+    (murmur2(key) & 0x7fff_ffff) as usize % partitions
+}
+
+// A record to publish: its key, value and timestamp (microseconds
+// since the epoch.)
+
+struct Record {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    timestamp: u64,
+}
+
+// Encodes records as a record batch (of "magic" version 2.)
+
+fn record_batch(records: &[&Record]) -> Vec<u8> {
+    let millis = |r: &Record| (r.timestamp / 1000) as i64;
+    let first = records.iter().map(|r| millis(r)).min().unwrap_or(0);
+    let last = records.iter().map(|r| millis(r)).max().unwrap_or(0);
+    let mut body = vec![];
+
+    body.extend(0i16.to_be_bytes()); // attributes: uncompressed, create time
+    body.extend((records.len() as i32 - 1).to_be_bytes());
+    body.extend(first.to_be_bytes());
+    body.extend(last.to_be_bytes());
+    body.extend((-1i64).to_be_bytes()); // no producer ID,
+    body.extend((-1i16).to_be_bytes()); // epoch
+    body.extend((-1i32).to_be_bytes()); // or sequence
+    body.extend((records.len() as i32).to_be_bytes());
+    for (i, r) in records.iter().enumerate() {
+        let mut record = vec![0];
+
+        varlong(&mut record, millis(r) - first);
+        varlong(&mut record, i as i64);
+        varlong(&mut record, r.key.len() as i64);
+        record.extend(&r.key);
+        varlong(&mut record, r.value.len() as i64);
+        record.extend(&r.value);
+        varlong(&mut record, 0);
+        varlong(&mut body, record.len() as i64);
+        body.extend(record)
+    }
+
+    let mut batch = vec![];
+
+    batch.extend(0i64.to_be_bytes()); // base offset
+    batch.extend((body.len() as i32 + 9).to_be_bytes());
+    batch.extend(0i32.to_be_bytes()); // partition leader epoch
+    batch.push(2);
+    batch.extend(crc32c(&body).to_be_bytes());
+    batch.extend(body);
+    batch
+}
+
+// Writes the primitive types of requests.
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn i16(&mut self, n: i16) -> &mut Encoder {
+        self.0.extend(n.to_be_bytes());
+        self
+    }
+
+    fn i32(&mut self, n: i32) -> &mut Encoder {
+        self.0.extend(n.to_be_bytes());
+        self
+    }
+
+    fn string(&mut self, s: &str) -> &mut Encoder {
+        self.i16(s.len() as i16);
+        self.0.extend(s.as_bytes());
+        self
+    }
+
+    fn bytes(&mut self, b: &[u8]) -> &mut Encoder {
+        self.i32(b.len() as i32);
+        self.0.extend(b);
+        self
+    }
+}
+
+// Reads the primitive types of responses.
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < n {
+            return Err(Error::Connection(String::from("bad reply from Kafka: it ended early")));
+        }
+
+        let (head, rest) = self.data.split_at(n);
+
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        self.take(2).map(|b| i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        self.take(4).map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        self.take(8).map(|b| i64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    fn string(&mut self) -> Result<Option<String>, Error> {
+        match self.i16()? {
+            n if n < 0 => Ok(None),
+            n => self.take(n as usize).map(|b| Some(String::from_utf8_lossy(b).into_owned())),
+        }
+    }
+
+    // Reads an array's length. A null array is empty.
+
+    fn len(&mut self) -> Result<usize, Error> {
+        self.i32().map(|n| n.max(0) as usize)
+    }
+}
+
+// Kafka's error codes which are worth retrying, after fetching the
+// metadata again, and those which need a word of explanation.
+
+fn broker_error(code: i16, what: &str) -> Error {
+    let text = match code {
+        3 => "unknown topic or partition",
+        5 => "leader not available",
+        6 => "not the leader",
+        7 => "request timed out",
+        10 => "message too large",
+        13 => "network exception",
+        19 | 20 => "not enough replicas",
+        29 => "not authorized for the topic",
+        _ => "",
+    };
+    let message = match text {
+        "" => format!("Kafka refused {} (error {})", what, code),
+        t => format!("Kafka refused {}: {} (error {})", what, t, code),
+    };
+
+    match code {
+        3 | 5 | 6 | 7 | 13 | 19 | 20 => Error::Connection(message),
+        29 => Error::PermissionDenied(message),
+        _ => Error::Other(message),
+    }
+}
+
+// A connection to one broker.
+
+struct Connection {
+    addr: String,
+    stream: TcpStream,
+    correlation: i32,
+}
+
+impl Connection {
+    fn open(addr: &str, timeout: Duration) -> Result<Connection, Error> {
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((h, p)) => (h, p.parse().map_err(|_| Error::Parse(format!("{}: bad port '{}'", addr, p)))?),
+            None => (addr, 9092),
+        };
+        let stream = http::connect(addr, host, port, timeout)?;
+
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(Connection { addr: String::from(addr), stream, correlation: 0 })
+    }
+
+    // Sends a request and returns its response's body.
+
+    fn call(&mut self, api: i16, version: i16, body: &[u8]) -> Result<Vec<u8>, Error> {
+        self.correlation += 1;
+
+        let mut request = Encoder::default();
+
+        request.i16(api).i16(version).i32(self.correlation).string(CLIENT_ID);
+        request.0.extend(body);
+
+        let mut frame = (request.0.len() as i32).to_be_bytes().to_vec();
+
+        frame.extend(request.0);
+        self.stream.write_all(&frame)?;
+
+        let mut size = [0; 4];
+
+        self.stream.read_exact(&mut size)?;
+
+        let mut response = vec![0; i32::from_be_bytes(size).max(0) as usize];
+
+        self.stream.read_exact(&mut response)?;
+
+        let mut d = Decoder { data: &response };
+
+        if d.i32()? != self.correlation {
+            return Err(Error::Connection(format!("bad reply from Kafka broker {}: it answered another request", self.addr)));
+        }
+        Ok(d.data.to_vec())
+    }
+}
+
+// The brokers leading each partition of the topic.
+
+#[derive(Debug, PartialEq)]
+struct Leaders(Vec<String>);
+
+fn metadata_request(topic: &str) -> Vec<u8> {
+    let mut e = Encoder::default();
+
+    e.i32(1).string(topic);
+    e.0.push(0); // don't create the topic
+    e.0
+}
+
+fn parse_metadata(topic: &str, body: &[u8]) -> Result<Leaders, Error> {
+    let mut d = Decoder { data: body };
+    let mut brokers = vec![];
+
+    d.i32()?; // throttle time
+    for _ in 0..d.len()? {
+        let id = d.i32()?;
+        let host = d.string()?.unwrap_or_default();
+        let port = d.i32()?;
+
+        d.string()?; // rack
+        brokers.push((id, format!("{}:{}", host, port)))
+    }
+    d.string()?; // cluster ID
+    d.i32()?; // controller
+
+    let mut leaders = vec![];
+
+    for _ in 0..d.len()? {
+        let error = d.i16()?;
+        let name = d.string()?.unwrap_or_default();
+
+        d.take(1)?; // is internal
+        for _ in 0..d.len()? {
+            d.i16()?;
+
+            let index = d.i32()?;
+            let leader = d.i32()?;
+
+            for _ in 0..2 {
+                let n = d.len()?;
+
+                d.take(4 * n)?; // replicas, in-sync replicas
+            }
+            if name == topic {
+                leaders.push((index, leader))
+            }
+        }
+        if name == topic && error != 0 {
+            return Err(broker_error(error, &format!("the metadata of topic {}", topic)));
+        }
+    }
+    if leaders.is_empty() {
+        return Err(broker_error(3, &format!("the metadata of topic {}", topic)));
+    }
+    leaders.sort_unstable();
+    leaders
+        .iter()
+        .enumerate()
+        .map(|(i, (index, leader))| match brokers.iter().find(|(id, _)| id == leader) {
+            Some((_, addr)) if *index as usize == i => Ok(addr.clone()),
+            _ => Err(broker_error(5, &format!("partition {} of topic {}", index, topic))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Leaders)
+}
+
+fn produce_request(topic: &str, acks: i16, timeout: Duration, partitions: &[(usize, Vec<u8>)]) -> Vec<u8> {
+    let mut e = Encoder::default();
+
+    e.i16(-1).i16(acks).i32(timeout.as_millis() as i32).i32(1).string(topic).i32(partitions.len() as i32);
+    for (index, batch) in partitions {
+        e.i32(*index as i32).bytes(batch);
+    }
+    e.0
+}
+
+// Returns the partitions a produce response says failed, each with
+// its error.
+
+fn parse_produce(topic: &str, body: &[u8]) -> Result<Vec<(usize, Error)>, Error> {
+    let mut d = Decoder { data: body };
+    let mut failed = vec![];
+
+    for _ in 0..d.len()? {
+        d.string()?;
+        for _ in 0..d.len()? {
+            let index = d.i32()?;
+            let error = d.i16()?;
+
+            d.i64()?; // base offset
+            d.i64()?; // log append time
+            if error != 0 {
+                failed.push((index as usize, broker_error(error, &format!("records for partition {} of topic {}", index, topic))))
+            }
+        }
+    }
+    Ok(failed)
+}
+
+pub struct Producer {
+    brokers: Vec<String>,
+    topic: String,
+    timeout: Duration,
+    policy: retry::Policy,
+    leaders: Option<Leaders>,
+    connections: Vec<Connection>,
+    batch: Vec<Record>,
+    oldest: Instant,
+}
+
+impl Producer {
+    // Returns a producer for `topic` of the cluster which `brokers`
+    // (a comma-separated list of HOST[:PORT]) belong to.
+
+    pub fn new(brokers: &str, topic: &str, timeout: Duration, retries: u32) -> Result<Producer, Error> {
+        let brokers: Vec<String> = brokers.split(',').map(str::trim).filter(|b| !b.is_empty()).map(String::from).collect();
+
+        if brokers.is_empty() {
+            return Err(Error::Parse(String::from("no Kafka brokers were given")));
+        }
+        Ok(Producer {
+            brokers,
+            topic: String::from(topic),
+            timeout,
+            policy: retry::Policy::new(retries),
+            leaders: None,
+            connections: vec![],
+            batch: vec![],
+            oldest: Instant::now(),
+        })
+    }
+
+    pub fn send(&mut self, key: &str, value: Vec<u8>, timestamp: u64) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            self.oldest = Instant::now()
+        }
+        self.batch.push(Record { key: key.as_bytes().to_vec(), value, timestamp });
+        if self.batch.len() >= BATCH || self.oldest.elapsed() >= BATCH_AGE {
+            self.flush()?
+        }
+        Ok(())
+    }
+
+    // Delivers the records held, retrying as the policy allows.
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let policy = self.policy.clone();
+
+        retry::call(&policy, || {
+            self.deliver().inspect_err(|_| {
+                self.leaders = None;
+                self.connections.clear()
+            })
+        })
+    }
+
+    fn connection(&mut self, addr: &str) -> Result<&mut Connection, Error> {
+        let n = match self.connections.iter().position(|c| c.addr == addr) {
+            Some(n) => n,
+            None => {
+                self.connections.push(Connection::open(addr, self.timeout)?);
+                self.connections.len() - 1
+            }
+        };
+
+        Ok(&mut self.connections[n])
+    }
+
+    // Learns which brokers lead the topic's partitions from the first
+    // broker that answers.
+
+    fn leaders(&mut self) -> Result<Vec<String>, Error> {
+        if let Some(Leaders(l)) = &self.leaders {
+            return Ok(l.clone());
+        }
+
+        let mut failure = None;
+
+        let request = metadata_request(&self.topic);
+
+        for addr in self.brokers.clone() {
+            let reply = self.connection(&addr).and_then(|c| c.call(METADATA, 4, &request));
+
+            match reply.and_then(|body| parse_metadata(&self.topic, &body)) {
+                Ok(leaders) => {
+                    info!(topic = %self.topic, partitions = leaders.0.len(), "found the topic's partitions");
+                    self.leaders = Some(leaders);
+                    return self.leaders();
+                }
+                Err(e) => failure = Some(e),
+            }
+        }
+        Err(failure.unwrap_or_else(|| Error::Connection(String::from("no Kafka broker answered"))))
+    }
+
+    // Sends the records held to their partitions' leaders, one
+    // leader at a time. Records are dropped once they've been
+    // written, so a retry only sends the rest.
+
+    fn deliver(&mut self) -> Result<(), Error> {
+        while !self.batch.is_empty() {
+            let leaders = self.leaders()?;
+            let parts: Vec<usize> = self.batch.iter().map(|r| partition(&r.key, leaders.len())).collect();
+            let addr = leaders[parts[0]].clone();
+            let mut batches = vec![];
+
+            for p in (0..leaders.len()).filter(|p| leaders[*p] == addr) {
+                let records: Vec<&Record> =
+                    self.batch.iter().zip(&parts).filter(|(_, q)| **q == p).map(|(r, _)| r).collect();
+
+                if !records.is_empty() {
+                    batches.push((p, record_batch(&records)))
+                }
+            }
+
+            let request = produce_request(&self.topic, -1, self.timeout, &batches);
+            let body = self.connection(&addr)?.call(PRODUCE, 3, &request)?;
+            let failed = parse_produce(&self.topic, &body)?;
+            let mut written = parts.iter().map(|p| leaders[*p] == addr && !failed.iter().any(|(q, _)| q == p));
+
+            debug!(broker = %addr, partitions = batches.len() - failed.len(), "wrote records to Kafka");
+            self.batch.retain(|_| !written.next().unwrap_or(false));
+            if let Some((_, e)) = failed.into_iter().next() {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(murmur2(b"21"), -973_932_308);
+        assert_eq!(murmur2(b"foobar"), -790_332_482);
+        assert_eq!(murmur2(b"a-little-bit-long-string"), -985_981_536);
+        assert_eq!(murmur2(b"abc"), 479_470_107);
+        assert!(partition(b"M:OUTTMP", 3) < 3);
+    }
+
+    #[test]
+    fn test_record_batch() {
+        let r = Record { key: b"k".to_vec(), value: b"v".to_vec(), timestamp: 1_500_000 };
+        let batch = record_batch(&[&r]);
+
+        assert_eq!(batch.len(), 61 + 9);
+        assert_eq!(batch[8..12], (batch.len() as i32 - 12).to_be_bytes());
+        assert_eq!(batch[16], 2);
+        assert_eq!(batch[17..21], crc32c(&batch[21..]).to_be_bytes());
+
+        // The record: its length, attributes, timestamp and offset
+        // deltas, key, value and no headers.
+
+        assert_eq!(batch[61..], [16, 0, 0, 0, 2, b'k', 2, b'v', 0]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut e = Encoder::default();
+
+        e.i32(0).i32(2).i32(1).string("kafka1").i32(9092).i16(-1).i32(2).string("kafka2").i32(9093).i16(-1);
+        e.i16(-1).i32(1).i32(1).i16(0).string("acsys");
+        e.0.push(0);
+        e.i32(2);
+        e.i16(0).i32(1).i32(2).i32(0).i32(0);
+        e.i16(0).i32(0).i32(1).i32(0).i32(0);
+
+        assert_eq!(parse_metadata("acsys", &e.0), Ok(Leaders(vec![String::from("kafka1:9092"), String::from("kafka2:9093")])));
+        assert!(matches!(parse_metadata("other", &e.0), Err(Error::Connection(_))));
+        assert!(matches!(parse_metadata("acsys", &e.0[..20]), Err(Error::Connection(_))));
+    }
+
+    #[test]
+    fn test_avro() {
+        let req = drf::parse_drf("B:BPM[2:3]").unwrap();
+        let record = avro(&req, &Reading::new(0, 1, Value::Array(vec![0.5, 1.0])), Some("S"));
+        let drf = req.canonical();
+        let mut expected = vec![2, drf.len() as u8 * 2];
+
+        expected.extend(drf.as_bytes());
+        expected.extend([10, b'B', b':', b'B', b'P', b'M', 4, 4]);
+        expected.extend(0.5f64.to_le_bytes());
+        expected.extend(1.0f64.to_le_bytes());
+        expected.extend([0, 4, 0, 2, 2, b'S']);
+        assert_eq!(record, expected);
+        assert_eq!(avro(&req, &Reading::new(0, 1, Value::Status(-6)), None)[drf.len() + 8..], [0, 0, 11, 0]);
+    }
+}
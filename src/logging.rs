@@ -0,0 +1,67 @@
+use tracing::Level;
+
+// Selects how diagnostic messages are rendered on stderr.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Format, String> {
+        match text.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown log format '{}'", text)),
+        }
+    }
+}
+
+// Maps the number of `-v` options to the most verbose level that gets
+// reported. With none, only warnings and errors are shown; `-v` adds
+// connection lifecycle and request IDs; `-vv` adds protocol frames.
+
+pub fn level(verbosity: u64) -> Level {
+    match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+// Installs the global subscriber. Log messages always go to stderr
+// so they never get mixed with the data written to stdout.
+
+pub fn init(verbosity: u64, format: Format) {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level(verbosity))
+        .with_writer(std::io::stderr)
+        .with_target(false);
+
+    match format {
+        Format::Text => builder.init(),
+        Format::Json => builder.json().init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!(Format::parse("text"), Ok(Format::Text));
+        assert_eq!(Format::parse("JSON"), Ok(Format::Json));
+        assert!(Format::parse("xml").is_err());
+    }
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(level(0), Level::WARN);
+        assert_eq!(level(1), Level::INFO);
+        assert_eq!(level(2), Level::DEBUG);
+        assert_eq!(level(5), Level::TRACE);
+    }
+}
@@ -1,6 +1,54 @@
-use clap::{clap_app, crate_name, crate_version};
+use clap::{clap_app, crate_name, crate_version, ArgMatches};
+use error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::process;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
 
-mod drf;
+mod acs;
+mod alarm;
+mod alarm_block;
+mod average;
+mod backend;
+mod bulk;
+mod capture;
+mod change;
+mod check;
+mod data;
+mod error;
+mod expr;
+mod gzip;
+mod history;
+mod http;
+mod influx;
+mod job;
+mod journal;
+mod json;
+mod kafka;
+mod logging;
+mod mock;
+mod monitor;
+mod output;
+mod overlay;
+mod parquet;
+mod plot;
+mod prometheus;
+mod put;
+mod replay;
+mod report;
+mod retry;
+mod rotate;
+mod selftest;
+mod settings;
+mod shell;
+mod snapshot;
+mod simulate;
+mod sink;
+mod states;
+mod stats;
+mod status;
+mod table;
+mod timefmt;
 
 // Returns a data type that handles all details of command line
 // arguments.
@@ -10,6 +58,35 @@ fn cmd_cfg() -> clap::App<'static, 'static> {
         (crate_name!()) =>
             (version: crate_version!())
             (about: "Command line utility to access Fermilab's accelerator data")
+            (after_help: "EXIT STATUS:\n    0  success\n    1  other error\n    2  parse error\n    3  connection failure\n    4  device not found\n    5  permission denied\n    6  timeout")
+
+            // These options override the connection settings found
+            // in the environment and the configuration file.
+
+            (@arg config: --config +takes_value +global "reads settings from the given file")
+            (@arg backend: --backend +takes_value +global "sets the data source: dpm (default, not yet available) or mock [env: ACSYS_BACKEND]")
+            (@arg dpm_host: --("dpm-host") +takes_value +global "sets the DPM host [env: ACSYS_DPM_HOST]")
+            (@arg role: --role +takes_value +global "sets the role used for settings [env: ACSYS_ROLE]")
+            (@arg timeout: --timeout +takes_value +global "sets the seconds to wait to connect or for a reply [env: ACSYS_TIMEOUT]")
+            (@arg retries: --retries +takes_value +global "sets how many times a failed connection is retried [env: ACSYS_RETRIES]")
+            (@arg journal: --journal +takes_value +global "sets the file settings are recorded in [env: ACSYS_JOURNAL]")
+
+            // These options control the diagnostic messages written
+            // to stderr.
+
+            (@arg verbose: -v --verbose +multiple +global "increases diagnostic output (-v: connections, -vv: protocol frames)")
+            (@arg log_format: --("log-format") +takes_value +global possible_value[text json] "sets the format of diagnostic output")
+
+            // These options control how timestamps are written.
+
+            (@arg tz: --tz +takes_value +global "writes timestamps in a time zone: local, utc or an IANA name (implies --time-format iso)")
+            (@arg time_format: --("time-format") +takes_value +global "sets the format of timestamps: epoch (default), epoch-ns or iso")
+
+            // Deprecated DRF names and out-of-range rates are
+            // normally accepted with a warning; this rejects them.
+
+            (@arg strict: --strict +global "rejects DRF requests using deprecated names or out-of-range rates")
+            (@arg clock_events: --("clock-events") +takes_value +global "reads names of clock events (NAME = EVENT lines) from a file")
 
             // The GET subcommand is used to retrieve accelerator
             // data. It has its own subcommands.
@@ -23,9 +100,30 @@ fn cmd_cfg() -> clap::App<'static, 'static> {
 
              (@subcommand history =>
               (about: "Makes the retrieval get historical data from a logger")
-              (@arg START: -s --start <TIME> +required "sets the start time of the range")
-              (@arg END: -e --end <TIME> !required "sets the end time of the range")
-              (@arg DRF: +required "specifies the device and rate for acquisition")
+              (@arg START: -s --start <TIME> +required "sets the start time of the range (seconds since the epoch)")
+              (@arg END: -e --end <TIME> !required "sets the end time of the range (default: now)")
+              (@arg JOBS: -j --jobs [N] "sets how many requests, or chunks, are fetched at once (default: 4)")
+              (@arg CHUNK: --chunk [SPAN] "splits the range into chunks, e.g. 6h or 1d, or 'none' (default: 1d)")
+              (@arg EVERY: --every [SPAN] "bins each channel's points into intervals, e.g. 1m, and shows one point per interval")
+              (@arg AGG: --agg [AGG] requires[EVERY] possible_value[mean min max last] "sets how the points of an interval are reduced (default: mean)")
+              (@arg TAG_STATE: --("tag-state") [DEVICE] "stamps each line with the value of a states device, e.g. V:MSTATE")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json parquet influx] "sets the output format (default: text)")
+              (@arg FIELDS: --fields [LIST] conflicts_with[TEMPLATE] "writes only these fields of each reply, e.g. time,device,value,units,status")
+              (@arg TEMPLATE: --template [TEXT] "writes each reply by a template, e.g. \"{device} = {value} {units}\"")
+              (@arg STATS: --stats "prints the count, min, max, mean, stddev and percentiles of each channel at the end, in place of the data")
+              (@arg PLOT: --plot "draws a chart of each channel in the terminal, in place of the data")
+              (@arg PLOT_HEIGHT: --("plot-height") [LINES] requires[PLOT] "sets the height of each chart; more than 1 draws braille (default: 1, a sparkline)")
+              (@arg OUTPUT: -o --output [FILE] "writes the data to a file, as CSV or JSON for .csv or .json names; needed by parquet")
+              (@arg ROTATE: --rotate [WHEN] "rotates output files out at a size (e.g. 100MB), hourly or daily, gzipping the old ones")
+              (@arg DELIMITER: --delimiter [CHAR] "sets the CSV field separator, e.g. ';' or tab (default: ',', or ';' with --decimal-comma)")
+              (@arg DECIMAL_COMMA: --("decimal-comma") "writes CSV numbers with a decimal comma, for spreadsheets in European locales")
+              (@arg INFLUX_URL: --("influx-url") [URL] requires[INFLUX_BUCKET] "writes the data to an InfluxDB server, e.g. http://localhost:8086")
+              (@arg INFLUX_BUCKET: --("influx-bucket") [BUCKET] requires[INFLUX_URL] "sets the InfluxDB bucket written to")
+              (@arg SINK: --sink [SINK] ... number_of_values(1) "sends the data to stdout, file:FILE, csv:FILE, json:FILE, socket:HOST:PORT, kafka or influx; may be repeated (default: stdout)")
+              (@arg BROKERS: --brokers [LIST] "sets the Kafka brokers to contact first, e.g. kafka1:9092,kafka2:9092")
+              (@arg TOPIC: --topic [TOPIC] "sets the Kafka topic published to")
+              (@arg ENCODING: --encoding [ENCODING] possible_value[json avro] "sets how published readings are encoded (default: json)")
+              (@arg DRF: +required ... "specifies the device and rate for acquisition")
              )
 
              // LIVE is a subcommand of get which returns live
@@ -33,21 +131,2021 @@ fn cmd_cfg() -> clap::App<'static, 'static> {
 
              (@subcommand live =>
               (about: "Retrieves live data from the accelerator")
-              (@arg DRF: +required "specifies the device and rate for acquisition")
+              (@arg DERIVE: -d --derive [EXPR] ... number_of_values(1) "adds a derived channel of the form NAME=EXPR")
+              (@arg CONSTANTS: --constants [FILE] ... number_of_values(1) "loads constants, used by derived channels, from a file")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json influx] "sets the output format (default: text)")
+              (@arg FIELDS: --fields [LIST] conflicts_with[TEMPLATE] "writes only these fields of each reply, e.g. time,device,value,units,status")
+              (@arg TEMPLATE: --template [TEXT] "writes each reply by a template, e.g. \"{device} = {value} {units}\"")
+              (@arg STATS: --stats "prints the count, min, max, mean, stddev and percentiles of each channel on exit, in place of the data")
+              (@arg STATS_INTERVAL: --("stats-interval") [SPAN] requires[STATS] "also prints the statistics this often, e.g. 10s or 1m")
+              (@arg PLOT: --plot "draws a chart of each channel in the terminal, in place of the data")
+              (@arg PLOT_HEIGHT: --("plot-height") [LINES] requires[PLOT] "sets the height of each chart; more than 1 draws braille (default: 1, a sparkline)")
+              (@arg INFLUX_URL: --("influx-url") [URL] requires[INFLUX_BUCKET] "writes the data to an InfluxDB server, e.g. http://localhost:8086")
+              (@arg INFLUX_BUCKET: --("influx-bucket") [BUCKET] requires[INFLUX_URL] "sets the InfluxDB bucket written to")
+              (@arg SINK: --sink [SINK] ... number_of_values(1) "sends the data to stdout, file:FILE, csv:FILE, json:FILE, socket:HOST:PORT, kafka or influx; may be repeated (default: stdout)")
+              (@arg BROKERS: --brokers [LIST] "sets the Kafka brokers to contact first, e.g. kafka1:9092,kafka2:9092")
+              (@arg TOPIC: --topic [TOPIC] "sets the Kafka topic published to")
+              (@arg ENCODING: --encoding [ENCODING] possible_value[json avro] "sets how published readings are encoded (default: json)")
+              (@arg FILE: --file [FILE] "reads requests, with optional overrides, from a file")
+              (@arg ON_CHANGE: --("on-change") "only shows a reply if it differs from the last one shown")
+              (@arg MIN_DELTA: --("min-delta") [X] "only shows a reply once its value has moved by more than X")
+              (@arg TAG_STATE: --("tag-state") [DEVICE] "stamps each line with the value of a states device, e.g. V:MSTATE")
+              (@arg OUTPUT: -o --output [FILE] "writes the data to a file, as CSV or JSON for .csv or .json names")
+              (@arg ROTATE: --rotate [WHEN] "rotates output files out at a size (e.g. 100MB), hourly or daily, gzipping the old ones")
+              (@arg DELIMITER: --delimiter [CHAR] "sets the CSV field separator, e.g. ';' or tab (default: ',', or ';' with --decimal-comma)")
+              (@arg DECIMAL_COMMA: --("decimal-comma") "writes CSV numbers with a decimal comma, for spreadsheets in European locales")
+              (@arg RECORD: --record [FILE] "also writes every reply to a capture file (binary if it ends in .acs), which --backend mock:FILE can serve")
+              (@arg DRF: required_unless[FILE] ... "specifies the device and rate for acquisition")
+             )
+            )
+
+            // The AVERAGE subcommand collects many windows of data,
+            // each aligned to an occurrence of a clock event, and
+            // reports the averaged waveform of each device.
+
+            (@subcommand average =>
+             (about: "Averages device data over many event-triggered windows")
+             (@arg EVENT: -E --event <EVENT> +required "sets the (hex) clock event which starts each window")
+             (@arg WINDOW: -w --window <RANGE> +required "sets the time range, relative to the event, to collect (e.g. 0..2s)")
+             (@arg COUNT: -n --count [N] "sets the number of windows to average (default: until interrupted)")
+             (@arg DRF: +required ... "specifies the devices to average")
+            )
+
+            // The MONITOR subcommand shows the latest value of each
+            // device in a table which is continuously updated.
+
+            (@subcommand monitor =>
+             (about: "Shows live data in a continuously updating table (also: WATCH)")
+             (aliases: &["watch"])
+             (@arg DRF: +required ... "specifies the device and rate for acquisition")
+            )
+
+            // The OVERLAY subcommand lines up a device's logged
+            // setting and reading, to show how well it tracks its
+            // setpoint.
+
+            (@subcommand overlay =>
+             (about: "Shows a device's logged setting and reading side by side")
+             (@arg START: -s --start <TIME> +required "sets the start time of the range (seconds since the epoch)")
+             (@arg END: -e --end <TIME> !required "sets the end time of the range (default: now)")
+             (@arg FORMAT: -f --format [FORMAT] possible_value[text json] "sets the output format (default: text)")
+             (@arg DEVICE: +required "specifies the device (and, optionally, the logged rate)")
+            )
+
+            // The REPORT subcommand summarizes logged data over a
+            // period, e.g. a shift, as a document.
+
+            (@subcommand report =>
+             (about: "Writes a Markdown or HTML report of logged data, e.g. for a shift")
+             (@arg SPEC: --spec <FILE> +required "reads the report's sections and devices from a file")
+             (@arg FOR: --for [PERIOD] "sets the period covered: today, yesterday, a span (e.g. 8h) or START..END (default: yesterday)")
+             (@arg FORMAT: -f --format [FORMAT] possible_value[markdown md html] "sets the report format (default: markdown)")
+             (@arg OUTPUT: -o --output [FILE] "writes the report to a file (default: stdout)")
+            )
+
+            // The STATES subcommand groups tools which work on states
+            // devices, which hold the machine's modes.
+
+            (@subcommand states =>
+             (about: "Tools for working with states devices")
+             (@setting SubcommandRequiredElseHelp)
+
+             // WATCH logs every transition of the states devices.
+
+             (@subcommand watch =>
+              (about: "Shows each change of state of states devices")
+              (@arg UNTIL: --until [STATE] "exits once a device enters STATE (a value)")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json] "sets the output format (default: text)")
+              (@arg DRF: +required ... "specifies the states devices, e.g. V:MSTATE")
+             )
+            )
+
+            // The TABLE subcommand is a plain text version of the
+            // monitor, which can be piped to other programs.
+
+            (@subcommand table =>
+             (about: "Shows the devices of a request file in a periodically refreshed table")
+             (@arg FILE: -f --file <FILE> +required "reads the devices from a request file")
+             (@arg REFRESH: -r --refresh [SPAN] "sets how often the table is shown, e.g. 500ms or 2s (default: 1s)")
+            )
+
+            // The CAPTURE subcommand groups tools which work on
+            // recorded sessions (the files of GET LIVE --record, as
+            // JSON lines or in the binary .acs format.)
+
+            (@subcommand capture =>
+             (about: "Tools for working with capture files")
+             (@setting SubcommandRequiredElseHelp)
+
+             (@subcommand diff =>
+              (about: "Compares two capture files, channel by channel")
+              (@arg TOLERANCE: -t --tolerance [VALUE] "sets the largest difference treated as equal (default: 0)")
+              (@arg A: +required "the first capture file")
+              (@arg B: +required "the second capture file")
+             )
+
+             (@subcommand merge =>
+              (about: "Interleaves capture files by timestamp")
+              (@arg OUTPUT: -o --output [FILE] "writes the merged capture to a file, binary if it ends in .acs (default: stdout)")
+              (@arg FILE: +required ... "the capture files to merge")
+             )
+            )
+
+            // The CONVERT subcommand exports a capture file, or part
+            // of one, in another format.
+
+            (@subcommand convert =>
+             (about: "Converts a capture file to another format")
+             (@arg FORMAT: -f --format <FORMAT> +required possible_value[csv json parquet] "sets the output format")
+             (@arg DELIMITER: --delimiter [CHAR] "sets the CSV field separator, e.g. ';' or tab (default: ',', or ';' with --decimal-comma)")
+             (@arg DECIMAL_COMMA: --("decimal-comma") "writes CSV numbers with a decimal comma, for spreadsheets in European locales")
+             (@arg START: -s --start [TIME] "skips records before this time (seconds since the epoch)")
+             (@arg END: -e --end [TIME] "skips records at, or after, this time (seconds since the epoch)")
+             (@arg DEVICES: --devices [DEVICE] ... number_of_values(1) "only keeps records of the given devices")
+             (@arg OUTPUT: -o --output [FILE] "sets the file parquet output is written to")
+             (@arg FILE: +required "the capture file")
+            )
+
+            // The EXPORT subcommand groups services which make device
+            // data available to other monitoring systems.
+
+            (@subcommand export =>
+             (about: "Makes live data available to other monitoring systems")
+             (@setting SubcommandRequiredElseHelp)
+
+             // PROMETHEUS serves the latest data of the devices as
+             // metrics for a Prometheus server to scrape.
+
+             (@subcommand prometheus =>
+              (about: "Serves the latest data of devices as Prometheus metrics")
+              (@arg LISTEN: -l --listen [ADDR] "sets the address and port to serve /metrics on (default: 127.0.0.1:9099)")
+              (@arg DRF: +required ... "specifies the device and rate for acquisition")
+             )
+            )
+
+            // The REPLAY subcommand plays back a capture file, with
+            // its original timing, as if it were live data.
+
+            (@subcommand replay =>
+             (about: "Replays a capture file into a sink as if it were live")
+             (@arg SPEED: --speed [SPEED] "sets the replay speed: realtime, a factor (e.g. 10x) or max (default: realtime)")
+             (@arg FORMAT: -f --format [FORMAT] possible_value[text json influx] "sets the output format (default: text); csv: sinks write CSV")
+             (@arg FIELDS: --fields [LIST] conflicts_with[TEMPLATE] "writes only these fields of each reply, e.g. time,device,value,units,status")
+             (@arg TEMPLATE: --template [TEXT] "writes each reply by a template, e.g. \"{device} = {value} {units}\"")
+             (@arg SINK: --sink [SINK] ... number_of_values(1) "sends the data to stdout, file:FILE, csv:FILE, json:FILE, socket:HOST:PORT, kafka, kafka://BROKERS/TOPIC or influx; may be repeated (default: stdout)")
+             (@arg OUTPUT: -o --output [FILE] "writes the data to a file, as CSV or JSON for .csv or .json names")
+             (@arg ROTATE: --rotate [WHEN] "rotates output files out at a size (e.g. 100MB), hourly or daily, gzipping the old ones")
+             (@arg DELIMITER: --delimiter [CHAR] "sets the CSV field separator, e.g. ';' or tab (default: ',', or ';' with --decimal-comma)")
+             (@arg DECIMAL_COMMA: --("decimal-comma") "writes CSV numbers with a decimal comma, for spreadsheets in European locales")
+             (@arg INFLUX_URL: --("influx-url") [URL] requires[INFLUX_BUCKET] "writes the data to an InfluxDB server, e.g. http://localhost:8086")
+             (@arg INFLUX_BUCKET: --("influx-bucket") [BUCKET] requires[INFLUX_URL] "sets the InfluxDB bucket written to")
+             (@arg BROKERS: --brokers [LIST] "sets the Kafka brokers to contact first, e.g. kafka1:9092,kafka2:9092")
+             (@arg TOPIC: --topic [TOPIC] "sets the Kafka topic published to")
+             (@arg ENCODING: --encoding [ENCODING] possible_value[json avro] "sets how published readings are encoded (default: json)")
+             (@arg FILE: +required "the capture file")
+            )
+
+            // The SIMULATE subcommand generates synthetic device data
+            // and sends it through the output pipeline, to load test
+            // downstream systems and the program itself.
+
+            (@subcommand simulate =>
+             (about: "Generates synthetic device data")
+             (@arg DEVICES: -n --devices [N] "sets the number of synthetic devices (default: 10)")
+             (@arg RATE: -r --rate [RATE] "sets each device's update rate, e.g. 15Hz or 100ms (default: 1Hz)")
+             (@arg DURATION: -d --duration [SECS] "stops after the given number of seconds (default: run until interrupted)")
+             (@arg FORMAT: -f --format [FORMAT] possible_value[text json influx] "sets the output format (default: text); csv: sinks write CSV")
+             (@arg FIELDS: --fields [LIST] conflicts_with[TEMPLATE] "writes only these fields of each reply, e.g. time,device,value,units,status")
+             (@arg TEMPLATE: --template [TEXT] "writes each reply by a template, e.g. \"{device} = {value} {units}\"")
+             (@arg SINK: --sink [SINK] ... number_of_values(1) "sends the data to stdout, file:FILE, csv:FILE, json:FILE, socket:HOST:PORT, kafka, kafka://BROKERS/TOPIC or influx; may be repeated (default: stdout)")
+             (@arg OUTPUT: -o --output [FILE] "writes the data to a file, as CSV or JSON for .csv or .json names")
+             (@arg ROTATE: --rotate [WHEN] "rotates output files out at a size (e.g. 100MB), hourly or daily, gzipping the old ones")
+             (@arg DELIMITER: --delimiter [CHAR] "sets the CSV field separator, e.g. ';' or tab (default: ',', or ';' with --decimal-comma)")
+             (@arg DECIMAL_COMMA: --("decimal-comma") "writes CSV numbers with a decimal comma, for spreadsheets in European locales")
+             (@arg INFLUX_URL: --("influx-url") [URL] requires[INFLUX_BUCKET] "writes the data to an InfluxDB server, e.g. http://localhost:8086")
+             (@arg INFLUX_BUCKET: --("influx-bucket") [BUCKET] requires[INFLUX_URL] "sets the InfluxDB bucket written to")
+             (@arg BROKERS: --brokers [LIST] "sets the Kafka brokers to contact first, e.g. kafka1:9092,kafka2:9092")
+             (@arg TOPIC: --topic [TOPIC] "sets the Kafka topic published to")
+             (@arg ENCODING: --encoding [ENCODING] possible_value[json avro] "sets how published readings are encoded (default: json)")
+            )
+
+            // The SHELL subcommand reads commands interactively.
+            // Acquisitions can run in the background while other
+            // commands are entered.
+
+            (@subcommand shell =>
+             (about: "Runs an interactive shell with background acquisitions")
+            )
+
+            // SELFTEST checks that the program can parse requests and
+            // reach the control system.
+
+            (@subcommand selftest =>
+             (about: "Checks the DRF parser, data source, logger and role, and reports what failed")
+             (@arg DEVICE: --device [DRF] "sets the device to read (default: M:OUTTMP)")
+            )
+
+            // The DRF subcommand groups tools which work on DRF
+            // requests without acquiring any data.
+
+            // Its help (`acsys help drf`) is the DRF reference.
+
+            (@subcommand drf =>
+             (about: "Tools for working with DRF requests (see 'help drf' for the DRF syntax)")
+             (after_help: &*Box::leak(drf::reference().into_boxed_str()))
+             (@setting SubcommandRequiredElseHelp)
+
+             // FMT rewrites a request file in canonical form.
+
+             (@subcommand fmt =>
+              (about: "Canonicalizes, de-duplicates and sorts a request file")
+              (@arg FILE: --file [FILE] "reads the request file (default: stdin)")
+              (@arg WRITE: -w --write requires[FILE] "rewrites the file in place instead of printing the result")
+              (@arg SYMBOLIC: --symbolic "writes clock events by name, where they have one")
+             )
+
+             // CHECK, CANON and EXPLAIN work on requests given on the
+             // command line.
+
+             (@subcommand check =>
+              (about: "Checks requests, pointing out errors and warnings")
+              (@arg STDIN: --stdin conflicts_with[DRF] "reads the requests from stdin, one per line")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json] "sets the output format (default: text)")
+              (@arg DRF: required_unless[STDIN] ... "specifies the requests to check")
+             )
+             (@subcommand canon =>
+              (about: "Prints requests in canonical form")
+              (@arg MINIMAL: --minimal conflicts_with[SYMBOLIC] "prints the shortest equivalent request instead")
+              (@arg SYMBOLIC: --symbolic "writes clock events by name, where they have one")
+              (@arg DRF: +required +multiple "specifies the requests to print")
+             )
+             (@subcommand explain =>
+              (about: "Describes each part of a request")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json] "sets the output format (default: text)")
+              (@arg DRF: +required +multiple "specifies the requests to describe")
              )
             )
 
+            // The CONTROL subcommand sends a named command, like ON or
+            // RESET, to a device's control property.
+
+            (@subcommand control =>
+             (about: "Sends a basic control command to a device")
+             (@arg DEVICE: +required "specifies the device")
+             (@arg COMMAND: +required "specifies the command: ON, OFF, RESET, POSITIVE, NEGATIVE, RAMP, DC or TRIP")
+             (@arg YES: -y --yes "sends the command without asking for confirmation")
+             (@arg DRY_RUN: -n --("dry-run") "shows the command without sending it")
+            )
+
             // The PUT subcommand allows settings to be sent to devices.
 
             (@subcommand put =>
              (about: "Updates the value of a device (also: MODIFY, UPDATE)")
              (aliases: &["modify", "update"])
-             (@arg DRF: +required "specifies the device to be modified")
-             (@arg VALUE: +required "specifies the new value for the device")
+             (@arg DRF: required_unless[FILE] "specifies the device to be modified")
+             (@arg VALUE: required_unless[FILE] "specifies the new value (a number, list, 0x-prefixed raw value, boolean or control command)")
+             (@arg FILE: --file [FILE] conflicts_with[DRF] "sends the settings of a file, a DRF,VALUE per line")
+             (@arg STOP_ON_ERROR: --("stop-on-error") requires[FILE] "skips the rest of the file once a setting fails (default)")
+             (@arg CONTINUE: --continue requires[FILE] conflicts_with[STOP_ON_ERROR] "sends the rest of the file after a setting fails")
+             (@arg ROLLBACK: --rollback [FILE] requires[FILE] "writes a settings file which puts back the values the settings replaced")
+             (@arg YES: -y --yes "sends the setting without asking for confirmation")
+             (@arg DRY_RUN: -n --("dry-run") "shows the setting without sending it")
+             (@arg VERIFY: --verify "reads the setting back once sent and fails if the device didn't take the value")
+             (@arg READBACK: --readback requires[VERIFY] "also checks that the device's reading follows the setting")
+             (@arg TOLERANCE: --tolerance [X] requires[VERIFY] "sets how far a value read back may be from the one set (default: 0)")
+             (@arg VERIFY_RETRIES: --("verify-retries") [N] requires[VERIFY] "sets how many more times, half a second apart, a value is read back (default: 3)")
+            )
+
+            // SAVE and RESTORE record the settings of a few devices
+            // and put them back later.
+
+            (@subcommand save =>
+             (about: "Saves the settings (and readings) of devices to a snapshot file")
+             (@arg FILE: -o --file <FILE> +required "sets the snapshot file to write")
+             (@arg DRF: +required ... "specifies the devices whose settings are saved")
+            )
+
+            (@subcommand restore =>
+             (about: "Sends the settings saved in a snapshot file back to their devices")
+             (@arg FILE: -i --file <FILE> +required "sets the snapshot file to read")
+             (@arg YES: -y --yes "sends the settings without asking for confirmation")
+             (@arg DRY_RUN: -n --("dry-run") "shows the settings without sending them")
+            )
+
+            // The JOURNAL subcommand reviews the record of settings
+            // made by PUT and CONTROL.
+
+            (@subcommand journal =>
+             (about: "Tools for reviewing the settings journal")
+             (@setting SubcommandRequiredElseHelp)
+
+             (@subcommand show =>
+              (about: "Shows past settings, oldest first")
+              (@arg LAST: -l --last [N] "only shows the last N settings")
+              (@arg FORMAT: -f --format [FORMAT] possible_value[text json] "sets the output format (default: text)")
+              (@arg DEVICE: "only shows settings of the device, or of devices matching a pattern using * and ?")
+             )
             )
     )
 }
 
+// Parses a DRF string given on the command line, strictly if
+// `--strict` was given. Otherwise, what strict parsing would reject
+// is reported as a warning.
+
+fn parse_request(m: &ArgMatches, drf: &str) -> Result<drf::Request, String> {
+    let events = clock_events(m).map_err(|e| e.to_string())?;
+    let options = drf::ParseOptions { strict: m.is_present("strict"), clock_events: Some(&events) };
+    let parsed = drf::parse_with(drf, &options).map_err(|e| drf::describe(drf, &e))?;
+
+    for w in parsed.warnings {
+        warn!(drf, "{}", w)
+    }
+    Ok(parsed.request)
+}
+
+// Loads the names of clock events given by --clock-events. Without
+// it, only the built-in names are known.
+
+fn clock_events(m: &ArgMatches) -> Result<drf::ClockEvents, Error> {
+    match m.value_of("clock_events") {
+        Some(file) => drf::ClockEvents::parse(&std::fs::read_to_string(file)?)
+            .map_err(|e| Error::Parse(format!("{}: {}", file, e))),
+        None => Ok(drf::ClockEvents::default()),
+    }
+}
+
+// Parses each DRF string given on the command line. All requests
+// are validated before any are used so the user sees every bad entry.
+
+fn parse_requests(m: &ArgMatches) -> Result<Vec<drf::Request>, Error> {
+    parse_each(m, m.values_of("DRF").into_iter().flatten())
+}
+
+fn parse_each<'a>(m: &ArgMatches, drfs: impl Iterator<Item = &'a str>) -> Result<Vec<drf::Request>, Error> {
+    let mut requests = vec![];
+    let mut errors = vec![];
+
+    for drf in drfs {
+        match parse_request(m, drf) {
+            Ok(req) => requests.push(req),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(requests)
+    } else {
+        Err(Error::Parse(errors.join("\n")))
+    }
+}
+
+// Configures diagnostic output from the global options. It is given
+// the matches of the subcommand being run since that's where clap
+// stores the values of global arguments.
+
+fn init_logging(m: &ArgMatches) -> Result<(), Error> {
+    let format =
+        logging::Format::parse(m.value_of("log_format").unwrap_or("text")).map_err(Error::Parse)?;
+
+    logging::init(m.occurrences_of("verbose"), format);
+    Ok(())
+}
+
+// Builds the timestamp formatter selected by the global options.
+
+fn time_formatter(m: &ArgMatches) -> Result<timefmt::Formatter, Error> {
+    timefmt::Formatter::new(m.value_of("time_format"), m.value_of("tz")).map_err(Error::Parse)
+}
+
+// With --tag-state, adds a request for the states device to the end
+// of `requests` and returns the tagger which watches its replies.
+
+fn state_tag(m: &ArgMatches, requests: &mut Vec<drf::Request>) -> Result<Option<states::StateTag>, Error> {
+    match m.value_of("TAG_STATE") {
+        Some(dev) => {
+            requests.push(parse_request(m, dev).map_err(Error::Parse)?);
+            Ok(Some(states::StateTag::new(requests.len() - 1)))
+        }
+        None => Ok(None),
+    }
+}
+
+// Returns how CSV is written, as given by --delimiter and
+// --decimal-comma.
+
+fn csv_style(m: &ArgMatches) -> Result<capture::CsvStyle, Error> {
+    capture::CsvStyle::new(m.value_of("DELIMITER"), m.is_present("DECIMAL_COMMA")).map_err(Error::Parse)
+}
+
+// Opens the sinks given by --sink (see `sink`), which can be
+// repeated. Without any, the data is shown on stdout or, with
+// --influx-url, written to InfluxDB.
+
+fn open_sinks(m: &ArgMatches, client: &backend::Client, tf: &timefmt::Formatter) -> Result<Vec<Box<dyn sink::Sink>>, Error> {
+    let mut specs = m
+        .values_of("SINK")
+        .into_iter()
+        .flatten()
+        .map(sink::Spec::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::Parse)?;
+
+    specs.extend(m.value_of("OUTPUT").map(sink::Spec::output));
+    if specs.is_empty() && m.is_present("INFLUX_URL") {
+        specs.push(sink::Spec::Influx)
+    } else if specs.is_empty() && !m.is_present("STATS") && !m.is_present("PLOT") {
+        specs.push(sink::Spec::Stdout)
+    }
+
+    let rotate = m.value_of("ROTATE").map(rotate::Rotate::parse).transpose().map_err(Error::Parse)?;
+    let csv = csv_style(m)?;
+
+    let template = match (m.value_of("FIELDS"), m.value_of("TEMPLATE")) {
+        (Some(f), _) => Some(output::Template::fields(f).map_err(Error::Parse)?),
+        (None, Some(t)) => Some(output::Template::parse(t).map_err(Error::Parse)?),
+        (None, None) => None,
+    };
+    let style = match (m.value_of("FORMAT"), template) {
+        (None | Some("text"), Some(t)) => sink::Style::Template(t),
+        (Some(f), Some(_)) => return Err(Error::Parse(format!("--fields and --template shape text output, not {}", f))),
+        (Some("influx"), None) => sink::Style::Influx,
+        (f, None) => sink::Style::Output(output::Format::parse(f.unwrap_or("text")).map_err(Error::Parse)?),
+    };
+
+    // Options of a sink which isn't used are mistakes.
+
+    if ((m.is_present("FORMAT") && !m.is_present("STATS")) || m.is_present("FIELDS") || m.is_present("TEMPLATE"))
+        && style != sink::Style::Influx
+        && !specs.iter().any(|s| matches!(s, sink::Spec::Stdout | sink::Spec::File(_)))
+    {
+        return Err(Error::Parse(String::from("--format, --fields and --template only apply to stdout and file: sinks")));
+    }
+    if rotate.is_some() && !specs.iter().any(sink::Spec::is_file) {
+        return Err(Error::Parse(String::from("--rotate needs a file to rotate, given by --output or --sink")));
+    }
+    if (m.is_present("DELIMITER") || m.is_present("DECIMAL_COMMA"))
+        && !specs.iter().any(|s| matches!(s, sink::Spec::Csv(_)))
+    {
+        return Err(Error::Parse(String::from("--delimiter and --decimal-comma only apply to csv: sinks")));
+    }
+    if m.is_present("INFLUX_URL") && !specs.contains(&sink::Spec::Influx) {
+        return Err(Error::Parse(String::from("--influx-url is only used by --sink influx")));
+    }
+    if ["BROKERS", "TOPIC", "ENCODING"].iter().any(|a| m.is_present(a)) && !specs.contains(&sink::Spec::Kafka(None)) {
+        return Err(Error::Parse(String::from("--brokers, --topic and --encoding are only used by --sink kafka")));
+    }
+    if m.is_present("PLOT") && specs.contains(&sink::Spec::Stdout) {
+        return Err(Error::Parse(String::from("--plot draws on stdout, so it can't be used with --sink stdout")));
+    }
+
+    let plot_height = m
+        .value_of("PLOT_HEIGHT")
+        .map(|h| h.parse::<usize>().ok().filter(|h| *h > 0).ok_or_else(|| Error::Parse(format!("bad plot height '{}'", h))))
+        .transpose()?;
+
+    let settings = client.settings();
+    let mut sinks: Vec<Box<dyn sink::Sink>> = vec![];
+
+    for spec in specs {
+        let json = sink::Style::Output(output::Format::Json);
+
+        sinks.push(match spec {
+            sink::Spec::Stdout => Box::new(sink::Lines::stdout(style.clone(), tf.clone())),
+            sink::Spec::File(file) => Box::new(sink::Lines::file(&file, style.clone(), tf.clone(), rotate)?),
+            sink::Spec::Csv(file) => Box::new(sink::Csv::file(&file, csv, tf.clone(), rotate)?),
+            sink::Spec::Json(file) => Box::new(sink::Lines::file(&file, json, tf.clone(), rotate)?),
+            sink::Spec::Socket(addr) => Box::new(sink::Lines::socket(&addr, json, tf.clone(), settings.timeout)?),
+            sink::Spec::Kafka(url) => {
+                let (brokers, topic) = url
+                    .or_else(|| m.value_of("BROKERS").map(String::from).zip(m.value_of("TOPIC").map(String::from)))
+                    .ok_or_else(|| Error::Parse(String::from("--sink kafka needs --brokers and --topic")))?;
+                let producer = kafka::Producer::new(&brokers, &topic, settings.timeout, settings.retries)?;
+                let encoding = kafka::Encoding::parse(m.value_of("ENCODING").unwrap_or("json")).map_err(Error::Parse)?;
+
+                Box::new(sink::Kafka::new(producer, encoding, tf.clone()))
+            }
+            sink::Spec::Influx => {
+                let (url, bucket) = m
+                    .value_of("INFLUX_URL")
+                    .zip(m.value_of("INFLUX_BUCKET"))
+                    .ok_or_else(|| Error::Parse(String::from("--sink influx needs --influx-url and --influx-bucket")))?;
+
+                Box::new(influx::Server::new(url, bucket, settings.timeout)?)
+            }
+        })
+    }
+    // Live charts scroll; those of history (which has --start) are
+    // drawn once the data ends.
+
+    if m.is_present("PLOT") {
+        sinks.push(Box::new(plot::Plot::new(plot_height.unwrap_or(1), !m.is_present("START"), tf.clone())))
+    }
+    Ok(sinks)
+}
+
+// Returns the statistics of --stats, or `None` without it. Channels
+// are named by their label or device or, if another request reads the
+// same device, their DRF; derived channels follow.
+
+fn stats_for(
+    m: &ArgMatches,
+    requests: &[(Option<&str>, &drf::Request)],
+    derived: &[expr::Derived],
+) -> Result<Option<Arc<Mutex<stats::Stats>>>, Error> {
+    if !m.is_present("STATS") {
+        return Ok(None);
+    }
+    if m.value_of("FORMAT") == Some("influx") {
+        return Err(Error::Parse(String::from("--stats are written as text or json, not influx")));
+    }
+
+    let names = requests
+        .iter()
+        .map(|(label, r)| match label {
+            Some(l) => String::from(*l),
+            None if requests.iter().filter(|(_, o)| o.device.name() == r.device.name()).count() > 1 => r.canonical(),
+            None => r.device.name().into_owned(),
+        })
+        .chain(derived.iter().map(|d| d.name.clone()))
+        .collect();
+
+    Ok(Some(Arc::new(Mutex::new(stats::Stats::new(names)))))
+}
+
+fn print_stats(stats: &Mutex<stats::Stats>, fmt: output::Format) {
+    let stats = stats.lock().unwrap();
+
+    match fmt {
+        output::Format::Text => print!("{}", stats.render()),
+        output::Format::Json => stats.json().iter().for_each(|line| println!("{}", line)),
+    }
+}
+
+// Prints the statistics every --stats-interval and, when the program
+// is interrupted or terminated, once more before it exits, so a run
+// that is stopped by hand still gets its summary.
+
+fn watch_stats(m: &ArgMatches, stats: &Arc<Mutex<stats::Stats>>, fmt: output::Format) -> Result<(), Error> {
+    let interval = m
+        .value_of("STATS_INTERVAL")
+        .map(|i| history::parse_span(i).map(std::time::Duration::from_micros))
+        .transpose()
+        .map_err(Error::Parse)?;
+    let signal = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let stats = stats.clone();
+
+    for s in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register_usize(s, signal.clone(), s as usize)?;
+    }
+    std::thread::spawn(move || {
+        let mut shown = std::time::Instant::now();
+
+        loop {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            match signal.load(std::sync::atomic::Ordering::Relaxed) {
+                0 => (),
+
+                // Exit with the shell's status for a program ended by
+                // the signal.
+                s => {
+                    print_stats(&stats, fmt);
+                    process::exit(128 + s as i32)
+                }
+            }
+            if interval.is_some_and(|i| shown.elapsed() >= i) {
+                print_stats(&stats, fmt);
+                shown = std::time::Instant::now()
+            }
+        }
+    });
+    Ok(())
+}
+
+// Delivers what the sinks hold back. Every sink is finished, even if
+// an earlier one fails; the first failure is returned.
+
+fn finish_sinks(sinks: &mut [Box<dyn sink::Sink>]) -> Result<(), Error> {
+    let results: Vec<Result<(), Error>> = sinks.iter_mut().map(|s| s.finish()).collect();
+
+    results.into_iter().collect()
+}
+
+// Implements the AVERAGE subcommand. An extra request, for the
+// triggering clock event, is placed at the front of the list. Its
+// replies mark the start of each window.
+
+fn cmd_average(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let event = average::parse_event(m.value_of("EVENT").unwrap()).map_err(Error::Parse)?;
+    let window = average::Window::parse(m.value_of("WINDOW").unwrap()).map_err(Error::Parse)?;
+    let count = m
+        .value_of("COUNT")
+        .map(|v| {
+            v.parse::<u64>()
+                .map_err(|_| Error::Parse(format!("bad window count '{}'", v)))
+        })
+        .transpose()?;
+    let requests = parse_requests(m)?;
+
+    let mut trigger = requests[0].clone();
+
+    trigger.event = drf::Event::Clock {
+        event,
+        clk_type: drf::ClockType::default(),
+        delay: 0,
+    };
+
+    let all: Vec<drf::Request> = std::iter::once(trigger)
+        .chain(requests.iter().cloned())
+        .collect();
+    let mut averager = average::Averager::new(window);
+
+    for reading in client.subscribe(all) {
+        let reading = reading?;
+
+        if reading.index == 0 {
+            if count.is_some_and(|n| averager.windows() >= n) {
+                break;
+            }
+            averager.trigger(reading.timestamp)
+        } else {
+            let name = requests[reading.index - 1].canonical();
+
+            averager.sample(&name, reading.timestamp, reading.value.as_slice())
+        }
+    }
+
+    for (device, points) in averager.finish() {
+        println!("{}", device);
+        for p in points {
+            println!("{:>8} {:>6} {:>16.6} {:>16.6}", p.index, p.count, p.mean, p.std_dev)
+        }
+    }
+    Ok(())
+}
+
+// Returns the range of time given by the START and END options. The
+// range ends now if END isn't given.
+
+fn time_range(m: &ArgMatches) -> Result<(u64, u64), Error> {
+    let start = history::parse_time(m.value_of("START").unwrap()).map_err(Error::Parse)?;
+    let end = match m.value_of("END") {
+        Some(t) => history::parse_time(t).map_err(Error::Parse)?,
+        None => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0),
+    };
+
+    if end <= start {
+        return Err(Error::Parse(String::from("the end of the range must follow its start")));
+    }
+    Ok((start, end))
+}
+
+// Returns the file given by --output for parquet output, which can't
+// be written to stdout, or `None` for the other formats (where
+// --output, if allowed, is a sink.)
+
+fn parquet_output<'a>(m: &'a ArgMatches) -> Result<Option<&'a str>, Error> {
+    match (m.value_of("FORMAT"), m.value_of("OUTPUT")) {
+        (Some("parquet"), Some(file)) => Ok(Some(file)),
+        (Some("parquet"), None) => Err(Error::Parse(String::from("parquet output needs a file, given by --output"))),
+        _ => Ok(None),
+    }
+}
+
+// Writes `rows` to a new parquet file.
+
+fn write_parquet(file: &str, rows: impl Iterator<Item = parquet::Row>) -> Result<(), Error> {
+    let out = std::fs::File::create(file).map_err(|e| Error::Other(format!("can't create {}: {}", file, e)))?;
+    let mut writer = parquet::Writer::new(io::BufWriter::new(out))?;
+
+    for row in rows {
+        writer.push(row)?
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// Implements the GET HISTORY subcommand. The requests are fetched
+// concurrently, in chunks of time, and their data is printed in
+// timestamp order. If stderr is a terminal, the progress of the
+// fetches is shown there. With --every, each channel's points are
+// reduced to one per interval (see `history::bin()`.) With --format
+// parquet, the data is written to the --output file instead.
+
+fn cmd_get_history(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let parquet = parquet_output(m)?;
+    let tf = time_formatter(m)?;
+    let mut requests = parse_requests(m)?;
+
+    if parquet.is_some() && ["TAG_STATE", "SINK", "INFLUX_URL", "ROTATE", "FIELDS", "TEMPLATE", "STATS", "PLOT", "DELIMITER", "DECIMAL_COMMA"].iter().any(|a| m.is_present(a)) {
+        return Err(Error::Parse(String::from("parquet output can't be used with --tag-state, --rotate, --fields, --template, --stats, --plot, CSV options or other sinks")));
+    }
+
+    let mut sinks = if parquet.is_none() { open_sinks(m, &client, &tf)? } else { vec![] };
+    let stats = stats_for(m, &requests.iter().map(|r| (None, r)).collect::<Vec<_>>(), &[])?;
+    let every = m.value_of("EVERY").map(history::parse_span).transpose().map_err(Error::Parse)?;
+    let agg = history::Agg::parse(m.value_of("AGG").unwrap_or("mean")).map_err(Error::Parse)?;
+    let channels = requests.len();
+
+    let mut tag = state_tag(m, &mut requests)?;
+    let (start, end) = time_range(m)?;
+    let jobs = match m.value_of("JOBS") {
+        Some(n) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| Error::Parse(format!("bad job count '{}'", n)))?,
+        None => 4,
+    };
+
+    let chunk = match m.value_of("CHUNK").unwrap_or("1d") {
+        "none" => None,
+        c => Some(history::parse_span(c).map_err(Error::Parse)?),
+    };
+
+    let query = history::Query {
+        start,
+        end,
+        chunk,
+        jobs,
+    };
+    let show_progress = io::stderr().is_terminal();
+    let progress = |done: usize, total: usize| {
+        info!(done, total, "fetched history chunk");
+        if show_progress {
+            eprint!("\rfetched {}/{}{}", done, total, if done == total { "\n" } else { "" })
+        }
+    };
+    let readings = history::fetch_all(&requests, &query, progress, |idx, req, start, end| {
+        client.history(idx, req, start, end)
+    });
+
+    if show_progress && readings.is_err() {
+        eprintln!()
+    }
+
+    let readings = match every {
+        Some(every) => history::bin(readings?, every, agg, channels),
+        None => readings?,
+    };
+
+    if let Some(file) = parquet {
+        return write_parquet(file, readings.iter().flat_map(|r| parquet::reading_rows(&requests[r.index], r)));
+    }
+
+    for reading in readings {
+        if tag.as_mut().is_some_and(|t| t.update(&reading)) {
+            continue;
+        }
+        if let Some(s) = &stats {
+            s.lock().unwrap().update(reading.index, &reading.value, reading.meta.units.as_deref())
+        }
+        for s in sinks.iter_mut() {
+            s.reading(&requests[reading.index], None, &reading, tag.as_ref())?
+        }
+    }
+    finish_sinks(&mut sinks)?;
+    if let Some(s) = &stats {
+        print_stats(s, output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?)
+    }
+    Ok(())
+}
+
+// Implements the OVERLAY subcommand. The device's setting and
+// reading are fetched from the logger and joined into one table.
+
+fn cmd_overlay(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let format =
+        output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?;
+    let tf = time_formatter(m)?;
+    let req = parse_request(m, m.value_of("DEVICE").unwrap()).map_err(Error::Parse)?;
+    let requests = overlay::requests(&req);
+    let (start, end) = time_range(m)?;
+    let query = history::Query {
+        start,
+        end,
+        chunk: Some(86_400_000_000),
+        jobs: 2,
+    };
+    let readings = history::fetch_all(&requests, &query, |_, _| (), |idx, req, start, end| {
+        client.history(idx, req, start, end)
+    })?;
+
+    for line in overlay::format(format, &tf, &overlay::join(&readings)) {
+        println!("{}", line)
+    }
+    Ok(())
+}
+
+// Implements the REPORT subcommand. The data of every device in the
+// report is fetched from the logger at once, then each section's
+// table is computed from it. Times are local, in ISO format, unless
+// the global options say otherwise.
+
+fn cmd_report(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let file = m.value_of("SPEC").unwrap();
+    let spec = report::parse_spec(&std::fs::read_to_string(file)?)
+        .map_err(|e| Error::Parse(format!("{}: {}", file, e)))?;
+    let format = report::Format::parse(m.value_of("FORMAT").unwrap_or("markdown")).map_err(Error::Parse)?;
+    let tf = timefmt::Formatter::new(Some(m.value_of("time_format").unwrap_or("iso")), Some(m.value_of("tz").unwrap_or("local")))
+        .map_err(Error::Parse)?;
+    let (start, end) = report::parse_period(m.value_of("FOR").unwrap_or("yesterday"), jiff::Timestamp::now(), tf.zone())
+        .map_err(Error::Parse)?;
+    let (requests, errors): (Vec<_>, Vec<_>) = spec.devices().map(|d| parse_request(m, d)).partition(Result::is_ok);
+
+    if !errors.is_empty() {
+        return Err(Error::Parse(errors.into_iter().filter_map(Result::err).collect::<Vec<_>>().join("\n")));
+    }
+
+    let requests: Vec<drf::Request> = requests.into_iter().filter_map(Result::ok).collect();
+    let query = history::Query {
+        start,
+        end,
+        chunk: Some(86_400_000_000),
+        jobs: 4,
+    };
+    let readings = history::fetch_all(&requests, &query, |_, _| (), |idx, req, start, end| {
+        client.history(idx, req, start, end)
+    })?;
+    let mut samples = vec![vec![]; requests.len()];
+
+    for r in readings {
+        if let Some(&v) = r.value.as_slice().first() {
+            samples[r.index].push((r.timestamp, v))
+        }
+    }
+
+    let mut samples = samples.into_iter();
+    let tables: Vec<Vec<Vec<String>>> = spec
+        .sections
+        .iter()
+        .map(|s| {
+            s.devices
+                .iter()
+                .zip(samples.by_ref())
+                .map(|(d, data)| report::row(&s.kind, d, &data, end, |ts| tf.format(ts)))
+                .collect()
+        })
+        .collect();
+    let period = format!("From {} to {}", tf.format(start), tf.format(end));
+    let text = report::render(format, &spec, &period, &tables);
+
+    match m.value_of("OUTPUT") {
+        Some(file) => std::fs::write(file, text)?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+// Implements the GET LIVE subcommand. Each reply is printed as it
+// arrives. Derived channels are recomputed, and printed, whenever
+// one of the devices they reference is updated. The events of the
+// requests decide when the command ends (see `backend::Acquisition`.)
+
+fn cmd_get_live(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let tf = time_formatter(m)?;
+    let mut sinks = open_sinks(m, &client, &tf)?;
+    let mut jobs: Vec<job::Job> = parse_requests(m)?.into_iter().map(job::Job::new).collect();
+
+    if let Some(file) = m.value_of("FILE") {
+        jobs.extend(
+            job::parse_file(&std::fs::read_to_string(file)?)
+                .map_err(|e| Error::Parse(format!("{}: {}", file, e)))?,
+        )
+    }
+
+    let mut requests: Vec<drf::Request> = jobs.iter().map(|j| j.request.clone()).collect();
+    let acquisition = backend::Acquisition::of(&requests);
+
+    if acquisition == backend::Acquisition::Validate {
+        info!(requests = requests.len(), "every request uses @N, which returns no data; nothing to acquire");
+        return Ok(());
+    }
+
+    let mut tag = state_tag(m, &mut requests)?;
+    let derived = m
+        .values_of("DERIVE")
+        .into_iter()
+        .flatten()
+        .map(expr::Derived::parse)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::Parse)?;
+
+    let mut consts = expr::Constants::builtin();
+
+    for file in m.values_of("CONSTANTS").into_iter().flatten() {
+        consts
+            .load(&std::fs::read_to_string(file)?)
+            .map_err(|e| Error::Parse(format!("{}: {}", file, e)))?;
+    }
+
+    // Make sure every derived channel only refers to requested
+    // devices and that its units make sense. The devices' units aren't
+    // known until their first readings arrive, so this only catches
+    // mistakes among the constants.
+
+    let mut samples = expr::Samples::new();
+    let mut device_units = std::collections::HashMap::new();
+
+    for d in &derived {
+        for dev in d.expr.devices() {
+            if !requests.iter().any(|r| r.device.name() == dev) {
+                return Err(Error::Parse(format!(
+                    "derived channel '{}' uses unrequested device {}",
+                    d.name, dev
+                )));
+            }
+        }
+
+        d.expr
+            .units(&|_| None, &consts)
+            .map_err(|e| Error::Parse(format!("derived channel '{}': {}", d.name, e)))?;
+
+        for (dev, n) in d.expr.windows() {
+            samples.keep(dev, n)
+        }
+    }
+
+    // Jobs which convert their values do so from the units the data
+    // source reports. Values reported without units are shown
+    // unconverted, which is noted once per job.
+
+    let mut units_unknown = vec![false; jobs.len()];
+    let mut last_shown: Vec<Option<f64>> = vec![None; jobs.len()];
+    let min_delta = m
+        .value_of("MIN_DELTA")
+        .map(|v| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|v| *v >= 0.0)
+                .ok_or_else(|| Error::Parse(format!("bad minimum delta '{}'", v)))
+        })
+        .transpose()?;
+    let mut changes = change::ChangeFilter::new(m.is_present("ON_CHANGE"), min_delta, jobs.len());
+
+    // With --stats, every value (before --on-change and deadbands
+    // thin them out) is summarized.
+
+    let stats_format = output::Format::parse(m.value_of("FORMAT").filter(|f| *f != "influx").unwrap_or("text"))
+        .map_err(Error::Parse)?;
+    let stats = stats_for(m, &jobs.iter().map(|j| (j.label.as_deref(), &j.request)).collect::<Vec<_>>(), &derived)?;
+
+    if let Some(s) = &stats {
+        watch_stats(m, s, stats_format)?
+    }
+
+    // With --record, every reply is also written to a capture file
+    // (see `capture::Recorder`.)
+
+    let mut record = m.value_of("RECORD").map(capture::Recorder::create).transpose()?;
+
+    // Without a streaming request, the command ends once every
+    // one-shot request has been answered.
+
+    let waiting = jobs
+        .iter()
+        .map(|j| acquisition == backend::Acquisition::OneShot && j.request.event.is_one_shot())
+        .collect();
+
+    // A request which fails is shown with its status while the others
+    // carry on. Once the replies end, the command fails too, with the
+    // exit status of the first failure's ACNET status.
+
+    let mut failed = vec![None; jobs.len()];
+
+    for reading in backend::until_answered(client.subscribe(requests.clone()), waiting) {
+        let mut reading = reading?;
+
+        if let (true, data::Value::Status(s)) = (reading.failed(), &reading.value) {
+            failed[reading.index] = failed[reading.index].or(Some(status::Status(*s)))
+        }
+
+        if let Some(f) = record.as_mut() {
+            f.write(&requests[reading.index].canonical(), &reading)?
+        }
+        if tag.as_mut().is_some_and(|t| t.update(&reading)) {
+            continue;
+        }
+
+        let job = &jobs[reading.index];
+        let req = &job.request;
+
+        let block = match &reading.value {
+            data::Value::Raw(raw) => alarm_block::decode(&req.property, raw),
+            _ => None,
+        };
+
+        match block {
+            Some(Ok(a)) => reading.value = data::Value::Alarm(a),
+            Some(Err(e)) => warn!(device = %req.device, "can't decode the alarm block: {}", e),
+            None => (),
+        }
+
+        // Derived channels are computed from the values as returned
+        // by the data source, before any conversion is applied. Their
+        // units follow from the units the data source reports.
+
+        if let data::Value::Scalar(v) = reading.value {
+            let dev = req.device.name();
+
+            samples.push(&dev, v);
+
+            if let Some(u) = reading.meta.units.as_deref().and_then(|u| expr::Unit::parse(u).ok()) {
+                device_units.insert(dev.to_string(), u);
+            }
+
+            for (i, d) in derived.iter().enumerate() {
+                if d.expr.devices().contains(&dev.as_ref()) {
+                    if let Some(v) = d.expr.eval(&samples, &consts) {
+                        let units = d
+                            .expr
+                            .units(&|dev| device_units.get(dev).cloned(), &consts)
+                            .map_err(|e| Error::Parse(format!("derived channel '{}': {}", d.name, e)))?
+                            .map(|u| u.to_string())
+                            .unwrap_or_default();
+
+                        if let Some(s) = &stats {
+                            s.lock().unwrap().update(jobs.len() + i, &data::Value::Scalar(v), Some(&units))
+                        }
+                        for s in sinks.iter_mut() {
+                            s.derived(&d.name, reading.timestamp, v, &units, tag.as_ref())?
+                        }
+                    }
+                }
+            }
+        }
+
+        if let (data::Value::Scalar(v), Some(to)) = (&mut reading.value, &job.convert) {
+            match &reading.meta.units {
+                Some(from) => *v = job::convert(*v, from, to).unwrap_or(*v),
+                None if !units_unknown[reading.index] => {
+                    warn!(device = %req.device, "units unknown; can't convert to {}", to);
+                    units_unknown[reading.index] = true
+                }
+                None => (),
+            }
+        }
+        if let Some(s) = &stats {
+            let units = job.convert.as_deref().filter(|_| reading.meta.units.is_some()).or(reading.meta.units.as_deref());
+
+            s.lock().unwrap().update(reading.index, &reading.value, units)
+        }
+        if let data::Value::Scalar(v) = &mut reading.value {
+            let last = &mut last_shown[reading.index];
+
+            if let (Some(db), Some(prev)) = (job.deadband, *last) {
+                if (*v - prev).abs() < db {
+                    continue;
+                }
+            }
+            *last = Some(*v)
+        }
+
+        if !changes.pass(reading.index, &reading.value) {
+            continue;
+        }
+
+        for s in sinks.iter_mut() {
+            s.reading(req, job.label.as_deref(), &reading, tag.as_ref())?
+        }
+    }
+    finish_sinks(&mut sinks)?;
+    if let Some(s) = &stats {
+        print_stats(s, stats_format)
+    }
+
+    let n = failed.iter().flatten().count();
+
+    match failed.iter().flatten().next() {
+        Some(first) => Err(first.fail(format!("{} of the {} requests failed", n, jobs.len()))),
+        None => Ok(()),
+    }
+}
+
+// Reads and parses a capture file.
+
+fn read_capture(file: &str) -> Result<Vec<capture::Record>, Error> {
+    capture::parse_bytes(&std::fs::read(file)?)
+        .map_err(|e| Error::Parse(format!("{}: {}", file, e)))
+}
+
+// Implements the CAPTURE DIFF subcommand. Every channel is listed
+// with the number of records in each file, how many of the compared
+// pairs differ and the largest difference found.
+
+fn cmd_capture_diff(m: &ArgMatches) -> Result<(), Error> {
+    let tolerance = match m.value_of("TOLERANCE") {
+        Some(t) => t
+            .parse::<f64>()
+            .ok()
+            .filter(|t| *t >= 0.0)
+            .ok_or_else(|| Error::Parse(format!("bad tolerance '{}'", t)))?,
+        None => 0.0,
+    };
+    let (a, b) = (m.value_of("A").unwrap(), m.value_of("B").unwrap());
+    let result = capture::diff(&read_capture(a)?, &read_capture(b)?, tolerance);
+
+    println!("{:<40} {:>8} {:>8} {:>8} {:>16}", "CHANNEL", "A", "B", "DIFFER", "MAX DIFF");
+    for d in &result {
+        println!(
+            "{:<40} {:>8} {:>8} {:>8} {:>16.6}",
+            d.key, d.count_a, d.count_b, d.differ, d.max_diff
+        )
+    }
+    println!(
+        "{} of {} channel(s) differ",
+        result.iter().filter(|d| !d.is_same()).count(),
+        result.len()
+    );
+    Ok(())
+}
+
+// Implements the STATES WATCH subcommand. Each device's first state
+// is shown, then every change. With --until, it stops once a device
+// is in the given state, which may be its first.
+
+fn cmd_states_watch(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let format =
+        output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?;
+    let tf = time_formatter(m)?;
+    let requests = parse_requests(m)?;
+    let until = m
+        .value_of("UNTIL")
+        .map(|s| s.trim().parse::<u16>().map_err(|_| Error::Parse(format!("bad state '{}' (expected a value)", s))))
+        .transpose()?;
+    let mut watcher = states::Watcher::new(requests.len());
+
+    for reading in client.subscribe(requests.clone()) {
+        let Some(t) = watcher.update(&reading?) else { continue };
+
+        println!("{}", states::format_transition(format, &tf, &requests[t.index].device.name(), &t));
+
+        if until == Some(t.to) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Implements the MONITOR subcommand.
+
+fn cmd_monitor(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+
+    monitor::run(&client, time_formatter(m)?, parse_requests(m)?)
+}
+
+// Implements the EXPORT PROMETHEUS subcommand.
+
+fn cmd_export_prometheus(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+
+    prometheus::run(&client, m.value_of("LISTEN").unwrap_or("127.0.0.1:9099"), parse_requests(m)?)
+}
+
+// Implements the TABLE subcommand.
+
+fn cmd_table(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let file = m.value_of("FILE").unwrap();
+    let jobs = job::parse_file(&std::fs::read_to_string(file)?)
+        .map_err(|e| Error::Parse(format!("{}: {}", file, e)))?;
+    let refresh = history::parse_span(m.value_of("REFRESH").unwrap_or("1s")).map_err(Error::Parse)?;
+
+    table::run(&client, &jobs, std::time::Duration::from_micros(refresh))
+}
+
+// Implements the CAPTURE MERGE subcommand. The merged capture is
+// written as `--record` writes one, so it's binary for .acs files;
+// on stdout it's JSON lines.
+
+fn cmd_capture_merge(m: &ArgMatches) -> Result<(), Error> {
+    let captures = m
+        .values_of("FILE")
+        .into_iter()
+        .flatten()
+        .map(|file| replay::load(&std::fs::read(file)?).map_err(|e| Error::Parse(format!("{}: {}", file, e))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let merged = capture::merge(captures);
+
+    match m.value_of("OUTPUT") {
+        Some(file) => {
+            let mut out = capture::Recorder::create(file)?;
+
+            for e in &merged {
+                out.write(&e.key, &e.reading)?
+            }
+        }
+        None => {
+            for e in merged {
+                let record = capture::Record { timestamp: e.reading.timestamp, key: e.key, value: e.reading.value };
+
+                println!("{}", capture::format_json(&record, &timefmt::Formatter::default()))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Implements the CONVERT subcommand.
+
+fn cmd_convert(m: &ArgMatches) -> Result<(), Error> {
+    let time = |arg| {
+        m.value_of(arg)
+            .map(|t| {
+                capture::parse_timestamp(t).ok_or_else(|| Error::Parse(format!("bad time '{}'", t)))
+            })
+            .transpose()
+    };
+    let filter = capture::Filter {
+        start: time("START")?,
+        end: time("END")?,
+        devices: m.values_of("DEVICES").into_iter().flatten().map(String::from).collect(),
+    };
+    let parquet = parquet_output(m)?;
+    let tf = time_formatter(m)?;
+
+    if parquet.is_none() && m.is_present("OUTPUT") {
+        return Err(Error::Parse(String::from("--output is only used with --format parquet")));
+    }
+
+    let records = read_capture(m.value_of("FILE").unwrap())?;
+    let records = records.iter().filter(|r| filter.matches(r));
+
+    if let Some(file) = parquet {
+        return write_parquet(file, records.flat_map(|r| parquet::rows(r.timestamp, &r.key, 0, &r.value)));
+    }
+
+    match m.value_of("FORMAT").unwrap() {
+        "csv" => {
+            let style = csv_style(m)?;
+
+            println!("{}", style.header());
+            for line in records.flat_map(|r| capture::format_csv(r, &tf, &style)) {
+                println!("{}", line)
+            }
+        }
+        "json" => {
+            for r in records {
+                println!("{}", capture::format_json(r, &tf))
+            }
+        }
+        _ => unreachable!("clap only allows csv, json and parquet"),
+    }
+    Ok(())
+}
+
+// Implements the PUT subcommand. VALUE is interpreted according to
+// the property being set and, for arrays, the DRF's range selects the
+// elements to set, so the number of values must match it.
+
+fn cmd_put(m: &ArgMatches) -> Result<(), Error> {
+    if let Some(file) = m.value_of("FILE") {
+        return cmd_put_file(m, file);
+    }
+
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let drf = m.value_of("DRF").unwrap();
+    let req = parse_request(m, drf).map_err(Error::Parse)?;
+    let req = put::setting_request(&req).map_err(Error::Parse)?;
+    let value = put::parse_value(&req, m.value_of("VALUE").unwrap()).map_err(Error::Parse)?;
+
+    let verify = match m.is_present("VERIFY") {
+        true => Some(Verification::parse(m, &req)?),
+        false => None,
+    };
+
+    send_setting(m, &client, &req, &req, &value, verify.as_ref())
+}
+
+// Implements PUT --file. Every line is checked before anything is
+// sent and the settings are confirmed together. They're then sent in
+// order; with --stop-on-error (the default) the first that fails ends
+// the run and the rest are skipped, while --continue carries on. A
+// table of what came of each line is printed at the end and, with
+// --rollback, a settings file putting back the old values is written.
+
+fn cmd_put_file(m: &ArgMatches, path: &str) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Other(format!("can't read {}: {}", path, e)))?;
+    let lines = bulk::parse(&text).map_err(|e| Error::Parse(format!("{}: {}", path, e)))?;
+
+    send_settings(m, &client, path, &lines, !m.is_present("CONTINUE"))
+}
+
+// Sends the settings of `lines`, read from `path`, as described for
+// PUT --file. It's also how RESTORE puts a snapshot back.
+
+fn send_settings(
+    m: &ArgMatches,
+    client: &backend::Client,
+    path: &str,
+    lines: &[bulk::Line],
+    stop_on_error: bool,
+) -> Result<(), Error> {
+    let mut settings = vec![];
+    let mut errors = vec![];
+
+    for line in lines {
+        let parsed = parse_request(m, &line.drf)
+            .and_then(|req| put::setting_request(&req))
+            .and_then(|req| put::parse_value(&req, &line.value).map(|value| (req, value)))
+            .map_err(Error::Parse)
+            .and_then(|(req, value)| match m.is_present("VERIFY") {
+                true => Verification::parse(m, &req).map(|v| (req, value, Some(v))),
+                false => Ok((req, value, None)),
+            });
+
+        match parsed {
+            Ok(setting) => settings.push((line.number, setting)),
+            Err(Error::Parse(e)) => errors.push(format!("{}: line {}: {}", path, line.number, e)),
+            Err(e) => return Err(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Parse(errors.join("\n")));
+    }
+
+    let mut file = open_journal(client)?;
+    let old: Vec<Option<data::Value>> = settings.iter().map(|(_, (req, _, _))| read_current(client, req)).collect();
+    let summary: Vec<String> =
+        settings.iter().zip(&old).map(|((_, (req, value, _)), old)| put::describe(req, old.as_ref(), value)).collect();
+    let confirmed = confirm_setting(m, &format!("{}\n{} setting(s) from {}", summary.join("\n"), settings.len(), path));
+    let mut rows = vec![];
+    let mut failed = 0;
+
+    for ((line, (req, value, verify)), old) in settings.iter().zip(&old) {
+        let outcome = match &confirmed {
+            Ok(true) if failed > 0 && stop_on_error => None,
+            Ok(true) => match apply_setting(client, req, value, verify.as_ref()) {
+                Ok(()) => Some(journal::Outcome::Sent),
+                Err(e) => {
+                    failed += 1;
+                    Some(journal::Outcome::Failed(e.to_string()))
+                }
+            },
+            Ok(false) if m.is_present("DRY_RUN") => Some(journal::Outcome::DryRun),
+            _ => Some(journal::Outcome::Declined),
+        };
+
+        if let Some(outcome) = &outcome {
+            record_setting(file.as_mut(), req, old.as_ref(), value, outcome.clone())
+        }
+        rows.push(bulk::Row {
+            line: *line,
+            drf: req.canonical(),
+            old: old.as_ref().map(put::value_text),
+            new: put::value_text(&value.to_value()),
+            outcome,
+        })
+    }
+    confirmed?;
+
+    if !m.is_present("DRY_RUN") {
+        for row in bulk::format_results(&rows) {
+            println!("{}", row)
+        }
+    }
+    if let Some(undo) = m.value_of("ROLLBACK") {
+        std::fs::write(undo, bulk::rollback(&rows)).map_err(|e| Error::Other(format!("can't write {}: {}", undo, e)))?
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(Error::Other(format!("{} of the {} settings failed", n, rows.len()))),
+    }
+}
+
+// Implements the SAVE subcommand. The setting of each device is read,
+// along with its reading where it has one, and written to a snapshot
+// (see `snapshot.rs`). Settings which can't be read are saved as null,
+// and make the command fail once the snapshot is written.
+
+fn cmd_save(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let path = m.value_of("FILE").unwrap();
+    let settings: Vec<drf::Request> = parse_requests(m)?
+        .iter()
+        .map(|req| put::setting_request(req).map(|req| drf::Request { event: drf::Event::Immediate, ..req }))
+        .collect::<Result<_, _>>()
+        .map_err(Error::Parse)?;
+    let readings: Vec<Option<drf::Request>> = settings.iter().map(put::readback_request).collect();
+    let requests: Vec<drf::Request> = settings.iter().cloned().chain(readings.iter().flatten().cloned()).collect();
+
+    // Each request is done with its first reply, whether a value or a
+    // failed status; those which fail keep the status as the reason.
+
+    let mut values: Vec<Option<Result<String, String>>> = vec![None; requests.len()];
+
+    for r in backend::until_answered(client.acquire(&requests)?, vec![true; requests.len()]) {
+        let r = r?;
+
+        if let Some(slot @ None) = values.get_mut(r.index) {
+            *slot = Some(if r.failed() { Err(monitor::show(&r.value)) } else { Ok(put::value_text(&r.value)) })
+        }
+    }
+
+    // The readings were submitted after the settings, in order, by
+    // those which have one.
+
+    let mut reading_values = values.split_off(settings.len()).into_iter().map(|v| v.and_then(Result::ok));
+    let names: Vec<String> =
+        settings.iter().map(|req| drf::Request { event: drf::Event::Default, ..req.clone() }.canonical()).collect();
+    let unread: Vec<String> = names
+        .iter()
+        .zip(&values)
+        .filter_map(|(name, value)| match value {
+            Some(Ok(_)) => None,
+            Some(Err(reason)) => Some(format!("{} ({})", name, reason)),
+            None => Some(format!("{} (no reply)", name)),
+        })
+        .collect();
+    let snapshot = snapshot::Snapshot {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0),
+        user: journal::user(),
+        settings: names
+            .into_iter()
+            .zip(values)
+            .zip(&readings)
+            .map(|((drf, value), reading)| snapshot::Saved {
+                drf,
+                value: value.and_then(Result::ok),
+                reading: reading.as_ref().and_then(|_| reading_values.next().flatten()),
+            })
+            .collect(),
+    };
+
+    std::fs::write(path, snapshot.format()).map_err(|e| Error::Other(format!("can't write {}: {}", path, e)))?;
+
+    match unread.len() {
+        0 => Ok(()),
+        n => Err(Error::Other(format!("{} of the {} settings couldn't be read: {}", n, settings.len(), unread.join(", ")))),
+    }
+}
+
+// Implements the RESTORE subcommand: the settings of a snapshot are
+// sent like those of PUT --file, except that a failure doesn't stop
+// the rest from being restored.
+
+fn cmd_restore(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let path = m.value_of("FILE").unwrap();
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Other(format!("can't read {}: {}", path, e)))?;
+    let snapshot = snapshot::Snapshot::parse(&text).map_err(|e| Error::Parse(format!("{}: {}", path, e)))?;
+
+    for s in snapshot.settings.iter().filter(|s| s.value.is_none()) {
+        warn!(drf = %s.drf, "not restored: the setting wasn't saved")
+    }
+    send_settings(m, &client, path, &snapshot.lines(), false)
+}
+
+// How long to wait between reading a setting back with --verify.
+
+const VERIFY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+// How PUT --verify checks a setting: the requests read back (the
+// setting itself and, with --readback, the device's reading), how far
+// their values may be from the one sent and how many more times
+// they're read before giving up.
+
+struct Verification {
+    reads: Vec<drf::Request>,
+    tolerance: f64,
+    retries: u32,
+}
+
+impl Verification {
+    fn parse(m: &ArgMatches, req: &drf::Request) -> Result<Verification, Error> {
+        if req.property == drf::Property::Control {
+            return Err(Error::Parse(format!("{} can't be read back to verify it", req.canonical())));
+        }
+
+        let mut reads = vec![drf::Request { event: drf::Event::Immediate, ..req.clone() }];
+
+        if m.is_present("READBACK") {
+            reads.push(
+                put::readback_request(&reads[0])
+                    .ok_or_else(|| Error::Parse(format!("{} has no reading to follow it", req.canonical())))?,
+            )
+        }
+        Ok(Verification {
+            reads,
+            tolerance: match m.value_of("TOLERANCE") {
+                Some(t) => t
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| *t >= 0.0)
+                    .ok_or_else(|| Error::Parse(format!("bad tolerance '{}'", t)))?,
+                None => 0.0,
+            },
+            retries: match m.value_of("VERIFY_RETRIES") {
+                Some(n) => n.parse().map_err(|_| Error::Parse(format!("bad retry count '{}'", n)))?,
+                None => 3,
+            },
+        })
+    }
+}
+
+// Reads back a setting just made until the values match the one sent
+// or the retries run out.
+
+fn verify_setting(client: &backend::Client, verify: &Verification, value: &put::SetValue) -> Result<(), Error> {
+    let (reads, tolerance) = (&verify.reads, verify.tolerance);
+    let sent = value.to_value();
+    let mut wrong = vec![];
+
+    for attempt in 0..=verify.retries {
+        if attempt > 0 {
+            std::thread::sleep(VERIFY_DELAY)
+        }
+
+        // A read which fails answers with its status, which then
+        // shows as the value read.
+
+        let mut read = vec![None; reads.len()];
+
+        for r in backend::until_answered(client.acquire(reads)?, vec![true; reads.len()]) {
+            let r = r?;
+
+            if let Some(slot @ None) = read.get_mut(r.index) {
+                *slot = Some(r.value)
+            }
+        }
+
+        wrong = reads
+            .iter()
+            .zip(&read)
+            .filter(|(_, v)| !v.as_ref().is_some_and(|v| put::same_value(&sent, v, tolerance)))
+            .map(|(req, v)| {
+                format!("{} reads {}", req.canonical(), v.as_ref().map_or_else(|| String::from("nothing"), monitor::show))
+            })
+            .collect();
+        if wrong.is_empty() {
+            info!(attempt, "setting verified");
+            return Ok(());
+        }
+        info!(attempt, "setting not taken yet: {}", wrong.join(", "))
+    }
+    Err(Error::Other(format!(
+        "the device didn't take {}: {}",
+        monitor::show(&sent),
+        wrong.join(", ")
+    )))
+}
+
+// Makes a setting: shows it, with the value `current` (a request for
+// the device) reads now, has it confirmed, sends it and, if asked,
+// verifies it. Whatever
+// comes of it is recorded in the journal, which is opened first so a
+// setting isn't made if it can't be recorded.
+
+fn send_setting(
+    m: &ArgMatches,
+    client: &backend::Client,
+    req: &drf::Request,
+    current: &drf::Request,
+    value: &put::SetValue,
+    verify: Option<&Verification>,
+) -> Result<(), Error> {
+    let mut file = open_journal(client)?;
+    let now = read_current(client, current);
+    let (outcome, result) = match confirm_setting(m, &put::describe(req, now.as_ref(), value)) {
+        Ok(true) => match apply_setting(client, req, value, verify) {
+            Ok(()) => (journal::Outcome::Sent, Ok(())),
+            Err(e) => (journal::Outcome::Failed(e.to_string()), Err(e)),
+        },
+        Ok(false) if m.is_present("DRY_RUN") => (journal::Outcome::DryRun, Ok(())),
+        Ok(false) => (journal::Outcome::Declined, Ok(())),
+        Err(e) => (journal::Outcome::Declined, Err(e)),
+    };
+
+    record_setting(file.as_mut(), req, now.as_ref(), value, outcome);
+    result
+}
+
+// Opens the settings journal. Without a location for it (there's no
+// home directory), settings are made without being recorded.
+
+fn open_journal(client: &backend::Client) -> Result<Option<std::fs::File>, Error> {
+    match &client.settings().journal {
+        Some(path) => journal::open(path).map(Some).map_err(Error::Other),
+        None => {
+            warn!("no journal location (set --journal or ACSYS_JOURNAL); settings won't be recorded");
+            Ok(None)
+        }
+    }
+}
+
+// Reads the value `current` has now, if it can be read.
+
+fn read_current(client: &backend::Client, current: &drf::Request) -> Option<data::Value> {
+    let read = drf::Request { event: drf::Event::Immediate, ..current.clone() };
+
+    match client.acquire(std::slice::from_ref(&read)).and_then(|mut s| s.next().transpose()) {
+        Ok(Some(r)) if !r.failed() => Some(r.value),
+        Ok(_) => None,
+        Err(e) => {
+            info!(drf = %read, "can't read the current value: {}", e);
+            None
+        }
+    }
+}
+
+// Sends a setting and, if asked, verifies it.
+
+fn apply_setting(
+    client: &backend::Client,
+    req: &drf::Request,
+    value: &put::SetValue,
+    verify: Option<&Verification>,
+) -> Result<(), Error> {
+    client.set(req, value)?;
+    verify.map_or(Ok(()), |v| verify_setting(client, v, value))
+}
+
+// Appends what came of a setting to the journal. The setting has been
+// dealt with by now, so failing to record it is only reported.
+
+fn record_setting(
+    file: Option<&mut std::fs::File>,
+    req: &drf::Request,
+    old: Option<&data::Value>,
+    value: &put::SetValue,
+    outcome: journal::Outcome,
+) {
+    let entry = journal::Entry {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0),
+        user: journal::user(),
+        drf: req.canonical(),
+        old: old.map(put::value_text),
+        new: put::value_text(&value.to_value()),
+        result: outcome,
+    };
+
+    if let Some(Err(e)) = file.map(|f| journal::append(f, &entry)) {
+        warn!("the setting wasn't recorded: {}", e)
+    }
+}
+
+// Shows a setting about to be made and returns whether it should be
+// sent. With --dry-run it never is; with --yes it always is.
+// Otherwise the user is asked, which needs a terminal: a setting
+// can't go out unconfirmed by accident.
+
+fn confirm_setting(m: &ArgMatches, summary: &str) -> Result<bool, Error> {
+    if m.is_present("DRY_RUN") {
+        eprintln!("{} (dry run; not sent)", summary);
+        return Ok(false);
+    }
+    if m.is_present("YES") {
+        info!("{}", summary);
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        return Err(Error::Other(format!("{}: not sent without confirmation (give --yes to send it)", summary)));
+    }
+
+    let mut answer = String::new();
+
+    eprint!("{}\nSend? [y/N] ", summary);
+    io::stderr().flush()?;
+    io::stdin().read_line(&mut answer)?;
+
+    let send = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+
+    if !send {
+        eprintln!("not sent")
+    }
+    Ok(send)
+}
+
+// Implements the CONTROL subcommand. The command is sent by name for
+// the data source to translate.
+
+fn cmd_control(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let req = parse_request(m, m.value_of("DEVICE").unwrap()).map_err(Error::Parse)?;
+    let req = drf::Request { property: drf::Property::Control, ..req };
+    let command = put::Command::parse(m.value_of("COMMAND").unwrap()).map_err(Error::Parse)?;
+    let value = put::SetValue::Command(command);
+    let status = drf::Request { property: drf::Property::Status(drf::StatusField::All), ..req.clone() };
+
+    send_setting(m, &client, &req, &status, &value, None)
+}
+
+// Implements the JOURNAL SHOW subcommand. Entries whose DRF doesn't
+// parse (e.g. written by a newer version) are shown when no device
+// is given.
+
+fn cmd_journal_show(m: &ArgMatches) -> Result<(), Error> {
+    let settings = settings::Settings::resolve(m)?;
+    let format =
+        output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?;
+    let tf = time_formatter(m)?;
+    let path = settings
+        .journal
+        .ok_or_else(|| Error::Other(String::from("no journal location (set --journal or ACSYS_JOURNAL)")))?;
+    let last = match m.value_of("LAST") {
+        Some(n) => Some(n.parse::<usize>().map_err(|_| Error::Parse(format!("bad count '{}'", n)))?),
+        None => None,
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Other(format!("can't read {}: {}", path.display(), e))),
+    };
+    let entries: Vec<journal::Entry> = journal::parse_file(&text)
+        .map_err(|e| Error::Parse(format!("{}: {}", path.display(), e)))?
+        .into_iter()
+        .filter(|e| match m.value_of("DEVICE") {
+            Some(pattern) => drf::parse_drf(&e.drf).is_ok_and(|r| alarm::matches(pattern, &r.device.name())),
+            None => true,
+        })
+        .collect();
+    let skip = last.map_or(0, |n| entries.len().saturating_sub(n));
+
+    for e in &entries[skip..] {
+        println!("{}", journal::format(format, &tf, e))
+    }
+    Ok(())
+}
+
+// Implements the REPLAY subcommand.
+
+fn cmd_replay(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let speed = replay::Speed::parse(m.value_of("SPEED").unwrap_or("realtime")).map_err(Error::Parse)?;
+    let tf = time_formatter(m)?;
+    let file = m.value_of("FILE").unwrap();
+    let entries = replay::load(&std::fs::read(file)?).map_err(|e| Error::Parse(format!("{}: {}", file, e)))?;
+    let mut sinks = open_sinks(m, &client, &tf)?;
+
+    replay::replay(&entries, speed, &mut sinks)
+}
+
+// Implements the SELFTEST subcommand. The report is printed even when
+// checks fail; the command then fails too, so scripts can use it as a
+// health check.
+
+fn cmd_selftest(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let device = parse_request(m, m.value_of("DEVICE").unwrap_or(selftest::REFERENCE_DEVICE)).map_err(Error::Parse)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    let checks = selftest::run(&client, &device, now);
+
+    print!("{}", selftest::report(&checks));
+    match selftest::failures(&checks) {
+        0 => Ok(()),
+        n => Err(Error::Other(format!("{} of {} checks failed", n, checks.len()))),
+    }
+}
+
+// Implements the SHELL subcommand.
+
+fn cmd_shell(m: &ArgMatches) -> Result<(), Error> {
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+
+    shell::run(&client, &time_formatter(m)?)
+}
+
+// Implements the SIMULATE subcommand. Updates are paced in real time;
+// if output can't keep up, updates are sent as fast as possible and a
+// warning is logged. An interrupt or terminate signal ends the run
+// like --duration does, so the sinks still deliver what they hold.
+
+fn cmd_simulate(m: &ArgMatches) -> Result<(), Error> {
+    let devices = match m.value_of("DEVICES") {
+        Some(n) => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| Error::Parse(format!("bad device count '{}'", n)))?,
+        None => 10,
+    };
+    let period = simulate::parse_rate(m.value_of("RATE").unwrap_or("1Hz")).map_err(Error::Parse)?;
+    let duration = m
+        .value_of("DURATION")
+        .map(|d| {
+            d.parse::<f64>()
+                .ok()
+                .and_then(|d| std::time::Duration::try_from_secs_f64(d).ok())
+                .ok_or_else(|| Error::Parse(format!("bad duration '{}'", d)))
+        })
+        .transpose()?;
+    let client = backend::Client::new(settings::Settings::resolve(m)?);
+    let tf = time_formatter(m)?;
+    let mut sinks = open_sinks(m, &client, &tf)?;
+    let requests = simulate::requests(devices, period);
+    let started = std::time::Instant::now();
+    let start_ts = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    let mut generator = simulate::Generator::new(devices, period, start_ts);
+    let mut records = 0u64;
+    let mut behind = false;
+    let signal = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    for s in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register_usize(s, signal.clone(), s as usize)?;
+    }
+
+    loop {
+        let offset = generator.next_offset();
+
+        if duration.is_some_and(|d| offset >= d) || signal.load(std::sync::atomic::Ordering::Relaxed) != 0 {
+            break;
+        }
+
+        match offset.checked_sub(started.elapsed()) {
+            Some(wait) => std::thread::sleep(wait),
+            None if !behind && started.elapsed() - offset > period => {
+                behind = true;
+                warn!("output can't keep up with {} devices at the requested rate", devices)
+            }
+            None => (),
+        }
+
+        for reading in generator.next().unwrap() {
+            for sink in sinks.iter_mut() {
+                sink.reading(&requests[reading.index], None, &reading, None)?
+            }
+            records += 1
+        }
+    }
+    finish_sinks(&mut sinks)?;
+
+    let elapsed = started.elapsed().as_secs_f64();
+
+    info!(records, elapsed, rate = records as f64 / elapsed, "simulation finished");
+
+    // Exit with the shell's status for a program ended by the signal.
+
+    match signal.load(std::sync::atomic::Ordering::Relaxed) {
+        0 => Ok(()),
+        s => process::exit(128 + s as i32),
+    }
+}
+
+// Implements the DRF FMT subcommand.
+
+fn cmd_drf_fmt(m: &ArgMatches) -> Result<(), Error> {
+    let text = match m.value_of("FILE") {
+        Some(file) => std::fs::read_to_string(file)?,
+        None => io::read_to_string(io::stdin())?,
+    };
+    let name = m.value_of("FILE").unwrap_or("<stdin>");
+    let events = clock_events(m)?;
+    let result = job::format_file(&text, &events, m.is_present("SYMBOLIC"))
+        .map_err(|e| Error::Parse(format!("{}: {}", name, e)))?;
+
+    if m.is_present("WRITE") {
+        if result != text {
+            std::fs::write(name, result)?
+        }
+    } else {
+        print!("{}", result)
+    }
+    Ok(())
+}
+
+// Implements the DRF CHECK subcommand. Every request is checked; the
+// command fails if any is invalid. Requests read from stdin are
+// reported with their line number.
+
+fn cmd_drf_check(m: &ArgMatches) -> Result<(), Error> {
+    let events = clock_events(m)?;
+    let options = drf::ParseOptions { strict: m.is_present("strict"), clock_events: Some(&events) };
+    let fmt = output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?;
+    let checked: Vec<(Option<usize>, check::Checked)> = if m.is_present("STDIN") {
+        check::check_lines(&io::read_to_string(io::stdin())?, &options)
+            .into_iter()
+            .map(|(n, c)| (Some(n), c))
+            .collect()
+    } else {
+        m.values_of("DRF").into_iter().flatten().map(|drf| (None, check::check(drf, &options))).collect()
+    };
+    let mut out = io::stdout().lock();
+
+    for (line, c) in &checked {
+        writeln!(out, "{}", check::format(fmt, *line, c))?
+    }
+
+    match checked.iter().filter(|(_, c)| !c.is_valid()).count() {
+        0 => Ok(()),
+        n => Err(Error::Parse(format!("{} of {} request(s) are invalid", n, checked.len()))),
+    }
+}
+
+// Implements the DRF CANON subcommand.
+
+fn cmd_drf_canon(m: &ArgMatches) -> Result<(), Error> {
+    let events = clock_events(m)?;
+
+    for req in parse_requests(m)? {
+        if m.is_present("MINIMAL") {
+            println!("{}", req.canonical_minimal())
+        } else if m.is_present("SYMBOLIC") {
+            println!("{}", req.symbolic(&events))
+        } else {
+            println!("{}", req.canonical())
+        }
+    }
+    Ok(())
+}
+
+// Implements the DRF EXPLAIN subcommand. Requests are separated by
+// a blank line or, in JSON, written one per line.
+
+fn cmd_drf_explain(m: &ArgMatches) -> Result<(), Error> {
+    let events = clock_events(m)?;
+    let fmt = output::Format::parse(m.value_of("FORMAT").unwrap_or("text")).map_err(Error::Parse)?;
+    let requests = parse_requests(m)?;
+
+    match fmt {
+        output::Format::Text => {
+            let explained: Vec<String> = requests.iter().map(|req| drf::explain(req, &events)).collect();
+
+            print!("{}", explained.join("\n"))
+        }
+        output::Format::Json => {
+            for req in &requests {
+                println!("{}", check::explain_json(req, &events))
+            }
+        }
+    }
+    Ok(())
+}
+
 fn main() {
-    let _matches = cmd_cfg().get_matches();
+    let matches = cmd_cfg().get_matches();
+
+    let result = match matches.subcommand() {
+        ("average", Some(m)) => init_logging(m).and_then(|_| cmd_average(m)),
+        ("capture", Some(m)) => match m.subcommand() {
+            ("diff", Some(m)) => init_logging(m).and_then(|_| cmd_capture_diff(m)),
+            ("merge", Some(m)) => init_logging(m).and_then(|_| cmd_capture_merge(m)),
+            _ => Ok(()),
+        },
+        ("convert", Some(m)) => init_logging(m).and_then(|_| cmd_convert(m)),
+        ("export", Some(m)) => match m.subcommand() {
+            ("prometheus", Some(m)) => init_logging(m).and_then(|_| cmd_export_prometheus(m)),
+            _ => Ok(()),
+        },
+        ("drf", Some(m)) => match m.subcommand() {
+            ("check", Some(m)) => init_logging(m).and_then(|_| cmd_drf_check(m)),
+            ("canon", Some(m)) => init_logging(m).and_then(|_| cmd_drf_canon(m)),
+            ("explain", Some(m)) => init_logging(m).and_then(|_| cmd_drf_explain(m)),
+            ("fmt", Some(m)) => init_logging(m).and_then(|_| cmd_drf_fmt(m)),
+            _ => Ok(()),
+        },
+        ("get", Some(m)) => match m.subcommand() {
+            ("history", Some(m)) => init_logging(m).and_then(|_| cmd_get_history(m)),
+            ("live", Some(m)) => init_logging(m).and_then(|_| cmd_get_live(m)),
+            _ => Ok(()),
+        },
+        ("monitor", Some(m)) => init_logging(m).and_then(|_| cmd_monitor(m)),
+        ("report", Some(m)) => init_logging(m).and_then(|_| cmd_report(m)),
+        ("replay", Some(m)) => init_logging(m).and_then(|_| cmd_replay(m)),
+        ("save", Some(m)) => init_logging(m).and_then(|_| cmd_save(m)),
+        ("restore", Some(m)) => init_logging(m).and_then(|_| cmd_restore(m)),
+        ("journal", Some(m)) => match m.subcommand() {
+            ("show", Some(m)) => init_logging(m).and_then(|_| cmd_journal_show(m)),
+            _ => Ok(()),
+        },
+        ("selftest", Some(m)) => init_logging(m).and_then(|_| cmd_selftest(m)),
+        ("shell", Some(m)) => init_logging(m).and_then(|_| cmd_shell(m)),
+        ("simulate", Some(m)) => init_logging(m).and_then(|_| cmd_simulate(m)),
+        ("overlay", Some(m)) => init_logging(m).and_then(|_| cmd_overlay(m)),
+        ("states", Some(m)) => match m.subcommand() {
+            ("watch", Some(m)) => init_logging(m).and_then(|_| cmd_states_watch(m)),
+            _ => Ok(()),
+        },
+        ("table", Some(m)) => init_logging(m).and_then(|_| cmd_table(m)),
+        ("put", Some(m)) => init_logging(m).and_then(|_| cmd_put(m)),
+        ("control", Some(m)) => init_logging(m).and_then(|_| cmd_control(m)),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}: {}", crate_name!(), e);
+        process::exit(e.exit_code())
+    }
 }
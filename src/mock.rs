@@ -0,0 +1,260 @@
+use crate::{backend::Backend, capture, data::Reading, error::Error, put::SetValue, simulate};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+// A backend which needs no data source, so the program can be tried,
+// and tested, offline. Settings are accepted, and ignored, in any
+// role.
+//
+// Without a capture, it makes up its data: each request gets the
+// synthetic device of `simulate::sample()` whose number is the
+// request's ID. Requests for a single reply get one, periodic ones
+// are answered at their rate and other repeating events once a
+// second. Requests for logged data (see `drf::DataSource::Logger`)
+// are answered at once with the points of the same device, one a
+// second (or at the request's rate), over the logged range.
+//
+// Given a capture (e.g. one written by `get live --record`), it
+// serves the recorded readings of each request instead, as fast as
+// they're read and with their recorded timestamps. A request's
+// readings are the records of its DRF or, if there are none, of its
+// device. Requests for a single reply get the first of them and
+// those for logged data the ones in the logged range. The stream ends
+// after the last one.
+
+pub struct Session {
+    capture: Option<Vec<capture::Record>>,
+    requests: Vec<Schedule>,
+    logged: Vec<Logged>,
+    canned: VecDeque<Reading>,
+    start: Option<(Instant, u64)>,
+}
+
+// When a request's next reply is due, as an offset from the start,
+// and how often it repeats (if it does.)
+
+struct Schedule {
+    id: usize,
+    next: Duration,
+    period: Option<Duration>,
+}
+
+// The points of a request for logged data which are still to be
+// served: the next is at `next` and they end before `end` (both in
+// microseconds since the epoch.)
+
+struct Logged {
+    id: usize,
+    next: u64,
+    end: u64,
+    period: u64,
+}
+
+fn period(event: &drf::Event) -> Option<Duration> {
+    match event {
+        drf::Event::Periodic { .. } => event.period(),
+        e if e.is_streaming() => Some(Duration::from_secs(1)),
+        _ => None,
+    }
+}
+
+impl Session {
+    pub fn new(capture: Option<Vec<capture::Record>>) -> Session {
+        Session { capture, requests: vec![], logged: vec![], canned: VecDeque::new(), start: None }
+    }
+
+    // Returns the recorded readings of a request, whatever its data
+    // source.
+
+    fn recorded(records: &[capture::Record], id: usize, request: &drf::Request) -> Vec<Reading> {
+        let drf = drf::Request { source: drf::DataSource::Default, ..request.clone() }.canonical();
+        let device = request.device.name();
+        let by_drf = records.iter().any(|r| r.key == drf);
+
+        records
+            .iter()
+            .filter(|r| if by_drf { r.key == drf } else { capture::device(&r.key).eq_ignore_ascii_case(&device) })
+            .map(|r| Reading::new(id, r.timestamp, r.value.clone()))
+            .collect()
+    }
+}
+
+impl Backend for Session {
+    fn add_request(&mut self, id: usize, request: &drf::Request) -> Result<(), Error> {
+        if request.event == drf::Event::Never {
+            return Ok(());
+        }
+
+        let range = match request.source {
+            drf::DataSource::Logger { start, end } => Some((start.saturating_mul(1000), end.saturating_mul(1000))),
+            _ => None,
+        };
+
+        match (&self.capture, range) {
+            (Some(records), _) => {
+                let mut readings = Session::recorded(records, id, request);
+
+                if readings.is_empty() {
+                    return Err(Error::DeviceNotFound(format!("{} isn't in the capture", request.canonical())));
+                }
+                match range {
+                    Some((start, end)) => readings.retain(|r| r.timestamp >= start && r.timestamp < end),
+                    None if !request.event.is_streaming() => readings.truncate(1),
+                    None => (),
+                }
+                self.canned.extend(readings)
+            }
+            (None, Some((start, end))) => {
+                let period = period(&request.event).map_or(1_000_000, |p| p.as_micros().max(1) as u64);
+
+                self.logged.push(Logged { id, next: start.div_ceil(period).saturating_mul(period), end, period })
+            }
+            (None, None) => self.requests.push(Schedule { id, next: Duration::ZERO, period: period(&request.event) }),
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+
+        self.canned.make_contiguous().sort_by_key(|r| r.timestamp);
+        self.start = Some((Instant::now(), now));
+        Ok(())
+    }
+
+    fn next_reply(&mut self) -> Option<Result<Reading, Error>> {
+        let (start, epoch) = self.start?;
+
+        if self.capture.is_some() {
+            return self.canned.pop_front().map(Ok);
+        }
+
+        self.logged.retain(|l| l.next < l.end);
+        if let Some(l) = self.logged.iter_mut().min_by_key(|l| l.next) {
+            let reading = Reading::new(l.id, l.next, simulate::sample(l.id, l.next as f64 / 1e6).into());
+
+            l.next = l.next.saturating_add(l.period);
+            return Some(Ok(reading));
+        }
+
+        let pos = (0..self.requests.len()).min_by_key(|&i| self.requests[i].next)?;
+        let due = self.requests[pos].next;
+
+        thread::sleep(due.saturating_sub(start.elapsed()));
+
+        let id = self.requests[pos].id;
+
+        match self.requests[pos].period {
+            Some(p) => self.requests[pos].next += p,
+            None => {
+                self.requests.remove(pos);
+            }
+        }
+        Some(Ok(Reading::new(id, epoch + due.as_micros() as u64, simulate::sample(id, due.as_secs_f64()).into())))
+    }
+
+    fn apply_setting(&mut self, id: usize, value: &SetValue) -> Result<(), Error> {
+        info!(id, ?value, "the mock backend ignores settings");
+        Ok(())
+    }
+
+    fn verify_role(&mut self, role: &str) -> Result<(), Error> {
+        info!(role, "the mock backend accepts every role");
+        Ok(())
+    }
+
+    fn cancel(&mut self) {
+        self.requests.clear();
+        self.logged.clear();
+        self.canned.clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    #[test]
+    fn test_synthetic() {
+        let mut mock = Session::new(None);
+
+        for (id, drf) in ["M:OUTTMP@P,20", "G:AMANDA@I", "Z:NONE@N", "Z:EVENT@E,8F"].iter().enumerate() {
+            mock.add_request(id, &drf::parse_drf(drf).unwrap()).unwrap();
+        }
+        assert!(mock.next_reply().is_none());
+        mock.start().unwrap();
+
+        let replies: Vec<Reading> = (0..5).map(|_| mock.next_reply().unwrap().unwrap()).collect();
+        let first = replies[0].timestamp;
+
+        assert_eq!(replies.iter().map(|r| r.index).collect::<Vec<_>>(), [0, 1, 3, 0, 0]);
+        assert_eq!(replies.iter().map(|r| r.timestamp - first).collect::<Vec<_>>(), [0, 0, 0, 20_000, 40_000]);
+        assert_eq!(replies[1].value, simulate::sample(1, 0.0).into());
+
+        mock.cancel();
+        assert!(mock.next_reply().is_none());
+
+        // Logged data is served over its range, on the request's
+        // grid, and then the stream ends.
+
+        let mut mock = Session::new(None);
+
+        mock.add_request(0, &drf::parse_drf("M:OUTTMP<-LOGGER:1500:4000").unwrap()).unwrap();
+        mock.add_request(1, &drf::parse_drf("G:AMANDA@P,500<-LOGGER:2000:3000").unwrap()).unwrap();
+        mock.start().unwrap();
+
+        let replies: Vec<Reading> = std::iter::from_fn(|| mock.next_reply()).map(Result::unwrap).collect();
+
+        assert_eq!(
+            replies.iter().map(|r| (r.index, r.timestamp)).collect::<Vec<_>>(),
+            [(0, 2_000_000), (1, 2_000_000), (1, 2_500_000), (0, 3_000_000)]
+        );
+        assert_eq!(replies[3].value, simulate::sample(0, 3.0).into());
+    }
+
+    #[test]
+    fn test_capture() {
+        let records = capture::parse_file(
+            "{\"timestamp\":1.0,\"drf\":\"M:OUTTMP@P,1000\",\"value\":70}\n\
+             {\"timestamp\":1.5,\"drf\":\"G:AMANDA.READING.SCALED\",\"value\":[1,2]}\n\
+             {\"timestamp\":2.0,\"drf\":\"M:OUTTMP@P,1000\",\"value\":71}\n\
+             {\"timestamp\":2.5,\"drf\":\"G:AMANDA.READING.SCALED\",\"value\":[3,4]}\n",
+        )
+        .unwrap();
+        let mut mock = Session::new(Some(records));
+
+        for (id, drf) in ["M:OUTTMP@P,1000", "G:AMANDA"].iter().enumerate() {
+            mock.add_request(id, &drf::parse_drf(drf).unwrap()).unwrap();
+        }
+        assert!(matches!(
+            mock.add_request(2, &drf::parse_drf("Z:ACLTST").unwrap()),
+            Err(Error::DeviceNotFound(_))
+        ));
+        mock.start().unwrap();
+
+        let replies: Vec<Reading> = std::iter::from_fn(|| mock.next_reply()).map(Result::unwrap).collect();
+
+        assert_eq!(
+            replies.iter().map(|r| (r.index, r.timestamp)).collect::<Vec<_>>(),
+            [(0, 1_000_000), (1, 1_500_000), (0, 2_000_000)]
+        );
+        assert_eq!(replies[1].value, Value::Array(vec![1.0, 2.0]));
+
+        let mut mock = Session::new(Some(capture::parse_file(
+            "{\"timestamp\":1.0,\"drf\":\"M:OUTTMP@P,1000\",\"value\":70}\n\
+             {\"timestamp\":2.0,\"drf\":\"M:OUTTMP@P,1000\",\"value\":71}\n\
+             {\"timestamp\":3.0,\"drf\":\"M:OUTTMP@P,1000\",\"value\":72}\n",
+        )
+        .unwrap()));
+
+        mock.add_request(0, &drf::parse_drf("M:OUTTMP<-LOGGER:1500:3000").unwrap()).unwrap();
+        mock.start().unwrap();
+        assert_eq!(
+            std::iter::from_fn(|| mock.next_reply()).map(|r| r.unwrap().value).collect::<Vec<_>>(),
+            [Value::Scalar(71.0)]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::data::{Reading, Value};
+use crate::{backend::Client, error::Error, status::Status, timefmt::Formatter};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// The columns by which the table can be sorted. Pressing 's' moves to
+// the next one.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Request,
+    Value,
+    Time,
+}
+
+impl SortKey {
+    fn next(self) -> SortKey {
+        match self {
+            SortKey::Request => SortKey::Value,
+            SortKey::Value => SortKey::Time,
+            SortKey::Time => SortKey::Request,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Request => "device",
+            SortKey::Value => "value",
+            SortKey::Time => "time",
+        }
+    }
+}
+
+// One monitored device. `latest` holds the timestamp and value of the
+// most recent reply and `units` the units it was reported in. No backend reports alarm state yet, so `alarm`
+// is always empty.
+
+pub struct Entry {
+    pub request: drf::Request,
+    pub units: Option<String>,
+    pub latest: Option<(u64, Value)>,
+    pub alarm: Option<String>,
+}
+
+// The state of the monitor, independent of the terminal. `selected`
+// is a position in the displayed (sorted) order. `time` formats the
+// timestamp column.
+
+pub struct Model {
+    pub entries: Vec<Entry>,
+    pub sort: SortKey,
+    pub paused: bool,
+    pub selected: usize,
+    pub time: Formatter,
+}
+
+impl Model {
+    pub fn new(time: Formatter) -> Model {
+        Model {
+            entries: vec![],
+            sort: SortKey::Request,
+            paused: false,
+            selected: 0,
+            time,
+        }
+    }
+
+    // Adds a device to the table. Returns `false` if it was already
+    // being monitored.
+
+    pub fn add(&mut self, request: drf::Request) -> bool {
+        if self.entries.iter().any(|e| e.request == request) {
+            return false;
+        }
+        self.entries.push(Entry {
+            request,
+            units: None,
+            latest: None,
+            alarm: None,
+        });
+        true
+    }
+
+    // Removes the selected device and returns its request.
+
+    pub fn remove_selected(&mut self) -> Option<drf::Request> {
+        let idx = *self.order().get(self.selected)?;
+        let entry = self.entries.remove(idx);
+
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        Some(entry.request)
+    }
+
+    pub fn requests(&self) -> Vec<drf::Request> {
+        self.entries.iter().map(|e| e.request.clone()).collect()
+    }
+
+    // Records a reply. The reading's index refers to the position of
+    // the entry since the subscription is rebuilt whenever the list
+    // changes. Replies are dropped while the display is paused.
+
+    pub fn update(&mut self, reading: Reading) {
+        if self.paused {
+            return;
+        }
+        if let Some(e) = self.entries.get_mut(reading.index) {
+            if reading.meta.units.is_some() {
+                e.units = reading.meta.units
+            }
+            e.latest = Some((reading.timestamp, reading.value))
+        }
+    }
+
+    pub fn select(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1) as isize;
+
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize
+    }
+
+    // Returns the indices of the entries in display order. Entries
+    // without data sort after the others.
+
+    pub fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        let value = |i: usize| {
+            self.entries[i]
+                .latest
+                .as_ref()
+                .and_then(|(_, v)| v.as_slice().first().copied())
+        };
+
+        match self.sort {
+            SortKey::Request => {
+                order.sort_by_key(|i| self.entries[*i].request.canonical())
+            }
+            SortKey::Value => order.sort_by(|a, b| match (value(*a), value(*b)) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }),
+            SortKey::Time => order.sort_by_key(|i| {
+                std::cmp::Reverse(self.entries[*i].latest.as_ref().map(|(ts, _)| *ts))
+            }),
+        }
+        order
+    }
+
+    // Returns the cells of each row in display order: the DRF, the
+    // latest value, its units, its timestamp and the alarm state.
+
+    pub fn rows(&self) -> Vec<[String; 5]> {
+        self.order()
+            .into_iter()
+            .map(|i| {
+                let e = &self.entries[i];
+                let (ts, value) = match &e.latest {
+                    Some((ts, v)) => (self.time.format(*ts), show(v)),
+                    None => (String::from("-"), String::from("-")),
+                };
+
+                [
+                    e.request.canonical(),
+                    value,
+                    e.units.clone().unwrap_or_default(),
+                    ts,
+                    e.alarm.clone().unwrap_or_default(),
+                ]
+            })
+            .collect()
+    }
+}
+
+// Renders a value so it fits in a table cell. Long arrays are
+// abbreviated.
+
+pub fn show(v: &Value) -> String {
+    match v {
+        Value::Scalar(v) => v.to_string(),
+        Value::Array(v) if v.len() > 4 => format!(
+            "[{}, ... ({} elements)]",
+            v[..3].iter().map(f64::to_string).collect::<Vec<_>>().join(", "),
+            v.len()
+        ),
+        Value::Array(v) => format!("[{}]", v.iter().map(f64::to_string).collect::<Vec<_>>().join(", ")),
+        Value::Raw(v) => v.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "),
+        Value::Text(v) => v.clone(),
+        Value::Status(s) => Status(*s).to_string(),
+        Value::Alarm(a) => a.format_text(),
+    }
+}
+
+// Acquires data for the requests on a separate thread. The thread
+// stops once the returned receiver is dropped, which is how an old
+// subscription is cancelled when the device list changes.
+
+pub fn subscribe(client: &Client, requests: Vec<drf::Request>) -> mpsc::Receiver<Result<Reading, Error>> {
+    let (tx, rx) = mpsc::channel();
+    let client = client.clone();
+
+    thread::spawn(move || {
+        for reading in client.subscribe(requests) {
+            if tx.send(reading).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// What the keyboard is being used for: commands, or typing the DRF of
+// a device to add.
+
+enum Input {
+    Command,
+    Adding(String),
+}
+
+const HELP: &str = "q: quit  p: pause  s: sort  a: add  d: remove  \u{2191}/\u{2193}: select";
+
+fn draw(f: &mut Frame, model: &Model, input: &Input, status: &str) {
+    let [table_area, status_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(f.area());
+    let title = format!(
+        " acsys monitor - sorted by {}{} ",
+        model.sort.name(),
+        if model.paused { " (paused)" } else { "" }
+    );
+    let table = Table::new(
+        model.rows().into_iter().map(Row::new),
+        [
+            Constraint::Percentage(40),
+            Constraint::Percentage(25),
+            Constraint::Length(8),
+            Constraint::Length(18),
+            Constraint::Min(6),
+        ],
+    )
+    .header(
+        Row::new(["DEVICE", "VALUE", "UNITS", "TIMESTAMP", "ALARM"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(title))
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = TableState::default().with_selected(Some(model.selected));
+
+    f.render_stateful_widget(table, table_area, &mut state);
+
+    let line = match input {
+        Input::Adding(text) => format!("add device: {}", text),
+        Input::Command if !status.is_empty() => status.to_string(),
+        Input::Command => String::from(HELP),
+    };
+
+    f.render_widget(Paragraph::new(line), status_area);
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, client: &Client, model: &mut Model) -> Result<(), Error> {
+    let mut rx = subscribe(client, model.requests());
+    let mut input = Input::Command;
+    let mut status = String::new();
+
+    loop {
+        while let Ok(reading) = rx.try_recv() {
+            match reading {
+                Ok(reading) => model.update(reading),
+                Err(e) => status = e.to_string(),
+            }
+        }
+
+        terminal.draw(|f| draw(f, model, &input, &status))?;
+
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+            _ => continue,
+        };
+        let mut changed = false;
+
+        match &mut input {
+            Input::Command => {
+                status.clear();
+                match key {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('p') => model.paused = !model.paused,
+                    KeyCode::Char('s') => model.sort = model.sort.next(),
+                    KeyCode::Char('a') => input = Input::Adding(String::new()),
+                    KeyCode::Char('d') => changed = model.remove_selected().is_some(),
+                    KeyCode::Up => model.select(-1),
+                    KeyCode::Down => model.select(1),
+                    _ => (),
+                }
+            }
+            Input::Adding(text) => match key {
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Esc => input = Input::Command,
+                KeyCode::Enter => {
+                    match drf::parse_with(text.trim(), &drf::ParseOptions::default()) {
+                        Ok(parsed) => {
+                            changed = model.add(parsed.request);
+                            if !changed {
+                                status = format!("already monitoring {}", text.trim())
+                            } else if let Some(w) = parsed.warnings.first() {
+                                status = format!("warning: {}", w)
+                            }
+                        }
+                        Err(e) => status = drf::describe(text.trim(), &e),
+                    }
+                    input = Input::Command
+                }
+                _ => (),
+            },
+        }
+
+        if changed {
+            rx = subscribe(client, model.requests())
+        }
+    }
+}
+
+// Runs the monitor until the user quits. The terminal is restored
+// even if an error ends the monitor early.
+
+pub fn run(client: &Client, time: Formatter, requests: Vec<drf::Request>) -> Result<(), Error> {
+    let mut model = Model::new(time);
+
+    for req in requests {
+        model.add(req);
+    }
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, client, &mut model);
+
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drf::parse_drf;
+
+    fn reading(index: usize, timestamp: u64, v: f64) -> Reading {
+        Reading::new(index, timestamp, v.into())
+    }
+
+    #[test]
+    fn test_model() {
+        let mut m = Model::new(Formatter::default());
+
+        assert!(m.add(parse_drf("M:OUTTMP").unwrap()));
+        assert!(m.add(parse_drf("G:AMANDA").unwrap()));
+        assert!(!m.add(parse_drf("G:AMANDA").unwrap()));
+
+        let mut r = reading(0, 2_000_000, 72.5);
+
+        r.meta.units = Some(String::from("DegF"));
+        m.update(r);
+        m.update(reading(1, 1_000_000, 100.0));
+
+        let rows = m.rows();
+
+        assert_eq!(rows[0][0], "G:AMANDA.READING.SCALED");
+        assert_eq!(rows[1], ["M:OUTTMP.READING.SCALED", "72.5", "DegF", "2.000000", ""].map(String::from));
+
+        m.sort = SortKey::Time;
+        assert_eq!(m.order(), vec![0, 1]);
+        m.sort = SortKey::Value;
+        assert_eq!(m.order(), vec![0, 1]);
+
+        m.paused = true;
+        m.update(reading(0, 3_000_000, 0.0));
+        assert_eq!(m.rows()[0][1], "72.5");
+
+        m.select(5);
+        assert_eq!(m.remove_selected().map(|r| r.canonical()), Some(String::from("G:AMANDA.READING.SCALED")));
+        assert_eq!((m.entries.len(), m.selected), (1, 0));
+    }
+
+    #[test]
+    fn test_show() {
+        assert_eq!(show(&Value::Array(vec![1.0, 2.0])), "[1, 2]");
+        assert_eq!(show(&Value::Array(vec![1.0; 6])), "[1, 1, 1, ... (6 elements)]");
+        assert_eq!(show(&Value::Raw(vec![0x1f, 0x02])), "1F 02");
+    }
+}
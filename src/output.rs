@@ -0,0 +1,506 @@
+use crate::status::Status;
+use crate::data::{Reading, Value};
+use drf::{Range, Request};
+use crate::timefmt::Formatter;
+
+// Selects how readings are written to stdout.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Format, String> {
+        match text.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown output format '{}'", text)),
+        }
+    }
+}
+
+// Returns `text` as a JSON string literal.
+
+pub fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Returns a number as JSON. JSON has no representation for NaN or
+// the infinities so they're written as `null`.
+
+pub fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+// Returns the elements of an array (or raw) reply along with the
+// index of the first one. Data sources normally return only the
+// elements covered by the range, but if more were returned, the range
+// is used to select them.
+
+pub fn elements<'a, T>(range: &Range, data: &'a [T]) -> (usize, &'a [T]) {
+    // A relative range is asked for as the whole array, since where
+    // it starts depends on the array's length.
+
+    if let Range::Relative { .. } = range {
+        return (range.resolve(data.len()).map_or(0, |r| r.start()), range.slice(data));
+    }
+
+    match range.count() {
+        Some(n) if data.len() > n => (range.start(), range.slice(data)),
+        _ => (range.start(), data),
+    }
+}
+
+// Like `elements()`, but for raw replies. Only byte ranges apply to
+// raw data; an array range selects whole elements, whose size isn't
+// known here, so the data is used as-is.
+
+fn raw_elements<'a>(range: &Range, data: &'a [u8]) -> (usize, &'a [u8]) {
+    match range {
+        Range::Raw { .. } => elements(range, data),
+        _ => (0, data),
+    }
+}
+
+// Renders a reading in the requested format. Each string in the
+// result is one line of output. In text format, each element of an
+// array is written on its own line, labeled with its index. If a
+// `label` is given, it's used in place of the DRF and device name.
+// Timestamps are written by `tf`. Whatever else the data source told
+// of the reading (see `data::Meta`) is added: the units follow the
+// values in text and every known item is a member in JSON. A failed
+// request shows its status in place of a value.
+
+pub fn format_reading(
+    fmt: Format,
+    tf: &Formatter,
+    label: Option<&str>,
+    req: &Request,
+    reading: &Reading,
+) -> Vec<String> {
+    let ts = tf.format(reading.timestamp);
+    let drf = label.map(String::from).unwrap_or_else(|| req.canonical());
+    let meta = &reading.meta;
+    let name = meta.device.clone().unwrap_or_else(|| req.device.name().into_owned());
+    let device = label.unwrap_or(&name);
+    let units = meta.units.as_ref().map(|u| format!(" {}", u)).unwrap_or_default();
+
+    match fmt {
+        Format::Text => match &reading.value {
+            Value::Scalar(v) => vec![format!("{} {} {}{}", ts, drf, v, units)],
+            Value::Array(v) => {
+                let (start, data) = elements(&req.range, v);
+
+                data.iter()
+                    .enumerate()
+                    .map(|(i, v)| format!("{} {}[{}] {}{}", ts, device, start + i, v, units))
+                    .collect()
+            }
+            Value::Raw(v) => {
+                let (_, data) = raw_elements(&req.range, v);
+                let bytes: Vec<String> = data.iter().map(|b| format!("{:02X}", b)).collect();
+
+                vec![format!("{} {} {}", ts, drf, bytes.join(" "))]
+            }
+            Value::Text(v) => vec![format!("{} {} {}", ts, drf, v)],
+            Value::Status(s) => vec![format!("{} {} {}", ts, drf, Status(*s))],
+            Value::Alarm(a) => vec![format!("{} {} {}", ts, drf, a.format_text())],
+        },
+        Format::Json => {
+            let value = match &reading.value {
+                Value::Scalar(v) => json_number(*v),
+                Value::Array(v) => {
+                    let (start, data) = elements(&req.range, v);
+                    let data: Vec<String> = data.iter().map(|v| json_number(*v)).collect();
+
+                    format!("[{}],\"start\":{}", data.join(","), start)
+                }
+                Value::Raw(v) => {
+                    let (start, data) = raw_elements(&req.range, v);
+                    let data: Vec<String> = data.iter().map(u8::to_string).collect();
+
+                    format!("[{}],\"start\":{}", data.join(","), start)
+                }
+                Value::Text(v) => json_string(v),
+                Value::Status(s) => format!("null,\"status\":{},\"error\":{}", s, json_string(&Status(*s).to_string())),
+                Value::Alarm(a) => a.format_json(),
+            };
+            let extra: String = [
+                meta.device.as_ref().map(|d| format!(",\"device\":{}", json_string(d))),
+                meta.di.map(|di| format!(",\"di\":{}", di)),
+                meta.units.as_ref().map(|u| format!(",\"units\":{}", json_string(u))),
+                meta.cycle.map(|c| format!(",\"cycle\":{}", tf.json(c))),
+                Some(meta.status).filter(|s| *s != 0).map(|s| format!(",\"status\":{}", s)),
+            ]
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+
+            vec![format!(
+                "{{\"timestamp\":{},\"drf\":{},\"value\":{}{}}}",
+                tf.json(reading.timestamp),
+                json_string(&drf),
+                value,
+                extra
+            )]
+        }
+    }
+}
+
+// Renders the value of a derived channel.
+
+pub fn format_derived(fmt: Format, tf: &Formatter, name: &str, timestamp: u64, v: f64, units: &str) -> String {
+    match fmt {
+        Format::Text => format!("{} {} {} {}", tf.format(timestamp), name, v, units).trim_end().to_string(),
+        Format::Json => format!(
+            "{{\"timestamp\":{},\"name\":{},\"value\":{},\"units\":{}}}",
+            tf.json(timestamp),
+            json_string(name),
+            json_number(v),
+            json_string(units)
+        ),
+    }
+}
+
+// A field of a reply, as named by --fields and --template.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Time,
+    Drf,
+    Device,
+    Value,
+    Units,
+    Status,
+    Index,
+    Di,
+    Cycle,
+}
+
+impl Field {
+    pub fn parse(text: &str) -> Result<Field, String> {
+        Ok(match text.trim().to_lowercase().as_str() {
+            "time" | "timestamp" => Field::Time,
+            "drf" => Field::Drf,
+            "device" => Field::Device,
+            "value" => Field::Value,
+            "units" => Field::Units,
+            "status" => Field::Status,
+            "index" => Field::Index,
+            "di" => Field::Di,
+            "cycle" => Field::Cycle,
+            _ => {
+                return Err(format!(
+                    "unknown field '{}' (expected time, drf, device, value, units, status, index, di or cycle)",
+                    text
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+// Shapes text output in place of its usual columns: a list of fields
+// separated by spaces (--fields time,device,value), with "-" for a
+// field a reply doesn't have, or a template naming fields in braces
+// (--template "{device} = {value} {units}"), where a missing field is
+// empty and "{{" and "}}" are literal braces. Each element of an array
+// is a line of its own, as in text output.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+    missing: &'static str,
+}
+
+impl Template {
+    pub fn fields(list: &str) -> Result<Template, String> {
+        let mut parts = vec![];
+
+        for (n, f) in list.split(',').enumerate() {
+            if n > 0 {
+                parts.push(Part::Literal(String::from(" ")))
+            }
+            parts.push(Part::Field(Field::parse(f)?))
+        }
+        Ok(Template { parts, missing: "-" })
+    }
+
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    literal.push(c);
+                    chars.next();
+                }
+                ('{', _) => {
+                    let mut name = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(String::from("unterminated '{' in template")),
+                        }
+                    }
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    parts.push(Part::Field(Field::parse(&name)?))
+                }
+                ('}', _) => return Err(String::from("unmatched '}' in template (write '}}' for a brace)")),
+                _ => literal.push(c),
+            }
+        }
+        parts.push(Part::Literal(literal));
+        parts.retain(|p| *p != Part::Literal(String::new()));
+        Ok(Template { parts, missing: "" })
+    }
+
+    fn line(&self, field: impl Fn(Field) -> Option<String>) -> String {
+        self.parts
+            .iter()
+            .map(|p| match p {
+                Part::Literal(l) => l.clone(),
+                Part::Field(f) => field(*f).unwrap_or_else(|| String::from(self.missing)),
+            })
+            .collect()
+    }
+
+    // Renders a reading as `format_reading()` does in text.
+
+    pub fn format_reading(&self, tf: &Formatter, label: Option<&str>, req: &Request, reading: &Reading) -> Vec<String> {
+        let meta = &reading.meta;
+        let device = label.map(String::from).or_else(|| meta.device.clone());
+        let device = device.unwrap_or_else(|| req.device.name().into_owned());
+        let status = match reading.value {
+            Value::Status(s) => s,
+            _ => meta.status,
+        };
+        let field = |f: Field, index: Option<usize>, value: &str| match f {
+            Field::Time => Some(tf.format(reading.timestamp)),
+            Field::Drf => Some(label.map(String::from).unwrap_or_else(|| req.canonical())),
+            Field::Device => Some(device.clone()),
+            Field::Value => Some(String::from(value)),
+            Field::Units => meta.units.clone(),
+            Field::Status => Some(status.to_string()),
+            Field::Index => index.map(|i| i.to_string()),
+            Field::Di => meta.di.map(|di| di.to_string()),
+            Field::Cycle => meta.cycle.map(|c| tf.format(c)),
+        };
+        let value = match &reading.value {
+            Value::Scalar(v) => v.to_string(),
+            Value::Array(v) => {
+                let (start, data) = elements(&req.range, v);
+
+                return data
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| self.line(|f| field(f, Some(start + i), &v.to_string())))
+                    .collect();
+            }
+            Value::Raw(v) => {
+                let bytes: Vec<String> = raw_elements(&req.range, v).1.iter().map(|b| format!("{:02X}", b)).collect();
+
+                bytes.join(" ")
+            }
+            Value::Text(v) => v.clone(),
+            Value::Status(s) => Status(*s).to_string(),
+            Value::Alarm(a) => a.format_text(),
+        };
+
+        vec![self.line(|f| field(f, None, &value))]
+    }
+
+    // Renders the value of a derived channel, whose name is its DRF
+    // and device.
+
+    pub fn format_derived(&self, tf: &Formatter, name: &str, timestamp: u64, v: f64, units: &str) -> String {
+        self.line(|f| match f {
+            Field::Time => Some(tf.format(timestamp)),
+            Field::Drf | Field::Device => Some(String::from(name)),
+            Field::Value => Some(v.to_string()),
+            Field::Units => Some(String::from(units)).filter(|u| !u.is_empty()),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Meta;
+    use drf::parse_drf;
+
+    fn reading(value: Value) -> Reading {
+        Reading::new(0, 1_500_000, value)
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn test_array_expansion() {
+        let tf = Formatter::default();
+        let req = parse_drf("M:OUTTMP[2:4]").unwrap();
+        let r = reading(vec![1.0, 2.5, 3.0].into());
+
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &r),
+            vec![
+                "1.500000 M:OUTTMP[2] 1",
+                "1.500000 M:OUTTMP[3] 2.5",
+                "1.500000 M:OUTTMP[4] 3",
+            ]
+        );
+        assert_eq!(
+            format_reading(Format::Json, &tf, None, &req, &r),
+            vec![
+                "{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING[2:4].SCALED\",\
+                 \"value\":[1,2.5,3],\"start\":2}"
+            ]
+        );
+
+        // A reply holding the whole array gets sliced by the range.
+
+        let r = reading(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0].into());
+
+        assert_eq!(format_reading(Format::Text, &tf, None, &req, &r).len(), 3);
+        assert_eq!(format_reading(Format::Text, &tf, None, &req, &r)[0], "1.500000 M:OUTTMP[2] 2");
+        assert_eq!(format_reading(Format::Text, &tf, Some("tmp"), &req, &r)[0], "1.500000 tmp[2] 2");
+
+        // A relative range is resolved against the whole array.
+
+        let req = parse_drf("M:OUTTMP[-2:]").unwrap();
+
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &r),
+            vec!["1.500000 M:OUTTMP[4] 4", "1.500000 M:OUTTMP[5] 5"]
+        );
+    }
+
+    #[test]
+    fn test_scalar_formats() {
+        let tf = Formatter::default();
+        let req = parse_drf("M:OUTTMP").unwrap();
+
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &reading(72.5.into())),
+            vec!["1.500000 M:OUTTMP.READING.SCALED 72.5"]
+        );
+        assert_eq!(
+            format_reading(Format::Json, &tf, None, &req, &reading(f64::NAN.into())),
+            vec!["{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":null}"]
+        );
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &reading(String::from("warm").into())),
+            vec!["1.500000 M:OUTTMP.READING.SCALED warm"]
+        );
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &reading(vec![0x1fu8, 0x02].into())),
+            vec!["1.500000 M:OUTTMP.READING.SCALED 1F 02"]
+        );
+
+        let iso = Formatter::new(Some("iso"), None).unwrap();
+
+        assert_eq!(
+            format_reading(Format::Json, &iso, None, &req, &reading(1.0.into())),
+            vec!["{\"timestamp\":\"1970-01-01T00:00:01.500000+00:00[UTC]\",\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":1}"]
+        );
+    }
+
+    #[test]
+    fn test_meta_and_status() {
+        let tf = Formatter::default();
+        let req = parse_drf("M:OUTTMP").unwrap();
+        let mut r = reading(72.5.into());
+
+        r.meta = Meta {
+            device: Some(String::from("M:OUTTMP")),
+            di: Some(27235),
+            units: Some(String::from("DegF")),
+            cycle: Some(1_000_000),
+            status: 1,
+        };
+        assert_eq!(
+            format_reading(Format::Text, &tf, None, &req, &r),
+            vec!["1.500000 M:OUTTMP.READING.SCALED 72.5 DegF"]
+        );
+        assert_eq!(
+            format_reading(Format::Json, &tf, None, &req, &r),
+            vec![
+                "{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":72.5,\
+                 \"device\":\"M:OUTTMP\",\"di\":27235,\"units\":\"DegF\",\"cycle\":1.000000,\"status\":1}"
+            ]
+        );
+
+        // A failed request shows its status in place of a value.
+
+        let r = reading(Value::Status(-42));
+
+        assert!(r.failed());
+        assert_eq!(Template::fields("status,value").unwrap().format_reading(&tf, None, &req, &r)[0], format!("-42 {}", Status(-42)));
+        assert_eq!(
+            format_reading(Format::Json, &tf, None, &req, &r),
+            vec![format!(
+                "{{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":null,\"status\":-42,\"error\":{}}}",
+                json_string(&Status(-42).to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let tf = Formatter::default();
+        let req = parse_drf("B:BPM[1:2]").unwrap();
+        let mut r = reading(vec![1.0, 2.5].into());
+
+        r.meta.units = Some(String::from("mm"));
+
+        let fields = Template::fields("time, device,index,value,di").unwrap();
+
+        assert_eq!(fields.format_reading(&tf, None, &req, &r), ["1.500000 B:BPM 1 1 -", "1.500000 B:BPM 2 2.5 -"]);
+        assert_eq!(fields.format_derived(&tf, "X", 1_500_000, 3.0, ""), "1.500000 X - 3 -");
+
+        let template = Template::parse("{device}[{index}] = {value} {units} {{ok}}").unwrap();
+
+        assert_eq!(template.format_reading(&tf, Some("bpm"), &req, &r)[1], "bpm[2] = 2.5 mm {ok}");
+        assert_eq!(template.format_derived(&tf, "X", 0, 3.0, "mm"), "X[] = 3 mm {ok}");
+        assert_eq!(Template::parse("{drf}").unwrap().format_reading(&tf, None, &req, &r)[0], "B:BPM.READING[1:2].SCALED");
+        assert!(Template::parse("{speed}").is_err());
+        assert!(Template::parse("a } b").is_err());
+        assert!(Template::parse("{value").is_err());
+        assert!(Template::fields("time,").is_err());
+    }
+}
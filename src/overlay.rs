@@ -0,0 +1,185 @@
+use crate::data::Reading;
+use crate::output::{self, json_number};
+use crate::timefmt::Formatter;
+use drf::{Property, ReadingField, Request, SettingField};
+
+// Returns the requests for the logged setting and reading of the
+// device named by `req`, at its event. Only the device and event of
+// `req` are used. They're fetched in this order, so a reading's index
+// tells which it is.
+
+pub fn requests(req: &Request) -> [Request; 2] {
+    let build = |property| {
+        Request::builder()
+            .device(&req.device.name())
+            .property(property)
+            .event(req.event.clone())
+            .build()
+            .expect("a device's canonical name accepts every property")
+    };
+
+    [build(Property::Setting(SettingField::default())), build(Property::Reading(ReadingField::default()))]
+}
+
+// One row of the overlay: the setting and reading in effect at a
+// time. A value holds until the next one is logged, so each row
+// carries forward whichever of the two didn't change.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub timestamp: u64,
+    pub setting: Option<f64>,
+    pub reading: Option<f64>,
+}
+
+impl Row {
+    // Returns how far the reading is from the setting.
+
+    pub fn error(&self) -> Option<f64> {
+        Some(self.reading? - self.setting?)
+    }
+}
+
+// Joins the logged settings (index 0) and readings (index 1), which
+// must be in timestamp order, into rows. Values logged at the same
+// time share a row. Non-numeric values are ignored.
+
+pub fn join(readings: &[Reading]) -> Vec<Row> {
+    let mut rows: Vec<Row> = vec![];
+    let (mut setting, mut reading) = (None, None);
+
+    for r in readings {
+        let Some(&v) = r.value.as_slice().first() else { continue };
+
+        match r.index {
+            0 => setting = Some(v),
+            1 => reading = Some(v),
+            _ => continue,
+        }
+
+        let row = Row { timestamp: r.timestamp, setting, reading };
+
+        match rows.last_mut() {
+            Some(last) if last.timestamp == r.timestamp => *last = row,
+            _ => rows.push(row),
+        }
+    }
+    rows
+}
+
+// How well the reading tracked the setting: the mean, and the
+// largest, distance between them, over the rows where both are known.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    pub max: f64,
+    pub max_at: u64,
+}
+
+pub fn summarize(rows: &[Row]) -> Option<Summary> {
+    let errors: Vec<(u64, f64)> = rows.iter().filter_map(|r| Some((r.timestamp, r.error()?.abs()))).collect();
+    let &(max_at, max) = errors.iter().max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    Some(Summary {
+        mean: errors.iter().map(|(_, e)| e).sum::<f64>() / errors.len() as f64,
+        max,
+        max_at,
+    })
+}
+
+// Formats the rows. Text output is a table with a summary line at the
+// end; JSON output is a record per row. Unknown values are '-' in
+// text and null in JSON.
+
+pub fn format(fmt: output::Format, tf: &Formatter, rows: &[Row]) -> Vec<String> {
+    let text = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_else(|| String::from("-"));
+    let json = |v: Option<f64>| v.map(json_number).unwrap_or_else(|| String::from("null"));
+
+    match fmt {
+        output::Format::Text => {
+            let mut lines = vec![format!("{:<20} {:>14} {:>14} {:>14}", "TIMESTAMP", "SETTING", "READING", "ERROR")];
+
+            lines.extend(rows.iter().map(|r| {
+                format!(
+                    "{:<20} {:>14} {:>14} {:>14}",
+                    tf.format(r.timestamp),
+                    text(r.setting),
+                    text(r.reading),
+                    text(r.error())
+                )
+            }));
+            if let Some(s) = summarize(rows) {
+                lines.push(format!("mean error {}, largest {} at {}", s.mean, s.max, tf.format(s.max_at)))
+            }
+            lines
+        }
+        output::Format::Json => rows
+            .iter()
+            .map(|r| {
+                format!(
+                    "{{\"timestamp\":{},\"setting\":{},\"reading\":{},\"error\":{}}}",
+                    tf.json(r.timestamp),
+                    json(r.setting),
+                    json(r.reading),
+                    json(r.error())
+                )
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn reading(index: usize, timestamp: u64, v: f64) -> Reading {
+        Reading::new(index, timestamp, v.into())
+    }
+
+    #[test]
+    fn test_overlay() {
+        let [setting, reading_req] = requests(&drf::parse_drf("M|OUTTMP.ON@p,1s").unwrap());
+
+        assert_eq!(setting.to_string(), "M:OUTTMP.SETTING.SCALED@P,1S,TRUE");
+        assert_eq!(reading_req.to_string(), "M:OUTTMP.READING.SCALED@P,1S,TRUE");
+
+        let readings = [
+            reading(1, 1_000_000, 70.0),
+            reading(0, 2_000_000, 72.0),
+            reading(1, 2_000_000, 71.0),
+            Reading::new(1, 2_500_000, Value::Text(String::from("?"))),
+            reading(1, 3_000_000, 72.5),
+        ];
+        let rows = join(&readings);
+
+        assert_eq!(
+            rows,
+            [
+                Row { timestamp: 1_000_000, setting: None, reading: Some(70.0) },
+                Row { timestamp: 2_000_000, setting: Some(72.0), reading: Some(71.0) },
+                Row { timestamp: 3_000_000, setting: Some(72.0), reading: Some(72.5) },
+            ]
+        );
+        assert_eq!(summarize(&rows), Some(Summary { mean: 0.75, max: 1.0, max_at: 2_000_000 }));
+        assert_eq!(summarize(&rows[..1]), None);
+
+        let tf = Formatter::default();
+
+        assert_eq!(
+            format(output::Format::Text, &tf, &rows),
+            [
+                "TIMESTAMP                   SETTING        READING          ERROR",
+                "1.000000                          -             70              -",
+                "2.000000                         72             71             -1",
+                "3.000000                         72           72.5            0.5",
+                "mean error 0.75, largest 1 at 2.000000",
+            ]
+        );
+        assert_eq!(
+            format(output::Format::Json, &tf, &rows[..1]),
+            ["{\"timestamp\":1.000000,\"setting\":null,\"reading\":70,\"error\":null}"]
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use crate::data::{Reading, Value};
+use crate::output;
+use drf::Request;
+use std::io::Write;
+
+// A writer of Parquet files, for loading large pulls of data into
+// pandas, polars and the like. Each row holds one value:
+//
+//    timestamp  INT64 (TIMESTAMP_MICROS)   when it was taken
+//    device     BYTE_ARRAY (UTF8)          the device, or channel
+//    index      INT32, optional            the array element, if any
+//    value      DOUBLE, optional           null if it isn't a number
+//    status     INT32 (INT_16)             the ACNET status, 0 if OK
+//
+// Only what's needed to write these columns is implemented: every
+// page is PLAIN-encoded and uncompressed, null values are marked by
+// RLE-encoded definition levels and the metadata uses Thrift's
+// compact protocol. Rows are written out in row groups, so a file of
+// any size is written in bounded memory.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub timestamp: u64,
+    pub device: String,
+    pub index: Option<u32>,
+    pub value: Option<f64>,
+    pub status: i16,
+}
+
+// Returns the rows of a value: one for a scalar, one per element of
+// an array (the first of which is element `start`) and a row without
+// a value for anything else.
+
+pub fn rows(timestamp: u64, device: &str, start: usize, value: &Value) -> Vec<Row> {
+    let row = |index: Option<usize>, value, status| Row {
+        timestamp,
+        device: String::from(device),
+        index: index.map(|i| i as u32),
+        value,
+        status,
+    };
+
+    match value {
+        Value::Scalar(v) => vec![row(None, Some(*v), 0)],
+        Value::Array(v) => v.iter().enumerate().map(|(i, v)| row(Some(start + i), Some(*v), 0)).collect(),
+        Value::Status(s) => vec![row(None, None, *s)],
+        Value::Raw(_) | Value::Text(_) | Value::Alarm(_) => vec![row(None, None, 0)],
+    }
+}
+
+// Returns the rows of a reply to `req`. Arrays hold only the elements
+// covered by the request's range and the device is named as the data
+// source named it, if it did.
+
+pub fn reading_rows(req: &Request, reading: &Reading) -> Vec<Row> {
+    let device = reading.meta.device.clone().unwrap_or_else(|| req.device.name().into_owned());
+    let mut rows = match &reading.value {
+        Value::Array(v) => {
+            let (start, data) = output::elements(&req.range, v);
+
+            rows(reading.timestamp, &device, start, &Value::Array(data.to_vec()))
+        }
+        v => rows(reading.timestamp, &device, 0, v),
+    };
+
+    for r in rows.iter_mut().filter(|r| r.status == 0) {
+        r.status = reading.meta.status
+    }
+    rows
+}
+
+const MAGIC: &[u8] = b"PAR1";
+const ROW_GROUP: usize = 1 << 20;
+
+// Parquet's physical types, converted types, encodings and the like.
+
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+const UTF8: i32 = 0;
+const TIMESTAMP_MICROS: i32 = 10;
+const INT_16: i32 = 16;
+
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+// The columns: name, physical type, converted type and whether
+// they're optional.
+
+const COLUMNS: [(&str, i32, Option<i32>, bool); 5] = [
+    ("timestamp", INT64, Some(TIMESTAMP_MICROS), false),
+    ("device", BYTE_ARRAY, Some(UTF8), false),
+    ("index", INT32, None, true),
+    ("value", DOUBLE, None, true),
+    ("status", INT32, Some(INT_16), false),
+];
+
+// Where a column chunk was written and how big it is.
+
+struct Chunk {
+    offset: u64,
+    size: u64,
+}
+
+pub struct Writer<W: Write> {
+    out: W,
+    offset: u64,
+    rows: Vec<Row>,
+    groups: Vec<(usize, Vec<Chunk>)>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut out: W) -> std::io::Result<Writer<W>> {
+        out.write_all(MAGIC)?;
+        Ok(Writer { out, offset: MAGIC.len() as u64, rows: vec![], groups: vec![] })
+    }
+
+    pub fn push(&mut self, row: Row) -> std::io::Result<()> {
+        self.rows.push(row);
+        if self.rows.len() >= ROW_GROUP {
+            self.flush_group()?
+        }
+        Ok(())
+    }
+
+    // Writes the rows held as a row group, a column chunk (of one
+    // page) per column.
+
+    fn flush_group(&mut self) -> std::io::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let mut chunks = vec![];
+
+        for column in 0..COLUMNS.len() {
+            let page = page(column, &rows);
+
+            self.out.write_all(&page)?;
+            chunks.push(Chunk { offset: self.offset, size: page.len() as u64 });
+            self.offset += page.len() as u64;
+        }
+        self.groups.push((rows.len(), chunks));
+        Ok(())
+    }
+
+    // Writes the last row group and the file's metadata.
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.flush_group()?;
+
+        let footer = metadata(&self.groups);
+
+        self.out.write_all(&footer)?;
+        self.out.write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Returns a column's data page, header included, for the rows.
+
+fn page(column: usize, rows: &[Row]) -> Vec<u8> {
+    let mut data = vec![];
+    let mut present = vec![];
+
+    for r in rows {
+        match column {
+            0 => data.extend((r.timestamp as i64).to_le_bytes()),
+            1 => {
+                data.extend((r.device.len() as u32).to_le_bytes());
+                data.extend(r.device.as_bytes())
+            }
+            2 => {
+                present.push(r.index.is_some());
+                data.extend(r.index.map(|i| i as i32).iter().flat_map(|i| i.to_le_bytes()))
+            }
+            3 => {
+                present.push(r.value.is_some());
+                data.extend(r.value.iter().flat_map(|v| v.to_le_bytes()))
+            }
+            _ => data.extend(i32::from(r.status).to_le_bytes()),
+        }
+    }
+
+    let mut body = vec![];
+
+    if COLUMNS[column].3 {
+        let levels = definition_levels(&present);
+
+        body.extend((levels.len() as u32).to_le_bytes());
+        body.extend(levels);
+    }
+    body.extend(data);
+
+    let mut header = Compact::default();
+
+    header.begin();
+    header.i32(1, DATA_PAGE);
+    header.i32(2, body.len() as i32);
+    header.i32(3, body.len() as i32);
+    header.struct_field(5);
+    header.i32(1, rows.len() as i32);
+    header.i32(2, PLAIN);
+    header.i32(3, RLE);
+    header.i32(4, RLE);
+    header.end();
+    header.end();
+
+    let mut page = header.out;
+
+    page.extend(body);
+    page
+}
+
+// Encodes definition levels (1 where there's a value, 0 where it's
+// null) as runs of the RLE/bit-packing hybrid encoding, with a bit
+// width of 1.
+
+fn definition_levels(present: &[bool]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+
+    while i < present.len() {
+        let n = present[i..].iter().take_while(|p| **p == present[i]).count();
+
+        varint(&mut out, (n as u64) << 1);
+        out.push(u8::from(present[i]));
+        i += n
+    }
+    out
+}
+
+// Returns the file's metadata (a FileMetaData structure).
+
+fn metadata(groups: &[(usize, Vec<Chunk>)]) -> Vec<u8> {
+    let mut m = Compact::default();
+
+    m.begin();
+    m.i32(1, 1);
+
+    // The schema: a root with a child per column.
+
+    m.list(2, STRUCT, COLUMNS.len() + 1);
+    m.begin();
+    m.binary(4, b"schema");
+    m.i32(5, COLUMNS.len() as i32);
+    m.end();
+    for (name, kind, converted, optional) in COLUMNS {
+        m.begin();
+        m.i32(1, kind);
+        m.i32(3, if optional { OPTIONAL } else { REQUIRED });
+        m.binary(4, name.as_bytes());
+        if let Some(c) = converted {
+            m.i32(6, c)
+        }
+        m.end();
+    }
+
+    m.i64(3, groups.iter().map(|(n, _)| *n as i64).sum());
+    m.list(4, STRUCT, groups.len());
+    for (rows, chunks) in groups {
+        m.begin();
+        m.list(1, STRUCT, chunks.len());
+        for ((name, kind, _, _), chunk) in COLUMNS.iter().zip(chunks) {
+            m.begin();
+            m.i64(2, chunk.offset as i64);
+            m.struct_field(3);
+            m.i32(1, *kind);
+            m.list(2, I32, 2);
+            m.list_i32(PLAIN);
+            m.list_i32(RLE);
+            m.list(3, BINARY, 1);
+            m.list_binary(name.as_bytes());
+            m.i32(4, UNCOMPRESSED);
+            m.i64(5, *rows as i64);
+            m.i64(6, chunk.size as i64);
+            m.i64(7, chunk.size as i64);
+            m.i64(9, chunk.offset as i64);
+            m.end();
+            m.end();
+        }
+        m.i64(2, chunks.iter().map(|c| c.size as i64).sum());
+        m.i64(3, *rows as i64);
+        m.end();
+    }
+    m.binary(6, format!("acsys version {}", env!("CARGO_PKG_VERSION")).as_bytes());
+    m.end();
+    m.out
+}
+
+// Thrift's compact protocol, as far as the metadata needs it. Field
+// headers hold the difference from the previous field's ID, so the
+// ID of the last field of each open structure is kept.
+
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+#[derive(Default)]
+struct Compact {
+    out: Vec<u8>,
+    last: Vec<i16>,
+}
+
+fn varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7
+    }
+    out.push(n as u8)
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+impl Compact {
+    fn begin(&mut self) {
+        self.last.push(0)
+    }
+
+    fn end(&mut self) {
+        self.out.push(0);
+        self.last.pop();
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("a field is inside a structure");
+
+        match id - *last {
+            delta @ 1..=15 => self.out.push(((delta as u8) << 4) | kind),
+            _ => {
+                self.out.push(kind);
+                varint(&mut self.out, zigzag(id.into()))
+            }
+        }
+        *last = id
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, I32);
+        varint(&mut self.out, zigzag(v.into()))
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, I64);
+        varint(&mut self.out, zigzag(v))
+    }
+
+    fn binary(&mut self, id: i16, v: &[u8]) {
+        self.field(id, BINARY);
+        self.list_binary(v)
+    }
+
+    fn struct_field(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.begin()
+    }
+
+    // Starts a list of `n` elements of type `kind`, which follow.
+
+    fn list(&mut self, id: i16, kind: u8, n: usize) {
+        self.field(id, LIST);
+        if n < 15 {
+            self.out.push(((n as u8) << 4) | kind)
+        } else {
+            self.out.push(0xf0 | kind);
+            varint(&mut self.out, n as u64)
+        }
+    }
+
+    fn list_i32(&mut self, v: i32) {
+        varint(&mut self.out, zigzag(v.into()))
+    }
+
+    fn list_binary(&mut self, v: &[u8]) {
+        varint(&mut self.out, v.len() as u64);
+        self.out.extend(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows() {
+        let rows = rows(5, "B:BPM", 2, &Value::Array(vec![1.5, 2.5]));
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1], Row { timestamp: 5, device: String::from("B:BPM"), index: Some(3), value: Some(2.5), status: 0 });
+        assert_eq!(super::rows(5, "M:OUTTMP", 0, &Value::Status(-6))[0].status, -6);
+        assert_eq!(super::rows(5, "M:OUTTMP", 0, &Value::Text(String::from("x")))[0].value, None);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut out = vec![];
+
+        varint(&mut out, 300);
+        assert_eq!(out, [0xac, 0x02]);
+        assert_eq!((zigzag(0), zigzag(-1), zigzag(1), zigzag(-64)), (0, 1, 2, 127));
+        assert_eq!(definition_levels(&[true, true, true, false, true]), [0x06, 1, 0x02, 0, 0x02, 1]);
+
+        let mut c = Compact::default();
+
+        c.begin();
+        c.i32(1, 1);
+        c.i64(3, -2);
+        c.binary(20, b"ab");
+        c.end();
+        assert_eq!(c.out, [0x15, 0x02, 0x26, 0x03, 0x08, 0x28, 0x02, b'a', b'b', 0x00]);
+    }
+
+    #[test]
+    fn test_file() {
+        let mut w = Writer::new(vec![]).unwrap();
+
+        w.push(rows(1_700_000_000_000_000, "M:OUTTMP", 0, &Value::Scalar(72.5)).remove(0)).unwrap();
+        w.push(rows(1_700_000_001_000_000, "M:OUTTMP", 0, &Value::Status(-6)).remove(0)).unwrap();
+
+        let file = w.finish().unwrap();
+        let n = file.len();
+        let footer = u32::from_le_bytes([file[n - 8], file[n - 7], file[n - 6], file[n - 5]]) as usize;
+
+        assert_eq!(&file[..4], b"PAR1");
+        assert_eq!(&file[n - 4..], b"PAR1");
+        assert_eq!(file[n - 8 - footer..][..2], [0x15, 0x02]);
+
+        // A value column's page has the length of its definition
+        // levels, the levels (a run of one present value) and the
+        // double.
+
+        let values = page(3, &[Row { timestamp: 0, device: String::new(), index: None, value: Some(1.0), status: 0 }]);
+
+        assert!(values.ends_with(&[2, 0, 0, 0, 0x02, 1].iter().copied().chain(1.0f64.to_le_bytes()).collect::<Vec<_>>()));
+    }
+}
@@ -0,0 +1,351 @@
+use crate::data::Reading;
+use crate::states::StateTag;
+use crate::{error::Error, sink::Sink, timefmt::Formatter};
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// Charts of each channel drawn in the terminal, for `get --plot`:
+// a sparkline a channel or, given more than one line of height, a
+// braille chart, in which each character holds a 2x4 grid of dots.
+// The vertical axis is scaled to the data shown. Live charts scroll,
+// keeping the latest points that fit, and are redrawn in place as
+// data arrives (at most ten times a second, and only on a terminal);
+// history charts are drawn once the data ends, with the points binned
+// across the width.
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// The width of the vertical axis' labels.
+
+const LABEL: usize = 10;
+
+// The bits of the dots of a braille character, by row (from the top)
+// and column.
+
+const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+const REDRAW: Duration = Duration::from_millis(100);
+
+// A reply, or the range of the replies in one column of a chart.
+// Arrays are plotted as the range of their elements.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    timestamp: u64,
+    min: f64,
+    max: f64,
+    mean: f64,
+}
+
+struct Channel {
+    key: String,
+    name: String,
+    units: String,
+    points: VecDeque<Point>,
+}
+
+pub struct Plot {
+    height: usize,
+    live: bool,
+    tf: Formatter,
+    channels: Vec<Channel>,
+    drawn: usize,
+    shown: Option<Instant>,
+}
+
+// Returns the points in `n` columns. Up to `n` points take a column
+// each, from the left; more are binned by time.
+
+fn columns(points: &[Point], n: usize) -> Vec<Option<Point>> {
+    let mut cols = vec![None; n];
+
+    if points.len() <= n {
+        for (c, p) in cols.iter_mut().zip(points) {
+            *c = Some(*p)
+        }
+        return cols;
+    }
+
+    let (first, last) = (points[0].timestamp, points[points.len() - 1].timestamp);
+    let mut counts = vec![0; n];
+
+    for p in points {
+        let c = ((u128::from(p.timestamp - first) * n as u128) / (u128::from(last - first) + 1)) as usize;
+
+        counts[c] += 1;
+        cols[c] = Some(match cols[c] {
+            None => *p,
+            Some(q) => Point {
+                timestamp: q.timestamp,
+                min: q.min.min(p.min),
+                max: q.max.max(p.max),
+                mean: q.mean + (p.mean - q.mean) / f64::from(counts[c]),
+            },
+        })
+    }
+    cols
+}
+
+// Returns the range of the vertical axis. A flat line is drawn in the
+// middle.
+
+fn scale(cols: &[Option<Point>]) -> (f64, f64) {
+    let (lo, hi) = cols.iter().flatten().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+        (lo.min(p.min), hi.max(p.max))
+    });
+
+    if lo < hi {
+        (lo, hi)
+    } else {
+        (lo - 0.5, hi + 0.5)
+    }
+}
+
+// Returns the number of decimals worth showing for values spanning
+// `lo` to `hi`.
+
+fn precision(lo: f64, hi: f64) -> usize {
+    (2.0 - (hi - lo).log10().floor()).clamp(0.0, 6.0) as usize
+}
+
+// Returns `v` as a dot row, from 0 at the bottom to `rows - 1`.
+
+fn row_of(v: f64, (lo, hi): (f64, f64), rows: usize) -> usize {
+    (((v - lo) / (hi - lo)) * (rows - 1) as f64).round().clamp(0.0, (rows - 1) as f64) as usize
+}
+
+fn sparkline(cols: &[Option<Point>], range: (f64, f64)) -> String {
+    cols.iter()
+        .map(|c| match c {
+            Some(p) => SPARKS[row_of(p.mean, range, SPARKS.len())],
+            None => ' ',
+        })
+        .collect()
+}
+
+// Returns the lines of a braille chart `height` characters high, of
+// dot columns drawn from each column's minimum to its maximum.
+
+fn braille(cols: &[Option<Point>], range: (f64, f64), height: usize) -> Vec<String> {
+    let rows = height * 4;
+    let mut cells = vec![vec![0u32; cols.len().div_ceil(2)]; height];
+
+    for (x, p) in cols.iter().enumerate() {
+        if let Some(p) = p {
+            let (bottom, top) = (row_of(p.min, range, rows), row_of(p.max, range, rows));
+
+            for y in bottom..=top {
+                let y = rows - 1 - y;
+
+                cells[y / 4][x / 2] |= DOTS[y % 4][x % 2]
+            }
+        }
+    }
+    cells
+        .into_iter()
+        .map(|line| line.into_iter().map(|bits| char::from_u32(0x2800 + bits).unwrap_or(' ')).collect())
+        .collect()
+}
+
+impl Plot {
+    pub fn new(height: usize, live: bool, tf: Formatter) -> Plot {
+        Plot { height, live, tf, channels: vec![], drawn: 0, shown: None }
+    }
+
+    // Adds a reply to its channel's chart. Live charts keep only as
+    // many points as could be shown.
+
+    fn add(&mut self, key: &str, name: &str, units: &str, timestamp: u64, values: &[f64]) {
+        let values = values.iter().copied().filter(|v| !v.is_nan());
+        let (n, min, max, sum) =
+            values.fold((0, f64::INFINITY, f64::NEG_INFINITY, 0.0), |(n, lo, hi, sum), v| (n + 1, lo.min(v), hi.max(v), sum + v));
+
+        if n == 0 {
+            return;
+        }
+
+        let idx = match self.channels.iter().position(|c| c.key == key) {
+            Some(idx) => idx,
+            None => {
+                self.channels.push(Channel {
+                    key: String::from(key),
+                    name: String::from(name),
+                    units: String::new(),
+                    points: VecDeque::new(),
+                });
+                self.channels.len() - 1
+            }
+        };
+        let c = &mut self.channels[idx];
+
+        if !units.is_empty() {
+            c.units = String::from(units)
+        }
+        c.points.push_back(Point { timestamp, min, max, mean: sum / f64::from(n) });
+        if self.live && c.points.len() > 1024 {
+            c.points.pop_front();
+        }
+    }
+
+    // Returns the charts, `width` characters wide.
+
+    fn render(&self, width: usize) -> Vec<String> {
+        let names: Vec<&str> = self
+            .channels
+            .iter()
+            .map(|c| match self.channels.iter().filter(|o| o.name == c.name).count() {
+                1 => c.name.as_str(),
+                _ => c.key.as_str(),
+            })
+            .collect();
+        let name_width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+        let mut lines = vec![];
+
+        for (c, name) in self.channels.iter().zip(names) {
+            let points: Vec<Point> = c.points.iter().copied().collect();
+            let last = points[points.len() - 1];
+
+            if self.height == 1 {
+                let n = width.saturating_sub(name_width + 40).max(10);
+                let cols = columns(&points[points.len().saturating_sub(n)..], n);
+                let (lo, hi) = scale(&cols);
+                let p = precision(lo, hi);
+                let value = format!("{:.p$} {}", last.mean, c.units, p = p);
+
+                lines.push(format!(
+                    "{:<nw$} {} {} ({:.p$} to {:.p$})",
+                    name,
+                    sparkline(&cols, (lo, hi)),
+                    value.trim_end(),
+                    lo,
+                    hi,
+                    nw = name_width,
+                    p = p
+                ))
+            } else {
+                let n = width.saturating_sub(LABEL + 2).max(10);
+                let cols = columns(&points[points.len().saturating_sub(n * 2)..], n * 2);
+                let (lo, hi) = scale(&cols);
+                let p = precision(lo, hi);
+                let first = cols.iter().flatten().next().map_or(last.timestamp, |p| p.timestamp);
+
+                lines.push(format!("{} {:.p$} {}", name, last.mean, c.units, p = p).trim_end().to_string());
+                for (i, row) in braille(&cols, (lo, hi), self.height).into_iter().enumerate() {
+                    let label = match i {
+                        0 => format!("{:.p$}", hi, p = p),
+                        _ if i == self.height - 1 => format!("{:.p$}", lo, p = p),
+                        _ => String::new(),
+                    };
+
+                    lines.push(format!("{:>w$} {}{}", label, if label.is_empty() { '│' } else { '┤' }, row, w = LABEL))
+                }
+
+                let (from, to) = (self.tf.format(first), self.tf.format(last.timestamp));
+
+                lines.push(format!("{:>w$} └{}{:>pad$}", "", from, to, w = LABEL, pad = n.saturating_sub(from.len()).max(to.len() + 1)))
+            }
+        }
+        lines
+    }
+
+    // Draws the charts over the ones drawn last.
+
+    fn draw(&mut self) -> Result<(), Error> {
+        let width = ratatui::crossterm::terminal::size().map_or(80, |(w, _)| usize::from(w));
+        let lines = self.render(width);
+        let mut out = io::stdout().lock();
+
+        if self.drawn > 0 {
+            write!(out, "\x1b[{}A\x1b[J", self.drawn)?
+        }
+        for line in &lines {
+            writeln!(out, "{}", line)?
+        }
+        out.flush()?;
+        self.drawn = lines.len();
+        self.shown = Some(Instant::now());
+        Ok(())
+    }
+
+    fn update(&mut self) -> Result<(), Error> {
+        if self.live && io::stdout().is_terminal() && self.shown.is_none_or(|t| t.elapsed() >= REDRAW) {
+            self.draw()?
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Plot {
+    fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, _: Option<&StateTag>) -> Result<(), Error> {
+        let key = label.map_or_else(|| req.canonical(), String::from);
+        let name = label.map_or_else(|| req.device.name().into_owned(), String::from);
+
+        self.add(&key, &name, reading.meta.units.as_deref().unwrap_or(""), reading.timestamp, reading.value.as_slice());
+        self.update()
+    }
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, units: &str, _: Option<&StateTag>) -> Result<(), Error> {
+        self.add(name, name, units, timestamp, &[v]);
+        self.update()
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        if !self.channels.is_empty() {
+            self.draw()?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+    use drf::parse_drf;
+
+    #[test]
+    fn test_plot() {
+        let req = parse_drf("M:OUTTMP@p,1000").unwrap();
+        let mut plot = Plot::new(1, false, Formatter::default());
+
+        for (i, v) in [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0].iter().enumerate() {
+            plot.reading(&req, None, &Reading::new(0, i as u64 * 1_000_000, Value::Scalar(*v)), None).unwrap()
+        }
+        plot.reading(&req, None, &Reading::new(0, 9_000_000, Value::Status(-6)), None).unwrap();
+        plot.derived("T", 0, 2.0, "K", None).unwrap();
+
+        assert_eq!(
+            plot.render(50),
+            vec!["M:OUTTMP ▁▂▃▄▅▆▇█   8.00 (1.00 to 8.00)", "T        ▅          2.00 K (1.50 to 2.50)"]
+        );
+
+        // Arrays are drawn as the range of their elements; each dot
+        // column spans a column's minimum to maximum.
+
+        let mut chart = Plot::new(2, false, Formatter::default());
+
+        chart.reading(&req, Some("temp"), &Reading::new(0, 0, Value::Array(vec![0.0, 7.0])), None).unwrap();
+        chart.reading(&req, Some("temp"), &Reading::new(0, 1_000_000, 0.0.into()), None).unwrap();
+        chart.reading(&req, Some("temp"), &Reading::new(0, 2_000_000, 7.0.into()), None).unwrap();
+
+        assert_eq!(
+            chart.render(22),
+            vec!["temp 7.00", "      7.00 ┤⡇⠁⠀⠀⠀⠀⠀⠀⠀⠀", "      0.00 ┤⣇⠀⠀⠀⠀⠀⠀⠀⠀⠀", "           └0.000000 2.000000"]
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        let points: Vec<Point> =
+            (0..10).map(|i| Point { timestamp: i, min: i as f64, max: i as f64, mean: i as f64 }).collect();
+        let cols = columns(&points, 4);
+
+        assert_eq!(cols.len(), 4);
+        assert_eq!(cols[0].map(|p| (p.min, p.max, p.mean)), Some((0.0, 2.0, 1.0)));
+        assert_eq!(cols[3].map(|p| (p.min, p.max)), Some((8.0, 9.0)));
+        assert_eq!(columns(&points[..2], 4)[2], None);
+        assert_eq!(precision(71.9, 73.2), 2);
+        assert_eq!(precision(0.0, 5000.0), 0);
+    }
+}
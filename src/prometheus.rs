@@ -0,0 +1,231 @@
+use crate::data::{Reading, Value};
+use crate::{backend::Client, error::Error, output};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+// Serves the latest data of some devices as Prometheus metrics, so
+// they can be scraped, graphed and alerted on. Each request is a set
+// of series labeled with its DRF and device,
+//
+//    acsys_value{drf="M:OUTTMP.READING.SCALED@P,1S,TRUE",device="M:OUTTMP",units="degF"} 72.5
+//    acsys_status{drf="M:OUTTMP.READING.SCALED@P,1S,TRUE",device="M:OUTTMP"} 0
+//    acsys_timestamp_seconds{drf="M:OUTTMP.READING.SCALED@P,1S,TRUE",device="M:OUTTMP"} 1700000000.5
+//    acsys_replies_total{drf="M:OUTTMP.READING.SCALED@P,1S,TRUE",device="M:OUTTMP"} 12
+//
+// where array elements are labeled with their "index" and only
+// numbers have an `acsys_value`. A request is missing from the
+// first three until its first reply.
+
+struct Entry {
+    request: drf::Request,
+    latest: Option<Reading>,
+    replies: u64,
+}
+
+pub struct Metrics {
+    entries: Vec<Entry>,
+}
+
+// Escapes a label's value.
+
+fn label(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Writes a sample's value as Prometheus does, which includes NaN and
+// the infinities.
+
+fn number(v: f64) -> String {
+    match v {
+        v if v.is_nan() => String::from("NaN"),
+        v if v == f64::INFINITY => String::from("+Inf"),
+        v if v == f64::NEG_INFINITY => String::from("-Inf"),
+        v => v.to_string(),
+    }
+}
+
+const METRICS: [(&str, &str, &str); 4] = [
+    ("acsys_value", "gauge", "The latest value of the device."),
+    ("acsys_status", "gauge", "The ACNET status of the latest reply (0 if OK)."),
+    ("acsys_timestamp_seconds", "gauge", "When the latest reply was taken, in seconds since the epoch."),
+    ("acsys_replies_total", "counter", "The number of replies received."),
+];
+
+impl Metrics {
+    pub fn new(requests: Vec<drf::Request>) -> Metrics {
+        Metrics { entries: requests.into_iter().map(|request| Entry { request, latest: None, replies: 0 }).collect() }
+    }
+
+    pub fn update(&mut self, reading: Reading) {
+        if let Some(e) = self.entries.get_mut(reading.index) {
+            e.latest = Some(reading);
+            e.replies += 1
+        }
+    }
+
+    // Returns the samples of one metric (by its position in
+    // `METRICS`) for an entry.
+
+    fn samples(e: &Entry, metric: usize) -> Vec<String> {
+        let labels = |extra: &[(&str, String)]| {
+            let device = e.latest.as_ref().and_then(|r| r.meta.device.clone());
+            let device = device.unwrap_or_else(|| e.request.device.name().into_owned());
+            let all: Vec<String> = [("drf", e.request.canonical()), ("device", device)]
+                .iter()
+                .chain(extra)
+                .map(|(k, v)| format!("{}=\"{}\"", k, label(v)))
+                .collect();
+
+            format!("{{{}}}", all.join(","))
+        };
+        let name = METRICS[metric].0;
+
+        if metric == 3 {
+            return vec![format!("{}{} {}", name, labels(&[]), e.replies)];
+        }
+
+        let r = match &e.latest {
+            Some(r) => r,
+            None => return vec![],
+        };
+        let status = match r.value {
+            Value::Status(s) => s,
+            _ => r.meta.status,
+        };
+
+        match metric {
+            0 => {
+                let units: Vec<(&str, String)> = r.meta.units.iter().map(|u| ("units", u.clone())).collect();
+
+                match &r.value {
+                    Value::Scalar(v) => vec![format!("{}{} {}", name, labels(&units), number(*v))],
+                    Value::Array(v) => {
+                        let (start, data) = output::elements(&e.request.range, v);
+
+                        data.iter()
+                            .enumerate()
+                            .map(|(i, v)| {
+                                let extra: Vec<(&str, String)> =
+                                    units.iter().cloned().chain(Some(("index", (start + i).to_string()))).collect();
+
+                                format!("{}{} {}", name, labels(&extra), number(*v))
+                            })
+                            .collect()
+                    }
+                    _ => vec![],
+                }
+            }
+            1 => vec![format!("{}{} {}", name, labels(&[]), status)],
+            _ => vec![format!("{}{} {}", name, labels(&[]), r.timestamp as f64 / 1e6)],
+        }
+    }
+
+    // Returns the metrics in Prometheus' text exposition format.
+
+    pub fn format(&self) -> String {
+        let mut text = String::new();
+
+        for (metric, (name, kind, help)) in METRICS.iter().enumerate() {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+            for e in &self.entries {
+                for line in Metrics::samples(e, metric) {
+                    text.push_str(&line);
+                    text.push('\n')
+                }
+            }
+        }
+        text
+    }
+}
+
+// Answers one scrape. `/metrics` is the only resource.
+
+fn serve(stream: TcpStream, metrics: &Mutex<Metrics>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+
+    reader.read_line(&mut request)?;
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.lock().unwrap().format()),
+        (Some("GET"), Some(_)) => ("404 Not Found", String::from("metrics are served at /metrics\n")),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    debug!(request = request.trim(), status, "answered scrape");
+    write!(
+        reader.get_mut(),
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Subscribes to the requests and serves their metrics at `listen`
+// (an address and port) until the subscription ends.
+
+pub fn run(client: &Client, listen: &str, requests: Vec<drf::Request>) -> Result<(), Error> {
+    let listener =
+        TcpListener::bind(listen).map_err(|e| Error::Connection(format!("can't listen on {}: {}", listen, e)))?;
+    let metrics = Arc::new(Mutex::new(Metrics::new(requests.clone())));
+    let served = metrics.clone();
+
+    info!(listen, requests = requests.len(), "serving metrics at /metrics");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|s| serve(s, &served)) {
+                warn!("can't answer a scrape: {}", e)
+            }
+        }
+    });
+
+    for reading in client.subscribe(requests) {
+        metrics.lock().unwrap().update(reading?)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let requests = vec![drf::parse_drf("M:OUTTMP@p,1000").unwrap(), drf::parse_drf("B:BPM[2:3]@p,1000").unwrap()];
+        let mut metrics = Metrics::new(requests);
+        let mut reading = Reading::new(0, 1_500_000, Value::Scalar(72.5));
+
+        reading.meta.units = Some(String::from("deg\"F"));
+        metrics.update(reading);
+        metrics.update(Reading::new(1, 2_000_000, Value::Array(vec![1.0, f64::NAN])));
+        metrics.update(Reading::new(1, 3_000_000, Value::Status(-6)));
+
+        let text = metrics.format();
+        let m = "drf=\"M:OUTTMP.READING.SCALED@P,1S,TRUE\",device=\"M:OUTTMP\"";
+        let b = "drf=\"B:BPM.READING[2:3].SCALED@P,1S,TRUE\",device=\"B:BPM\"";
+
+        assert!(text.starts_with("# HELP acsys_value The latest value of the device.\n# TYPE acsys_value gauge\n"));
+        assert!(text.contains(&format!("\nacsys_value{{{},units=\"deg\\\"F\"}} 72.5\n", m)));
+        assert!(!text.contains(&format!("acsys_value{{{}", b)));
+        assert!(text.contains(&format!("\nacsys_status{{{}}} -6\n", b)));
+        assert!(text.contains(&format!("\nacsys_timestamp_seconds{{{}}} 1.5\n", m)));
+        assert!(text.contains(&format!("\nacsys_replies_total{{{}}} 2\n", b)));
+        assert_eq!(number(f64::NAN), "NaN");
+        assert_eq!(number(f64::NEG_INFINITY), "-Inf");
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{data::Value, monitor};
+use drf::{Property, Range, ReadingField, Request, SettingField, StatusField};
+
+// The commands accepted by the CONTROL property.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    On,
+    Off,
+    Reset,
+    Positive,
+    Negative,
+    Ramp,
+    Dc,
+    Trip,
+}
+
+pub const COMMANDS: [(&str, Command); 8] = [
+    ("ON", Command::On),
+    ("OFF", Command::Off),
+    ("RESET", Command::Reset),
+    ("POSITIVE", Command::Positive),
+    ("NEGATIVE", Command::Negative),
+    ("RAMP", Command::Ramp),
+    ("DC", Command::Dc),
+    ("TRIP", Command::Trip),
+];
+
+impl Command {
+    pub fn parse(text: &str) -> Result<Command, String> {
+        COMMANDS.iter().find(|(n, _)| n.eq_ignore_ascii_case(text.trim())).map(|(_, c)| *c).ok_or_else(|| {
+            let names: Vec<&str> = COMMANDS.iter().map(|(n, _)| *n).collect();
+
+            format!("unknown control command '{}' (expected one of {})", text, names.join(", "))
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        COMMANDS.iter().find(|(_, c)| *c == self).map(|(n, _)| *n).unwrap()
+    }
+}
+
+// A value to be sent to a device. Which variant is used depends on
+// the property being set.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SetValue {
+    Data(Value),
+    Bool(bool),
+    Command(Command),
+}
+
+impl SetValue {
+    // Returns the value as it's sent to a data source: booleans (for
+    // status bits) as 1 or 0 and commands by name.
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            SetValue::Data(v) => v.clone(),
+            SetValue::Bool(b) => Value::Scalar(if *b { 1.0 } else { 0.0 }),
+            SetValue::Command(c) => Value::Text(c.name().to_string()),
+        }
+    }
+}
+
+// Describes a setting about to be made, for confirmation: the
+// request, the value it has now (if it could be read) and the value
+// to be sent, e.g. "Z:DEV.SETTING.SCALED: 1.5 -> 2".
+
+pub fn describe(req: &Request, current: Option<&Value>, value: &SetValue) -> String {
+    format!(
+        "{}: {} -> {}",
+        req.canonical(),
+        current.map(monitor::show).unwrap_or_else(|| String::from("(unknown)")),
+        monitor::show(&value.to_value())
+    )
+}
+
+// Writes a value the way `parse_value()` reads it back, so it can be
+// recorded and sent again: numbers as they are, arrays as lists and
+// raw data as hex, most significant byte first. Unlike
+// `monitor::show()`, nothing is abbreviated.
+
+pub fn value_text(v: &Value) -> String {
+    let hex = |b: &[u8]| format!("0x{}", b.iter().rev().map(|b| format!("{:02X}", b)).collect::<String>());
+
+    match v {
+        Value::Scalar(v) => v.to_string(),
+        Value::Array(v) => v.iter().map(f64::to_string).collect::<Vec<_>>().join(","),
+        Value::Raw(b) => hex(b),
+        Value::Text(t) => t.clone(),
+        Value::Status(s) => format!("status {}", s),
+        Value::Alarm(a) => hex(a.raw()),
+    }
+}
+
+// Returns whether a value read back from a device is the value that
+// was set. Numbers may differ by up to `tolerance`; a single element
+// may come back as a scalar or a one-element array.
+
+pub fn same_value(sent: &Value, read: &Value, tolerance: f64) -> bool {
+    let numbers = |v: &Value| match v {
+        Value::Scalar(v) => Some(vec![*v]),
+        Value::Array(v) => Some(v.clone()),
+        _ => None,
+    };
+
+    match (sent, read) {
+        (Value::Raw(a), Value::Raw(b)) => a == b,
+        (Value::Text(a), Value::Text(b)) => a.eq_ignore_ascii_case(b),
+        _ => match (numbers(sent), numbers(read)) {
+            (Some(a), Some(b)) => a.len() == b.len() && a.iter().zip(&b).all(|(a, b)| (a - b).abs() <= tolerance),
+            _ => false,
+        },
+    }
+}
+
+// Returns the request for the reading which follows a setting, e.g.
+// `G:DEV.READING[2:5].SCALED` for `G:DEV.SETTING[2:5].SCALED`. Only
+// the setting property has one.
+
+pub fn readback_request(req: &Request) -> Option<Request> {
+    let field = match req.property {
+        Property::Setting(SettingField::Raw) => ReadingField::Raw,
+        Property::Setting(SettingField::Primary) => ReadingField::Primary,
+        Property::Setting(SettingField::Scaled) => ReadingField::Scaled,
+        _ => return None,
+    };
+
+    Some(Request { property: Property::Reading(field), ..req.clone() })
+}
+
+// Returns the request used to send a setting to the device. A request
+// for the reading property (which is the default) is redirected to
+// the corresponding setting field. Besides settings, the control
+// property and the individual status bits may be set.
+
+pub fn setting_request(req: &Request) -> Result<Request, String> {
+    let property = match req.property {
+        Property::Reading(ReadingField::Raw) => Property::Setting(SettingField::Raw),
+        Property::Reading(ReadingField::Primary) => Property::Setting(SettingField::Primary),
+        Property::Reading(ReadingField::Scaled) => Property::Setting(SettingField::Scaled),
+        p @ Property::Setting(_) => p,
+        p @ Property::Control => p,
+        p @ Property::Status(
+            StatusField::On
+            | StatusField::Ready
+            | StatusField::Remote
+            | StatusField::Positive
+            | StatusField::Ramp,
+        ) => p,
+        _ => return Err(format!("can't set the {} property", req.property.canonical().0)),
+    };
+
+    Ok(Request {
+        property,
+        ..req.clone()
+    })
+}
+
+// Parses a list of numbers, either comma-separated ("1.0, 2.0") or as
+// a JSON array ("[1.0, 2.0]").
+
+pub fn parse_numbers(text: &str) -> Result<Vec<f64>, String> {
+    let text = text.trim();
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .unwrap_or(text);
+
+    inner
+        .split(',')
+        .map(|v| {
+            let v = v.trim();
+
+            v.parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| format!("bad value '{}'", v))
+        })
+        .collect()
+}
+
+// Builds the value to send for the request. The number of values must
+// match the request's range. A single value for the first element
+// (which is what a DRF without a range selects) is sent as a scalar;
+// anything else is sent as an array starting at the range's index.
+
+pub fn array_value(req: &Request, text: &str) -> Result<Value, String> {
+    let values = parse_numbers(text)?;
+
+    // Where a relative range starts depends on the array's length,
+    // which isn't known here.
+
+    if let Range::Relative { .. } = req.range {
+        return Err(format!("can't set {} with a relative range; give the indices", req.device.name()));
+    }
+
+    match req.range.count() {
+        Some(n) if n != values.len() => Err(format!(
+            "the range of {} covers {} element(s) but {} value(s) were given",
+            req.device.name(),
+            n,
+            values.len()
+        )),
+        _ if values.len() == 1 && req.range.start() == 0 => Ok(Value::Scalar(values[0])),
+        _ => Ok(Value::Array(values)),
+    }
+}
+
+// Parses a boolean, as used for status bits.
+
+pub fn parse_bool(text: &str) -> Result<bool, String> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err(format!("bad boolean '{}'", text)),
+    }
+}
+
+// Parses a raw value written in hex (e.g. "0x1F"). The bytes are
+// returned in little-endian order, which is how devices store raw
+// data. If the request has a raw range, the value must fill it.
+
+pub fn parse_raw(req: &Request, text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .filter(|d| !d.is_empty() && d.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("raw values must be written in hex (e.g. 0x1F), not '{}'", text))?;
+    let digits = if digits.len().is_multiple_of(2) {
+        digits.to_string()
+    } else {
+        format!("0{}", digits)
+    };
+    let mut bytes: Vec<u8> = (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .rev()
+        .collect();
+
+    if let Range::Raw {
+        length: Some(n), ..
+    } = req.range
+    {
+        let n = n as usize;
+
+        while bytes.len() > n && bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        if bytes.len() > n {
+            return Err(format!("{} doesn't fit in {} byte(s)", text, n));
+        }
+        bytes.resize(n, 0)
+    }
+    Ok(bytes)
+}
+
+// Interprets the text given on the command line according to the
+// property being set: hex for raw settings, numbers (or lists of
+// them) for other settings, booleans for status bits and commands for
+// the control property.
+
+pub fn parse_value(req: &Request, text: &str) -> Result<SetValue, String> {
+    match req.property {
+        Property::Setting(SettingField::Raw) => parse_raw(req, text).map(|b| SetValue::Data(Value::Raw(b))),
+        Property::Setting(_) => array_value(req, text).map(SetValue::Data),
+        Property::Status(_) => parse_bool(text).map(SetValue::Bool),
+        Property::Control => Command::parse(text).map(SetValue::Command),
+        _ => Err(format!("can't set the {} property", req.property.canonical().0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drf::parse_drf;
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(parse_numbers("1.0,2, 3e1"), Ok(vec![1.0, 2.0, 30.0]));
+        assert_eq!(parse_numbers(" [1.5, -2] "), Ok(vec![1.5, -2.0]));
+        assert!(parse_numbers("1,,2").is_err());
+        assert!(parse_numbers("nan").is_err());
+        assert!(parse_numbers("[1,2").is_err());
+    }
+
+    #[test]
+    fn test_array_values() {
+        let req = parse_drf("G:DEV[2:5]").unwrap();
+
+        assert_eq!(array_value(&req, "1.0,2.0,3.0,4.0"), Ok(Value::Array(vec![1.0, 2.0, 3.0, 4.0])));
+        assert!(array_value(&req, "1.0,2.0").is_err());
+        assert_eq!(array_value(&parse_drf("G:DEV").unwrap(), "7"), Ok(Value::Scalar(7.0)));
+        assert_eq!(array_value(&parse_drf("G:DEV[3]").unwrap(), "7"), Ok(Value::Array(vec![7.0])));
+        assert!(array_value(&parse_drf("G:DEV[-1]").unwrap(), "7").is_err());
+    }
+
+    #[test]
+    fn test_setting_requests() {
+        let req = setting_request(&parse_drf("G:DEV[2:5]").unwrap()).unwrap();
+
+        assert_eq!(req.canonical(), "G:DEV.SETTING[2:5].SCALED");
+        assert!(setting_request(&parse_drf("G:DEV.STATUS").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let value = |drf: &str, text: &str| parse_value(&setting_request(&parse_drf(drf).unwrap()).unwrap(), text);
+
+        assert_eq!(value("G:DEV", "1.5"), Ok(SetValue::Data(Value::Scalar(1.5))));
+        assert_eq!(value("G:DEV.RAW", "0x1F"), Ok(SetValue::Data(Value::Raw(vec![0x1f]))));
+        assert_eq!(value("G:DEV.RAW", "0x123"), Ok(SetValue::Data(Value::Raw(vec![0x23, 0x01]))));
+        assert_eq!(value("G:DEV.SETTING{0:4}.RAW", "0x1F"), Ok(SetValue::Data(Value::Raw(vec![0x1f, 0, 0, 0]))));
+        assert!(value("G:DEV.SETTING{0:1}.RAW", "0x1234").is_err());
+        assert!(value("G:DEV.RAW", "31").is_err());
+        assert_eq!(value("G:DEV.STATUS.ON", "true"), Ok(SetValue::Bool(true)));
+        assert!(value("G:DEV.STATUS.ON", "maybe").is_err());
+        assert_eq!(value("G:DEV.CONTROL", "reset"), Ok(SetValue::Command(Command::Reset)));
+        assert!(value("G:DEV.CONTROL", "explode").is_err());
+
+        assert_eq!(SetValue::Bool(true).to_value(), Value::Scalar(1.0));
+        assert_eq!(SetValue::Command(Command::Reset).to_value(), Value::Text(String::from("RESET")));
+        assert_eq!(SetValue::Command(Command::Dc).to_value(), Value::Text(String::from("DC")));
+    }
+
+    #[test]
+    fn test_verification() {
+        assert!(same_value(&Value::Scalar(1.5), &Value::Scalar(1.52), 0.05));
+        assert!(!same_value(&Value::Scalar(1.5), &Value::Scalar(1.52), 0.0));
+        assert!(same_value(&Value::Scalar(7.0), &Value::Array(vec![7.0]), 0.0));
+        assert!(!same_value(&Value::Array(vec![1.0, 2.0]), &Value::Array(vec![1.0]), 1.0));
+        assert!(same_value(&Value::Raw(vec![0x1f]), &Value::Raw(vec![0x1f]), 0.0));
+        assert!(!same_value(&Value::Raw(vec![0x1f]), &Value::Scalar(31.0), 0.0));
+        assert!(!same_value(&Value::Scalar(1.0), &Value::Status(-6), 0.0));
+
+        let req = setting_request(&parse_drf("G:DEV[2:5]").unwrap()).unwrap();
+
+        assert_eq!(readback_request(&req).unwrap().canonical(), "G:DEV.READING[2:5].SCALED");
+        assert_eq!(readback_request(&parse_drf("G:DEV.SETTING.RAW").unwrap()).unwrap().canonical(), "G:DEV.READING.RAW");
+        assert_eq!(readback_request(&parse_drf("G:DEV.STATUS.ON").unwrap()), None);
+    }
+
+    #[test]
+    fn test_value_text() {
+        let req = setting_request(&parse_drf("G:DEV.RAW").unwrap()).unwrap();
+
+        assert_eq!(value_text(&Value::Array(vec![1.5, -2.0, 3.0])), "1.5,-2,3");
+        assert_eq!(value_text(&Value::Raw(vec![0x23, 0x01])), "0x0123");
+        assert_eq!(parse_value(&req, &value_text(&Value::Raw(vec![0x23, 0x01]))), Ok(SetValue::Data(Value::Raw(vec![0x23, 0x01]))));
+        assert_eq!(parse_numbers(&value_text(&Value::Array(vec![1.5, -2.0]))), Ok(vec![1.5, -2.0]));
+    }
+
+    #[test]
+    fn test_describe() {
+        let req = setting_request(&parse_drf("Z:DEV").unwrap()).unwrap();
+        let value = SetValue::Data(Value::Scalar(2.0));
+
+        assert_eq!(describe(&req, Some(&Value::Scalar(1.5)), &value), "Z:DEV.SETTING.SCALED: 1.5 -> 2");
+        assert_eq!(describe(&req, None, &SetValue::Command(Command::On)), "Z:DEV.SETTING.SCALED: (unknown) -> ON");
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(Command::parse(" trip"), Ok(Command::Trip));
+        assert!(Command::parse("explode").unwrap_err().ends_with("(expected one of ON, OFF, RESET, POSITIVE, NEGATIVE, RAMP, DC, TRIP)"));
+        assert_eq!(Command::Ramp.name(), "RAMP");
+    }
+}
@@ -0,0 +1,158 @@
+use crate::data::{Reading, Value};
+use crate::sink::Sink;
+use crate::{acs, capture, error::Error};
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+// How fast a capture is replayed: as fast as possible, or with the
+// original spacing of the records divided by a factor ("realtime" is
+// a factor of 1.)
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Max,
+    Factor(f64),
+}
+
+impl Speed {
+    pub fn parse(text: &str) -> Result<Speed, String> {
+        match text.to_lowercase().as_str() {
+            "max" => Ok(Speed::Max),
+            "realtime" => Ok(Speed::Factor(1.0)),
+            t => t
+                .strip_suffix('x')
+                .unwrap_or(t)
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite() && *f > 0.0)
+                .map(Speed::Factor)
+                .ok_or_else(|| format!("bad replay speed '{}'", text)),
+        }
+    }
+
+    // Returns how long to wait between two records, given their
+    // timestamps.
+
+    pub fn delay(&self, prev: u64, next: u64) -> Duration {
+        match self {
+            Speed::Max => Duration::ZERO,
+            Speed::Factor(f) => Duration::from_micros(next.saturating_sub(prev)).div_f64(*f),
+        }
+    }
+}
+
+// Loads a capture of either format. The records of a JSON capture
+// become readings without metadata, numbered by their key.
+
+pub fn load(data: &[u8]) -> Result<Vec<acs::Entry>, String> {
+    if data.starts_with(acs::MAGIC) {
+        return acs::read(data);
+    }
+
+    let mut channels: HashMap<String, usize> = HashMap::new();
+
+    Ok(capture::parse_bytes(data)?
+        .into_iter()
+        .map(|r| {
+            let n = channels.len();
+            let index = *channels.entry(r.key.clone()).or_insert(n);
+
+            acs::Entry { reading: Reading::new(index, r.timestamp, r.value), key: r.key }
+        })
+        .collect())
+}
+
+// Hands the entries to the sinks, spaced out in time as requested,
+// as `get live` hands over its replies. Keys which aren't DRF, the
+// names of derived channels, are handed over as derived values, which
+// must be numbers.
+
+pub fn replay(entries: &[acs::Entry], speed: Speed, sinks: &mut [Box<dyn Sink>]) -> Result<(), Error> {
+    let mut requests: HashMap<usize, Option<drf::Request>> = HashMap::new();
+    let mut prev = None;
+
+    for e in entries {
+        if let Some(prev) = prev {
+            thread::sleep(speed.delay(prev, e.reading.timestamp))
+        }
+        prev = Some(e.reading.timestamp);
+
+        let r = &e.reading;
+        let units = r.meta.units.as_deref().unwrap_or("");
+
+        match (requests.entry(r.index).or_insert_with(|| drf::parse_drf(&e.key).ok()), &r.value) {
+            (Some(req), _) => sinks.iter_mut().try_for_each(|s| s.reading(req, None, r, None))?,
+            (None, Value::Scalar(v)) => sinks.iter_mut().try_for_each(|s| s.derived(&e.key, r.timestamp, *v, units, None))?,
+            (None, _) => warn!(name = %e.key, "skipped a derived value which isn't a number"),
+        }
+    }
+    sinks.iter_mut().try_for_each(|s| s.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::states::StateTag;
+    use crate::{output, timefmt::Formatter};
+
+    #[test]
+    fn test_speed() {
+        assert_eq!(Speed::parse("realtime"), Ok(Speed::Factor(1.0)));
+        assert_eq!(Speed::parse("10x"), Ok(Speed::Factor(10.0)));
+        assert_eq!(Speed::parse("MAX"), Ok(Speed::Max));
+        assert!(Speed::parse("0").is_err());
+        assert!(Speed::parse("fast").is_err());
+
+        assert_eq!(Speed::Factor(2.0).delay(1_000_000, 2_000_000), Duration::from_millis(500));
+        assert_eq!(Speed::Factor(1.0).delay(2_000_000, 1_000_000), Duration::ZERO);
+        assert_eq!(Speed::Max.delay(0, 1_000_000), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_replay() {
+        // A sink keeping the JSON records of what it's handed.
+
+        struct Records(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+        impl Sink for Records {
+            fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, _: Option<&StateTag>)
+                -> Result<(), Error> {
+                let tf = Formatter::default();
+
+                self.0.borrow_mut().extend(output::format_reading(output::Format::Json, &tf, label, req, reading));
+                Ok(())
+            }
+
+            fn derived(&mut self, name: &str, timestamp: u64, v: f64, units: &str, _: Option<&StateTag>)
+                -> Result<(), Error> {
+                let tf = Formatter::default();
+
+                self.0.borrow_mut().push(output::format_derived(output::Format::Json, &tf, name, timestamp, v, units));
+                Ok(())
+            }
+        }
+
+        let entries = load(
+            b"{\"timestamp\":1.0,\"drf\":\"M:OUTTMP\",\"value\":1}\n\
+              {\"timestamp\":1.001,\"name\":\"X\",\"value\":2}\n\
+              {\"timestamp\":1.002,\"drf\":\"M:OUTTMP\",\"value\":3}\n\
+              {\"timestamp\":1.003,\"name\":\"X\",\"value\":\"on\"}\n",
+        )
+        .unwrap();
+        let records = std::rc::Rc::default();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(Records(std::rc::Rc::clone(&records)))];
+
+        assert_eq!(entries.iter().map(|e| e.reading.index).collect::<Vec<_>>(), [0, 1, 0, 1]);
+        replay(&entries, Speed::Factor(1.0), &mut sinks).unwrap();
+        assert_eq!(
+            *records.borrow(),
+            [
+                "{\"timestamp\":1.000000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":1}",
+                "{\"timestamp\":1.001000,\"name\":\"X\",\"value\":2,\"units\":\"\"}",
+                "{\"timestamp\":1.002000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":3}",
+            ]
+        );
+    }
+}
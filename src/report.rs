@@ -0,0 +1,403 @@
+use crate::history;
+use jiff::{tz::TimeZone, Timestamp, Zoned};
+use std::convert::TryFrom;
+
+// What a section of the report computes for each of its devices:
+// summary statistics, the extremes and when they occurred, or the
+// fraction of the time the device was "up", i.e. at or above a
+// threshold.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Stats,
+    MinMax,
+    Uptime { threshold: f64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: Kind,
+    pub devices: Vec<String>,
+}
+
+// A report specification. It's written in a small subset of TOML:
+// `key = value` lines, where a value is a string, a number or an
+// array of strings on one line, and a `[[section]]` header before
+// each section:
+//
+//     title = "Linac shift report"
+//
+//     # The kind is stats, minmax or uptime. Only uptime sections
+//     # have a threshold (default: 0.5).
+//
+//     [[section]]
+//     name = "RF"
+//     kind = "uptime"
+//     devices = ["L:RFON", "L:RF2ON"]
+//     threshold = 0.5
+//
+// Comments fill a whole line.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spec {
+    pub title: String,
+    pub sections: Vec<Section>,
+}
+
+impl Spec {
+    // Returns every device of the report, in section order. Their
+    // data is fetched in this order.
+
+    pub fn devices(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().flat_map(|s| s.devices.iter().map(String::as_str))
+    }
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    value.strip_prefix('"')?.strip_suffix('"').map(String::from)
+}
+
+fn parse_strings(value: &str) -> Option<Vec<String>> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?.trim();
+
+    if inner.is_empty() {
+        return Some(vec![]);
+    }
+    inner.trim_end_matches(',').split(',').map(|v| parse_string(v.trim())).collect()
+}
+
+// A section as it's read, before its required keys are checked.
+
+#[derive(Default)]
+struct Partial {
+    line: usize,
+    name: Option<String>,
+    kind: Option<String>,
+    devices: Option<Vec<String>>,
+    threshold: Option<f64>,
+}
+
+impl Partial {
+    fn finish(self) -> Result<Section, String> {
+        let line = self.line;
+        let missing = |key| format!("line {}: the section has no '{}'", line, key);
+        let kind = match self.kind.as_deref() {
+            Some("stats") => Kind::Stats,
+            Some("minmax") => Kind::MinMax,
+            Some("uptime") => Kind::Uptime { threshold: self.threshold.unwrap_or(0.5) },
+            Some(k) => return Err(format!("line {}: unknown kind '{}' (expected stats, minmax or uptime)", line, k)),
+            None => return Err(missing("kind")),
+        };
+
+        if self.threshold.is_some() && !matches!(kind, Kind::Uptime { .. }) {
+            return Err(format!("line {}: only uptime sections have a threshold", line));
+        }
+        Ok(Section {
+            name: self.name.ok_or_else(|| missing("name"))?,
+            kind,
+            devices: self.devices.ok_or_else(|| missing("devices"))?,
+        })
+    }
+}
+
+pub fn parse_spec(text: &str) -> Result<Spec, String> {
+    let mut title = None;
+    let mut sections = vec![];
+    let mut current: Option<Partial> = None;
+
+    for (n, line) in text.lines().enumerate() {
+        let (n, line) = (n + 1, line.trim());
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[[section]]" {
+            if let Some(s) = current.replace(Partial { line: n, ..Partial::default() }) {
+                sections.push(s.finish()?)
+            }
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected 'key = value'", n))?;
+        let (key, value) = (key.trim(), value.trim());
+        let bad = || format!("line {}: bad value for '{}'", n, key);
+
+        match (&mut current, key) {
+            (None, "title") => title = Some(parse_string(value).ok_or_else(bad)?),
+            (Some(s), "name") => s.name = Some(parse_string(value).ok_or_else(bad)?),
+            (Some(s), "kind") => s.kind = Some(parse_string(value).ok_or_else(bad)?),
+            (Some(s), "devices") => s.devices = Some(parse_strings(value).ok_or_else(bad)?),
+            (Some(s), "threshold") => s.threshold = Some(value.parse().map_err(|_| bad())?),
+            _ => return Err(format!("line {}: unknown key '{}'", n, key)),
+        }
+    }
+    if let Some(s) = current {
+        sections.push(s.finish()?)
+    }
+
+    Ok(Spec {
+        title: title.unwrap_or_else(|| String::from("Shift report")),
+        sections,
+    })
+}
+
+fn micros(t: &Zoned) -> Result<u64, String> {
+    u64::try_from(t.timestamp().as_microsecond()).map_err(|_| String::from("the period starts before the epoch"))
+}
+
+// Parses the period a report covers: "today" (so far), "yesterday",
+// the last span of time (e.g. "8h") or "START..END". Days are
+// counted in the time zone `tz`. Returns the range, in microseconds
+// since the epoch.
+
+pub fn parse_period(text: &str, now: Timestamp, tz: &TimeZone) -> Result<(u64, u64), String> {
+    let text = text.trim();
+    let bad = |_| format!("bad report period '{}'", text);
+    let now = now.to_zoned(tz.clone());
+    let today = now.start_of_day().map_err(bad)?;
+
+    let (start, end) = match text.to_lowercase().as_str() {
+        "today" => (micros(&today)?, micros(&now)?),
+        "yesterday" => (micros(&today.yesterday().map_err(bad)?)?, micros(&today)?),
+        _ => match text.split_once("..") {
+            Some((start, end)) => (history::parse_time(start)?, history::parse_time(end)?),
+            None => {
+                let end = micros(&now)?;
+
+                (end.saturating_sub(history::parse_span(text)?), end)
+            }
+        },
+    };
+
+    if end <= start {
+        return Err(format!("the report period '{}' is empty", text));
+    }
+    Ok((start, end))
+}
+
+// Formats a statistic for the report.
+
+fn num(v: f64) -> String {
+    format!("{:.3}", v)
+}
+
+// Returns the column headings of a section's table.
+
+pub fn header(kind: &Kind) -> Vec<&'static str> {
+    match kind {
+        Kind::Stats => vec!["DEVICE", "SAMPLES", "MEAN", "STD DEV", "MIN", "MAX"],
+        Kind::MinMax => vec!["DEVICE", "MIN", "AT", "MAX", "AT"],
+        Kind::Uptime { .. } => vec!["DEVICE", "UPTIME", "OUTAGES"],
+    }
+}
+
+// Computes a device's row of a section's table from its samples
+// (timestamp and value, in timestamp order), logged up to `end`.
+// `time` formats timestamps. A device without data has '-' in every
+// column.
+
+pub fn row(kind: &Kind, device: &str, samples: &[(u64, f64)], end: u64, time: impl Fn(u64) -> String) -> Vec<String> {
+    let mut row = vec![String::from(device)];
+
+    if samples.is_empty() {
+        row.extend((1..header(kind).len()).map(|_| String::from("-")));
+        return row;
+    }
+
+    let min = samples.iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+    let max = samples.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+
+    match kind {
+        Kind::Stats => {
+            let n = samples.len() as f64;
+            let mean = samples.iter().map(|s| s.1).sum::<f64>() / n;
+            let var = samples.iter().map(|s| (s.1 - mean).powi(2)).sum::<f64>() / n;
+
+            row.extend([samples.len().to_string(), num(mean), num(var.sqrt()), num(min.1), num(max.1)])
+        }
+        Kind::MinMax => row.extend([num(min.1), time(min.0), num(max.1), time(max.0)]),
+
+        // Each value holds until the next sample; the time before
+        // the first sample is unknown, so it isn't counted.
+        Kind::Uptime { threshold } => {
+            let up = |v: f64| v >= *threshold;
+            let ends = samples.iter().skip(1).map(|s| s.0).chain(std::iter::once(end));
+            let (mut up_time, mut outages) = (0, 0);
+
+            for (i, (&(ts, v), until)) in samples.iter().zip(ends).enumerate() {
+                if up(v) {
+                    up_time += until.saturating_sub(ts)
+                } else if i == 0 || up(samples[i - 1].1) {
+                    outages += 1
+                }
+            }
+
+            let total = end.saturating_sub(samples[0].0).max(1);
+
+            row.extend([format!("{:.1}%", up_time as f64 * 100.0 / total as f64), outages.to_string()])
+        }
+    }
+    row
+}
+
+// The formats in which a report is written.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+impl Format {
+    pub fn parse(text: &str) -> Result<Format, String> {
+        match text.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err(format!("unknown report format '{}'", text)),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// Markdown table cells can't contain '|'.
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+// Renders the report: its title, the period it covers and a table
+// for each section, whose rows were computed by `row()`.
+
+pub fn render(format: Format, spec: &Spec, period: &str, tables: &[Vec<Vec<String>>]) -> String {
+    let mut out = String::new();
+
+    match format {
+        Format::Markdown => {
+            out.push_str(&format!("# {}\n\n{}\n", spec.title, period));
+            for (section, rows) in spec.sections.iter().zip(tables) {
+                let head = header(&section.kind);
+
+                out.push_str(&format!("\n## {}\n\n| {} |\n", section.name, head.join(" | ")));
+                out.push_str(&format!("|{}\n", "---|".repeat(head.len())));
+                for r in rows {
+                    let cells: Vec<String> = r.iter().map(|c| escape_markdown(c)).collect();
+
+                    out.push_str(&format!("| {} |\n", cells.join(" | ")))
+                }
+            }
+        }
+        Format::Html => {
+            let title = escape_html(&spec.title);
+
+            out.push_str(&format!(
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n<h1>{}</h1>\n<p>{}</p>\n",
+                title,
+                title,
+                escape_html(period)
+            ));
+            for (section, rows) in spec.sections.iter().zip(tables) {
+                let cells = |tag: &str, r: &[String]| {
+                    r.iter().map(|c| format!("<{0}>{1}</{0}>", tag, escape_html(c))).collect::<String>()
+                };
+                let head: Vec<String> = header(&section.kind).into_iter().map(String::from).collect();
+
+                out.push_str(&format!("<h2>{}</h2>\n<table>\n<tr>{}</tr>\n", escape_html(&section.name), cells("th", &head)));
+                for r in rows {
+                    out.push_str(&format!("<tr>{}</tr>\n", cells("td", r)))
+                }
+                out.push_str("</table>\n")
+            }
+            out.push_str("</body>\n</html>\n")
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = "\
+# The morning report.
+title = \"Linac\"
+
+[[section]]
+name = \"Temperatures\"
+kind = \"stats\"
+devices = [\"M:OUTTMP\", \"G:AMANDA\"]
+
+[[section]]
+name = \"RF\"
+kind = \"uptime\"
+devices = [\"L:RFON\",]
+threshold = 1
+";
+
+    #[test]
+    fn test_spec() {
+        let spec = parse_spec(SPEC).unwrap();
+
+        assert_eq!(spec.title, "Linac");
+        assert_eq!(spec.sections[1], Section {
+            name: String::from("RF"),
+            kind: Kind::Uptime { threshold: 1.0 },
+            devices: vec![String::from("L:RFON")],
+        });
+        assert_eq!(spec.devices().collect::<Vec<_>>(), ["M:OUTTMP", "G:AMANDA", "L:RFON"]);
+        assert_eq!(parse_spec("[[section]]\nname = \"x\"\nkind = \"mean\"\ndevices = []"),
+                   Err(String::from("line 1: unknown kind 'mean' (expected stats, minmax or uptime)")));
+        assert_eq!(parse_spec("[[section]]\nkind = \"stats\""), Err(String::from("line 1: the section has no 'name'")));
+        assert_eq!(parse_spec("name = \"x\""), Err(String::from("line 1: unknown key 'name'")));
+        assert!(parse_spec("title = Linac").is_err());
+        assert!(parse_spec("[[section]]\nname = \"x\"\nkind = \"stats\"\ndevices = []\nthreshold = 2").is_err());
+    }
+
+    #[test]
+    fn test_period() {
+        let now: Timestamp = "2024-03-11T15:00:00Z".parse().unwrap();
+        let chicago = TimeZone::get("America/Chicago").unwrap();
+        let hour = 3_600_000_000;
+        let midnight = 1_710_133_200_000_000; // 2024-03-11T00:00-05:00
+
+        // The clocks changed the day before, so yesterday was only
+        // 23h long.
+
+        assert_eq!(parse_period("today", now, &chicago), Ok((midnight, midnight + 10 * hour)));
+        assert_eq!(parse_period("Yesterday", now, &chicago), Ok((midnight - 23 * hour, midnight)));
+        assert_eq!(parse_period("8h", now, &TimeZone::UTC), Ok((midnight + 2 * hour, midnight + 10 * hour)));
+        assert_eq!(parse_period("100..200", now, &TimeZone::UTC), Ok((100_000_000, 200_000_000)));
+        assert!(parse_period("200..100", now, &TimeZone::UTC).is_err());
+        assert!(parse_period("last week", now, &TimeZone::UTC).is_err());
+    }
+
+    #[test]
+    fn test_rows() {
+        let samples = [(0, 1.0), (10, 0.0), (20, 0.0), (30, 2.0), (40, 0.0)];
+        let time = |ts: u64| ts.to_string();
+
+        assert_eq!(row(&Kind::Stats, "X", &samples, 50, time), ["X", "5", "0.600", "0.800", "0.000", "2.000"]);
+        assert_eq!(row(&Kind::MinMax, "X", &samples, 50, time), ["X", "0.000", "10", "2.000", "30"]);
+        assert_eq!(row(&Kind::Uptime { threshold: 0.5 }, "X", &samples, 50, time), ["X", "40.0%", "2"]);
+        assert_eq!(row(&Kind::Uptime { threshold: 0.5 }, "X", &[], 50, time), ["X", "-", "-"]);
+    }
+
+    #[test]
+    fn test_render() {
+        let spec = Spec {
+            title: String::from("A <b> report"),
+            sections: vec![Section { name: String::from("RF"), kind: Kind::Uptime { threshold: 0.5 }, devices: vec![] }],
+        };
+        let tables = [vec![vec![String::from("L:RF|ON"), String::from("99.0%"), String::from("1")]]];
+
+        assert_eq!(
+            render(Format::Markdown, &spec, "From 1 to 2", &tables),
+            "# A <b> report\n\nFrom 1 to 2\n\n## RF\n\n| DEVICE | UPTIME | OUTAGES |\n|---|---|---|\n| L:RF\\|ON | 99.0% | 1 |\n"
+        );
+        assert!(render(Format::Html, &spec, "From 1 to 2", &tables)
+            .contains("<h1>A &lt;b&gt; report</h1>\n<p>From 1 to 2</p>\n<h2>RF</h2>\n<table>\n<tr><th>DEVICE</th>"));
+    }
+}
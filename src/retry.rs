@@ -0,0 +1,252 @@
+use crate::{data::Reading, error::Error};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+pub type Stream = Box<dyn Iterator<Item = Result<Reading, Error>>>;
+
+// Describes how failed connections are retried. The delay before
+// each retry doubles, starting at `base` and limited to `max`, and is
+// then scaled by a random factor between 0.5 and 1 so many clients
+// don't reconnect in lock-step after an outage.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub retries: u32,
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Policy {
+    pub fn new(retries: u32) -> Policy {
+        Policy {
+            retries,
+            base: Duration::from_millis(250),
+            max: Duration::from_secs(30),
+        }
+    }
+
+    // Returns the delay before retry number `attempt` (starting at
+    // 0). `jitter` is expected to be in the range [0, 1).
+
+    pub fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let delay = self.base.saturating_mul(1 << attempt.min(16)).min(self.max);
+
+        delay.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+// Returns a pseudo-random number in [0, 1). It only needs to differ
+// between processes, so the clock's sub-second part is good enough.
+
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+
+    (nanos.wrapping_mul(2_654_435_761) % 1_000_000) as f64 / 1_000_000.0
+}
+
+// Connection failures and timeouts may clear up on their own; other
+// errors won't.
+
+fn is_transient(e: &Error) -> bool {
+    matches!(e, Error::Connection(_) | Error::Timeout(_))
+}
+
+// Calls `f` until it succeeds, retrying transient failures as the
+// policy allows. The last failure is returned once the retries are
+// exhausted.
+
+pub fn call<T>(policy: &Policy, mut f: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+    let mut attempt = 0;
+
+    loop {
+        match f() {
+            Err(e) if is_transient(&e) && attempt < policy.retries => {
+                let delay = policy.delay(attempt, jitter());
+
+                attempt += 1;
+                warn!(attempt, retries = policy.retries, "{}; retrying in {:.1}s", e, delay.as_secs_f64());
+                thread::sleep(delay)
+            }
+            result => return result,
+        }
+    }
+}
+
+// Wraps a stream of readings so that a transient failure, either
+// while connecting or in the middle of the stream, reconnects using
+// `connect` and resumes the stream. The retry count is reset whenever
+// a reading arrives. Once the retries are exhausted, the error is
+// returned and the stream ends.
+
+pub struct Resume<F> {
+    connect: F,
+    policy: Policy,
+    stream: Option<Stream>,
+    attempt: u32,
+    done: bool,
+}
+
+pub fn resume<F>(policy: Policy, connect: F) -> Resume<F>
+where
+    F: FnMut() -> Result<Stream, Error>,
+{
+    Resume {
+        connect,
+        policy,
+        stream: None,
+        attempt: 0,
+        done: false,
+    }
+}
+
+impl<F> Resume<F>
+where
+    F: FnMut() -> Result<Stream, Error>,
+{
+    // Decides what to do with an error: either wait and let the
+    // caller retry (returning `None`) or give up and return it.
+
+    fn failed(&mut self, e: Error) -> Option<Error> {
+        self.stream = None;
+
+        if is_transient(&e) && self.attempt < self.policy.retries {
+            let delay = self.policy.delay(self.attempt, jitter());
+
+            self.attempt += 1;
+            warn!(
+                attempt = self.attempt,
+                retries = self.policy.retries,
+                "{}; retrying in {:.1}s",
+                e,
+                delay.as_secs_f64()
+            );
+            thread::sleep(delay);
+            None
+        } else {
+            self.done = true;
+            Some(e)
+        }
+    }
+}
+
+impl<F> Iterator for Resume<F>
+where
+    F: FnMut() -> Result<Stream, Error>,
+{
+    type Item = Result<Reading, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => match (self.connect)() {
+                    Ok(stream) => self.stream.insert(stream),
+                    Err(e) => match self.failed(e) {
+                        Some(e) => return Some(Err(e)),
+                        None => continue,
+                    },
+                },
+            };
+
+            match stream.next() {
+                Some(Ok(reading)) => {
+                    self.attempt = 0;
+                    return Some(Ok(reading));
+                }
+                Some(Err(e)) => {
+                    if let Some(e) = self.failed(e) {
+                        return Some(Err(e));
+                    }
+                }
+                None => {
+                    self.done = true;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quick(retries: u32) -> Policy {
+        Policy {
+            retries,
+            base: Duration::from_millis(1),
+            max: Duration::from_millis(4),
+        }
+    }
+
+    fn reading(index: usize) -> Result<Reading, Error> {
+        Ok(Reading::new(index, 0, 0.0.into()))
+    }
+
+    #[test]
+    fn test_delays() {
+        let p = Policy::new(5);
+
+        assert_eq!(p.delay(0, 0.0), Duration::from_millis(125));
+        assert_eq!(p.delay(2, 0.999_999).as_millis(), 999);
+        assert_eq!(p.delay(40, 0.0), Duration::from_secs(15));
+        assert!((0.0..1.0).contains(&jitter()));
+    }
+
+    #[test]
+    fn test_resume() {
+        // The first connection drops after one reading, the second
+        // attempt fails and the third completes the stream.
+
+        let mut calls = 0;
+        let stream: Vec<usize> = resume(quick(2), || {
+            calls += 1;
+            match calls {
+                1 => Ok(Box::new(vec![reading(1), Err(Error::Connection(String::new()))].into_iter()) as Stream),
+                2 => Err(Error::Timeout(String::new())),
+                _ => Ok(Box::new(vec![reading(2), reading(3)].into_iter()) as Stream),
+            }
+        })
+        .map(|r| r.unwrap().index)
+        .collect();
+
+        assert_eq!(stream, vec![1, 2, 3]);
+
+        // Retries run out.
+
+        let result: Vec<_> = resume(quick(2), || Err(Error::Connection(String::from("down")))).collect();
+
+        assert_eq!(result.len(), 1);
+        assert!(matches!(result[0], Err(Error::Connection(_))));
+
+        // Other errors aren't retried.
+
+        let mut calls = 0;
+        let result: Vec<_> = resume(quick(2), || {
+            calls += 1;
+            Err(Error::Other(String::from("bad")))
+        })
+        .collect();
+
+        assert_eq!((result.len(), calls), (1, 1));
+    }
+
+    #[test]
+    fn test_call() {
+        let mut calls = 0;
+
+        assert_eq!(
+            call(&quick(2), || {
+                calls += 1;
+                if calls < 3 { Err(Error::Timeout(String::new())) } else { Ok(calls) }
+            }),
+            Ok(3)
+        );
+        assert_eq!(call(&quick(1), || Err::<(), _>(Error::Connection(String::from("down")))), Err(Error::Connection(String::from("down"))));
+        assert_eq!(call(&quick(2), || Err::<(), _>(Error::Other(String::from("bad")))), Err(Error::Other(String::from("bad"))));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{error::Error, gzip};
+use jiff::{tz::TimeZone, Timestamp, Zoned};
+use std::fs;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use tracing::{info, warn};
+
+// Output files which are closed, compressed and replaced as they grow
+// (e.g. every 100MB) or as time passes (hourly or daily), so long
+// monitoring runs don't fill one unbounded file. Files are named by a
+// template, expanded with strftime's fields (e.g. `out-%Y%m%d.csv`)
+// when the file is opened; a file whose name doesn't change is
+// renamed with the time it was opened (`out-20240102-030405.csv`) as
+// it's rotated out. Rotated files are gzipped in the background.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotate {
+    Size(u64),
+    Hourly,
+    Daily,
+}
+
+impl Rotate {
+    // Parses "hourly", "daily" or a size: a number of bytes with an
+    // optional kB, MB or GB (or KiB, MiB or GiB) suffix.
+
+    pub fn parse(text: &str) -> Result<Rotate, String> {
+        let lower = text.to_ascii_lowercase();
+        let split = lower.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(split);
+        let scale = match unit.trim() {
+            _ if lower == "hourly" => return Ok(Rotate::Hourly),
+            _ if lower == "daily" => return Ok(Rotate::Daily),
+            "" | "b" => 1.0,
+            "k" | "kb" => 1e3,
+            "m" | "mb" => 1e6,
+            "g" | "gb" => 1e9,
+            "kib" => 1024.0,
+            "mib" => 1024.0 * 1024.0,
+            "gib" => 1024.0 * 1024.0 * 1024.0,
+            _ => return Err(format!("bad rotation '{}' (expected a size, e.g. 100MB, hourly or daily)", text)),
+        };
+
+        number
+            .parse::<f64>()
+            .ok()
+            .map(|n| (n * scale) as u64)
+            .filter(|n| *n > 0)
+            .map(Rotate::Size)
+            .ok_or_else(|| format!("bad rotation '{}' (expected a size, e.g. 100MB, hourly or daily)", text))
+    }
+}
+
+// Returns `path` with `stamp` added before its extension, if it has
+// one.
+
+fn stamped(path: &str, stamp: &str) -> String {
+    let name = path.rfind('/').map_or(0, |i| i + 1);
+
+    match path[name..].rfind('.').filter(|i| *i > 0) {
+        Some(dot) => format!("{}-{}{}", &path[..name + dot], stamp, &path[name + dot..]),
+        None => format!("{}-{}", path, stamp),
+    }
+}
+
+// Replaces a rotated file with its gzipped copy.
+
+fn compress(path: &str) -> io::Result<()> {
+    let gz = format!("{}.gz", path);
+
+    fs::write(&gz, gzip::compress(&fs::read(path)?))?;
+    fs::remove_file(path)?;
+    info!(file = gz.as_str(), "compressed rotated output");
+    Ok(())
+}
+
+pub struct File {
+    template: String,
+    header: Option<String>,
+    rotate: Option<Rotate>,
+    zone: TimeZone,
+    path: String,
+    opened: Zoned,
+    out: LineWriter<fs::File>,
+    size: u64,
+    line_start: bool,
+    compressing: Vec<JoinHandle<()>>,
+}
+
+impl File {
+    // Creates the first file of the template. `header` starts every
+    // file (e.g. a CSV header) and times are those of `zone`.
+
+    pub fn create(template: &str, header: Option<String>, rotate: Option<Rotate>, zone: &TimeZone) -> Result<File, Error> {
+        let opened = Timestamp::now().to_zoned(zone.clone());
+        let path = File::expand(template, &opened);
+        let mut file = File {
+            template: String::from(template),
+            header,
+            rotate,
+            zone: zone.clone(),
+            out: File::open(&path)?,
+            path,
+            opened,
+            size: 0,
+            line_start: true,
+            compressing: vec![],
+        };
+
+        file.start()?;
+        Ok(file)
+    }
+
+    fn expand(template: &str, time: &Zoned) -> String {
+        if template.contains('%') {
+            time.strftime(template).to_string()
+        } else {
+            String::from(template)
+        }
+    }
+
+    fn open(path: &str) -> Result<LineWriter<fs::File>, Error> {
+        fs::File::create(path)
+            .map(LineWriter::new)
+            .map_err(|e| Error::Other(format!("can't create {}: {}", path, e)))
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        self.size = 0;
+        if let Some(h) = &self.header {
+            writeln!(self.out, "{}", h)?;
+            self.size = h.len() as u64 + 1
+        }
+        Ok(())
+    }
+
+    // Returns whether the file is due to be rotated out at `now`.
+
+    fn due(&self, now: &Zoned) -> bool {
+        match self.rotate {
+            None => false,
+            Some(Rotate::Size(n)) => self.size >= n,
+            Some(Rotate::Hourly) => (now.date(), now.hour()) != (self.opened.date(), self.opened.hour()),
+            Some(Rotate::Daily) => now.date() != self.opened.date(),
+        }
+    }
+
+    // Closes the file, opens the next and compresses the old one.
+
+    fn next(&mut self, now: Zoned) -> Result<(), Error> {
+        self.out.flush()?;
+
+        let path = File::expand(&self.template, &now);
+        let mut old = self.path.clone();
+
+        if path == old {
+            let stamp = self.opened.strftime("%Y%m%d-%H%M%S").to_string();
+            let mut n = 1;
+
+            old = stamped(&self.path, &stamp);
+            while Path::new(&old).exists() || Path::new(&format!("{}.gz", old)).exists() {
+                n += 1;
+                old = stamped(&self.path, &format!("{}-{}", stamp, n))
+            }
+            fs::rename(&self.path, &old)?
+        }
+
+        self.out = File::open(&path)?;
+        self.path = path;
+        self.opened = now;
+        self.start()?;
+        self.compressing.retain(|h| !h.is_finished());
+        self.compressing.push(thread::spawn(move || {
+            if let Err(e) = compress(&old) {
+                warn!(file = old.as_str(), "can't compress rotated output: {}", e)
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Write for File {
+    // Files are only rotated between lines.
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.line_start && self.rotate.is_some() {
+            let now = Timestamp::now().to_zoned(self.zone.clone());
+
+            if self.due(&now) {
+                self.next(now).map_err(|e| io::Error::other(e.to_string()))?
+            }
+        }
+
+        let n = self.out.write(buf)?;
+
+        self.size += n as u64;
+        if n > 0 {
+            self.line_start = buf[n - 1] == b'\n'
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Waits for rotated files to be compressed, so none is left half done
+// when the program exits.
+
+impl Drop for File {
+    fn drop(&mut self) {
+        for h in self.compressing.drain(..) {
+            let _ = h.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Rotate::parse("100MB"), Ok(Rotate::Size(100_000_000)));
+        assert_eq!(Rotate::parse("1.5k"), Ok(Rotate::Size(1500)));
+        assert_eq!(Rotate::parse("2GiB"), Ok(Rotate::Size(2 << 30)));
+        assert_eq!(Rotate::parse("4096"), Ok(Rotate::Size(4096)));
+        assert_eq!(Rotate::parse("Daily"), Ok(Rotate::Daily));
+        assert_eq!(Rotate::parse("hourly"), Ok(Rotate::Hourly));
+        assert!(Rotate::parse("0MB").is_err());
+        assert!(Rotate::parse("weekly").is_err());
+        assert!(Rotate::parse("MB").is_err());
+    }
+
+    #[test]
+    fn test_stamped() {
+        assert_eq!(stamped("out.csv", "20240102-030405"), "out-20240102-030405.csv");
+        assert_eq!(stamped("/data/run.1/out", "x"), "/data/run.1/out-x");
+        assert_eq!(stamped("logs/.hidden", "x"), "logs/.hidden-x");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("acsys-rotate-{}", std::process::id()));
+        let path = dir.join("out.csv").to_string_lossy().into_owned();
+
+        fs::create_dir_all(&dir).unwrap();
+        {
+            let mut file = File::create(&path, Some(String::from("a,b")), Some(Rotate::Size(8)), &TimeZone::UTC).unwrap();
+
+            for i in 0..3 {
+                write!(file, "{},", i).unwrap();
+                writeln!(file, "{}", i * 10).unwrap()
+            }
+        }
+
+        let mut names: Vec<String> =
+            fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+
+        names.sort();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a,b\n2,20\n");
+        assert_eq!(names.len(), 3);
+        assert!(names[..2].iter().all(|n| n.starts_with("out-") && n.ends_with(".csv.gz")));
+        assert_eq!(names[2], "out.csv");
+        fs::remove_dir_all(&dir).unwrap()
+    }
+}
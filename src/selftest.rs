@@ -0,0 +1,205 @@
+use crate::{backend::Client, error::Error};
+
+// Requests covering each part of the DRF syntax. Each must parse and
+// survive a round trip through its canonical form.
+
+const CORPUS: &[&str] = &[
+    "M:OUTTMP",
+    "M:OUTTMP@p,1s",
+    "M:OUTTMP@q,100,false",
+    "M_OUTTMP.RAW",
+    "M|OUTTMP.ON",
+    "M&OUTTMP",
+    "Z@ACLTST.MAX",
+    "Z$ACLTST.NOM",
+    "M~OUTTMP",
+    "M:OUTTMP.INDEX",
+    "0:12345@I",
+    "B:LINFRQ[0:9]@e,02",
+    "B:LINFRQ[2;3]@e,SUPERCYCLE,h,10",
+    "B:LINFRQ[-1]@N",
+    "B:LINFRQ[]@p,1k<-ftp",
+    "G:AMANDA{0:4}.RAW@I",
+    "M:OUTTMP@S,V:STATE,5,100,=",
+    "M:OUTTMP@A,1700000000,1s",
+];
+
+// The device read by default. Its reading, the outdoor temperature,
+// is always available and harmless to request.
+
+pub const REFERENCE_DEVICE: &str = "M:OUTTMP";
+
+// How far back the logger check looks for data.
+
+const LOGGER_SPAN: u64 = 3_600_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Pass(String),
+    Fail(String),
+    // The check doesn't apply, e.g. there's no role to verify.
+    Skip(String),
+}
+
+// The result of one check. `name` says what was checked.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+fn fail(e: Error) -> Outcome {
+    Outcome::Fail(e.to_string())
+}
+
+// Parses the corpus, stopping at the first request which fails.
+
+pub fn check_parser() -> Outcome {
+    for text in CORPUS {
+        let outcome = match drf::parse_drf(text) {
+            Ok(req) => drf::verify(&req),
+            Err(e) => Err(drf::describe(text, &e)),
+        };
+
+        if let Err(e) = outcome {
+            return Outcome::Fail(e);
+        }
+    }
+    Outcome::Pass(format!("{} requests parsed", CORPUS.len()))
+}
+
+// Reads `device` once.
+
+pub fn check_read(client: &Client, device: &drf::Request) -> Outcome {
+    let mut req = device.clone();
+
+    req.event = drf::Event::Immediate;
+    match client.acquire(std::slice::from_ref(&req)).map(|mut s| s.next()) {
+        Ok(Some(Ok(_))) => Outcome::Pass(format!("{} replied", req.device)),
+        Ok(Some(Err(e))) | Err(e) => fail(e),
+        Ok(None) => Outcome::Fail(format!("{} didn't reply", req.device)),
+    }
+}
+
+// Fetches the last hour of `device`'s logged data, up to `now` (in
+// microseconds since the epoch.) It passes even if nothing was
+// logged, since that's up to the device.
+
+pub fn check_logger(client: &Client, device: &drf::Request, now: u64) -> Outcome {
+    match client.history(0, device, now.saturating_sub(LOGGER_SPAN), now) {
+        Ok(readings) => Outcome::Pass(format!("{} points logged in the last hour", readings.len())),
+        Err(e) => fail(e),
+    }
+}
+
+// Verifies the configured role, if there is one.
+
+pub fn check_auth(client: &Client) -> Outcome {
+    match &client.settings().role {
+        Some(role) => match client.verify_role(role) {
+            Ok(()) => Outcome::Pass(format!("role {} verified", role)),
+            Err(e) => fail(e),
+        },
+        None => Outcome::Skip(String::from("no role is configured")),
+    }
+}
+
+// Runs every check, in order, reading `device`.
+
+pub fn run(client: &Client, device: &drf::Request, now: u64) -> Vec<Check> {
+    vec![
+        Check { name: String::from("parse DRF corpus"), outcome: check_parser() },
+        Check { name: format!("read {}", device.device), outcome: check_read(client, device) },
+        Check { name: String::from("reach logger"), outcome: check_logger(client, device, now) },
+        Check { name: String::from("verify role"), outcome: check_auth(client) },
+    ]
+}
+
+// Formats the results, one check per line, e.g.
+// "PASS  parse DRF corpus: 18 requests parsed". If none failed, a
+// line saying so follows; otherwise the caller reports the failures.
+
+pub fn report(checks: &[Check]) -> String {
+    let mut text = String::new();
+
+    for check in checks {
+        let (verdict, detail) = match &check.outcome {
+            Outcome::Pass(d) => ("PASS", d),
+            Outcome::Fail(d) => ("FAIL", d),
+            Outcome::Skip(d) => ("SKIP", d),
+        };
+
+        text.push_str(&format!("{}  {}: {}\n", verdict, check.name, detail))
+    }
+
+    if failures(checks) == 0 {
+        text.push_str("all checks passed\n")
+    }
+    text
+}
+
+// Returns the number of checks which failed.
+
+pub fn failures(checks: &[Check]) -> usize {
+    checks.iter().filter(|c| matches!(c.outcome, Outcome::Fail(_))).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{BackendKind, Settings};
+
+    fn settings(backend: BackendKind, role: Option<&str>) -> Settings {
+        Settings {
+            backend,
+            dpm_host: String::from("localhost"),
+            role: role.map(String::from),
+            timeout: std::time::Duration::from_secs(1),
+            retries: 0,
+            journal: None,
+        }
+    }
+
+    #[test]
+    fn test_selftest() {
+        assert_eq!(check_parser(), Outcome::Pass(format!("{} requests parsed", CORPUS.len())));
+
+        let device = drf::parse_drf(REFERENCE_DEVICE).unwrap();
+        let now = 1_700_000_000_000_000;
+
+        // Every check passes against the mock backend.
+
+        let checks = run(&Client::new(settings(BackendKind::Mock(None), Some("operator"))), &device, now);
+
+        assert_eq!(failures(&checks), 0);
+        assert_eq!(
+            report(&checks),
+            format!(
+                "PASS  parse DRF corpus: {} requests parsed\n\
+                 PASS  read M:OUTTMP: M:OUTTMP replied\n\
+                 PASS  reach logger: 3600 points logged in the last hour\n\
+                 PASS  verify role: role operator verified\n\
+                 all checks passed\n",
+                CORPUS.len()
+            )
+        );
+
+        // Without a data source, reading and the logger fail. The
+        // failures are left to the caller to sum up.
+
+        let checks = run(&Client::new(settings(BackendKind::Dpm, None)), &device, now);
+
+        assert_eq!(checks.len(), 4);
+        assert!(matches!(checks[1].outcome, Outcome::Fail(_)));
+        assert!(matches!(checks[2].outcome, Outcome::Fail(_)));
+        assert_eq!(checks[3].outcome, Outcome::Skip(String::from("no role is configured")));
+        assert_eq!(failures(&checks), 2);
+
+        let report = report(&checks);
+
+        assert!(report.starts_with("PASS  parse DRF corpus: "));
+        assert!(report.contains("\nFAIL  read M:OUTTMP: the dpm backend isn't available yet"));
+        assert!(report.ends_with("\nSKIP  verify role: no role is configured\n"));
+    }
+}
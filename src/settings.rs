@@ -0,0 +1,324 @@
+use crate::error::Error;
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::{env, fs, path::PathBuf, time::Duration};
+
+// Each configurable setting is described by its key in the
+// configuration file (which is also the name of the command line
+// argument that overrides it) and the environment variable which
+// overrides it.
+
+struct Source {
+    key: &'static str,
+    var: &'static str,
+}
+
+const SOURCES: &[Source] = &[
+    Source {
+        key: "backend",
+        var: "ACSYS_BACKEND",
+    },
+    Source {
+        key: "dpm_host",
+        var: "ACSYS_DPM_HOST",
+    },
+    Source {
+        key: "role",
+        var: "ACSYS_ROLE",
+    },
+    Source {
+        key: "timeout",
+        var: "ACSYS_TIMEOUT",
+    },
+    Source {
+        key: "retries",
+        var: "ACSYS_RETRIES",
+    },
+    Source {
+        key: "journal",
+        var: "ACSYS_JOURNAL",
+    },
+];
+
+const DEFAULT_DPM_HOST: &str = "acsys-proxy.fnal.gov";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_RETRIES: u32 = 3;
+
+// The backend used to reach the data sources: DPM or a mock, which
+// makes up its data or serves the readings of the capture file it
+// names.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum BackendKind {
+    #[default]
+    Dpm,
+    Mock(Option<PathBuf>),
+}
+
+// Holds the connection parameters used by the backends. `timeout`
+// limits how long a backend waits to connect or for a reply and
+// `retries` is the number of times a failed connection is retried
+// before giving up. Settings made by `put` and `control` are recorded
+// in the `journal` (see `journal.rs`). Every value is resolved using
+// the following precedence (highest first):
+//
+//   1. the command line option,
+//   2. the environment variable,
+//   3. the configuration file,
+//   4. the built-in default.
+//
+// The configuration file is named by `--config`, or `ACSYS_CONFIG`,
+// and defaults to `$HOME/.config/acsys/config` (on Windows,
+// `%APPDATA%\acsys\config`).
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub backend: BackendKind,
+    pub dpm_host: String,
+    pub role: Option<String>,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub journal: Option<PathBuf>,
+}
+
+impl Settings {
+    // Builds the settings for this invocation of the program.
+
+    pub fn resolve(m: &ArgMatches) -> Result<Settings, Error> {
+        let config = match Settings::config_path(m) {
+            Some((path, required)) => match fs::read_to_string(&path) {
+                Ok(text) => parse_config(&text)
+                    .map_err(|e| Error::Parse(format!("{}: {}", path.display(), e)))?,
+                Err(e) if required => {
+                    return Err(match Error::from(e) {
+                        Error::Other(m) => {
+                            Error::Other(format!("can't read {}: {}", path.display(), m))
+                        }
+                        e => e,
+                    })
+                }
+                Err(_) => HashMap::new(),
+            },
+            None => HashMap::new(),
+        };
+
+        Settings::from_sources(
+            |arg| m.value_of(arg).map(String::from),
+            |var| env::var(var).ok(),
+            &config,
+        )
+        .map_err(Error::Parse)
+    }
+
+    // Returns the location of the configuration file and whether it
+    // must exist (it must, if the user explicitly named it.)
+
+    fn config_path(m: &ArgMatches) -> Option<(PathBuf, bool)> {
+        if let Some(path) = m.value_of("config") {
+            Some((PathBuf::from(path), true))
+        } else if let Ok(path) = env::var("ACSYS_CONFIG") {
+            Some((PathBuf::from(path), true))
+        } else {
+            default_config_path(|var| env::var(var).ok()).map(|path| (path, false))
+        }
+    }
+
+    fn from_sources<F, E>(arg: F, var: E, config: &HashMap<String, String>) -> Result<Settings, String>
+    where
+        F: Fn(&str) -> Option<String>,
+        E: Fn(&str) -> Option<String>,
+    {
+        let lookup = |key: &str| {
+            let src = SOURCES.iter().find(|s| s.key == key).unwrap();
+
+            arg(src.key)
+                .or_else(|| var(src.var))
+                .or_else(|| config.get(src.key).cloned())
+        };
+
+        let timeout = match lookup("timeout") {
+            Some(t) => t
+                .parse::<f64>()
+                .ok()
+                .filter(|t| t.is_finite() && *t > 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("bad timeout '{}'", t))?,
+            None => DEFAULT_TIMEOUT,
+        };
+        let retries = match lookup("retries") {
+            Some(r) => r.parse().map_err(|_| format!("bad retry count '{}'", r))?,
+            None => DEFAULT_RETRIES,
+        };
+
+        let backend = match lookup("backend").as_deref() {
+            None | Some("dpm") => BackendKind::Dpm,
+            Some("mock") => BackendKind::Mock(None),
+            Some(b) => match b.strip_prefix("mock:") {
+                Some(file) => BackendKind::Mock(Some(PathBuf::from(file))),
+                None => return Err(format!("unknown backend '{}' (expected dpm or mock[:FILE])", b)),
+            },
+        };
+
+        Ok(Settings {
+            backend,
+            dpm_host: lookup("dpm_host").unwrap_or_else(|| String::from(DEFAULT_DPM_HOST)),
+            role: lookup("role"),
+            timeout,
+            retries,
+            journal: lookup("journal").map(PathBuf::from).or_else(|| default_journal_path(&var)),
+        })
+    }
+}
+
+// Parses the configuration file. Each non-blank line, that isn't a
+// comment, has the form `key = value`. The value may be enclosed in
+// double quotes. Unknown keys are reported so typos don't go
+// unnoticed.
+
+fn parse_config(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut result = HashMap::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key = value'", n + 1))?;
+        let key = key.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        if !SOURCES.iter().any(|s| s.key == key) {
+            return Err(format!("line {}: unknown setting '{}'", n + 1, key));
+        }
+        result.insert(key.to_string(), value.to_string());
+    }
+    Ok(result)
+}
+
+// Returns where the configuration file is looked for when none is
+// named. Windows has no `HOME`; per-user settings live under
+// `APPDATA` there.
+
+fn default_config_path<E: Fn(&str) -> Option<String>>(var: E) -> Option<PathBuf> {
+    if cfg!(windows) {
+        var("APPDATA").map(|dir| PathBuf::from(dir).join("acsys").join("config"))
+    } else {
+        var("HOME").map(|home| PathBuf::from(home).join(".config").join("acsys").join("config"))
+    }
+}
+
+// Returns where the settings journal is kept when none is named:
+// `$HOME/.local/share/acsys/journal` (on Windows,
+// `%APPDATA%\acsys\journal`).
+
+fn default_journal_path<E: Fn(&str) -> Option<String>>(var: E) -> Option<PathBuf> {
+    if cfg!(windows) {
+        var("APPDATA").map(|dir| PathBuf::from(dir).join("acsys").join("journal"))
+    } else {
+        var("HOME").map(|home| PathBuf::from(home).join(".local").join("share").join("acsys").join("journal"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(data: &[(&str, &str)]) -> HashMap<String, String> {
+        data.iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_config_parsing() {
+        let cfg = parse_config("# comment\n\ndpm_host = \"dpm01\"\n role=operator \n").unwrap();
+
+        assert_eq!(cfg, table(&[("dpm_host", "dpm01"), ("role", "operator")]));
+        assert!(parse_config("dpm_host").is_err());
+        assert!(parse_config("dpm_hots = x").is_err());
+    }
+
+    #[test]
+    fn test_default_config_path() {
+        let var = |name: &str| match name {
+            "HOME" => Some(String::from("/home/op")),
+            "APPDATA" => Some(String::from("C:\\Users\\op\\AppData\\Roaming")),
+            _ => None,
+        };
+        let expected = if cfg!(windows) {
+            PathBuf::from("C:\\Users\\op\\AppData\\Roaming").join("acsys").join("config")
+        } else {
+            PathBuf::from("/home/op/.config/acsys/config")
+        };
+
+        assert_eq!(default_config_path(var), Some(expected));
+        assert_eq!(default_config_path(|_| None), None);
+        assert_eq!(
+            default_journal_path(var),
+            Some(if cfg!(windows) {
+                PathBuf::from("C:\\Users\\op\\AppData\\Roaming").join("acsys").join("journal")
+            } else {
+                PathBuf::from("/home/op/.local/share/acsys/journal")
+            })
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        let flags = table(&[("role", "flag-role"), ("timeout", "2.5")]);
+        let vars = table(&[("ACSYS_ROLE", "env-role"), ("ACSYS_DPM_HOST", "env-host")]);
+        let config = table(&[("role", "cfg-role"), ("dpm_host", "cfg-host"), ("retries", "7")]);
+
+        let s = Settings::from_sources(
+            |f| flags.get(f).cloned(),
+            |v| vars.get(v).cloned(),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(
+            s,
+            Settings {
+                backend: BackendKind::Dpm,
+                dpm_host: String::from("env-host"),
+                role: Some(String::from("flag-role")),
+                timeout: Duration::from_millis(2500),
+                retries: 7,
+                journal: None,
+            }
+        );
+
+        let s = Settings::from_sources(|_| None, |_| None, &config).unwrap();
+
+        assert_eq!(s.dpm_host, "cfg-host");
+        assert_eq!(s.role.as_deref(), Some("cfg-role"));
+        assert_eq!(s.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(
+            Settings::from_sources(|_| None, |v| vars.get(v).cloned(), &table(&[("journal", "/var/log/sets")]))
+                .unwrap()
+                .journal,
+            Some(PathBuf::from("/var/log/sets"))
+        );
+
+        let bad = table(&[("timeout", "-1")]);
+
+        assert!(Settings::from_sources(|_| None, |_| None, &bad).is_err());
+
+        let mock = table(&[("backend", "mock")]);
+
+        assert_eq!(Settings::from_sources(|_| None, |_| None, &mock).unwrap().backend, BackendKind::Mock(None));
+        assert!(Settings::from_sources(|_| None, |_| None, &table(&[("backend", "corba")])).is_err());
+        assert_eq!(
+            Settings::from_sources(|_| None, |_| None, &table(&[("backend", "mock:run.json")])).unwrap().backend,
+            BackendKind::Mock(Some(PathBuf::from("run.json")))
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use crate::data::Reading;
+use crate::output::{self, Format};
+use crate::{backend::Client, error::Error, timefmt::Formatter};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// A command typed at the shell's prompt. A `get` ending with '&' runs
+// in the background; otherwise it runs in the foreground until it
+// ends or the user moves it to the background.
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Get {
+        name: Option<String>,
+        drfs: Vec<String>,
+        background: bool,
+    },
+    Jobs,
+    Fg(String),
+    Stop(String),
+    Help,
+    Quit,
+    Empty,
+}
+
+pub const HELP: &str = "\
+get [--name NAME] DRF... [&]  acquires data (in the background, with '&')
+jobs                          lists the sessions
+fg NAME                       shows a session's data (press Enter to detach)
+stop NAME                     ends a session
+quit                          stops every session and leaves the shell";
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    let mut background = false;
+
+    if let Some(last) = words.pop() {
+        match last.strip_suffix('&') {
+            Some("") => background = true,
+            Some(rest) => {
+                background = true;
+                words.push(rest)
+            }
+            None => words.push(last),
+        }
+    }
+
+    let (cmd, args) = match words.split_first() {
+        Some((cmd, args)) => (cmd.to_lowercase(), args),
+        None if background => return Err(String::from("nothing to run in the background")),
+        None => return Ok(Command::Empty),
+    };
+
+    if background && cmd != "get" && cmd != "read" {
+        return Err(format!("'{}' can't run in the background", cmd));
+    }
+
+    let name = |args: &[&str]| match args {
+        [name] => Ok(name.to_string()),
+        _ => Err(format!("usage: {} NAME", cmd)),
+    };
+
+    match cmd.as_str() {
+        "get" | "read" => {
+            let (name, drfs) = match args {
+                ["-n", name, rest @ ..] | ["--name", name, rest @ ..] => (Some(name.to_string()), rest),
+                _ => (None, args),
+            };
+
+            if drfs.is_empty() {
+                return Err(String::from("usage: get [--name NAME] DRF... [&]"));
+            }
+            Ok(Command::Get {
+                name,
+                drfs: drfs.iter().map(|d| d.to_string()).collect(),
+                background,
+            })
+        }
+        "jobs" if args.is_empty() => Ok(Command::Jobs),
+        "fg" => Ok(Command::Fg(name(args)?)),
+        "stop" => Ok(Command::Stop(name(args)?)),
+        "help" => Ok(Command::Help),
+        "quit" | "exit" => Ok(Command::Quit),
+        _ => Err(format!("unknown command '{}' (try 'help')", line.trim())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Running,
+    Stopped,
+    Done,
+    Failed(String),
+}
+
+// The most lines a background session holds for `fg`. Older lines
+// are dropped.
+
+const BACKLOG: usize = 100;
+
+struct State {
+    lines: VecDeque<String>,
+    status: Status,
+    readings: u64,
+    foreground: bool,
+}
+
+// An acquisition running on its own thread. Its output is collected,
+// rather than printed, so it doesn't disturb the prompt; the shell
+// shows it when the session is in the foreground.
+
+pub struct Session {
+    pub name: String,
+    pub drfs: Vec<String>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    // Starts a session which formats the readings returned by
+    // `source`. The source is created on the session's thread.
+
+    pub fn start<F, I>(name: String, requests: Vec<drf::Request>, tf: Formatter, source: F) -> Session
+    where
+        F: FnOnce() -> I + Send + 'static,
+        I: Iterator<Item = Result<Reading, Error>>,
+    {
+        let state = Arc::new(Mutex::new(State {
+            lines: VecDeque::new(),
+            status: Status::Running,
+            readings: 0,
+            foreground: false,
+        }));
+        let session = Session {
+            name,
+            drfs: requests.iter().map(|r| r.canonical()).collect(),
+            state: state.clone(),
+        };
+
+        thread::spawn(move || {
+            for reading in source() {
+                let mut s = state.lock().unwrap();
+
+                if s.status != Status::Running {
+                    return;
+                }
+
+                match reading {
+                    Ok(reading) => {
+                        let req = &requests[reading.index];
+
+                        s.lines.extend(output::format_reading(Format::Text, &tf, None, req, &reading));
+                        s.readings += 1;
+                        while !s.foreground && s.lines.len() > BACKLOG {
+                            s.lines.pop_front();
+                        }
+                    }
+                    Err(e) => {
+                        s.status = Status::Failed(e.to_string());
+                        return;
+                    }
+                }
+            }
+
+            let mut s = state.lock().unwrap();
+
+            if s.status == Status::Running {
+                s.status = Status::Done
+            }
+        });
+        session
+    }
+
+    pub fn status(&self) -> Status {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    pub fn readings(&self) -> u64 {
+        self.state.lock().unwrap().readings
+    }
+
+    // Removes, and returns, the output collected so far.
+
+    pub fn take_lines(&self) -> Vec<String> {
+        self.state.lock().unwrap().lines.drain(..).collect()
+    }
+
+    pub fn set_foreground(&self, foreground: bool) {
+        self.state.lock().unwrap().foreground = foreground
+    }
+
+    // Asks the session to end. The thread stops when its next reply
+    // arrives.
+
+    pub fn stop(&self) {
+        let mut s = self.state.lock().unwrap();
+
+        if s.status == Status::Running {
+            s.status = Status::Stopped
+        }
+    }
+}
+
+// The sessions started in the shell. Sessions without a name are
+// numbered.
+
+#[derive(Default)]
+pub struct Sessions {
+    list: Vec<Session>,
+    next: usize,
+}
+
+impl Sessions {
+    // Returns the name of a new session, or an error if the requested
+    // name is already used.
+
+    pub fn name_for(&mut self, name: Option<String>) -> Result<String, String> {
+        match name {
+            Some(name) if self.get(&name).is_some() => Err(format!("a session named '{}' exists", name)),
+            Some(name) => Ok(name),
+            None => loop {
+                self.next += 1;
+
+                let name = self.next.to_string();
+
+                if self.get(&name).is_none() {
+                    break Ok(name);
+                }
+            },
+        }
+    }
+
+    pub fn add(&mut self, session: Session) {
+        self.list.push(session)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Session> {
+        self.list.iter().find(|s| s.name == name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Session> {
+        let idx = self.list.iter().position(|s| s.name == name)?;
+
+        Some(self.list.remove(idx))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Session> {
+        self.list.iter()
+    }
+}
+
+impl Drop for Sessions {
+    fn drop(&mut self) {
+        for s in &self.list {
+            s.stop()
+        }
+    }
+}
+
+fn show_status(status: &Status) -> String {
+    match status {
+        Status::Running => String::from("running"),
+        Status::Stopped => String::from("stopped"),
+        Status::Done => String::from("done"),
+        Status::Failed(e) => format!("failed: {}", e),
+    }
+}
+
+// Shows a session's output until it ends or the user presses Enter.
+// Returns whether the session is still running.
+
+fn foreground(session: &Session, input: &mpsc::Receiver<String>) -> bool {
+    let mut detached = false;
+
+    session.set_foreground(true);
+    eprintln!("(press Enter to move '{}' to the background)", session.name);
+
+    loop {
+        for line in session.take_lines() {
+            println!("{}", line)
+        }
+
+        match session.status() {
+            Status::Running => (),
+            Status::Failed(e) => {
+                eprintln!("{}: {}", session.name, e);
+                break;
+            }
+            _ => break,
+        }
+
+        match input.recv_timeout(Duration::from_millis(100)) {
+            Ok(_) => {
+                detached = true;
+                break;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(100)),
+        }
+    }
+
+    session.set_foreground(false);
+    detached
+}
+
+// Runs the shell until the user quits or closes its input. Every
+// session shares the client's connection.
+
+pub fn run(client: &Client, tf: &Formatter) -> Result<(), Error> {
+    let (tx, input) = mpsc::channel();
+    let mut sessions = Sessions::default();
+
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        print!("acsys> ");
+        io::stdout().flush()?;
+
+        let line = match input.recv() {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        match parse_command(&line) {
+            Err(e) => eprintln!("{}", e),
+            Ok(Command::Empty) => (),
+            Ok(Command::Help) => println!("{}", HELP),
+            Ok(Command::Quit) => break,
+            Ok(Command::Jobs) => {
+                for s in sessions.iter() {
+                    println!("{:<10} {:<10} {:>8} {}", s.name, show_status(&s.status()), s.readings(), s.drfs.join(" "))
+                }
+            }
+            Ok(Command::Get { name, drfs, background }) => {
+                let parsed: Result<Vec<drf::Parsed>, String> = drfs
+                    .iter()
+                    .map(|d| drf::parse_with(d, &drf::ParseOptions::default()).map_err(|e| drf::describe(d, &e)))
+                    .collect();
+                let requests: Vec<drf::Request> = match parsed {
+                    Ok(parsed) => {
+                        for (d, p) in drfs.iter().zip(&parsed) {
+                            for w in &p.warnings {
+                                eprintln!("warning: {}: {}", d, w)
+                            }
+                        }
+                        parsed.into_iter().map(|p| p.request).collect()
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                };
+                let name = match sessions.name_for(name) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                };
+                let source = {
+                    let client = client.clone();
+                    let requests = requests.clone();
+
+                    move || client.subscribe(requests)
+                };
+                let session = Session::start(name.clone(), requests, tf.clone(), source);
+
+                if background {
+                    println!("[{}] started", name);
+                    sessions.add(session)
+                } else if foreground(&session, &input) {
+                    println!("[{}] moved to the background", name);
+                    sessions.add(session)
+                }
+            }
+            Ok(Command::Fg(name)) => match sessions.get(&name) {
+                Some(s) => {
+                    if foreground(s, &input) {
+                        println!("[{}] moved to the background", name)
+                    } else {
+                        sessions.remove(&name);
+                    }
+                }
+                None => eprintln!("no session named '{}'", name),
+            },
+            Ok(Command::Stop(name)) => match sessions.remove(&name) {
+                Some(s) => {
+                    s.stop();
+                    println!("[{}] stopped", name)
+                }
+                None => eprintln!("no session named '{}'", name),
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(session: &Session) -> Status {
+        while session.status() == Status::Running {
+            thread::sleep(Duration::from_millis(1))
+        }
+        session.status()
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(
+            parse_command("get M:OUTTMP@p,1s &"),
+            Ok(Command::Get {
+                name: None,
+                drfs: vec![String::from("M:OUTTMP@p,1s")],
+                background: true
+            })
+        );
+        assert_eq!(
+            parse_command("get --name tmp M:OUTTMP G:AMANDA&"),
+            Ok(Command::Get {
+                name: Some(String::from("tmp")),
+                drfs: vec![String::from("M:OUTTMP"), String::from("G:AMANDA")],
+                background: true
+            })
+        );
+        assert_eq!(parse_command("fg tmp"), Ok(Command::Fg(String::from("tmp"))));
+        assert_eq!(parse_command("  "), Ok(Command::Empty));
+        assert!(parse_command("get").is_err());
+        assert!(parse_command("jobs &").is_err());
+        assert!(parse_command("stop").is_err());
+        assert!(parse_command("launch").is_err());
+    }
+
+    #[test]
+    fn test_sessions() {
+        let requests = vec![drf::parse_drf("M:OUTTMP").unwrap()];
+        let readings: Vec<Result<Reading, Error>> = (0..150)
+            .map(|ts| {
+                Ok(Reading::new(0, ts, 1.0.into()))
+            })
+            .collect();
+        let s = Session::start(String::from("1"), requests.clone(), Formatter::default(), move || readings.into_iter());
+
+        // A background session only keeps the latest lines.
+
+        assert_eq!(wait(&s), Status::Done);
+        assert_eq!(s.readings(), 150);
+        assert_eq!(s.take_lines().len(), BACKLOG);
+
+        let failing = std::iter::once(Err(Error::Timeout(String::from("no reply"))));
+        let s = Session::start(String::from("2"), requests, Formatter::default(), move || failing);
+
+        assert_eq!(wait(&s), Status::Failed(String::from("timed out: no reply")));
+
+        let mut sessions = Sessions::default();
+
+        sessions.add(s);
+        assert_eq!(sessions.name_for(None), Ok(String::from("1")));
+        assert_eq!(sessions.name_for(None), Ok(String::from("3")));
+        assert!(sessions.name_for(Some(String::from("2"))).is_err());
+        assert!(sessions.remove("2").is_some());
+        assert!(sessions.get("2").is_none());
+    }
+}
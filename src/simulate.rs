@@ -0,0 +1,115 @@
+use crate::data::Reading;
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+// Parses an update rate, given as a frequency ("15Hz", "15") or as a
+// period ("100ms", "2s", "1m30s"), into the period between updates.
+
+pub fn parse_rate(text: &str) -> Result<Duration, String> {
+    let t = text.trim().to_lowercase();
+    let period = match t.strip_suffix("hz").unwrap_or(&t).trim().parse::<f64>() {
+        Ok(v) if v.is_finite() && v > 0.0 => Duration::try_from_secs_f64(1.0 / v).ok(),
+        Ok(_) => None,
+        Err(_) => crate::history::parse_duration(&t).ok(),
+    };
+
+    period.filter(|d| !d.is_zero()).ok_or_else(|| format!("bad rate '{}'", text))
+}
+
+// Returns the requests for `n` synthetic devices, named Z:SIM0000,
+// Z:SIM0001, and so on.
+
+pub fn requests(n: usize, period: Duration) -> Vec<drf::Request> {
+    (0..n)
+        .map(|i| {
+            drf::parse_drf(&format!("Z:SIM{:04}@P,{}", i, period.as_millis().max(1)))
+                .expect("synthetic DRF must be valid")
+        })
+        .collect()
+}
+
+// Returns the value of synthetic device `i` at `t` seconds. Each
+// device is a sine wave with its own period and phase so the streams
+// are easy to tell apart.
+
+pub fn sample(i: usize, t: f64) -> f64 {
+    let cycle = 10.0 + (i % 50) as f64;
+
+    100.0 * (TAU * t / cycle + i as f64).sin()
+}
+
+// Generates the readings of `devices` synthetic devices, each updated
+// every `period` starting at `start` (in microseconds since the
+// epoch), using `sample()`. The readings of each update cycle are
+// returned together, in device order.
+
+pub struct Generator {
+    devices: usize,
+    period: Duration,
+    start: u64,
+    tick: u64,
+}
+
+impl Generator {
+    pub fn new(devices: usize, period: Duration, start: u64) -> Generator {
+        Generator {
+            devices,
+            period,
+            start,
+            tick: 0,
+        }
+    }
+
+    // Returns the offset, from the start, of the next update cycle.
+
+    pub fn next_offset(&self) -> Duration {
+        self.period * self.tick as u32
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Vec<Reading>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.next_offset();
+        let timestamp = self.start + offset.as_micros() as u64;
+        let t = offset.as_secs_f64();
+
+        self.tick += 1;
+        Some(
+            (0..self.devices)
+                .map(|i| Reading::new(i, timestamp, sample(i, t).into()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates() {
+        assert_eq!(parse_rate("15Hz"), Ok(Duration::from_secs_f64(1.0 / 15.0)));
+        assert_eq!(parse_rate("2"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_rate("100ms"), Ok(Duration::from_millis(100)));
+        assert_eq!(parse_rate("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_rate("1m30s"), Ok(Duration::from_secs(90)));
+        assert!(parse_rate("0s").is_err());
+        assert!(parse_rate("0Hz").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_generator() {
+        let mut g = Generator::new(3, Duration::from_millis(100), 1_000_000);
+        let first = g.next().unwrap();
+        let second = g.next().unwrap();
+
+        assert_eq!(first.len(), 3);
+        assert_eq!(first.iter().map(|r| r.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(second[0].timestamp, 1_100_000);
+        assert_ne!(first[0].value, first[1].value);
+        assert_eq!(requests(2, Duration::from_millis(100))[1].canonical(), "Z:SIM0001.READING.SCALED@P,100,TRUE");
+    }
+}
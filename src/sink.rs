@@ -0,0 +1,372 @@
+use crate::data::{Reading, Value};
+use crate::states::StateTag;
+use crate::{capture, error::Error, http, influx, kafka, output, rotate, timefmt::Formatter};
+use std::io::{self, LineWriter, Write};
+use std::time::Duration;
+
+// Where the data of an acquisition goes. Each reply is handed to
+// every sink in turn, so one acquisition can be shown, written to
+// files and published at once without requesting the data again.
+// Sinks are given with `--sink`, which can be repeated:
+//
+//    stdout             shown in the --format chosen (the default)
+//    file:FILE          written to a file in the --format chosen
+//    csv:FILE           written to a CSV file
+//    json:FILE          written to a file of JSON records, one a line
+//    socket:HOST:PORT   sent as JSON records over a TCP connection
+//    kafka              published to Kafka (see `kafka`), to the
+//                       brokers and topic of --brokers and --topic
+//    kafka://B/TOPIC    published to TOPIC on the brokers B
+//    influx             written to InfluxDB (see `influx`)
+//
+// Files can be rotated out as they grow (see `rotate`).
+
+pub trait Sink {
+    // Takes a reply to `req`, which is shown as `label` if one is
+    // given. `tag` holds the machine state, with --tag-state.
+
+    fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, tag: Option<&StateTag>)
+        -> Result<(), Error>;
+
+    // Takes a value of a derived channel.
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, units: &str, tag: Option<&StateTag>) -> Result<(), Error>;
+
+    // Delivers whatever is held back, once the data ends.
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Spec {
+    Stdout,
+    File(String),
+    Csv(String),
+    Json(String),
+    Socket(String),
+    Kafka(Option<(String, String)>),
+    Influx,
+}
+
+impl Spec {
+    pub fn parse(text: &str) -> Result<Spec, String> {
+        if let Some(url) = text.strip_prefix("kafka://") {
+            return match url.rsplit_once('/') {
+                Some((brokers, topic)) if !brokers.is_empty() && !topic.is_empty() => {
+                    Ok(Spec::Kafka(Some((String::from(brokers), String::from(topic)))))
+                }
+                _ => Err(format!("bad sink '{}' (expected kafka://BROKERS/TOPIC)", text)),
+            };
+        }
+
+        let spec = match text.split_once(':') {
+            None if text == "stdout" => Spec::Stdout,
+            None if text == "kafka" => Spec::Kafka(None),
+            None if text == "influx" => Spec::Influx,
+            Some(("file", file)) if !file.is_empty() => Spec::File(String::from(file)),
+            Some(("csv", file)) if !file.is_empty() => Spec::Csv(String::from(file)),
+            Some(("json", file)) if !file.is_empty() => Spec::Json(String::from(file)),
+            Some(("socket", addr)) if addr.rsplit_once(':').is_some_and(|(h, p)| !h.is_empty() && p.parse::<u16>().is_ok()) => {
+                Spec::Socket(String::from(addr))
+            }
+            _ => {
+                return Err(format!(
+                    "bad sink '{}' (expected stdout, file:FILE, csv:FILE, json:FILE, socket:HOST:PORT, kafka, kafka://BROKERS/TOPIC or influx)",
+                    text
+                ))
+            }
+        };
+
+        Ok(spec)
+    }
+
+    // Returns the sink of an --output file: CSV or JSON records for
+    // names ending in .csv or .json (or .jsonl), and --format's lines
+    // otherwise.
+
+    pub fn output(file: &str) -> Spec {
+        let lower = file.to_ascii_lowercase();
+
+        if lower.ends_with(".csv") {
+            Spec::Csv(String::from(file))
+        } else if lower.ends_with(".json") || lower.ends_with(".jsonl") {
+            Spec::Json(String::from(file))
+        } else {
+            Spec::File(String::from(file))
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self, Spec::File(_) | Spec::Csv(_) | Spec::Json(_))
+    }
+}
+
+// Returns the InfluxDB tags of the machine state, when it's known.
+
+pub fn state_tags(tag: Option<&StateTag>) -> Vec<(&'static str, String)> {
+    tag.and_then(StateTag::state).map(|s| ("state", s)).into_iter().collect()
+}
+
+// How a stream of lines is written: as `output` formats readings, as
+// text shaped by a template, or as InfluxDB line protocol.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Style {
+    Output(output::Format),
+    Template(output::Template),
+    Influx,
+}
+
+// A sink writing a line (or several, for arrays in text) per reply to
+// stdout, a file or a socket.
+
+pub struct Lines {
+    style: Style,
+    tf: Formatter,
+    out: Box<dyn Write>,
+}
+
+impl Lines {
+    pub fn stdout(style: Style, tf: Formatter) -> Lines {
+        Lines { style, tf, out: Box::new(io::stdout()) }
+    }
+
+    // Creates the file, which is written a line at a time so an
+    // interrupted session keeps what it got.
+
+    pub fn file(path: &str, style: Style, tf: Formatter, rotate: Option<rotate::Rotate>) -> Result<Lines, Error> {
+        let file = rotate::File::create(path, None, rotate, tf.zone())?;
+
+        Ok(Lines { style, tf, out: Box::new(file) })
+    }
+
+    // Connects to a HOST:PORT address.
+
+    pub fn socket(addr: &str, style: Style, tf: Formatter, timeout: Duration) -> Result<Lines, Error> {
+        let (host, port) = addr
+            .rsplit_once(':')
+            .and_then(|(h, p)| Some((h, p.parse().ok()?)))
+            .ok_or_else(|| Error::Parse(format!("bad address '{}'", addr)))?;
+        let stream = http::connect(addr, host, port, timeout)?;
+
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(Lines { style, tf, out: Box::new(LineWriter::new(stream)) })
+    }
+
+    fn write(&mut self, lines: Vec<String>, tag: Option<&StateTag>) -> Result<(), Error> {
+        for line in lines {
+            match (&self.style, tag) {
+                (Style::Output(f), Some(t)) => writeln!(self.out, "{}", t.tag(*f, &line))?,
+                (Style::Template(_), Some(t)) => writeln!(self.out, "{}", t.tag(output::Format::Text, &line))?,
+                _ => writeln!(self.out, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Lines {
+    fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, tag: Option<&StateTag>)
+        -> Result<(), Error> {
+        let lines = match &self.style {
+            Style::Output(f) => output::format_reading(*f, &self.tf, label, req, reading),
+            Style::Template(t) => t.format_reading(&self.tf, label, req, reading),
+            Style::Influx => influx::lines(req, reading, &state_tags(tag)),
+        };
+
+        self.write(lines, tag)
+    }
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, units: &str, tag: Option<&StateTag>) -> Result<(), Error> {
+        let lines = match &self.style {
+            Style::Output(f) => vec![output::format_derived(*f, &self.tf, name, timestamp, v, units)],
+            Style::Template(t) => vec![t.format_derived(&self.tf, name, timestamp, v, units)],
+            Style::Influx => influx::derived(name, timestamp, v, &state_tags(tag)).into_iter().collect(),
+        };
+
+        self.write(lines, tag)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(self.out.flush()?)
+    }
+}
+
+// A sink writing CSV, with the columns of `acsys convert`'s, in the
+// given style. Replies are named by their label or DRF.
+
+pub struct Csv {
+    style: capture::CsvStyle,
+    tf: Formatter,
+    out: rotate::File,
+}
+
+impl Csv {
+    // Creates the file. Each file, when rotated, starts with the
+    // header.
+
+    pub fn file(path: &str, style: capture::CsvStyle, tf: Formatter, rotate: Option<rotate::Rotate>) -> Result<Csv, Error> {
+        let out = rotate::File::create(path, Some(style.header()), rotate, tf.zone())?;
+
+        Ok(Csv { style, tf, out })
+    }
+
+    fn write(&mut self, key: String, timestamp: u64, value: Value) -> Result<(), Error> {
+        for row in capture::format_csv(&capture::Record { timestamp, key, value }, &self.tf, &self.style) {
+            writeln!(self.out, "{}", row)?
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Csv {
+    fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, _: Option<&StateTag>)
+        -> Result<(), Error> {
+        let key = label.map(String::from).unwrap_or_else(|| req.canonical());
+        let value = match &reading.value {
+            Value::Array(v) => Value::Array(output::elements(&req.range, v).1.to_vec()),
+            v => v.clone(),
+        };
+
+        self.write(key, reading.timestamp, value)
+    }
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, _: &str, _: Option<&StateTag>) -> Result<(), Error> {
+        self.write(String::from(name), timestamp, Value::Scalar(v))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(self.out.flush()?)
+    }
+}
+
+// A sink publishing records to Kafka, keyed by device (or by the
+// name of a derived channel.) JSON records are those of `--format
+// json`.
+
+pub struct Kafka {
+    producer: kafka::Producer,
+    encoding: kafka::Encoding,
+    tf: Formatter,
+}
+
+impl Kafka {
+    pub fn new(producer: kafka::Producer, encoding: kafka::Encoding, tf: Formatter) -> Kafka {
+        Kafka { producer, encoding, tf }
+    }
+
+    fn json(lines: Vec<String>, tag: Option<&StateTag>) -> Vec<u8> {
+        lines
+            .iter()
+            .map(|l| tag.map_or_else(|| l.clone(), |t| t.tag(output::Format::Json, l)))
+            .collect::<String>()
+            .into_bytes()
+    }
+}
+
+impl Sink for Kafka {
+    fn reading(&mut self, req: &drf::Request, label: Option<&str>, reading: &Reading, tag: Option<&StateTag>)
+        -> Result<(), Error> {
+        let record = match self.encoding {
+            kafka::Encoding::Json => {
+                Kafka::json(output::format_reading(output::Format::Json, &self.tf, label, req, reading), tag)
+            }
+            kafka::Encoding::Avro => kafka::avro(req, reading, tag.and_then(StateTag::state).as_deref()),
+        };
+
+        self.producer.send(&req.device.name(), record, reading.timestamp)
+    }
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, units: &str, tag: Option<&StateTag>) -> Result<(), Error> {
+        let record = match self.encoding {
+            kafka::Encoding::Json => {
+                Kafka::json(vec![output::format_derived(output::Format::Json, &self.tf, name, timestamp, v, units)], tag)
+            }
+            kafka::Encoding::Avro => kafka::avro_derived(name, timestamp, v, tag.and_then(StateTag::state).as_deref()),
+        };
+
+        self.producer.send(name, record, timestamp)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.producer.flush()
+    }
+}
+
+// A sink writing points to an InfluxDB server.
+
+impl Sink for influx::Server {
+    fn reading(&mut self, req: &drf::Request, _: Option<&str>, reading: &Reading, tag: Option<&StateTag>)
+        -> Result<(), Error> {
+        influx::lines(req, reading, &state_tags(tag)).into_iter().try_for_each(|l| self.write(l))
+    }
+
+    fn derived(&mut self, name: &str, timestamp: u64, v: f64, _: &str, tag: Option<&StateTag>) -> Result<(), Error> {
+        influx::derived(name, timestamp, v, &state_tags(tag)).map_or(Ok(()), |l| self.write(l))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specs() {
+        assert_eq!(Spec::parse("stdout"), Ok(Spec::Stdout));
+        assert_eq!(Spec::parse("csv:out/a.csv"), Ok(Spec::Csv(String::from("out/a.csv"))));
+        assert_eq!(Spec::parse("json:C:\\data.json"), Ok(Spec::Json(String::from("C:\\data.json"))));
+        assert_eq!(Spec::parse("socket:localhost:5000"), Ok(Spec::Socket(String::from("localhost:5000"))));
+        assert_eq!(Spec::parse("kafka"), Ok(Spec::Kafka(None)));
+        assert_eq!(
+            Spec::parse("kafka://k1:9092,k2:9092/acnet"),
+            Ok(Spec::Kafka(Some((String::from("k1:9092,k2:9092"), String::from("acnet")))))
+        );
+        assert!(Spec::parse("kafka://k1:9092").is_err());
+        assert_eq!(Spec::parse("file:out.txt"), Ok(Spec::File(String::from("out.txt"))));
+        assert_eq!(Spec::output("run-%Y%m%d.CSV"), Spec::Csv(String::from("run-%Y%m%d.CSV")));
+        assert_eq!(Spec::output("run.jsonl"), Spec::Json(String::from("run.jsonl")));
+        assert_eq!(Spec::output("run.txt"), Spec::File(String::from("run.txt")));
+        assert!(Spec::parse("socket:localhost").is_err());
+        assert!(Spec::parse("csv:").is_err());
+        assert!(Spec::parse("file").is_err());
+    }
+
+    #[test]
+    fn test_lines() {
+        // A sink writing to a shared buffer, so what it wrote can be
+        // looked at.
+
+        #[derive(Clone, Default)]
+        struct Buffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+        impl Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let mut json = Lines { style: Style::Output(output::Format::Json), tf: Formatter::default(), out: Box::new(buffer.clone()) };
+        let mut tag = StateTag::new(1);
+        let req = drf::parse_drf("M:OUTTMP").unwrap();
+
+        tag.update(&Reading::new(1, 0, Value::Scalar(3.0)));
+        json.reading(&req, None, &Reading::new(0, 1_500_000, Value::Scalar(72.5)), Some(&tag)).unwrap();
+        json.derived("X", 2_000_000, 1.0, "", None).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.borrow().clone()).unwrap(),
+            "{\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":72.5,\"state\":3}\n\
+             {\"timestamp\":2.000000,\"name\":\"X\",\"value\":1,\"units\":\"\"}\n"
+        );
+    }
+}
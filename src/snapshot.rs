@@ -0,0 +1,139 @@
+use crate::bulk::Line;
+use crate::output::json_string;
+use crate::json::Json;
+use crate::timefmt::Formatter;
+
+// A snapshot of device settings, as written by `save` and read by
+// `restore`. It's a JSON object with when it was taken, by whom, and
+// an entry per setting, one per line:
+//
+//    {"timestamp":1700000000.000000,"user":"op","settings":[
+//    {"drf":"Z:CORR1.SETTING.SCALED","value":"1.5","reading":"1.49"},
+//    {"drf":"Z:CORR2.SETTING.RAW","value":null,"reading":null}
+//    ]}
+//
+// Values are written the way `put` takes them (see
+// `put::value_text()`). `value` is null if the setting couldn't be
+// read, and `reading` if the device has no reading or it couldn't be
+// read; readings are only kept for reference.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub timestamp: u64,
+    pub user: String,
+    pub settings: Vec<Saved>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Saved {
+    pub drf: String,
+    pub value: Option<String>,
+    pub reading: Option<String>,
+}
+
+impl Snapshot {
+    pub fn format(&self) -> String {
+        let or_null = |v: &Option<String>| v.as_deref().map(json_string).unwrap_or_else(|| String::from("null"));
+        let entries: Vec<String> = self
+            .settings
+            .iter()
+            .map(|s| {
+                format!(
+                    "{{\"drf\":{},\"value\":{},\"reading\":{}}}",
+                    json_string(&s.drf),
+                    or_null(&s.value),
+                    or_null(&s.reading)
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"timestamp\":{},\"user\":{},\"settings\":[\n{}\n]}}\n",
+            Formatter::default().json(self.timestamp),
+            json_string(&self.user),
+            entries.join(",\n")
+        )
+    }
+
+    pub fn parse(text: &str) -> Result<Snapshot, String> {
+        let j = Json::parse(text)?;
+        let settings = match j.get("settings") {
+            Some(Json::Array(entries)) => entries
+                .iter()
+                .enumerate()
+                .map(|(n, e)| {
+                    Ok(Saved {
+                        drf: e
+                            .get("drf")
+                            .and_then(Json::as_str)
+                            .map(String::from)
+                            .ok_or_else(|| format!("setting {} has no \"drf\"", n + 1))?,
+                        value: e.get("value").and_then(Json::as_str).map(String::from),
+                        reading: e.get("reading").and_then(Json::as_str).map(String::from),
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            _ => return Err(String::from("no \"settings\" array")),
+        };
+
+        Ok(Snapshot {
+            timestamp: j.get("timestamp").and_then(Json::as_f64).map_or(0, |t| (t * 1e6).round() as u64),
+            user: j.get("user").and_then(Json::as_str).unwrap_or_default().to_string(),
+            settings,
+        })
+    }
+
+    // Returns the saved settings as the lines of a settings file,
+    // numbered by their place in the snapshot. Settings which
+    // couldn't be read when the snapshot was taken are left out.
+
+    pub fn lines(&self) -> Vec<Line> {
+        self.settings
+            .iter()
+            .enumerate()
+            .filter_map(|(n, s)| Some(Line { number: n + 1, drf: s.drf.clone(), value: s.value.clone()? }))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let snapshot = Snapshot {
+            timestamp: 1_700_000_000_500_000,
+            user: String::from("op"),
+            settings: vec![
+                Saved {
+                    drf: String::from("Z:CORR1.SETTING.SCALED"),
+                    value: Some(String::from("1.5")),
+                    reading: Some(String::from("1.49")),
+                },
+                Saved { drf: String::from("Z:CORR2.SETTING.RAW"), value: None, reading: None },
+                Saved { drf: String::from("Z:CORR3.SETTING[0:1].SCALED"), value: Some(String::from("1,2")), reading: None },
+            ],
+        };
+        let text = snapshot.format();
+
+        assert_eq!(
+            text,
+            "{\"timestamp\":1700000000.500000,\"user\":\"op\",\"settings\":[\n\
+             {\"drf\":\"Z:CORR1.SETTING.SCALED\",\"value\":\"1.5\",\"reading\":\"1.49\"},\n\
+             {\"drf\":\"Z:CORR2.SETTING.RAW\",\"value\":null,\"reading\":null},\n\
+             {\"drf\":\"Z:CORR3.SETTING[0:1].SCALED\",\"value\":\"1,2\",\"reading\":null}\n\
+             ]}\n"
+        );
+        assert_eq!(Snapshot::parse(&text), Ok(snapshot.clone()));
+        assert_eq!(
+            snapshot.lines(),
+            [
+                Line { number: 1, drf: String::from("Z:CORR1.SETTING.SCALED"), value: String::from("1.5") },
+                Line { number: 3, drf: String::from("Z:CORR3.SETTING[0:1].SCALED"), value: String::from("1,2") }
+            ]
+        );
+        assert!(Snapshot::parse("{\"settings\":[{}]}").is_err());
+        assert!(Snapshot::parse("[]").is_err());
+    }
+}
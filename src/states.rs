@@ -0,0 +1,198 @@
+use crate::alarm::format_duration;
+use crate::data::{Reading, Value};
+use crate::output::{self, json_number, json_string};
+use crate::timefmt::Formatter;
+
+// Tags output with the machine state. The states device is fetched
+// along with the other requests, as the one at `index`; its readings
+// update the current state, instead of being shown, and every other
+// line of output is stamped with the state in effect when it was
+// produced.
+
+pub struct StateTag {
+    index: usize,
+    current: Option<Value>,
+}
+
+impl StateTag {
+    pub fn new(index: usize) -> StateTag {
+        StateTag { index, current: None }
+    }
+
+    // Records the reading if it's from the states device. Returns
+    // whether it was, in which case it shouldn't be shown.
+
+    pub fn update(&mut self, reading: &Reading) -> bool {
+        if reading.index == self.index {
+            self.current = Some(reading.value.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns the current state as text, if it's known.
+
+    pub fn state(&self) -> Option<String> {
+        match &self.current {
+            Some(Value::Scalar(v)) => Some(v.to_string()),
+            Some(Value::Text(v)) => Some(v.clone()),
+            _ => None,
+        }
+    }
+
+    // Adds the current state to a line of output. Text lines end with
+    // "state=N" ('-' until the state is known); JSON records get a
+    // "state" field (null until it's known.)
+
+    pub fn tag(&self, fmt: output::Format, line: &str) -> String {
+        match fmt {
+            output::Format::Text => format!("{} state={}", line, self.state().as_deref().unwrap_or("-")),
+            output::Format::Json => {
+                let state = match &self.current {
+                    Some(Value::Scalar(v)) => json_number(*v),
+                    Some(Value::Text(v)) => json_string(v),
+                    _ => String::from("null"),
+                };
+
+                match line.strip_suffix('}') {
+                    Some(record) => format!("{},\"state\":{}}}", record, state),
+                    None => line.to_string(),
+                }
+            }
+        }
+    }
+}
+
+// A states device changing state. The first reading of a device is a
+// transition from an unknown state. `held` is how long, in
+// microseconds, the old state lasted, when it's known.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub index: usize,
+    pub timestamp: u64,
+    pub from: Option<u16>,
+    pub to: u16,
+    pub held: Option<u64>,
+}
+
+// Watches the readings of several states devices (by request index)
+// for transitions. Readings which repeat the current state, or which
+// aren't a state value, are ignored.
+
+pub struct Watcher {
+    current: Vec<Option<(u16, u64)>>,
+}
+
+impl Watcher {
+    pub fn new(devices: usize) -> Watcher {
+        Watcher { current: vec![None; devices] }
+    }
+
+    pub fn update(&mut self, reading: &Reading) -> Option<Transition> {
+        let state = match reading.value {
+            Value::Scalar(v) if v >= 0.0 && v <= f64::from(u16::MAX) => v.round() as u16,
+            _ => return None,
+        };
+        let current = self.current.get_mut(reading.index)?;
+
+        match *current {
+            Some((s, _)) if s == state => None,
+            prev => {
+                *current = Some((state, reading.timestamp));
+                Some(Transition {
+                    index: reading.index,
+                    timestamp: reading.timestamp,
+                    from: prev.map(|(s, _)| s),
+                    to: state,
+                    held: prev.map(|(_, since)| reading.timestamp.saturating_sub(since)),
+                })
+            }
+        }
+    }
+}
+
+// Formats a transition of `device`. Text output reads, e.g.,
+// "1.000000 V:MSTATE 3 -> 4 after 1m05s"; an unknown old state is
+// '-'. JSON output has a record with the states and the time held in
+// seconds (null when unknown.)
+
+pub fn format_transition(fmt: output::Format, tf: &Formatter, device: &str, t: &Transition) -> String {
+    match fmt {
+        output::Format::Text => {
+            let line = format!(
+                "{} {} {} -> {}",
+                tf.format(t.timestamp),
+                device,
+                t.from.map(|s| s.to_string()).unwrap_or_else(|| String::from("-")),
+                t.to
+            );
+
+            match t.held {
+                Some(held) => format!("{} after {}", line, format_duration(held)),
+                None => line,
+            }
+        }
+        output::Format::Json => format!(
+            "{{\"timestamp\":{},\"device\":{},\"from\":{},\"to\":{},\"held\":{}}}",
+            tf.json(t.timestamp),
+            json_string(device),
+            t.from.map(|s| s.to_string()).unwrap_or_else(|| String::from("null")),
+            t.to,
+            t.held.map(|h| json_number(h as f64 / 1e6)).unwrap_or_else(|| String::from("null"))
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_tag() {
+        let mut tag = StateTag::new(1);
+        let reading = |index, value: Value| Reading::new(index, 0, value);
+
+        assert_eq!(tag.tag(output::Format::Text, "1.000000 M:OUTTMP 70"), "1.000000 M:OUTTMP 70 state=-");
+        assert_eq!(tag.tag(output::Format::Json, "{\"value\":70}"), "{\"value\":70,\"state\":null}");
+
+        assert!(!tag.update(&reading(0, 71.0.into())));
+        assert!(tag.update(&reading(1, 3.0.into())));
+        assert_eq!(tag.tag(output::Format::Text, "1.000000 M:OUTTMP 70"), "1.000000 M:OUTTMP 70 state=3");
+        assert_eq!(tag.tag(output::Format::Json, "{\"value\":70}"), "{\"value\":70,\"state\":3}");
+
+        assert!(tag.update(&reading(1, String::from("STORE").into())));
+        assert_eq!(tag.tag(output::Format::Json, "{\"value\":70}"), "{\"value\":70,\"state\":\"STORE\"}");
+    }
+
+    #[test]
+    fn test_watcher() {
+        let mut w = Watcher::new(2);
+        let reading = |index, timestamp, value: Value| Reading::new(index, timestamp, value);
+
+        assert_eq!(
+            w.update(&reading(0, 1_000_000, 3.0.into())),
+            Some(Transition { index: 0, timestamp: 1_000_000, from: None, to: 3, held: None })
+        );
+        assert_eq!(w.update(&reading(0, 2_000_000, 3.0.into())), None);
+        assert_eq!(w.update(&reading(0, 2_500_000, String::from("?").into())), None);
+        assert_eq!(w.update(&reading(2, 2_500_000, 1.0.into())), None);
+
+        let t = w.update(&reading(0, 66_000_000, 4.0.into())).unwrap();
+
+        assert_eq!(t, Transition { index: 0, timestamp: 66_000_000, from: Some(3), to: 4, held: Some(65_000_000) });
+
+        let tf = Formatter::default();
+
+        assert_eq!(format_transition(output::Format::Text, &tf, "V:MSTATE", &t), "66.000000 V:MSTATE 3 -> 4 after 1m05s");
+        assert_eq!(
+            format_transition(output::Format::Json, &tf, "V:MSTATE", &t),
+            "{\"timestamp\":66.000000,\"device\":\"V:MSTATE\",\"from\":3,\"to\":4,\"held\":65}"
+        );
+
+        let first = Transition { from: None, held: None, ..t };
+
+        assert_eq!(format_transition(output::Format::Text, &tf, "V:MSTATE", &first), "66.000000 V:MSTATE - -> 4");
+    }
+}
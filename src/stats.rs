@@ -0,0 +1,207 @@
+use crate::data::Value;
+use crate::output::{json_number, json_string};
+
+// Summary statistics of each channel of an acquisition, for `get
+// --stats`. Every number a channel returns counts, including each
+// element of an array; NaNs, text and failures don't. Percentiles are
+// exact, so every value is kept.
+
+struct Channel {
+    name: String,
+    units: Option<String>,
+    values: Vec<f64>,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+pub struct Stats {
+    channels: Vec<Channel>,
+}
+
+const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+// Returns the nearest-rank percentile of sorted values.
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl Channel {
+    // Returns the count, minimum, maximum, mean, standard deviation
+    // (of the sample) and percentiles, or `None` before the first
+    // value.
+
+    fn summary(&self) -> Option<(usize, f64, f64, f64, f64, Vec<f64>)> {
+        let n = self.values.len();
+
+        if n == 0 {
+            return None;
+        }
+
+        let mut sorted = self.values.clone();
+
+        sorted.sort_by(f64::total_cmp);
+
+        let std_dev = if n > 1 { (self.m2 / (n - 1) as f64).sqrt() } else { 0.0 };
+        let ps = PERCENTILES.iter().map(|p| percentile(&sorted, *p)).collect();
+
+        Some((n, self.min, self.max, self.mean, std_dev, ps))
+    }
+}
+
+impl Stats {
+    pub fn new(names: Vec<String>) -> Stats {
+        Stats {
+            channels: names
+                .into_iter()
+                .map(|name| Channel {
+                    name,
+                    units: None,
+                    values: vec![],
+                    mean: 0.0,
+                    m2: 0.0,
+                    min: f64::INFINITY,
+                    max: f64::NEG_INFINITY,
+                })
+                .collect(),
+        }
+    }
+
+    // Adds a value of a channel. The mean and variance are kept with
+    // Welford's method, which doesn't lose precision to large sums.
+
+    pub fn update(&mut self, channel: usize, value: &Value, units: Option<&str>) {
+        let c = match self.channels.get_mut(channel) {
+            Some(c) => c,
+            None => return,
+        };
+
+        for v in value.as_slice().iter().copied().filter(|v| !v.is_nan()) {
+            let delta = v - c.mean;
+
+            c.values.push(v);
+            c.mean += delta / c.values.len() as f64;
+            c.m2 += delta * (v - c.mean);
+            c.min = c.min.min(v);
+            c.max = c.max.max(v)
+        }
+        if let Some(u) = units.filter(|u| !u.is_empty()) {
+            c.units = Some(String::from(u))
+        }
+    }
+
+    // Returns the summary as a table. Channels without values show
+    // only their count.
+
+    pub fn render(&self) -> String {
+        let width = self.channels.iter().map(|c| c.name.len()).max().unwrap_or(0).max(7);
+        let mut text = format!(
+            "{:<width$} {:>8} {:>14} {:>14} {:>14} {:>14} {:>14} {:>14} {:>14} UNITS\n",
+            "CHANNEL",
+            "COUNT",
+            "MIN",
+            "MAX",
+            "MEAN",
+            "STDDEV",
+            "P50",
+            "P90",
+            "P99",
+            width = width
+        );
+
+        for c in &self.channels {
+            let units = c.units.as_deref().unwrap_or("");
+            let line = match c.summary() {
+                Some((n, min, max, mean, std_dev, ps)) => format!(
+                    "{:<width$} {:>8} {:>14.6} {:>14.6} {:>14.6} {:>14.6} {:>14.6} {:>14.6} {:>14.6} {}",
+                    c.name,
+                    n,
+                    min,
+                    max,
+                    mean,
+                    std_dev,
+                    ps[0],
+                    ps[1],
+                    ps[2],
+                    units,
+                    width = width
+                ),
+                None => format!("{:<width$} {:>8}", c.name, 0, width = width),
+            };
+
+            text.push_str(line.trim_end());
+            text.push('\n')
+        }
+        text
+    }
+
+    // Returns the summary as a JSON object per channel. Channels
+    // without values only have a count.
+
+    pub fn json(&self) -> Vec<String> {
+        self.channels
+            .iter()
+            .map(|c| {
+                let name = json_string(&c.name);
+                let units = c.units.as_ref().map(|u| format!(",\"units\":{}", json_string(u))).unwrap_or_default();
+
+                match c.summary() {
+                    Some((n, min, max, mean, std_dev, ps)) => format!(
+                        "{{\"name\":{},\"count\":{},\"min\":{},\"max\":{},\"mean\":{},\"stddev\":{},\"p50\":{},\"p90\":{},\"p99\":{}{}}}",
+                        name,
+                        n,
+                        json_number(min),
+                        json_number(max),
+                        json_number(mean),
+                        json_number(std_dev),
+                        json_number(ps[0]),
+                        json_number(ps[1]),
+                        json_number(ps[2]),
+                        units
+                    ),
+                    None => format!("{{\"name\":{},\"count\":0{}}}", name, units),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::new(vec![String::from("M:OUTTMP"), String::from("B:BPM"), String::from("X")]);
+
+        for v in 1..=10 {
+            stats.update(0, &Value::Scalar(f64::from(v)), Some("degF"))
+        }
+        stats.update(0, &Value::Scalar(f64::NAN), None);
+        stats.update(1, &Value::Array(vec![2.0, 4.0]), None);
+        stats.update(1, &Value::Status(-6), None);
+        stats.update(7, &Value::Scalar(1.0), None);
+
+        let json = stats.json();
+
+        assert_eq!(
+            json[0],
+            "{\"name\":\"M:OUTTMP\",\"count\":10,\"min\":1,\"max\":10,\"mean\":5.5,\
+             \"stddev\":3.0276503540974917,\"p50\":5,\"p90\":9,\"p99\":10,\"units\":\"degF\"}"
+        );
+        assert!(json[1].starts_with("{\"name\":\"B:BPM\",\"count\":2,\"min\":2,\"max\":4,\"mean\":3,\"stddev\":1.414"));
+        assert_eq!(json[2], "{\"name\":\"X\",\"count\":0}");
+
+        let table = stats.render();
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(lines[0].starts_with("CHANNEL  "));
+        assert!(lines[1].starts_with("M:OUTTMP       10       1.000000      10.000000       5.500000"));
+        assert!(lines[1].ends_with(" degF"));
+        assert_eq!(lines[3], "X               0");
+    }
+}
@@ -0,0 +1,157 @@
+use crate::error::Error;
+use std::fmt;
+
+// An ACNET status, as returned by front ends, DPM and the gateways.
+// The low byte is the facility which reported it and the high byte,
+// taken as signed, the error: zero is success, negative values are
+// errors and positive ones warnings. 0xfa01, for instance, is error
+// -6 of facility 1 (ACNET_REQTMO.)
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(pub i16);
+
+const fn code(facility: u8, error: i8) -> i16 {
+    ((error as i16) << 8) | facility as i16
+}
+
+const ACNET: u8 = 1;
+const DPM: u8 = 17;
+
+// The facilities which have names.
+
+const FACILITIES: &[(u8, &str)] = &[(ACNET, "ACNET"), (DPM, "DPM")];
+
+// The statuses this program knows, with their symbolic names (those
+// of the ACNET headers) and what they mean.
+
+const CATALOG: &[(i16, &str, &str)] = &[
+    (code(ACNET, 1), "ACNET_PEND", "operation pending"),
+    (code(ACNET, 2), "ACNET_ENDMULT", "end of multiple replies"),
+    (code(ACNET, -1), "ACNET_RETRY", "retryable I/O error"),
+    (code(ACNET, -2), "ACNET_NOLCLMEM", "no local memory"),
+    (code(ACNET, -3), "ACNET_NOREMMEM", "no remote memory"),
+    (code(ACNET, -4), "ACNET_RPLYPACK", "reply packet assembly error"),
+    (code(ACNET, -5), "ACNET_REQPACK", "request packet assembly error"),
+    (code(ACNET, -6), "ACNET_REQTMO", "request timed out"),
+    (code(ACNET, -7), "ACNET_QUEFULL", "destination queue full"),
+    (code(ACNET, -8), "ACNET_BUSY", "destination task busy"),
+    (code(ACNET, -21), "ACNET_NOT_CONNECTED", "not connected to the network"),
+    (code(ACNET, -22), "ACNET_ARG", "missing arguments"),
+    (code(ACNET, -23), "ACNET_IVM", "invalid message length or buffer"),
+    (code(ACNET, -24), "ACNET_NO_SUCH", "no such request or reply"),
+    (code(ACNET, -25), "ACNET_REQREJ", "request rejected"),
+    (code(ACNET, -26), "ACNET_CANCELLED", "request cancelled"),
+    (code(ACNET, -27), "ACNET_NAME_IN_USE", "task name already in use"),
+    (code(ACNET, -28), "ACNET_NCR", "not connected as a RUM task"),
+    (code(ACNET, -30), "ACNET_NO_NODE", "no such node"),
+    (code(ACNET, -31), "ACNET_TRUNC_REQUEST", "truncated request"),
+    (code(ACNET, -32), "ACNET_TRUNC_REPLY", "truncated reply"),
+    (code(ACNET, -33), "ACNET_NO_TASK", "no such destination task"),
+    (code(ACNET, -34), "ACNET_DISCONNECTED", "replier task disconnected"),
+    (code(ACNET, -35), "ACNET_LEVEL2", "level II function error"),
+    (code(ACNET, -41), "ACNET_HARD_IO", "hard I/O error"),
+    (code(ACNET, -42), "ACNET_NODE_DOWN", "node down or offline"),
+    (code(ACNET, -43), "ACNET_SYS", "system service error"),
+    (code(ACNET, -44), "ACNET_NXE", "untranslatable error"),
+    (code(ACNET, -45), "ACNET_BUG", "network internal error"),
+    (code(ACNET, -49), "ACNET_UTIME", "user-specified timeout"),
+    (code(ACNET, -50), "ACNET_INVARG", "invalid argument"),
+    (code(ACNET, -51), "ACNET_MEMFAIL", "memory allocation failed"),
+    (code(ACNET, -52), "ACNET_NO_HANDLE", "no such handle"),
+    (code(DPM, 1), "DPM_PEND", "waiting for the first reading"),
+    (code(DPM, 2), "DPM_STALE", "data are stale"),
+    (code(DPM, -24), "DPM_BAD_REQUEST", "bad request"),
+    (code(DPM, -26), "DPM_PRIV", "not privileged to make the setting"),
+];
+
+impl Status {
+    pub fn facility(self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn error(self) -> i8 {
+        (self.0 >> 8) as i8
+    }
+
+    pub fn is_ok(self) -> bool {
+        self.0 >= 0
+    }
+
+    // Returns the status's symbolic name and meaning, if it's known.
+
+    pub fn name(self) -> Option<&'static str> {
+        CATALOG.iter().find(|(c, _, _)| *c == self.0).map(|(_, n, _)| *n)
+    }
+
+    pub fn message(self) -> Option<&'static str> {
+        CATALOG.iter().find(|(c, _, _)| *c == self.0).map(|(_, _, m)| *m)
+    }
+
+    // Returns the error reporting a failure, described by `what`, with
+    // this status. The kind of error, and so the exit status, follows
+    // from the status: timeouts, lost nodes and refused privileges
+    // are told apart from other failures.
+
+    pub fn fail(self, what: impl fmt::Display) -> Error {
+        let m = format!("{}: {}", what, self);
+
+        match self.name() {
+            Some("ACNET_REQTMO" | "ACNET_UTIME") => Error::Timeout(m),
+            Some(
+                "ACNET_NOT_CONNECTED" | "ACNET_NO_NODE" | "ACNET_NO_TASK" | "ACNET_DISCONNECTED" | "ACNET_NODE_DOWN",
+            ) => Error::Connection(m),
+            Some("DPM_PRIV") => Error::PermissionDenied(m),
+            _ => Error::Other(m),
+        }
+    }
+}
+
+// Known statuses are written by name, e.g. "ACNET_REQTMO (request
+// timed out; facility ACNET, error -6)", others by number, e.g.
+// "status -711 (facility 57, error -3)".
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let facility = FACILITIES
+            .iter()
+            .find(|(n, _)| *n == self.facility())
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| self.facility().to_string());
+
+        match (self.name(), self.message()) {
+            (Some(name), Some(message)) => {
+                write!(f, "{} ({}; facility {}, error {})", name, message, facility, self.error())
+            }
+            _ => write!(f, "status {} (facility {}, error {})", self.0, facility, self.error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoding() {
+        let s = Status(-1535);
+
+        assert_eq!((s.facility(), s.error()), (1, -6));
+        assert_eq!(s.name(), Some("ACNET_REQTMO"));
+        assert_eq!(s.to_string(), "ACNET_REQTMO (request timed out; facility ACNET, error -6)");
+        assert_eq!(Status(513).name(), Some("ACNET_ENDMULT"));
+        assert_eq!(Status(code(DPM, 1)).to_string(), "DPM_PEND (waiting for the first reading; facility DPM, error 1)");
+        assert!(Status(code(DPM, 1)).is_ok());
+        assert_eq!(Status(code(57, -3)).to_string(), "status -711 (facility 57, error -3)");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(Status(code(ACNET, -49)).fail("read"), Error::Timeout(_)));
+        assert!(matches!(Status(code(ACNET, -42)).fail("read"), Error::Connection(_)));
+        assert!(matches!(Status(code(DPM, -26)).fail("set"), Error::PermissionDenied(_)));
+        assert_eq!(
+            Status(code(57, -3)).fail("DPM rejected M:OUTTMP"),
+            Error::Other(String::from("DPM rejected M:OUTTMP: status -711 (facility 57, error -3)"))
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::data::{Reading, Value};
+use crate::{backend::Client, error::Error, job::Job, monitor};
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+const HEADER: [&str; 5] = ["DEVICE", "DESCRIPTION", "VALUE", "UNITS", "STATUS"];
+
+// One device of the report, with the latest reply to each of its
+// requests.
+
+struct Row {
+    label: String,
+    units: Option<String>,
+    description: Option<String>,
+    value: Option<Value>,
+    status: Option<Value>,
+}
+
+// A report on a list of devices. Each device is acquired with three
+// requests: its DRF, for the value, its description (once) and its
+// basic status (at the DRF's event). The requests are submitted as
+// the values, then the descriptions and then the statuses, so a
+// reply's index locates its row and column.
+
+pub struct Report {
+    rows: Vec<Row>,
+}
+
+impl Report {
+    pub fn new(jobs: &[Job]) -> Report {
+        Report {
+            rows: jobs
+                .iter()
+                .map(|j| Row {
+                    label: j.label.clone().unwrap_or_else(|| j.request.canonical()),
+                    units: None,
+                    description: None,
+                    value: None,
+                    status: None,
+                })
+                .collect(),
+        }
+    }
+
+    // Returns the requests to submit for the devices of `jobs`.
+
+    pub fn requests(jobs: &[Job]) -> Vec<drf::Request> {
+        let extra = |property, event: Option<drf::Event>| {
+            move |j: &Job| {
+                drf::Request::builder()
+                    .device(&j.request.device.name())
+                    .property(property)
+                    .event(event.clone().unwrap_or_else(|| j.request.event.clone()))
+                    .build()
+                    .expect("a device's canonical name accepts every property")
+            }
+        };
+        let description = extra(drf::Property::Description, Some(drf::Event::Immediate));
+        let status = extra(drf::Property::Status(drf::StatusField::default()), None);
+
+        jobs.iter()
+            .map(|j| j.request.clone())
+            .chain(jobs.iter().map(description))
+            .chain(jobs.iter().map(status))
+            .collect()
+    }
+
+    // Records a reply. The units are those the value was reported in.
+
+    pub fn update(&mut self, reading: Reading) {
+        let n = self.rows.len();
+
+        if n == 0 {
+            return;
+        }
+        if let Some(row) = self.rows.get_mut(reading.index % n) {
+            match reading.index / n {
+                0 => {
+                    if reading.meta.units.is_some() {
+                        row.units = reading.meta.units
+                    }
+                    row.value = Some(reading.value)
+                }
+                1 => row.description = Some(monitor::show(&reading.value)),
+                2 => row.status = Some(reading.value),
+                _ => (),
+            }
+        }
+    }
+
+    // Renders the report as a table whose columns are aligned with
+    // spaces. Missing data is shown as '-'.
+
+    pub fn render(&self) -> String {
+        let cell = |v: &Option<Value>| v.as_ref().map(monitor::show).unwrap_or_else(|| String::from("-"));
+        let rows: Vec<[String; 5]> = self
+            .rows
+            .iter()
+            .map(|r| {
+                [
+                    r.label.clone(),
+                    r.description.clone().unwrap_or_else(|| String::from("-")),
+                    cell(&r.value),
+                    r.units.clone().unwrap_or_default(),
+                    cell(&r.status),
+                ]
+            })
+            .collect();
+        let header = HEADER.map(String::from);
+        let mut widths = [0; 5];
+
+        for row in std::iter::once(&header).chain(&rows) {
+            for (w, c) in widths.iter_mut().zip(row) {
+                *w = (*w).max(c.chars().count())
+            }
+        }
+
+        std::iter::once(&header)
+            .chain(&rows)
+            .map(|row| {
+                let line: Vec<String> = row.iter().zip(widths).map(|(c, w)| format!("{:<1$}", c, w)).collect();
+
+                format!("{}\n", line.join("  ").trim_end())
+            })
+            .collect()
+    }
+}
+
+// Shows the report every `refresh` until the acquisition ends. On a
+// terminal, each table replaces the last one; otherwise the tables
+// are written one after the other, separated by a blank line, so the
+// output can be piped.
+
+pub fn run(client: &Client, jobs: &[Job], refresh: Duration) -> Result<(), Error> {
+    let mut report = Report::new(jobs);
+    let rx = monitor::subscribe(client, Report::requests(jobs));
+    let terminal = io::stdout().is_terminal();
+
+    loop {
+        let deadline = Instant::now() + refresh;
+        let done = loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Ok(reading)) => report.update(reading),
+                Ok(Err(e)) => return Err(e),
+                Err(RecvTimeoutError::Timeout) => break false,
+                Err(RecvTimeoutError::Disconnected) => break true,
+            }
+        };
+        let mut out = io::stdout().lock();
+
+        if terminal {
+            write!(out, "\x1b[2J\x1b[H{}", report.render())?
+        } else {
+            writeln!(out, "{}", report.render())?
+        }
+        out.flush()?;
+
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let jobs = vec![
+            Job::new(drf::parse_drf("M:OUTTMP@p,1s").unwrap()),
+            crate::job::parse_line("G:AMANDA[0:1] | label=amanda").unwrap().unwrap(),
+        ];
+        let requests = Report::requests(&jobs);
+
+        assert_eq!(
+            requests.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+            [
+                "M:OUTTMP.READING.SCALED@P,1S,TRUE",
+                "G:AMANDA.READING[0:1].SCALED",
+                "M:OUTTMP.DESCRIPTION@I",
+                "G:AMANDA.DESCRIPTION@I",
+                "M:OUTTMP.STATUS.ALL@P,1S,TRUE",
+                "G:AMANDA.STATUS.ALL",
+            ]
+        );
+
+        let mut report = Report::new(&jobs);
+        let reading = |index, value: Value| Reading::new(index, 0, value);
+        let mut value = reading(0, Value::Scalar(72.5));
+
+        value.meta.units = Some(String::from("DegF"));
+        report.update(value);
+        report.update(reading(2, Value::Text(String::from("Outdoor temperature"))));
+        report.update(reading(4, Value::Text(String::from("on"))));
+        report.update(reading(1, Value::Array(vec![1.0, 2.0])));
+        report.update(reading(9, Value::Scalar(0.0)));
+
+        assert_eq!(
+            report.render(),
+            "DEVICE                             DESCRIPTION          VALUE   UNITS  STATUS\n\
+             M:OUTTMP.READING.SCALED@P,1S,TRUE  Outdoor temperature  72.5    DegF   on\n\
+             amanda                             -                    [1, 2]         -\n"
+        );
+    }
+}
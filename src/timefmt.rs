@@ -0,0 +1,138 @@
+use crate::output::json_string;
+use jiff::{tz::TimeZone, Timestamp};
+use std::convert::TryFrom;
+
+// The ways a timestamp can be written. `Epoch` is seconds since the
+// epoch with a microsecond fraction (the default), `EpochNs` is
+// nanoseconds since the epoch and `Iso` is an RFC 3339 date and time
+// in the formatter's time zone.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeFormat {
+    Epoch,
+    EpochNs,
+    Iso,
+}
+
+impl TimeFormat {
+    pub fn parse(text: &str) -> Result<TimeFormat, String> {
+        match text.to_lowercase().as_str() {
+            "epoch" => Ok(TimeFormat::Epoch),
+            "epoch-ns" => Ok(TimeFormat::EpochNs),
+            "iso" => Ok(TimeFormat::Iso),
+            _ => Err(format!("unknown time format '{}'", text)),
+        }
+    }
+}
+
+// Formats every timestamp the program writes, so text, CSV and JSON
+// output agree. Timestamps are microseconds since the epoch.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Formatter {
+    format: TimeFormat,
+    tz: TimeZone,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            format: TimeFormat::Epoch,
+            tz: TimeZone::UTC,
+        }
+    }
+}
+
+impl Formatter {
+    // Builds a formatter from the `--time-format` and `--tz` options.
+    // The time zone is "local", "utc" or an IANA name (e.g.
+    // "America/Chicago".) Naming a time zone without a format selects
+    // the ISO format, since the epoch formats don't use one.
+
+    pub fn new(format: Option<&str>, tz: Option<&str>) -> Result<Formatter, String> {
+        let zone = match tz {
+            None => TimeZone::UTC,
+            Some(name) if name.eq_ignore_ascii_case("utc") => TimeZone::UTC,
+            Some(name) if name.eq_ignore_ascii_case("local") => TimeZone::system(),
+            Some(name) => TimeZone::get(name).map_err(|_| format!("unknown time zone '{}'", name))?,
+        };
+        let format = match format {
+            Some(f) => TimeFormat::parse(f)?,
+            None if tz.is_some() => TimeFormat::Iso,
+            None => TimeFormat::Epoch,
+        };
+
+        Ok(Formatter { format, tz: zone })
+    }
+
+    // Returns the time zone in which timestamps are written.
+
+    pub fn zone(&self) -> &TimeZone {
+        &self.tz
+    }
+
+    // Returns whether timestamps are written as plain numbers.
+
+    pub fn is_numeric(&self) -> bool {
+        self.format != TimeFormat::Iso
+    }
+
+    // Returns the timestamp as text.
+
+    pub fn format(&self, ts: u64) -> String {
+        match self.format {
+            TimeFormat::Epoch => format!("{}.{:06}", ts / 1_000_000, ts % 1_000_000),
+            TimeFormat::EpochNs => format!("{}000", ts),
+            TimeFormat::Iso => match Timestamp::from_microsecond(ts as i64) {
+                Ok(t) => format!("{:.6}", t.to_zoned(self.tz.clone())),
+                Err(_) => format!("{}.{:06}", ts / 1_000_000, ts % 1_000_000),
+            },
+        }
+    }
+
+    // Returns the timestamp as a JSON value: a number for the epoch
+    // formats and a string otherwise.
+
+    pub fn json(&self, ts: u64) -> String {
+        match self.format {
+            TimeFormat::Iso => json_string(&self.format(ts)),
+            _ => self.format(ts),
+        }
+    }
+}
+
+// Parses an RFC 3339 timestamp (e.g. "2024-01-02T03:04:05.5Z") into
+// microseconds since the epoch.
+
+pub fn parse_iso(text: &str) -> Option<u64> {
+    text.parse::<Timestamp>()
+        .ok()
+        .and_then(|t| u64::try_from(t.as_microsecond()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TS: u64 = 1_700_000_000_250_000;
+
+    #[test]
+    fn test_formats() {
+        let f = |format: Option<&str>, tz: Option<&str>| Formatter::new(format, tz).unwrap().format(TS);
+
+        assert_eq!(f(None, None), "1700000000.250000");
+        assert_eq!(f(Some("epoch-ns"), None), "1700000000250000000");
+        assert_eq!(f(Some("iso"), None), "2023-11-14T22:13:20.250000+00:00[UTC]");
+        assert_eq!(f(None, Some("America/Chicago")), "2023-11-14T16:13:20.250000-06:00[America/Chicago]");
+        assert!(Formatter::new(None, Some("Mars/Olympus_Mons")).is_err());
+        assert!(Formatter::new(Some("julian"), None).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(Formatter::default().json(TS), "1700000000.250000");
+        assert_eq!(Formatter::new(Some("iso"), None).unwrap().json(0), "\"1970-01-01T00:00:00.000000+00:00[UTC]\"");
+        assert_eq!(parse_iso("2023-11-14T22:13:20.25Z"), Some(TS));
+        assert_eq!(parse_iso("yesterday"), None);
+    }
+}
@@ -0,0 +1,225 @@
+// Runs the logged-data subcommands end to end, against the mock
+// backend, which makes up a point a second for each request (see
+// `mock::Session`.)
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// The range the tests fetch: five seconds, so five points a request.
+
+const START: &str = "1700000000";
+const END: &str = "1700000005";
+
+fn acsys(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_acsys")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "acsys failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+// Returns the path of a scratch file, unique to the test.
+
+fn scratch(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("acsys-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn test_get_history() {
+    let get = |extra: &[&str]| {
+        let args = [&["get", "history", "--backend", "mock", "--start", START, "--end", END], extra, &["M:OUTTMP", "G:AMANDA"]];
+
+        acsys(&args.concat())
+    };
+    let whole = stdout(&get(&["--chunk", "none"]));
+    let lines: Vec<&str> = whole.lines().collect();
+
+    assert_eq!(lines.len(), 10);
+    assert!(lines[0].starts_with("1700000000.000000 M:OUTTMP.READING.SCALED "), "{}", lines[0]);
+    assert!(lines[1].starts_with("1700000000.000000 G:AMANDA.READING.SCALED "), "{}", lines[1]);
+    assert!(lines[9].starts_with("1700000004.000000 G:AMANDA.READING.SCALED "), "{}", lines[9]);
+
+    // Chunks are stitched back into the same data, whatever their
+    // size and however many are fetched at once. Each is reported as
+    // it arrives.
+
+    let chunked = get(&["--chunk", "2s", "--jobs", "3", "-v"]);
+    let log = String::from_utf8_lossy(&chunked.stderr).into_owned();
+
+    assert_eq!(stdout(&chunked), whole);
+    assert_eq!(log.matches("fetched history chunk").count(), 6, "{}", log);
+    assert!(log.contains("done=6 total=6"), "{}", log);
+    assert_eq!(stdout(&get(&["--chunk", "1s", "--jobs", "1"])), whole);
+}
+
+#[test]
+fn test_get_history_capture() {
+    let capture = scratch("history.json");
+
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1700000000.5,\"drf\":\"M:OUTTMP@P,1000\",\"value\":70}\n\
+         {\"timestamp\":1700000001.5,\"drf\":\"M:OUTTMP@P,1000\",\"value\":71}\n\
+         {\"timestamp\":1700000009.5,\"drf\":\"M:OUTTMP@P,1000\",\"value\":72}\n",
+    )
+    .unwrap();
+
+    let backend = format!("mock:{}", capture.display());
+    let output = acsys(&["get", "history", "--backend", &backend, "--start", START, "--end", END, "--chunk", "1s", "M:OUTTMP"]);
+
+    std::fs::remove_file(&capture).unwrap();
+    assert_eq!(
+        stdout(&output),
+        "1700000000.500000 M:OUTTMP.READING.SCALED 70\n1700000001.500000 M:OUTTMP.READING.SCALED 71\n"
+    );
+}
+
+#[test]
+fn test_get_history_csv() {
+    let csv = scratch("history.csv");
+    let output = acsys(&[
+        "get", "history", "--backend", "mock", "--start", START, "--end", "1700000002", "--decimal-comma",
+        "--output", csv.to_str().unwrap(), "M:OUTTMP",
+    ]);
+
+    assert_eq!(stdout(&output), "");
+
+    let text = std::fs::read_to_string(&csv).unwrap();
+
+    std::fs::remove_file(&csv).unwrap();
+
+    let lines: Vec<&str> = text.lines().collect();
+
+    assert_eq!(lines[0], "timestamp;channel;index;value");
+    assert!(lines[1].starts_with("1700000000,000000;M:OUTTMP.READING.SCALED;;0,0000"), "{}", lines[1]);
+    assert_eq!(lines.len(), 3);
+
+    // The CSV options need a CSV file to apply to.
+
+    let output = acsys(&["get", "history", "--backend", "mock", "--start", START, "--end", END, "--delimiter", "tab", "M:OUTTMP"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_get_history_every() {
+    let capture = scratch("every.json");
+
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1700000000.0,\"drf\":\"M:OUTTMP@P,500\",\"value\":1}\n\
+         {\"timestamp\":1700000000.5,\"drf\":\"M:OUTTMP@P,500\",\"value\":3}\n\
+         {\"timestamp\":1700000001.0,\"drf\":\"M:OUTTMP@P,500\",\"value\":10}\n\
+         {\"timestamp\":1700000001.5,\"drf\":\"M:OUTTMP@P,500\",\"value\":20}\n\
+         {\"timestamp\":1700000002.2,\"drf\":\"M:OUTTMP@P,500\",\"value\":5}\n",
+    )
+    .unwrap();
+
+    let backend = format!("mock:{}", capture.display());
+    let get = |extra: &[&str]| {
+        let args = [&["get", "history", "--backend", &backend, "--start", START, "--end", END, "-f", "json"], extra, &["M:OUTTMP"]];
+        let values: Vec<String> = stdout(&acsys(&args.concat()))
+            .lines()
+            .map(|l| l.rsplit_once("\"value\":").unwrap().1.trim_end_matches('}').to_owned())
+            .collect();
+
+        values.join(" ")
+    };
+    let (mean, min, max, last) = (
+        get(&["--every", "1s"]),
+        get(&["--every", "1s", "--agg", "min"]),
+        get(&["--every", "1s", "--agg", "max"]),
+        get(&["--every", "1s", "--agg", "last"]),
+    );
+
+    // Bins are stamped with their start and span chunk boundaries,
+    // since the points are binned once they've all been fetched.
+
+    let stamped = stdout(&acsys(&[
+        "get", "history", "--backend", &backend, "--start", START, "--end", END, "--every", "2s", "--chunk", "1s", "M:OUTTMP",
+    ]));
+    let spans = acsys(&["get", "history", "--backend", &backend, "--start", START, "--end", END, "--every", "0s", "M:OUTTMP"]);
+
+    std::fs::remove_file(&capture).unwrap();
+    assert_eq!(mean, "2 15 5");
+    assert_eq!(min, "1 10 5");
+    assert_eq!(max, "3 20 5");
+    assert_eq!(last, "3 20 5");
+    assert_eq!(stamped, "1700000000.000000 M:OUTTMP.READING.SCALED 8.5\n1700000002.000000 M:OUTTMP.READING.SCALED 5\n");
+    assert_eq!(spans.status.code(), Some(2));
+}
+
+#[test]
+fn test_overlay() {
+    let capture = scratch("overlay.json");
+
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1700000000.0,\"drf\":\"Z:DEV.SETTING.SCALED\",\"value\":10}\n\
+         {\"timestamp\":1700000000.5,\"drf\":\"Z:DEV.READING.SCALED\",\"value\":9.5}\n\
+         {\"timestamp\":1700000002.0,\"drf\":\"Z:DEV.SETTING.SCALED\",\"value\":12}\n\
+         {\"timestamp\":1700000002.5,\"drf\":\"Z:DEV.READING.SCALED\",\"value\":11}\n\
+         {\"timestamp\":1700000009.0,\"drf\":\"Z:DEV.READING.SCALED\",\"value\":12}\n",
+    )
+    .unwrap();
+
+    let backend = format!("mock:{}", capture.display());
+    let overlay = |format| acsys(&["overlay", "--backend", &backend, "--start", START, "--end", END, "-f", format, "Z:DEV"]);
+    let (text, json) = (overlay("text"), overlay("json"));
+
+    std::fs::remove_file(&capture).unwrap();
+    assert_eq!(
+        stdout(&json),
+        "{\"timestamp\":1700000000.000000,\"setting\":10,\"reading\":null,\"error\":null}\n\
+         {\"timestamp\":1700000000.500000,\"setting\":10,\"reading\":9.5,\"error\":-0.5}\n\
+         {\"timestamp\":1700000002.000000,\"setting\":12,\"reading\":9.5,\"error\":-2.5}\n\
+         {\"timestamp\":1700000002.500000,\"setting\":12,\"reading\":11,\"error\":-1}\n"
+    );
+    assert!(stdout(&text).ends_with("mean error 1.3333333333333333, largest 2.5 at 1700000002.000000\n"));
+
+    // The synthetic data of the plain mock backend gives a row a
+    // second.
+
+    let output = acsys(&["overlay", "--backend", "mock", "--start", START, "--end", END, "-f", "json", "M:OUTTMP"]);
+
+    assert_eq!(stdout(&output).lines().count(), 5);
+}
+
+#[test]
+fn test_report() {
+    let (spec, capture) = (scratch("report.toml"), scratch("report.json"));
+
+    std::fs::write(
+        &spec,
+        "title = \"Test report\"\n\n\
+         [[section]]\nname = \"Temperatures\"\nkind = \"stats\"\ndevices = [\"Z:DEV\"]\n\n\
+         [[section]]\nname = \"RF\"\nkind = \"uptime\"\ndevices = [\"Z:RF\"]\nthreshold = 0.5\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1700000000.0,\"drf\":\"Z:DEV\",\"value\":10}\n\
+         {\"timestamp\":1700000001.0,\"drf\":\"Z:DEV\",\"value\":12}\n\
+         {\"timestamp\":1700000002.0,\"drf\":\"Z:DEV\",\"value\":14}\n\
+         {\"timestamp\":1700000000.0,\"drf\":\"Z:RF\",\"value\":1}\n\
+         {\"timestamp\":1700000002.0,\"drf\":\"Z:RF\",\"value\":0}\n\
+         {\"timestamp\":1700000003.0,\"drf\":\"Z:RF\",\"value\":1}\n\
+         {\"timestamp\":1700000009.0,\"drf\":\"Z:RF\",\"value\":0}\n",
+    )
+    .unwrap();
+
+    let backend = format!("mock:{}", capture.display());
+    let output = acsys(&[
+        "report", "--backend", &backend, "--spec", spec.to_str().unwrap(), "--for", "1700000000..1700000004", "--tz", "UTC",
+    ]);
+
+    std::fs::remove_file(&spec).unwrap();
+    std::fs::remove_file(&capture).unwrap();
+
+    let text = stdout(&output);
+
+    assert!(text.starts_with("# Test report\n"), "{}", text);
+    assert!(text.contains("| Z:DEV | 3 | 12.000 | 1.633 | 10.000 | 14.000 |\n"), "{}", text);
+    assert!(text.contains("| Z:RF | 75.0% | 1 |\n"), "{}", text);
+}
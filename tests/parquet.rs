@@ -0,0 +1,66 @@
+// Checks the Parquet output of GET HISTORY with pyarrow (see
+// `read_parquet.py`), so the file is read by something other than the
+// code which wrote it. Without python3 and pyarrow the test is
+// skipped, unless ACSYS_REQUIRE_PYARROW is set, as it is in CI.
+
+use std::process::Command;
+
+fn acsys(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_acsys")).args(args).output().unwrap();
+
+    assert!(output.status.success(), "acsys failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_parquet_pyarrow() {
+    let file = std::env::temp_dir().join(format!("acsys-{}-history.parquet", std::process::id()));
+    let get = ["get", "history", "--backend", "mock", "--start", "1700000000", "--end", "1700000005"];
+    let devices = ["M:OUTTMP", "G:AMANDA", "Z:STATUS"];
+
+    assert_eq!(acsys(&[&get[..], &["--format", "parquet", "--output", file.to_str().unwrap()], &devices].concat()), "");
+
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/read_parquet.py");
+    let read = Command::new("python3").arg(script).arg(&file).output();
+
+    std::fs::remove_file(&file).unwrap();
+
+    let read = match read {
+        Ok(read) if read.status.code() != Some(77) => read,
+        _ if std::env::var_os("ACSYS_REQUIRE_PYARROW").is_some() => panic!("python3 with pyarrow is needed"),
+        _ => {
+            eprintln!("skipped: python3 with pyarrow isn't available");
+            return;
+        }
+    };
+
+    assert!(read.status.success(), "pyarrow failed: {}", String::from_utf8_lossy(&read.stderr));
+
+    let text = String::from_utf8(read.stdout).unwrap();
+    let mut lines = text.lines().map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap());
+    let schema = lines.next().unwrap();
+
+    assert!(schema["timestamp"].as_str().unwrap().starts_with("timestamp[us"), "{}", schema);
+    assert_eq!(schema["device"], "string");
+    assert_eq!(schema["index"], "int32");
+    assert_eq!(schema["value"], "double");
+    assert_eq!(schema["status"], "int16");
+
+    // Each row holds what the JSON output shows for the same data.
+
+    let rows: Vec<serde_json::Value> = lines.collect();
+    let json = acsys(&[&get[..], &["--format", "json"], &devices].concat());
+    let expected: Vec<serde_json::Value> = json.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+
+    assert_eq!(rows.len(), 15);
+    assert_eq!(rows.len(), expected.len());
+    for (row, reading) in rows.iter().zip(&expected) {
+        let drf = reading["drf"].as_str().unwrap();
+
+        assert_eq!(row["timestamp"].as_i64(), Some((reading["timestamp"].as_f64().unwrap() * 1e6).round() as i64));
+        assert_eq!(Some(row["device"].as_str().unwrap()), drf.split('.').next());
+        assert!(row["index"].is_null());
+        assert_eq!(row["value"], reading["value"]);
+        assert_eq!(row["status"], 0);
+    }
+}
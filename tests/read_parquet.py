@@ -0,0 +1,28 @@
+#!/usr/bin/env python3
+#
+# Reads a Parquet file with pyarrow, a reader independent of the one
+# in `parquet.rs`, for tests/parquet.rs. The first line printed is the
+# file's schema, as a JSON object of column types, and each line
+# after it a row, with the timestamp in microseconds. Exits with 77
+# if pyarrow isn't installed.
+
+import json
+import sys
+
+try:
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+except ImportError:
+    sys.exit(77)
+
+table = pq.read_table(sys.argv[1])
+
+print(json.dumps({field.name: str(field.type) for field in table.schema}))
+
+columns = {
+    name: table.column(name).cast(pa.int64()) if name == "timestamp" else table.column(name)
+    for name in table.column_names
+}
+
+for row in zip(*(column.to_pylist() for column in columns.values())):
+    print(json.dumps(dict(zip(columns, row))))
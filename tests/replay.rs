@@ -0,0 +1,100 @@
+// Runs REPLAY and SIMULATE end to end, into the sinks `get live`
+// writes to, and CAPTURE MERGE, whose output REPLAY reads.
+
+use std::process::{Command, Output};
+
+fn acsys(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_acsys")).args(args).output().unwrap()
+}
+
+#[test]
+fn test_replay_sinks() {
+    let dir = std::env::temp_dir();
+    let capture = dir.join(format!("acsys-{}-replay.json", std::process::id()));
+    let (json, csv) = (
+        dir.join(format!("acsys-{}-replayed.json", std::process::id())),
+        dir.join(format!("acsys-{}-replayed.csv", std::process::id())),
+    );
+
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1.0,\"drf\":\"M:OUTTMP\",\"value\":1}\n\
+         {\"timestamp\":1.5,\"drf\":\"M:OUTTMP\",\"value\":2}\n",
+    )
+    .unwrap();
+
+    let output = acsys(&[
+        "replay", capture.to_str().unwrap(), "--speed", "max", "--sink", "stdout",
+        "--sink", &format!("json:{}", json.display()), "--output", csv.to_str().unwrap(),
+    ]);
+    let (json_text, csv_text) = (std::fs::read_to_string(&json).unwrap(), std::fs::read_to_string(&csv).unwrap());
+
+    std::fs::remove_file(&json).unwrap();
+    std::fs::remove_file(&csv).unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1.000000 M:OUTTMP.READING.SCALED 1\n1.500000 M:OUTTMP.READING.SCALED 2\n"
+    );
+    assert_eq!(
+        json_text,
+        "{\"timestamp\":1.000000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":1}\n\
+         {\"timestamp\":1.500000,\"drf\":\"M:OUTTMP.READING.SCALED\",\"value\":2}\n"
+    );
+    assert_eq!(csv_text.lines().count(), 3);
+
+    // Kafka is reached like any other sink; here, there's no broker.
+
+    let output = acsys(&["replay", capture.to_str().unwrap(), "--retries", "0", "--sink", "kafka://127.0.0.1:1/acnet"]);
+
+    std::fs::remove_file(&capture).unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn test_simulate_sinks() {
+    let json = std::env::temp_dir().join(format!("acsys-{}-simulated.json", std::process::id()));
+    let output = acsys(&["simulate", "--devices", "2", "--rate", "10Hz", "--duration", "0.25", "--output", json.to_str().unwrap()]);
+    let text = std::fs::read_to_string(&json).unwrap();
+
+    std::fs::remove_file(&json).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty());
+    assert!(text.lines().count() >= 4, "{}", text);
+    assert!(text.starts_with("{\"timestamp\":"), "{}", text);
+    assert!(text.contains("\"drf\":\"Z:SIM0001"), "{}", text);
+}
+
+#[test]
+fn test_merge_replay() {
+    let scratch = |name: &str| std::env::temp_dir().join(format!("acsys-{}-{}", std::process::id(), name));
+    let (a, b, merged) = (scratch("merge-a.json"), scratch("merge-b.json"), scratch("merged.acs"));
+
+    std::fs::write(&a, "{\"timestamp\":1.0,\"drf\":\"M:OUTTMP\",\"value\":1}\n").unwrap();
+    std::fs::write(&b, "{\"timestamp\":2.0,\"drf\":\"G:AMANDA\",\"status\":-6378}\n").unwrap();
+
+    let output = acsys(&["capture", "merge", a.to_str().unwrap(), b.to_str().unwrap(), "-o", merged.to_str().unwrap()]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The merged capture is binary, and reads back as the records
+    // merged.
+
+    let data = std::fs::read(&merged).unwrap();
+    let back = acsys(&["capture", "merge", merged.to_str().unwrap()]);
+    let influx = acsys(&["replay", merged.to_str().unwrap(), "--speed", "max", "--format", "influx"]);
+
+    for file in [&a, &b, &merged] {
+        std::fs::remove_file(file).unwrap()
+    }
+
+    assert!(!data.starts_with(b"{"));
+    assert_eq!(
+        String::from_utf8(back.stdout).unwrap(),
+        "{\"timestamp\":1.000000,\"drf\":\"M:OUTTMP\",\"value\":1}\n\
+         {\"timestamp\":2.000000,\"drf\":\"G:AMANDA\",\"status\":-6378}\n"
+    );
+    assert!(influx.status.success(), "{}", String::from_utf8_lossy(&influx.stderr));
+    assert!(String::from_utf8(influx.stdout).unwrap().starts_with("M:OUTTMP value=1,status=0i 1000000000\n"));
+}
@@ -0,0 +1,40 @@
+// Runs SAVE end to end against the mock backend, replaying a capture
+// in which one of the settings can't be read.
+
+use std::process::Command;
+
+#[test]
+fn test_save_unreadable() {
+    let dir = std::env::temp_dir();
+    let (capture, file) = (
+        dir.join(format!("acsys-{}-save.json", std::process::id())),
+        dir.join(format!("acsys-{}-snapshot.json", std::process::id())),
+    );
+
+    std::fs::write(
+        &capture,
+        "{\"timestamp\":1700000000.0,\"drf\":\"Z:A.SETTING\",\"value\":5}\n\
+         {\"timestamp\":1700000000.0,\"drf\":\"Z:B.SETTING\",\"status\":-6378}\n",
+    )
+    .unwrap();
+
+    // The failed read counts as the setting's reply, so the command
+    // ends, writes what it could and names what it couldn't.
+
+    let output = Command::new(env!("CARGO_BIN_EXE_acsys"))
+        .args(["save", "--backend", &format!("mock:{}", capture.display()), "--file", file.to_str().unwrap()])
+        .args(["Z:A", "Z:B"])
+        .output()
+        .unwrap();
+    let snapshot = std::fs::read_to_string(&file).unwrap();
+
+    std::fs::remove_file(&capture).unwrap();
+    std::fs::remove_file(&file).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("1 of the 2 settings couldn't be read: Z:B.SETTING.SCALED (status -6378"), "{}", stderr);
+    assert!(snapshot.contains("{\"drf\":\"Z:A.SETTING.SCALED\",\"value\":\"5\""), "{}", snapshot);
+    assert!(snapshot.contains("{\"drf\":\"Z:B.SETTING.SCALED\",\"value\":null"), "{}", snapshot);
+}